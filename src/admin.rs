@@ -1,5 +1,7 @@
 //! Admin message types used to initialize & control a Raft node.
 
+use std::collections::BTreeSet;
+
 use actix::prelude::*;
 
 use crate::{
@@ -52,10 +54,18 @@ impl std::error::Error for InitWithConfigError {}
 
 /// Propose a new membership config change to a running cluster.
 ///
+/// When using joint consensus, this command resolves once the joint config has been committed,
+/// while any new nodes may still be catching up. If `Config.catch_up_timeout` is set & a new
+/// node has not caught up by that deadline, the change will be rolled back. Non-voters added via
+/// `AddNonVoter` which are being added will be promoted to voting members.
+///
 /// There are a few invariants which must be upheld here:
 ///
 /// - if the node this command is sent to is not the leader of the cluster, it will be rejected.
 /// - if the given changes would leave the cluster in an inoperable state, it will be rejected.
+/// - if the node is configured with `MembershipChangeMode::SingleServer`, changes which add or
+///   remove more than one node, or which are proposed while a previous change is uncommitted,
+///   will be rejected.
 pub struct ProposeConfigChange<D: AppData, R: AppDataResponse, E: AppError> {
     /// New members to be added to the cluster.
     pub(crate) add_members: Vec<NodeId>,
@@ -83,6 +93,14 @@ impl<D: AppData, R: AppDataResponse, E: AppError> Message for ProposeConfigChang
 /// The set of errors which may take place when requesting to propose a config change.
 #[derive(Debug)]
 pub enum ProposeConfigChangeError<D: AppData, R: AppDataResponse, E: AppError> {
+    /// A previous membership change has not yet been committed.
+    ///
+    /// This error will be returned when using `MembershipChangeMode::SingleServer`, which only
+    /// allows one uncommitted change at a time. This also applies to a newly elected leader which
+    /// has not yet committed an entry from its term. It is also returned while a `ChangeMembership`
+    /// command is in progress, as merging into its change would leave the cluster with a different
+    /// set of members than it was given. It is safe to retry the operation.
+    ChangeInProgress,
     /// An error related to the processing of the config change request.
     ///
     /// Errors of this type will only come about from the internals of applying the config change
@@ -98,6 +116,10 @@ pub enum ProposeConfigChangeError<D: AppData, R: AppDataResponse, E: AppError> {
     /// These should never normally take place, but if one is encountered, it should be safe to
     /// retry the operation.
     Internal,
+    /// The proposed config changes would add or remove more than one node.
+    ///
+    /// This error will only be returned when using `MembershipChangeMode::SingleServer`.
+    MultipleChanges,
     /// The node the config change proposal was sent to was not the leader of the cluster.
     ///
     /// If the current cluster leader is known, its ID will be wrapped in this variant.
@@ -115,9 +137,11 @@ pub enum ProposeConfigChangeError<D: AppData, R: AppDataResponse, E: AppError> {
 impl<D: AppData, R: AppDataResponse, E: AppError> std::fmt::Display for ProposeConfigChangeError<D, R, E> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            ProposeConfigChangeError::ChangeInProgress => write!(f, "A previous membership change has not yet been committed."),
             ProposeConfigChangeError::ClientError(err) => write!(f, "{}", err),
             ProposeConfigChangeError::InoperableConfig => write!(f, "The given config would leave the cluster in an inoperable state."),
             ProposeConfigChangeError::Internal => write!(f, "An error internal to Raft has taken place."),
+            ProposeConfigChangeError::MultipleChanges => write!(f, "Only a single node may be added or removed per membership change."),
             ProposeConfigChangeError::NodeNotLeader(leader_opt) => write!(f, "The handling node is not the Raft leader. Tracked value for cluster leader: {:?}", leader_opt),
             ProposeConfigChangeError::Noop => write!(f, "The proposed config change would have no effect, this is a no-op."),
         }
@@ -125,3 +149,373 @@ impl<D: AppData, R: AppDataResponse, E: AppError> std::fmt::Display for ProposeC
 }
 
 impl<D: AppData, R: AppDataResponse, E: AppError> std::error::Error for ProposeConfigChangeError<D, R, E> {}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////
+// ChangeMembership //////////////////////////////////////////////////////////////////////////////
+
+/// Change the set of voting members of a running cluster to the given set of nodes.
+///
+/// Unlike `ProposeConfigChange`, which takes the nodes to be added & removed, this command takes
+/// the desired final set of voting members. Raft will work out the nodes to add & remove, will
+/// enter joint consensus, will bring any new nodes up-to-speed, and will finalize the change.
+/// This command resolves only once the final uniform config has been committed. Non-voters added
+/// via `AddNonVoter` which are part of the given set will be promoted to voting members.
+///
+/// This command always uses joint consensus, regardless of the configured membership change mode.
+///
+/// Members may also be designated as witnesses via `ChangeMembership::with_witnesses`. Witnesses
+/// vote & count toward commitment, but only store entry metadata, and never become leader. If
+/// only the set of witnesses changes, the new config is committed directly, without going
+/// through joint consensus.
+///
+/// If `Config.catch_up_timeout` is set & a new node has not caught up with the leader's log by
+/// that deadline, the change is rolled back & this command resolves with a `CatchUpTimeout`
+/// error. The progress of the new nodes is reported in the leader's metrics meanwhile.
+///
+/// There are a few invariants which must be upheld here:
+///
+/// - if the node this command is sent to is not the leader of the cluster, it will be rejected.
+/// - if the given set has less than two members, it will be rejected.
+/// - if a membership change is already in progress, it will be rejected.
+/// - if the witnesses are not members, include the leader, include every member, or omit a
+///   current witness which is staying in the cluster, it will be rejected.
+pub struct ChangeMembership<D: AppData, R: AppDataResponse, E: AppError> {
+    /// The desired set of voting members of the cluster.
+    pub(crate) members: BTreeSet<NodeId>,
+    /// The desired set of witnesses, which must be a subset of `members`.
+    pub(crate) witnesses: BTreeSet<NodeId>,
+    marker_data: std::marker::PhantomData<D>,
+    marker_res: std::marker::PhantomData<R>,
+    marker_error: std::marker::PhantomData<E>,
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError> ChangeMembership<D, R, E> {
+    /// Create a new instance.
+    pub fn new(members: BTreeSet<NodeId>) -> Self {
+        Self::with_witnesses(members, BTreeSet::new())
+    }
+
+    /// Create a new instance, designating the given members as witnesses.
+    pub fn with_witnesses(members: BTreeSet<NodeId>, witnesses: BTreeSet<NodeId>) -> Self {
+        Self{members, witnesses, marker_data: std::marker::PhantomData, marker_res: std::marker::PhantomData, marker_error: std::marker::PhantomData}
+    }
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError> Message for ChangeMembership<D, R, E> {
+    type Result = Result<(), ChangeMembershipError<D, R, E>>;
+}
+
+/// The set of errors which may take place when requesting to change the cluster's membership.
+#[derive(Debug)]
+pub enum ChangeMembershipError<D: AppData, R: AppDataResponse, E: AppError> {
+    /// The change was aborted via an `AbortConfigChange` command.
+    ///
+    /// The previous set of voting members has been committed again.
+    Aborted,
+    /// A new node did not catch up within the configured `catch_up_timeout`.
+    ///
+    /// The change has been aborted, & the previous set of voting members has been committed
+    /// again. Any nodes being added, including non-voters being promoted, have been dropped.
+    CatchUpTimeout,
+    /// A previous membership change has not yet been completed.
+    ///
+    /// It is safe to retry the operation once the previous change has completed.
+    ChangeInProgress,
+    /// An error related to committing the joint or the final config to the cluster.
+    ClientError(ClientError<D, R, E>),
+    /// The given set of members would leave the cluster in an inoperable state.
+    ///
+    /// This error will be returned if the given set has less than two members.
+    InoperableConfig,
+    /// An internal error has taken place.
+    ///
+    /// These should never normally take place, but if one is encountered, it should be safe to
+    /// retry the operation.
+    Internal,
+    /// The given set of witnesses is invalid.
+    ///
+    /// Witnesses must be members of the new config, must not include the leader, and must leave
+    /// at least one member which is not a witness. As witnesses hold no application data, a
+    /// current witness may not become a full member.
+    InvalidWitnesses,
+    /// The node the command was sent to was not the leader of the cluster, or it lost its
+    /// leadership before the final config could be committed.
+    ///
+    /// In the latter case, the change may still be completed by the new leader. If the current
+    /// cluster leader is known, its ID will be wrapped in this variant.
+    NodeNotLeader(Option<NodeId>),
+    /// The given sets of members & witnesses are the same as the current ones.
+    Noop,
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError> std::fmt::Display for ChangeMembershipError<D, R, E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChangeMembershipError::Aborted => write!(f, "The membership change was aborted, so it has been rolled back."),
+            ChangeMembershipError::CatchUpTimeout => write!(f, "A new node did not catch up in time, so the membership change has been rolled back."),
+            ChangeMembershipError::ChangeInProgress => write!(f, "A previous membership change has not yet been completed."),
+            ChangeMembershipError::ClientError(err) => write!(f, "{}", err),
+            ChangeMembershipError::InoperableConfig => write!(f, "The given config would leave the cluster in an inoperable state."),
+            ChangeMembershipError::Internal => write!(f, "An error internal to Raft has taken place."),
+            ChangeMembershipError::InvalidWitnesses => write!(f, "The given witnesses are not valid for the given members."),
+            ChangeMembershipError::NodeNotLeader(leader_opt) => write!(f, "The handling node is not the Raft leader. Tracked value for cluster leader: {:?}", leader_opt),
+            ChangeMembershipError::Noop => write!(f, "The given members & witnesses are the current ones of the cluster, this is a no-op."),
+        }
+    }
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError> std::error::Error for ChangeMembershipError<D, R, E> {}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////
+// AbortConfigChange /////////////////////////////////////////////////////////////////////////////
+
+/// Abort the joint consensus config change which is currently in progress.
+///
+/// This is useful when a node being added turns out to be unreachable, as the cluster would
+/// otherwise remain in joint consensus until that node catches up. Every node being added is
+/// dropped, the nodes being removed are kept, and the previous set of voting members is committed
+/// again. The replication streams of the dropped nodes are kept until they have replicated the
+/// reverted config. This command resolves once the reverted
+/// config has been committed. Any pending `ChangeMembership` command will resolve with an
+/// `Aborted` error.
+///
+/// There are a few invariants which must be upheld here:
+///
+/// - if the node this command is sent to is not the leader of the cluster, it will be rejected.
+/// - if the cluster is not in joint consensus, it will be rejected.
+pub struct AbortConfigChange<D: AppData, R: AppDataResponse, E: AppError> {
+    marker_data: std::marker::PhantomData<D>,
+    marker_res: std::marker::PhantomData<R>,
+    marker_error: std::marker::PhantomData<E>,
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError> AbortConfigChange<D, R, E> {
+    /// Create a new instance.
+    pub fn new() -> Self {
+        Self{marker_data: std::marker::PhantomData, marker_res: std::marker::PhantomData, marker_error: std::marker::PhantomData}
+    }
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError> Default for AbortConfigChange<D, R, E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError> Message for AbortConfigChange<D, R, E> {
+    type Result = Result<(), AbortConfigChangeError<D, R, E>>;
+}
+
+/// The set of errors which may take place when requesting to abort a config change.
+#[derive(Debug)]
+pub enum AbortConfigChangeError<D: AppData, R: AppDataResponse, E: AppError> {
+    /// An error related to committing the reverted config to the cluster.
+    ClientError(ClientError<D, R, E>),
+    /// An internal error has taken place.
+    Internal,
+    /// The node the command was sent to was not the leader of the cluster.
+    ///
+    /// If the current cluster leader is known, its ID will be wrapped in this variant.
+    NodeNotLeader(Option<NodeId>),
+    /// The cluster is not in joint consensus, so there is no config change to abort.
+    ///
+    /// Changes made via `MembershipChangeMode::SingleServer` take effect right away, and can not
+    /// be aborted.
+    NotInJointConsensus,
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError> std::fmt::Display for AbortConfigChangeError<D, R, E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AbortConfigChangeError::ClientError(err) => write!(f, "{}", err),
+            AbortConfigChangeError::Internal => write!(f, "An error internal to Raft has taken place."),
+            AbortConfigChangeError::NodeNotLeader(leader_opt) => write!(f, "The handling node is not the Raft leader. Tracked value for cluster leader: {:?}", leader_opt),
+            AbortConfigChangeError::NotInJointConsensus => write!(f, "The cluster is not in joint consensus, so there is no config change to abort."),
+        }
+    }
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError> std::error::Error for AbortConfigChangeError<D, R, E> {}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////
+// TransferLeadership ////////////////////////////////////////////////////////////////////////////
+
+/// Transfer leadership of the cluster to the target node (§3.10).
+///
+/// This is useful for rolling restarts, or for moving load off of the current leader. While the
+/// transfer is in progress, the leader will stop accepting new client payloads. Once the target
+/// is fully up-to-date, it will be instructed to start an election immediately.
+///
+/// There are a few invariants which must be upheld here:
+///
+/// - if the node this command is sent to is not the leader of the cluster, it will be rejected.
+/// - if the target is not a voting member of the cluster, or is a witness, it will be rejected.
+/// - if the transfer does not complete within an election timeout, it will be aborted.
+pub struct TransferLeadership {
+    /// The ID of the node which should become the new leader of the cluster.
+    pub target: NodeId,
+}
+
+impl TransferLeadership {
+    /// Create a new instance.
+    pub fn new(target: NodeId) -> Self {
+        Self{target}
+    }
+}
+
+impl Message for TransferLeadership {
+    type Result = Result<(), TransferLeadershipError>;
+}
+
+/// The set of errors which may take place when requesting to transfer leadership.
+#[derive(Debug)]
+pub enum TransferLeadershipError {
+    /// An internal error has taken place.
+    Internal,
+    /// The target node is not a voting member of the cluster, is a witness, or is the leader itself.
+    InvalidTarget,
+    /// The node the command was sent to was not the leader of the cluster, or it lost its
+    /// leadership before the transfer could complete.
+    ///
+    /// If the current cluster leader is known, its ID will be wrapped in this variant.
+    NodeNotLeader(Option<NodeId>),
+    /// A leadership transfer is already in progress.
+    InProgress,
+    /// The transfer did not complete within an election timeout.
+    Timeout,
+}
+
+impl std::fmt::Display for TransferLeadershipError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransferLeadershipError::Internal => write!(f, "An error internal to Raft has taken place."),
+            TransferLeadershipError::InvalidTarget => write!(f, "The target of the leadership transfer is not a voting member of the cluster, or is a witness."),
+            TransferLeadershipError::NodeNotLeader(leader_opt) => write!(f, "The handling node is not the Raft leader. Tracked value for cluster leader: {:?}", leader_opt),
+            TransferLeadershipError::InProgress => write!(f, "A leadership transfer is already in progress."),
+            TransferLeadershipError::Timeout => write!(f, "The leadership transfer did not complete within an election timeout."),
+        }
+    }
+}
+
+impl std::error::Error for TransferLeadershipError {}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////
+// AddNonVoter ///////////////////////////////////////////////////////////////////////////////////
+
+/// Add a permanent non-voting member to a running cluster.
+///
+/// Non-voters added via this command replicate the log from the leader, but they never vote,
+/// never campaign and do not count toward commitment. This is useful for read replicas, analytics
+/// nodes & warm standbys. As the voting members of the cluster are left unchanged, the new config
+/// is committed directly, without going through joint consensus.
+///
+/// There are a few invariants which must be upheld here:
+///
+/// - if the node this command is sent to is not the leader of the cluster, it will be rejected.
+/// - if the target is already a member of the cluster, in any capacity, it will be rejected.
+pub struct AddNonVoter<D: AppData, R: AppDataResponse, E: AppError> {
+    /// The ID of the node to be added as a non-voter.
+    pub(crate) id: NodeId,
+    marker_data: std::marker::PhantomData<D>,
+    marker_res: std::marker::PhantomData<R>,
+    marker_error: std::marker::PhantomData<E>,
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError> AddNonVoter<D, R, E> {
+    /// Create a new instance.
+    pub fn new(id: NodeId) -> Self {
+        Self{id, marker_data: std::marker::PhantomData, marker_res: std::marker::PhantomData, marker_error: std::marker::PhantomData}
+    }
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError> Message for AddNonVoter<D, R, E> {
+    type Result = Result<(), AddNonVoterError<D, R, E>>;
+}
+
+/// The set of errors which may take place when requesting to add a non-voter.
+#[derive(Debug)]
+pub enum AddNonVoterError<D: AppData, R: AppDataResponse, E: AppError> {
+    /// The target node is already a member of the cluster.
+    AlreadyMember,
+    /// An error related to committing the new config to the cluster.
+    ClientError(ClientError<D, R, E>),
+    /// An internal error has taken place.
+    Internal,
+    /// The node the command was sent to was not the leader of the cluster.
+    ///
+    /// If the current cluster leader is known, its ID will be wrapped in this variant.
+    NodeNotLeader(Option<NodeId>),
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError> std::fmt::Display for AddNonVoterError<D, R, E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AddNonVoterError::AlreadyMember => write!(f, "The target node is already a member of the cluster."),
+            AddNonVoterError::ClientError(err) => write!(f, "{}", err),
+            AddNonVoterError::Internal => write!(f, "An error internal to Raft has taken place."),
+            AddNonVoterError::NodeNotLeader(leader_opt) => write!(f, "The handling node is not the Raft leader. Tracked value for cluster leader: {:?}", leader_opt),
+        }
+    }
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError> std::error::Error for AddNonVoterError<D, R, E> {}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////
+// RemoveNonVoter ////////////////////////////////////////////////////////////////////////////////
+
+/// Remove a permanent non-voting member, previously added via `AddNonVoter`, from the cluster.
+///
+/// As with `AddNonVoter`, the new config is committed directly, without going through joint
+/// consensus. The target's replication stream is kept alive until the target has replicated the
+/// new config, after which the target will be in the `NonVoter` state & may be shutdown.
+///
+/// There are a few invariants which must be upheld here:
+///
+/// - if the node this command is sent to is not the leader of the cluster, it will be rejected.
+/// - if the target is not a non-voter added via `AddNonVoter`, it will be rejected.
+pub struct RemoveNonVoter<D: AppData, R: AppDataResponse, E: AppError> {
+    /// The ID of the non-voter to be removed.
+    pub(crate) id: NodeId,
+    marker_data: std::marker::PhantomData<D>,
+    marker_res: std::marker::PhantomData<R>,
+    marker_error: std::marker::PhantomData<E>,
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError> RemoveNonVoter<D, R, E> {
+    /// Create a new instance.
+    pub fn new(id: NodeId) -> Self {
+        Self{id, marker_data: std::marker::PhantomData, marker_res: std::marker::PhantomData, marker_error: std::marker::PhantomData}
+    }
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError> Message for RemoveNonVoter<D, R, E> {
+    type Result = Result<(), RemoveNonVoterError<D, R, E>>;
+}
+
+/// The set of errors which may take place when requesting to remove a non-voter.
+#[derive(Debug)]
+pub enum RemoveNonVoterError<D: AppData, R: AppDataResponse, E: AppError> {
+    /// An error related to committing the new config to the cluster.
+    ClientError(ClientError<D, R, E>),
+    /// An internal error has taken place.
+    Internal,
+    /// The node the command was sent to was not the leader of the cluster.
+    ///
+    /// If the current cluster leader is known, its ID will be wrapped in this variant.
+    NodeNotLeader(Option<NodeId>),
+    /// The target node is not a non-voter which was added via `AddNonVoter`.
+    NotNonVoter,
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError> std::fmt::Display for RemoveNonVoterError<D, R, E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RemoveNonVoterError::ClientError(err) => write!(f, "{}", err),
+            RemoveNonVoterError::Internal => write!(f, "An error internal to Raft has taken place."),
+            RemoveNonVoterError::NodeNotLeader(leader_opt) => write!(f, "The handling node is not the Raft leader. Tracked value for cluster leader: {:?}", leader_opt),
+            RemoveNonVoterError::NotNonVoter => write!(f, "The target node is not a non-voter of the cluster."),
+        }
+    }
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError> std::error::Error for RemoveNonVoterError<D, R, E> {}
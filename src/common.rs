@@ -1,14 +1,15 @@
 //! Common types and functionality used by the Raft actor.
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Instant};
 
 use futures::sync::oneshot;
 
 use crate::{
     AppData, AppDataResponse, AppError, NodeId,
     messages::{
-        ClientError, ClientPayload, ClientPayloadResponse,
-        Entry, ResponseMode,
+        AppendEntriesRequest, AppendEntriesResponse, ClientBatchError, ClientError, ClientPayload, ClientPayloadBatch, ClientPayloadResponse,
+        ClientReadError, ClientReadResponse,
+        ClientSession, Entry, EntryPayload, ResponseMode,
     },
 };
 
@@ -23,30 +24,152 @@ pub(crate) enum ApplyLogsTask<D: AppData, R: AppDataResponse, E: AppError> {
     /// Check for & apply any logs which have been committed but which have not yet been applied.
     Outstanding,
     /// Logs which need to be applied are supplied for immediate use.
-    Entry {
-        /// The payload of logs to be applied.
-        entry: Arc<Entry<D>>,
+    Entries {
+        /// The payload of logs to be applied, in order.
+        entries: Vec<Arc<Entry<D>>>,
         /// The optional response channel for when this task is complete.
-        chan: Option<oneshot::Sender<Result<ClientPayloadResponse<R>, ClientError<D, R, E>>>>,
+        chan: Option<ClientTx<D, R, E>>,
     },
 }
 
+//////////////////////////////////////////////////////////////////////////////////////////////////
+// ClientTx //////////////////////////////////////////////////////////////////////////////////////
+
+/// The response channel of a client request.
+pub(crate) enum ClientTx<D: AppData, R: AppDataResponse, E: AppError> {
+    /// The channel of a `ClientPayload` request.
+    Single(oneshot::Sender<Result<ClientPayloadResponse<R>, ClientError<D, R, E>>>),
+    /// The channel of a `ClientPayloadBatch` request.
+    Batch(ClientBatchTx<D, R, E>),
+}
+
+/// The response channel of a `ClientPayloadBatch` request.
+pub(crate) type ClientBatchTx<D, R, E> = oneshot::Sender<Result<Vec<ClientPayloadResponse<R>>, ClientBatchError<D, R, E>>>;
+
+impl<D: AppData, R: AppDataResponse, E: AppError> ClientTx<D, R, E> {
+    /// Send the given result to the client.
+    ///
+    /// On success, there must be one response per entry of the original request. This will
+    /// return an error if the receiving end of the channel has been dropped.
+    pub(crate) fn send(self, res: Result<Vec<ClientPayloadResponse<R>>, ClientError<D, R, E>>) -> Result<(), ()> {
+        match self {
+            ClientTx::Single(tx) => {
+                let res = match res {
+                    Ok(mut responses) => responses.pop().ok_or(ClientError::Internal),
+                    Err(err) => Err(err),
+                };
+                tx.send(res).map_err(|_| ())
+            }
+            ClientTx::Batch(tx) => tx.send(res.map_err(ClientBatchError::from)).map_err(|_| ()),
+        }
+    }
+}
+
 //////////////////////////////////////////////////////////////////////////////////////////////////
 // ClientPayloadWithChan /////////////////////////////////////////////////////////////////////////
 
+/// A client request, with one or more entries, which is waiting to be processed.
 pub(crate) struct ClientPayloadWithChan<D: AppData, R: AppDataResponse, E: AppError> {
-    pub tx: oneshot::Sender<Result<ClientPayloadResponse<R>, ClientError<D, R, E>>>,
-    pub rpc: ClientPayload<D, R, E>,
+    /// The channel of the original client request.
+    pub tx: ClientTx<D, R, E>,
+    /// The entries of the original client request.
+    pub entries: Vec<EntryPayload<D>>,
+    /// The response mode of the original client request.
+    pub response_mode: ResponseMode,
+    /// The client session of the first entry of the original client request, if any.
+    pub session: Option<ClientSession>,
+    /// A flag indicating if the original request was generated by Raft itself.
+    pub is_internal: bool,
 }
 
 impl<D: AppData, R: AppDataResponse, E: AppError> ClientPayloadWithChan<D, R, E> {
-    /// Upgrade a client payload with an assigned index & term.
+    /// Create a new instance from a `ClientPayload` request.
+    pub(crate) fn new_single(tx: oneshot::Sender<Result<ClientPayloadResponse<R>, ClientError<D, R, E>>>, rpc: ClientPayload<D, R, E>) -> Self {
+        Self{tx: ClientTx::Single(tx), entries: vec![rpc.entry], response_mode: rpc.response_mode, session: rpc.session, is_internal: rpc.is_internal}
+    }
+
+    /// Create a new instance from a `ClientPayloadBatch` request.
+    pub(crate) fn new_batch(tx: ClientBatchTx<D, R, E>, rpc: ClientPayloadBatch<D, R, E>) -> Self {
+        Self{tx: ClientTx::Batch(tx), entries: rpc.entries, response_mode: rpc.response_mode, session: rpc.session, is_internal: false}
+    }
+
+    /// Upgrade a client payload with assigned indices & a term.
     ///
-    /// - `index`: the index to assign to this payload.
+    /// - `index`: the index to assign to the first entry of this payload. The remaining entries
+    ///   are assigned contiguous indices.
     /// - `term`: the term to assign to this payload.
     pub(crate) fn upgrade(self, index: u64, term: u64) -> ClientPayloadWithIndex<D, R, E> {
         ClientPayloadWithIndex::new(self, index, term)
     }
+
+    /// Respond to the client with a forwarding error carrying the original request.
+    ///
+    /// This will return an error if the receiving end of the channel has been dropped.
+    pub(crate) fn forward_to_leader(mut self, leader: Option<NodeId>) -> Result<(), ()> {
+        match self.tx {
+            ClientTx::Single(tx) => {
+                let res = match self.entries.pop() {
+                    Some(entry) => {
+                        let mut payload = ClientPayload::new_base(entry, self.response_mode);
+                        payload.session = self.session;
+                        Err(ClientError::ForwardToLeader{payload, leader})
+                    }
+                    None => Err(ClientError::Internal),
+                };
+                tx.send(res).map_err(|_| ())
+            }
+            ClientTx::Batch(tx) => {
+                let mut payload = ClientPayloadBatch::new_base(self.entries, self.response_mode);
+                payload.session = self.session;
+                tx.send(Err(ClientBatchError::ForwardToLeader{payload, leader})).map_err(|_| ())
+            }
+        }
+    }
+
+    /// Convert this payload for forwarding to the Raft leader, which must be done before the given deadline.
+    ///
+    /// Only single payloads may be forwarded, so batches are given back as an error.
+    pub(crate) fn into_forwarded(mut self, deadline: Instant) -> Result<ForwardedClientPayload<D, R, E>, Self> {
+        match self.tx {
+            ClientTx::Single(tx) => match self.entries.pop() {
+                Some(entry) => {
+                    let mut payload = ClientPayload::new_base(entry, self.response_mode);
+                    payload.session = self.session;
+                    Ok(ForwardedClientPayload{tx, payload, deadline})
+                }
+                None => {
+                    self.tx = ClientTx::Single(tx);
+                    Err(self)
+                }
+            },
+            tx => {
+                self.tx = tx;
+                Err(self)
+            }
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////
+// ForwardedClientPayload ////////////////////////////////////////////////////////////////////////
+
+/// A client request which a follower is forwarding to the Raft leader.
+pub(crate) struct ForwardedClientPayload<D: AppData, R: AppDataResponse, E: AppError> {
+    /// The channel of the original client request.
+    pub tx: oneshot::Sender<Result<ClientPayloadResponse<R>, ClientError<D, R, E>>>,
+    /// The original client request.
+    pub payload: ClientPayload<D, R, E>,
+    /// The time at which the request is returned to the client, if it is still waiting for a leader.
+    pub deadline: Instant,
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError> ForwardedClientPayload<D, R, E> {
+    /// Respond to the client with a forwarding error carrying the original request.
+    ///
+    /// This will return an error if the receiving end of the channel has been dropped.
+    pub(crate) fn forward_to_leader(self, leader: Option<NodeId>) -> Result<(), ()> {
+        self.tx.send(Err(ClientError::ForwardToLeader{payload: self.payload, leader})).map_err(|_| ())
+    }
 }
 
 //////////////////////////////////////////////////////////////////////////////////////////////////
@@ -55,12 +178,16 @@ impl<D: AppData, R: AppDataResponse, E: AppError> ClientPayloadWithChan<D, R, E>
 /// A client payload which has made its way into the processing pipeline.
 pub(crate) struct ClientPayloadWithIndex<D: AppData, R: AppDataResponse, E: AppError> {
     /// The channel of the original client request.
-    pub tx: oneshot::Sender<Result<ClientPayloadResponse<R>, ClientError<D, R, E>>>,
-    /// The entry of the original request with an assigned index & term, ready for storage.
-    entry: Arc<Entry<D>>,
+    pub tx: ClientTx<D, R, E>,
+    /// The entries of the original request with assigned indices & term, ready for storage.
+    entries: Vec<Arc<Entry<D>>>,
     /// The response mode of the original client request.
     pub response_mode: ResponseMode,
-    /// The assigned log index of this payload.
+    /// The client session of the first entry of the original client request, if any.
+    session: Option<ClientSession>,
+    /// A flag indicating if the original request was generated by Raft itself.
+    is_internal: bool,
+    /// The assigned log index of the last entry of this payload.
     pub index: u64,
     /// The term associated with this payload.
     pub term: u64,
@@ -68,24 +195,71 @@ pub(crate) struct ClientPayloadWithIndex<D: AppData, R: AppDataResponse, E: AppE
 
 impl<D: AppData, R: AppDataResponse, E: AppError> ClientPayloadWithIndex<D, R, E> {
     /// Create a new instance.
+    ///
+    /// Each entry is tagged with the payload's client session, if any, numbered consecutively
+    /// from the session's sequence number.
     pub(self) fn new(payload: ClientPayloadWithChan<D, R, E>, index: u64, term: u64) -> Self {
-        let entry = Arc::new(Entry{index: index, term: term, payload: payload.rpc.entry.clone()});
-        Self{tx: payload.tx, entry, response_mode: payload.rpc.response_mode, index, term}
+        let session = payload.session;
+        let entries: Vec<_> = payload.entries.into_iter().enumerate()
+            .map(|(offset, entry)| {
+                let entry = Entry::new(entry, index + offset as u64, term);
+                Arc::new(match session {
+                    Some(ClientSession{client_id, sequence}) => entry.with_session(ClientSession{client_id, sequence: sequence + offset as u64}),
+                    None => entry,
+                })
+            })
+            .collect();
+        let last_index = entries.last().map(|entry| entry.index).unwrap_or(index);
+        Self{tx: payload.tx, entries, response_mode: payload.response_mode, session, is_internal: payload.is_internal, index: last_index, term}
     }
 
     /// Downgrade the payload, typically for forwarding purposes.
     pub(crate) fn downgrade(self) -> ClientPayloadWithChan<D, R, E> {
-        let entry = match Arc::try_unwrap(self.entry) {
+        let entries = self.entries.into_iter().map(|entry| match Arc::try_unwrap(entry) {
             Ok(entry) => entry.payload,
             Err(arc) => arc.payload.clone(),
-        };
-        ClientPayloadWithChan{tx: self.tx, rpc: ClientPayload::new_base(entry, self.response_mode)}
+        }).collect();
+        ClientPayloadWithChan{tx: self.tx, entries, response_mode: self.response_mode, session: self.session, is_internal: self.is_internal}
     }
 
-    /// Get a reference to the entry encapsulated by this payload.
-    pub(crate) fn entry(&self) -> Arc<Entry<D>> {
-        self.entry.clone()
+    /// Get a reference to the entries encapsulated by this payload.
+    pub(crate) fn entries(&self) -> Vec<Arc<Entry<D>>> {
+        self.entries.clone()
+    }
+
+    /// Respond to the client with an error, as the leader has stepped down before this payload was known to be committed.
+    ///
+    /// This will return an error if the receiving end of the channel has been dropped.
+    pub(crate) fn respond_indeterminate(self) -> Result<(), ()> {
+        self.tx.send(Err(ClientError::Indeterminate{index: self.index, term: self.term}))
     }
+
+    /// Build the responses to send to the client once this payload has been committed.
+    pub(crate) fn committed_responses(&self) -> Vec<ClientPayloadResponse<R>> {
+        self.entries.iter().map(|entry| ClientPayloadResponse::Committed{index: entry.index}).collect()
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////
+// AppendEntriesWithChan /////////////////////////////////////////////////////////////////////////
+
+/// An AppendEntries RPC carrying entries, which is waiting for the requests before it to be appended.
+pub(crate) struct AppendEntriesWithChan<D: AppData> {
+    /// The original request.
+    pub msg: AppendEntriesRequest<D>,
+    /// The channel on which the response to the request is sent.
+    pub tx: oneshot::Sender<Result<AppendEntriesResponse, ()>>,
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////
+// ClientReadWithIndex ///////////////////////////////////////////////////////////////////////////
+
+/// A client read request which has been assigned a read index & is awaiting the state machine.
+pub(crate) struct ClientReadWithIndex {
+    /// The read index of the request.
+    pub index: u64,
+    /// The channel of the original client request.
+    pub tx: oneshot::Sender<Result<ClientReadResponse, ClientReadError>>,
 }
 
 //////////////////////////////////////////////////////////////////////////////////////////////////
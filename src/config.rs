@@ -14,10 +14,22 @@ pub const DEFAULT_ELECTION_TIMEOUT_MIN: u16 = 200;
 pub const DEFAULT_ELECTION_TIMEOUT_MAX: u16 = 300;
 /// Default heartbeat interval.
 pub const DEFAULT_HEARTBEAT_INTERVAL: u16 = 50;
+/// Default clock drift margin for lease based reads.
+pub const DEFAULT_LEASE_DRIFT_MARGIN: u16 = 100;
 /// Default threshold for when to trigger a snapshot.
 pub const DEFAULT_LOGS_SINCE_LAST: u64 = 5000;
 /// Default maximum number of entries per replication payload.
 pub const DEFAULT_MAX_PAYLOAD_ENTRIES: u64 = 300;
+/// Default maximum size of a replication payload in bytes.
+pub const DEFAULT_MAX_PAYLOAD_BYTES: u64 = 1024 * 1024 * 3;
+/// Default maximum number of entries buffered by a replication stream.
+pub const DEFAULT_MAX_BUFFERED_ENTRIES: u64 = 3000;
+/// Default maximum size of the entries buffered by a replication stream in bytes.
+pub const DEFAULT_MAX_BUFFERED_BYTES: u64 = 1024 * 1024 * 32;
+/// Default maximum number of in-flight AppendEntries requests per follower.
+pub const DEFAULT_MAX_INFLIGHT_PAYLOADS: u64 = 4;
+/// Default maximum number of client requests held by a leader in each stage of processing.
+pub const DEFAULT_CLIENT_QUEUE_CAPACITY: u64 = 5000;
 /// Default metrics rate.
 pub const DEFAULT_METRICS_RATE: Duration = Duration::from_millis(5000);
 /// Default snapshot chunksize.
@@ -44,6 +56,31 @@ impl Default for SnapshotPolicy {
     }
 }
 
+/// The algorithm used by the leader to apply membership changes proposed via `ProposeConfigChange`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MembershipChangeMode {
+    /// Changes go through a joint consensus config, per §6 of the Raft spec.
+    ///
+    /// Any number of nodes may be added & removed as part of a single change. New nodes are
+    /// brought up-to-date as non-voters before the cluster transitions to the new config.
+    JointConsensus,
+    /// Changes add or remove a single node at a time, per §4.1 of the Raft thesis.
+    ///
+    /// Each change is a single config entry which takes effect as soon as it is appended to the
+    /// log. Only one change may be uncommitted at a time. As a new node becomes a voting member
+    /// right away, it is recommended to first add it via `AddNonVoter` so that it can catch up,
+    /// and to then promote it with `ProposeConfigChange`.
+    SingleServer,
+}
+
+// Not derived, as `#[default]` on enum variants requires a newer toolchain.
+#[allow(clippy::derivable_impls)]
+impl Default for MembershipChangeMode {
+    fn default() -> Self {
+        MembershipChangeMode::JointConsensus
+    }
+}
+
 /// The runtime configuration for a Raft node.
 ///
 /// When building the Raft configuration for your application, remember this inequality from the
@@ -70,6 +107,11 @@ pub struct Config {
     /// This value is randomly generated based on default confguration or a given min & max. The
     /// default value will be between 200-300 milliseconds.
     pub election_timeout_millis: u64,
+    /// The minimum election timeout from which `election_timeout_millis` was generated.
+    ///
+    /// As any node of the cluster may roll an election timeout this short, this is the value used
+    /// to determine the duration of the leader's lease. Defaults to 200 milliseconds.
+    pub election_timeout_min: u64,
     /// The heartbeat interval at which leaders will send heartbeats to followers.
     ///
     /// Defaults to 50 milliseconds.
@@ -79,9 +121,41 @@ pub struct Config {
     /// is performed for heartbeats, so the main item of concern here is network latency. This
     /// value is also used as the default timeout for sending heartbeats.
     pub heartbeat_interval: u64,
+    /// A flag indicating if the leader may serve client reads based on a leader lease.
+    ///
+    /// Defaults to `false`.
+    ///
+    /// When enabled, the leader will track the last time a majority of the cluster acknowledged
+    /// its heartbeats. For the minimum election timeout following that point in time (minus the
+    /// configured `lease_drift_margin`), no other node could have been elected leader, so the leader may
+    /// serve `ClientReadRequest`s without an additional round of heartbeats. Followers which have
+    /// heard from the current leader within their election timeout will also refuse to grant
+    /// votes (§4.2.3), as the lease depends upon this.
+    ///
+    /// **NOTE WELL:** this trades safety for performance, as correctness depends upon bounded
+    /// clock drift between the nodes of the cluster. All nodes of the cluster should use the same
+    /// value for this setting, as well as the same election timeout min & max.
+    pub lease_reads: bool,
+    /// The margin subtracted from the minimum election timeout to account for clock drift when
+    /// determining the duration of the leader's lease (in milliseconds).
+    ///
+    /// Defaults to 100 milliseconds. Must be less than the minimum election timeout. This value
+    /// is only used when `lease_reads` is enabled.
+    pub lease_drift_margin: u64,
+    /// A flag indicating if nodes should hold a Pre-Vote round before starting an election (§9.6).
+    ///
+    /// Defaults to `true`.
+    ///
+    /// When enabled, a node whose election timeout has elapsed will first ask its peers if they
+    /// would grant it their vote, without incrementing its term. Only once a majority of the
+    /// cluster has responded positively will the node become a candidate. This prevents a node
+    /// which has been partitioned from the cluster from forcing a healthy leader to step down
+    /// when it rejoins the cluster.
+    pub pre_vote: bool,
     /// The maximum number of entries per payload allowed to be transmitted during replication.
     ///
-    /// Defaults to 300.
+    /// This also bounds the number of entries which the leader will gather from queued client
+    /// requests to be appended to its log as a group. Defaults to 300.
     ///
     /// When configuring this value, it is important to note that setting this value too low could
     /// cause sub-optimal performance. This will primarily impact the speed at which slow nodes,
@@ -89,6 +163,88 @@ pub struct Config {
     /// up-to-speed. If this is too low, it will take longer for the nodes to be brought up to
     /// consistency with the rest of the cluster.
     pub max_payload_entries: u64,
+    /// The maximum size of the entries of a payload transmitted during replication (in bytes).
+    ///
+    /// The size of each entry is reported by `AppData::encoded_len`. A payload is cut off once it
+    /// would exceed either this limit or `max_payload_entries`, whichever is reached first. A
+    /// payload always holds at least one entry, so an entry larger than this limit will still be
+    /// sent on its own. Keep this below the frame limit of your network layer, leaving room for
+    /// the rest of the AppendEntries RPC.
+    ///
+    /// Defaults to 3 MiB.
+    pub max_payload_bytes: u64,
+    /// The maximum number of entries a replication stream will buffer for its target.
+    ///
+    /// While a target is replicating at line rate, new entries are buffered by its replication
+    /// stream until they can be sent. If a target is slow, this buffer will grow. Once it holds
+    /// more than this many entries, or more than `max_buffered_bytes`, the buffer is dropped &
+    /// the target is brought up-to-date from the log in storage instead, as a lagging target.
+    ///
+    /// Defaults to 3000.
+    pub max_buffered_entries: u64,
+    /// The maximum size of the entries a replication stream will buffer for its target (in bytes).
+    ///
+    /// The size of each entry is reported by `AppData::encoded_len`. See `max_buffered_entries`
+    /// for details.
+    ///
+    /// Defaults to 32 MiB.
+    pub max_buffered_bytes: u64,
+    /// The maximum number of AppendEntries requests which may be in-flight to a follower at once.
+    ///
+    /// When a follower is replicating at line rate, the leader will send new entries to it without
+    /// waiting for the responses to its previous requests, as long as fewer than this many
+    /// requests are outstanding. This keeps throughput from being capped at one payload per
+    /// round trip on links with real latency. A value of `1` disables pipelining.
+    ///
+    /// Defaults to 4. Must be greater than 0.
+    pub max_inflight_payloads: u64,
+    /// A flag indicating if the leader should replicate new entries while appending them locally.
+    ///
+    /// When enabled, the leader will send new entries to its followers while its own append of
+    /// those entries is still in flight, so that the latency of writing to disk and the latency
+    /// of the network overlap, per §10.2.1 of the Raft thesis. The leader's own log only counts
+    /// toward commitment once its local append is durable.
+    ///
+    /// As entries are replicated before the storage engine has seen them, application errors
+    /// from `AppendEntryToLog` & `AppendEntriesToLog` can not be returned to clients when this
+    /// is enabled. Any error will be treated as fatal, and will cause the Raft node to shutdown.
+    ///
+    /// Defaults to `false`.
+    pub parallel_append: bool,
+    /// The algorithm used to apply membership changes proposed via `ProposeConfigChange`.
+    ///
+    /// Defaults to `MembershipChangeMode::JointConsensus`. All nodes of a cluster should use the
+    /// same mode.
+    pub membership_change_mode: MembershipChangeMode,
+    /// The maximum amount of time new nodes have to catch up during a joint consensus config change.
+    ///
+    /// While the cluster is in joint consensus, the leader waits for each new node to catch up
+    /// with its log before finalizing the change. If any new node has not caught up once this
+    /// deadline has elapsed, the change is aborted: the new nodes are dropped, & the previous set
+    /// of voting members is committed again.
+    ///
+    /// Defaults to `None`, in which case the leader will wait indefinitely.
+    pub catch_up_timeout: Option<Duration>,
+    /// The maximum number of client requests a leader will hold in each stage of processing.
+    ///
+    /// Client requests are queued by the leader before being appended to its log, & are then
+    /// held until they have been committed & applied to the state machine. Once this many
+    /// requests are queued, or this many are awaiting commitment & application, new client
+    /// requests are rejected with `ClientError::Overloaded` until the backlog has been worked
+    /// through. This bounds the memory used by the leader under bursts of client requests.
+    ///
+    /// Defaults to 5000. Must be greater than 0.
+    pub client_queue_capacity: u64,
+    /// The maximum amount of time a follower will wait for a leader when forwarding client payloads.
+    ///
+    /// When set, followers forward each `ClientPayload` they receive to the Raft leader via a
+    /// `ForwardClientPayload` RPC, & relay the leader's response back to the client, instead of
+    /// responding with `ClientError::ForwardToLeader`. If the leader is not known, the payload is
+    /// held until a leader is elected. Payloads still waiting once this much time has elapsed are
+    /// returned to the client with `ClientError::ForwardToLeader`. Batches are never forwarded.
+    ///
+    /// Defaults to `None`, in which case client payloads are not forwarded.
+    pub forward_client_payloads: Option<Duration>,
     /// The rate at which metrics will be pumped out from the Raft node.
     ///
     /// Defaults to 5 seconds.
@@ -113,7 +269,19 @@ impl Config {
             election_timeout_min: None,
             election_timeout_max: None,
             heartbeat_interval: None,
+            lease_reads: None,
+            lease_drift_margin: None,
+            pre_vote: None,
             max_payload_entries: None,
+            max_payload_bytes: None,
+            max_buffered_entries: None,
+            max_buffered_bytes: None,
+            max_inflight_payloads: None,
+            parallel_append: None,
+            membership_change_mode: None,
+            catch_up_timeout: None,
+            client_queue_capacity: None,
+            forward_client_payloads: None,
             metrics_rate: None,
             snapshot_dir,
             snapshot_policy: None,
@@ -134,8 +302,32 @@ pub struct ConfigBuilder {
     pub election_timeout_max: Option<u16>,
     /// The interval at which leaders will send heartbeats to followers to avoid election timeout.
     pub heartbeat_interval: Option<u16>,
+    /// A flag indicating if the leader may serve client reads based on a leader lease.
+    pub lease_reads: Option<bool>,
+    /// The margin subtracted from the election timeout to account for clock drift in lease reads.
+    pub lease_drift_margin: Option<u16>,
+    /// A flag indicating if nodes should hold a Pre-Vote round before starting an election.
+    pub pre_vote: Option<bool>,
     /// The maximum number of entries per payload allowed to be transmitted during replication.
     pub max_payload_entries: Option<u64>,
+    /// The maximum size of the entries of a payload transmitted during replication (in bytes).
+    pub max_payload_bytes: Option<u64>,
+    /// The maximum number of entries a replication stream will buffer for its target.
+    pub max_buffered_entries: Option<u64>,
+    /// The maximum size of the entries a replication stream will buffer for its target (in bytes).
+    pub max_buffered_bytes: Option<u64>,
+    /// The maximum number of AppendEntries requests which may be in-flight to a follower at once.
+    pub max_inflight_payloads: Option<u64>,
+    /// A flag indicating if the leader should replicate new entries while appending them locally.
+    pub parallel_append: Option<bool>,
+    /// The algorithm used to apply membership changes.
+    pub membership_change_mode: Option<MembershipChangeMode>,
+    /// The maximum amount of time new nodes have to catch up during a joint consensus config change.
+    pub catch_up_timeout: Option<Duration>,
+    /// The maximum number of client requests a leader will hold in each stage of processing.
+    pub client_queue_capacity: Option<u64>,
+    /// The maximum amount of time a follower will wait for a leader when forwarding client payloads.
+    pub forward_client_payloads: Option<Duration>,
     /// The rate at which metrics will be pumped out from the Raft node.
     pub metrics_rate: Option<Duration>,
     /// The directory where the log snapshots are to be kept for a Raft node.
@@ -165,12 +357,84 @@ impl ConfigBuilder {
         self
     }
 
+    /// Set the desired value for `lease_reads`.
+    pub fn lease_reads(mut self, val: bool) -> Self {
+        self.lease_reads = Some(val);
+        self
+    }
+
+    /// Set the desired value for `lease_drift_margin`.
+    pub fn lease_drift_margin(mut self, val: u16) -> Self {
+        self.lease_drift_margin = Some(val);
+        self
+    }
+
+    /// Set the desired value for `pre_vote`.
+    pub fn pre_vote(mut self, val: bool) -> Self {
+        self.pre_vote = Some(val);
+        self
+    }
+
     /// Set the desired value for `max_payload_entries`.
     pub fn max_payload_entries(mut self, val: u64) -> Self {
         self.max_payload_entries = Some(val);
         self
     }
 
+    /// Set the desired value for `max_payload_bytes`.
+    pub fn max_payload_bytes(mut self, val: u64) -> Self {
+        self.max_payload_bytes = Some(val);
+        self
+    }
+
+    /// Set the desired value for `max_buffered_entries`.
+    pub fn max_buffered_entries(mut self, val: u64) -> Self {
+        self.max_buffered_entries = Some(val);
+        self
+    }
+
+    /// Set the desired value for `max_buffered_bytes`.
+    pub fn max_buffered_bytes(mut self, val: u64) -> Self {
+        self.max_buffered_bytes = Some(val);
+        self
+    }
+
+    /// Set the desired value for `max_inflight_payloads`.
+    pub fn max_inflight_payloads(mut self, val: u64) -> Self {
+        self.max_inflight_payloads = Some(val);
+        self
+    }
+
+    /// Set the desired value for `parallel_append`.
+    pub fn parallel_append(mut self, val: bool) -> Self {
+        self.parallel_append = Some(val);
+        self
+    }
+
+    /// Set the desired value for `membership_change_mode`.
+    pub fn membership_change_mode(mut self, val: MembershipChangeMode) -> Self {
+        self.membership_change_mode = Some(val);
+        self
+    }
+
+    /// Set the desired value for `catch_up_timeout`.
+    pub fn catch_up_timeout(mut self, val: Duration) -> Self {
+        self.catch_up_timeout = Some(val);
+        self
+    }
+
+    /// Set the desired value for `client_queue_capacity`.
+    pub fn client_queue_capacity(mut self, val: u64) -> Self {
+        self.client_queue_capacity = Some(val);
+        self
+    }
+
+    /// Set the desired value for `forward_client_payloads`.
+    pub fn forward_client_payloads(mut self, val: Duration) -> Self {
+        self.forward_client_payloads = Some(val);
+        self
+    }
+
     /// Set the desired value for `metrics_rate`.
     pub fn metrics_rate(mut self, val: Duration) -> Self {
         self.metrics_rate = Some(val);
@@ -207,17 +471,52 @@ impl ConfigBuilder {
         let election_timeout: u16 = rng.gen_range(election_min, election_max);
         let election_timeout_millis = election_timeout as u64;
 
+        // Validate the lease drift margin, which must leave a non-zero lease duration.
+        let lease_reads = self.lease_reads.unwrap_or(false);
+        let lease_drift_margin = self.lease_drift_margin.unwrap_or(DEFAULT_LEASE_DRIFT_MARGIN);
+        if lease_reads && lease_drift_margin >= election_min {
+            return Err(ConfigError::InvalidLeaseDriftMargin);
+        }
+        let lease_drift_margin = lease_drift_margin as u64;
+
+        // Validate the in-flight window, which must allow at least one request.
+        let max_inflight_payloads = self.max_inflight_payloads.unwrap_or(DEFAULT_MAX_INFLIGHT_PAYLOADS);
+        if max_inflight_payloads == 0 {
+            return Err(ConfigError::InvalidMaxInflightPayloads);
+        }
+
+        // Validate the client queue capacity, which must allow at least one request.
+        let client_queue_capacity = self.client_queue_capacity.unwrap_or(DEFAULT_CLIENT_QUEUE_CAPACITY);
+        if client_queue_capacity == 0 {
+            return Err(ConfigError::InvalidClientQueueCapacity);
+        }
+
         // Get other values or their defaults.
         let heartbeat_interval = self.heartbeat_interval.unwrap_or(DEFAULT_HEARTBEAT_INTERVAL) as u64;
+        let pre_vote = self.pre_vote.unwrap_or(true);
         let max_payload_entries = self.max_payload_entries.unwrap_or(DEFAULT_MAX_PAYLOAD_ENTRIES);
+        let max_payload_bytes = self.max_payload_bytes.unwrap_or(DEFAULT_MAX_PAYLOAD_BYTES);
+        let max_buffered_entries = self.max_buffered_entries.unwrap_or(DEFAULT_MAX_BUFFERED_ENTRIES);
+        let max_buffered_bytes = self.max_buffered_bytes.unwrap_or(DEFAULT_MAX_BUFFERED_BYTES);
+        let parallel_append = self.parallel_append.unwrap_or(false);
+        let membership_change_mode = self.membership_change_mode.unwrap_or_default();
         let metrics_rate = self.metrics_rate.unwrap_or(DEFAULT_METRICS_RATE);
         let snapshot_policy = self.snapshot_policy.unwrap_or_else(|| SnapshotPolicy::default());
         let snapshot_max_chunk_size = self.snapshot_max_chunk_size.unwrap_or(DEFAULT_SNAPSHOT_CHUNKSIZE);
 
         Ok(Config{
             election_timeout_millis,
+            election_timeout_min: election_min as u64,
             heartbeat_interval,
-            max_payload_entries,
+            lease_reads, lease_drift_margin,
+            pre_vote,
+            max_payload_entries, max_payload_bytes, max_inflight_payloads,
+            max_buffered_entries, max_buffered_bytes,
+            parallel_append,
+            membership_change_mode,
+            catch_up_timeout: self.catch_up_timeout,
+            client_queue_capacity,
+            forward_client_payloads: self.forward_client_payloads,
             metrics_rate,
             snapshot_dir: self.snapshot_dir, snapshot_policy, snapshot_max_chunk_size,
         })
@@ -231,6 +530,12 @@ pub enum ConfigError {
     InvalidSnapshotDir,
     /// The given values for election timeout min & max are invalid. Max must be greater than min.
     InvalidElectionTimeoutMinMax,
+    /// The given value for the lease drift margin is invalid. It must be less than the election timeout min.
+    InvalidLeaseDriftMargin,
+    /// The given value for the maximum number of in-flight payloads is invalid. It must be greater than 0.
+    InvalidMaxInflightPayloads,
+    /// The given value for the client queue capacity is invalid. It must be greater than 0.
+    InvalidClientQueueCapacity,
 }
 
 impl std::fmt::Display for ConfigError {
@@ -238,6 +543,9 @@ impl std::fmt::Display for ConfigError {
         match self {
             ConfigError::InvalidSnapshotDir => write!(f, "The specified value for `snapshot_dir` does not exist on disk or could not be accessed."),
             ConfigError::InvalidElectionTimeoutMinMax => write!(f, "The given values for election timeout min & max are invalid. Max must be greater than min."),
+            ConfigError::InvalidLeaseDriftMargin => write!(f, "The given value for the lease drift margin is invalid. It must be less than the election timeout min."),
+            ConfigError::InvalidMaxInflightPayloads => write!(f, "The given value for the maximum number of in-flight payloads is invalid. It must be greater than 0."),
+            ConfigError::InvalidClientQueueCapacity => write!(f, "The given value for the client queue capacity is invalid. It must be greater than 0."),
         }
     }
 }
@@ -258,8 +566,21 @@ mod tests {
 
         assert!(cfg.election_timeout_millis >= DEFAULT_ELECTION_TIMEOUT_MIN as u64);
         assert!(cfg.election_timeout_millis <= DEFAULT_ELECTION_TIMEOUT_MAX as u64);
+        assert!(cfg.election_timeout_min == DEFAULT_ELECTION_TIMEOUT_MIN as u64);
         assert!(cfg.heartbeat_interval == DEFAULT_HEARTBEAT_INTERVAL as u64);
+        assert!(!cfg.lease_reads);
+        assert!(cfg.lease_drift_margin == DEFAULT_LEASE_DRIFT_MARGIN as u64);
+        assert!(cfg.pre_vote);
         assert!(cfg.max_payload_entries == DEFAULT_MAX_PAYLOAD_ENTRIES);
+        assert!(cfg.max_payload_bytes == DEFAULT_MAX_PAYLOAD_BYTES);
+        assert!(cfg.max_buffered_entries == DEFAULT_MAX_BUFFERED_ENTRIES);
+        assert!(cfg.max_buffered_bytes == DEFAULT_MAX_BUFFERED_BYTES);
+        assert!(cfg.max_inflight_payloads == DEFAULT_MAX_INFLIGHT_PAYLOADS);
+        assert!(!cfg.parallel_append);
+        assert!(cfg.membership_change_mode == MembershipChangeMode::JointConsensus);
+        assert!(cfg.catch_up_timeout.is_none());
+        assert!(cfg.client_queue_capacity == DEFAULT_CLIENT_QUEUE_CAPACITY);
+        assert!(cfg.forward_client_payloads.is_none());
         assert!(cfg.metrics_rate == DEFAULT_METRICS_RATE);
         assert!(cfg.snapshot_dir == dirstring);
         assert!(cfg.snapshot_max_chunk_size == DEFAULT_SNAPSHOT_CHUNKSIZE);
@@ -274,7 +595,19 @@ mod tests {
             .election_timeout_max(200)
             .election_timeout_min(100)
             .heartbeat_interval(10)
+            .lease_reads(true)
+            .lease_drift_margin(20)
+            .pre_vote(false)
             .max_payload_entries(100)
+            .max_payload_bytes(1024)
+            .max_buffered_entries(500)
+            .max_buffered_bytes(4096)
+            .max_inflight_payloads(8)
+            .parallel_append(true)
+            .membership_change_mode(MembershipChangeMode::SingleServer)
+            .catch_up_timeout(Duration::from_millis(10000))
+            .client_queue_capacity(100)
+            .forward_client_payloads(Duration::from_millis(3000))
             .metrics_rate(Duration::from_millis(20000))
             .snapshot_max_chunk_size(200)
             .snapshot_policy(SnapshotPolicy::Disabled)
@@ -282,9 +615,22 @@ mod tests {
 
         assert!(cfg.election_timeout_millis >= 100);
         assert!(cfg.election_timeout_millis <= 200);
+        assert!(cfg.election_timeout_min == 100);
         assert!(cfg.heartbeat_interval == 10);
+        assert!(cfg.lease_reads);
+        assert!(cfg.lease_drift_margin == 20);
+        assert!(!cfg.pre_vote);
         assert!(cfg.max_payload_entries == 100);
         assert!(cfg.max_payload_entries == 100);
+        assert!(cfg.max_payload_bytes == 1024);
+        assert!(cfg.max_buffered_entries == 500);
+        assert!(cfg.max_buffered_bytes == 4096);
+        assert!(cfg.max_inflight_payloads == 8);
+        assert!(cfg.parallel_append);
+        assert!(cfg.membership_change_mode == MembershipChangeMode::SingleServer);
+        assert!(cfg.catch_up_timeout == Some(Duration::from_millis(10000)));
+        assert!(cfg.client_queue_capacity == 100);
+        assert!(cfg.forward_client_payloads == Some(Duration::from_millis(3000)));
         assert!(cfg.metrics_rate == Duration::from_millis(20000));
         assert!(cfg.snapshot_dir == dirstring);
         assert!(cfg.snapshot_max_chunk_size == 200);
@@ -309,4 +655,36 @@ mod tests {
         let err = res.unwrap_err();
         assert_eq!(err, ConfigError::InvalidElectionTimeoutMinMax);
     }
+
+    #[test]
+    fn test_invalid_lease_drift_margin_produces_expected_error() {
+        let dir = tempdir_in("/tmp").unwrap();
+        let dirstring = dir.path().to_string_lossy().to_string();
+        let res = Config::build(dirstring.clone())
+            .election_timeout_min(100).election_timeout_max(200)
+            .lease_reads(true).lease_drift_margin(100).validate();
+        assert!(res.is_err());
+        let err = res.unwrap_err();
+        assert_eq!(err, ConfigError::InvalidLeaseDriftMargin);
+    }
+
+    #[test]
+    fn test_invalid_max_inflight_payloads_produces_expected_error() {
+        let dir = tempdir_in("/tmp").unwrap();
+        let dirstring = dir.path().to_string_lossy().to_string();
+        let res = Config::build(dirstring.clone()).max_inflight_payloads(0).validate();
+        assert!(res.is_err());
+        let err = res.unwrap_err();
+        assert_eq!(err, ConfigError::InvalidMaxInflightPayloads);
+    }
+
+    #[test]
+    fn test_invalid_client_queue_capacity_produces_expected_error() {
+        let dir = tempdir_in("/tmp").unwrap();
+        let dirstring = dir.path().to_string_lossy().to_string();
+        let res = Config::build(dirstring.clone()).client_queue_capacity(0).validate();
+        assert!(res.is_err());
+        let err = res.unwrap_err();
+        assert_eq!(err, ConfigError::InvalidClientQueueCapacity);
+    }
 }
@@ -16,7 +16,7 @@ use serde::{Serialize, de::DeserializeOwned};
 
 // Top-level exports.
 pub use crate::{
-    config::{Config, ConfigBuilder, SnapshotPolicy},
+    config::{Config, ConfigBuilder, MembershipChangeMode, SnapshotPolicy},
     raft::Raft,
     metrics::RaftMetrics,
     network::RaftNetwork,
@@ -34,7 +34,14 @@ pub type NodeId = u64;
 /// models as-is to Raft, Raft will present it to the application's `RaftStorage` impl when ready,
 /// and the application may then deal with the data directly in the storage engine without having
 /// to do a preliminary deserialization.
-pub trait AppData: Clone + Debug + Send + Sync + Serialize + DeserializeOwned + 'static {}
+pub trait AppData: Clone + Debug + Send + Sync + Serialize + DeserializeOwned + 'static {
+    /// The size of this data once encoded for transmission by the application's network layer (in bytes).
+    ///
+    /// This is used to keep replication payloads within the configured `max_payload_bytes`, & to
+    /// bound the entries buffered by replication streams per `max_buffered_bytes`. An estimate is
+    /// fine, as long as it does not undercount the encoded size.
+    fn encoded_len(&self) -> u64;
+}
 
 /// A trait defining application specific response data.
 ///
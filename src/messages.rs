@@ -77,9 +77,14 @@ pub struct AppendEntriesResponse {
 /// which may be new to the cluster.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ConflictOpt {
-    /// The term of the most recent entry which does not conflict with the received request.
+    /// The term of the entry at the request's `prev_log_index`, which conflicts with the request.
+    ///
+    /// This will be `0` if the log has no entry at the request's `prev_log_index`.
     pub term: u64,
-    /// The index of the most recent entry which does not conflict with the received request.
+    /// The first index of the log in the conflicting `term`.
+    ///
+    /// If the log has no entry at the request's `prev_log_index`, this will instead be the index
+    /// just past the end of the log. The leader will resume replication from this index.
     pub index: u64,
 }
 
@@ -93,12 +98,44 @@ pub struct Entry<D: AppData> {
     /// This entry's payload.
     #[serde(bound="D: AppData")]
     pub payload: EntryPayload<D>,
+    /// The client session of the request which proposed this entry, if any.
+    #[serde(default)]
+    pub(crate) session: Option<ClientSession>,
 }
 
 impl<D: AppData> Entry<D> {
+    /// Create a new entry from the given data, without a client session.
+    pub fn new(payload: EntryPayload<D>, index: u64, term: u64) -> Self {
+        Entry{term, index, payload, session: None}
+    }
+
     /// Create a new snapshot pointer from the given data.
     pub fn new_snapshot_pointer(pointer: EntrySnapshotPointer, index: u64, term: u64) -> Self {
-        Entry{term, index, payload: EntryPayload::SnapshotPointer(pointer)}
+        Self::new(EntryPayload::SnapshotPointer(pointer), index, term)
+    }
+
+    /// Tag this entry with the given client session.
+    pub fn with_session(mut self, session: ClientSession) -> Self {
+        self.session = Some(session);
+        self
+    }
+
+    /// The client session of the request which proposed this entry, if any.
+    ///
+    /// Raft uses this to detect retried client requests, so that each request is appended to the
+    /// log only once. See `storage::ClientSessions`.
+    pub fn session(&self) -> Option<&ClientSession> {
+        self.session.as_ref()
+    }
+
+    /// The encoded size of this entry's application data, as reported by `AppData::encoded_len`.
+    ///
+    /// Entries without application data are small & are not counted.
+    pub(crate) fn encoded_len(&self) -> u64 {
+        match &self.payload {
+            EntryPayload::Normal(inner) => inner.data.encoded_len(),
+            _ => 0,
+        }
     }
 }
 
@@ -114,6 +151,11 @@ pub enum EntryPayload<D: AppData> {
     ConfigChange(EntryConfigChange),
     /// An entry which points to a snapshot.
     SnapshotPointer(EntrySnapshotPointer),
+    /// A normal log entry which has had its data stripped.
+    ///
+    /// Witnesses never store application data, so normal entries are replicated to them in this
+    /// form, retaining only their index & term.
+    Stripped,
 }
 
 /// A normal log entry.
@@ -159,24 +201,58 @@ pub struct MembershipConfig {
     pub non_voters: Vec<NodeId>,
     /// The set of nodes which are to be removed after joint consensus is complete.
     pub removing: Vec<NodeId>,
+    /// Permanent non-voting members of the cluster, added via the `AddNonVoter` command.
+    ///
+    /// These nodes replicate the log, but they never vote, never campaign, do not count toward
+    /// commitment, and are never transitioned over to being standard members.
+    #[serde(default)]
+    pub learners: Vec<NodeId>,
+    /// Members of the cluster which are witnesses.
+    ///
+    /// Witnesses are a subset of `members` & `non_voters`. They vote and count toward commitment
+    /// like any other member, but they only store entry metadata, never application data. As
+    /// such, a witness will never campaign to become the cluster leader.
+    #[serde(default)]
+    pub witnesses: Vec<NodeId>,
 }
 
 impl MembershipConfig {
     /// Check if the given NodeId exists in this membership config.
     ///
-    /// This checks only the contents of `members` & `non_voters`.
+    /// This checks only the contents of `members`, `non_voters` & `learners`.
     pub fn contains(&self, x: &NodeId) -> bool {
-        self.members.contains(x) || self.non_voters.contains(x)
+        self.members.contains(x) || self.non_voters.contains(x) || self.learners.contains(x)
     }
 
     /// Get an iterator over all nodes in the current config.
     pub fn all_nodes(&self) -> impl Iterator<Item=&NodeId> {
-        self.members.iter().chain(self.non_voters.iter())
+        self.members.iter().chain(self.non_voters.iter()).chain(self.learners.iter())
     }
 
-    /// Get the length of the members & non_voters vectors.
+    /// Get the length of the members, non_voters & learners vectors.
     pub fn len(&self) -> usize {
-        self.members.len() + self.non_voters.len()
+        self.members.len() + self.non_voters.len() + self.learners.len()
+    }
+
+    /// Get the sets of voting members which must each reach a majority for a decision to hold.
+    ///
+    /// Outside of joint consensus, this is only the set of voting members. In joint consensus,
+    /// this is both the old & the new set of voting members, as agreement requires separate
+    /// majorities from each (§6).
+    pub(crate) fn voting_sets(&self) -> Vec<Vec<NodeId>> {
+        if !self.is_in_joint_consensus {
+            return vec![self.members.clone()];
+        }
+        let new_members = self.members.iter()
+            .filter(|id| !self.removing.contains(id))
+            .chain(self.non_voters.iter())
+            .cloned().collect();
+        vec![self.members.clone(), new_members]
+    }
+
+    /// Check if the nodes for which `is_acked` holds form a majority of every voting set.
+    pub(crate) fn is_majority(&self, is_acked: impl Fn(&NodeId) -> bool) -> bool {
+        self.voting_sets().iter().all(|set| set.iter().filter(|id| is_acked(id)).count() > set.len() / 2)
     }
 }
 
@@ -207,6 +283,12 @@ pub struct VoteRequest {
     pub last_log_index: u64,
     /// The term of the candidate’s last log entry (§5.4).
     pub last_log_term: u64,
+    /// A flag indicating that the candidate is campaigning as part of a leadership transfer.
+    ///
+    /// Receivers will normally reject votes while they have heard from a current leader within
+    /// their election timeout (§4.2.3). When this flag is set, the current leader has asked the
+    /// candidate to take over, so that rule does not apply.
+    pub disrupt_leader: bool,
 }
 
 impl Message for VoteRequest {
@@ -219,8 +301,8 @@ impl Message for VoteRequest {
 
 impl VoteRequest {
     /// Create a new instance.
-    pub fn new(target: u64, term: u64, candidate_id: u64, last_log_index: u64, last_log_term: u64) -> Self {
-        Self{target, term, candidate_id, last_log_index, last_log_term}
+    pub fn new(target: u64, term: u64, candidate_id: u64, last_log_index: u64, last_log_term: u64, disrupt_leader: bool) -> Self {
+        Self{target, term, candidate_id, last_log_index, last_log_term, disrupt_leader}
     }
 }
 
@@ -238,6 +320,123 @@ pub struct VoteResponse {
     pub is_candidate_unknown: bool,
 }
 
+//////////////////////////////////////////////////////////////////////////////////////////////////
+// PreVoteRequest ////////////////////////////////////////////////////////////////////////////////
+
+/// An RPC invoked by prospective candidates to check if they could win an election (§9.6).
+///
+/// Before incrementing its term and becoming a candidate, a node will first ask its peers if they
+/// would grant it their vote. A peer will grant its pre-vote only if the candidate's log is at
+/// least as up-to-date as its own, and if it has not heard from a current leader within its
+/// election timeout. Handling this RPC never changes the receiver's term or vote. This prevents
+/// a node which has been partitioned from the cluster from disrupting a healthy leader when it
+/// rejoins the cluster.
+///
+/// ### actix::Message
+/// Applications using this Raft implementation are responsible for implementing the
+/// networking/transport layer which must move RPCs between nodes. Once the application instance
+/// recieves a Raft RPC, it must send the RPC to the Raft node via its `actix::Addr` and then
+/// return the response to the original sender.
+///
+/// The result type of calling the Raft actor with this message type is
+/// `Result<PreVoteResponse, ()>`. The Raft spec assigns no significance to failures during the
+/// handling or sending of RPCs and all RPCs are handled in an idempotent fashion, so Raft will
+/// almost always retry sending a failed RPC, depending on the state of the Raft.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PreVoteRequest {
+    /// A non-standard field, this is the ID of the intended recipient of this RPC.
+    pub target: u64,
+    /// The term which the prospective candidate would campaign with; its current term + 1.
+    pub term: u64,
+    /// The prospective candidate's ID.
+    pub candidate_id: u64,
+    /// The index of the prospective candidate’s last log entry (§5.4).
+    pub last_log_index: u64,
+    /// The term of the prospective candidate’s last log entry (§5.4).
+    pub last_log_term: u64,
+}
+
+impl Message for PreVoteRequest {
+    /// The result type of this message.
+    ///
+    /// The `Result::Err` type is `()` as Raft assigns no significance to RPC failures, they will
+    /// be retried almost always as long as permitted by the current state of the Raft.
+    type Result = Result<PreVoteResponse, ()>;
+}
+
+impl PreVoteRequest {
+    /// Create a new instance.
+    pub fn new(target: u64, term: u64, candidate_id: u64, last_log_index: u64, last_log_term: u64) -> Self {
+        Self{target, term, candidate_id, last_log_index, last_log_term}
+    }
+}
+
+/// An RPC response to a `PreVoteRequest` message.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PreVoteResponse {
+    /// The current term of the responding node, for the prospective candidate to update itself.
+    pub term: u64,
+    /// Will be true if the responder would grant its vote to the prospective candidate.
+    pub vote_granted: bool,
+    /// Will be true if the prospective candidate is unknown to the responding node's config.
+    ///
+    /// This is handled identically to `VoteResponse.is_candidate_unknown`.
+    pub is_candidate_unknown: bool,
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////
+// TimeoutNowRequest /////////////////////////////////////////////////////////////////////////////
+
+/// An RPC invoked by the leader to have the target start an election immediately (§3.10).
+///
+/// This RPC is used as the final step of a leadership transfer. The leader only sends it once the
+/// target's log is fully up-to-date with the leader's log. Upon receipt, the target will skip the
+/// Pre-Vote round and start a new election as if its election timeout had elapsed.
+///
+/// ### actix::Message
+/// Applications using this Raft implementation are responsible for implementing the
+/// networking/transport layer which must move RPCs between nodes. Once the application instance
+/// recieves a Raft RPC, it must send the RPC to the Raft node via its `actix::Addr` and then
+/// return the response to the original sender.
+///
+/// The result type of calling the Raft actor with this message type is
+/// `Result<TimeoutNowResponse, ()>`. The Raft spec assigns no significance to failures during the
+/// handling or sending of RPCs and all RPCs are handled in an idempotent fashion, so Raft will
+/// almost always retry sending a failed RPC, depending on the state of the Raft.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TimeoutNowRequest {
+    /// A non-standard field, this is the ID of the intended recipient of this RPC.
+    pub target: u64,
+    /// The leader's current term.
+    pub term: u64,
+    /// The leader's ID.
+    pub leader_id: u64,
+}
+
+impl Message for TimeoutNowRequest {
+    /// The result type of this message.
+    ///
+    /// The `Result::Err` type is `()` as Raft assigns no significance to RPC failures, they will
+    /// be retried almost always as long as permitted by the current state of the Raft.
+    type Result = Result<TimeoutNowResponse, ()>;
+}
+
+impl TimeoutNowRequest {
+    /// Create a new instance.
+    pub fn new(target: u64, term: u64, leader_id: u64) -> Self {
+        Self{target, term, leader_id}
+    }
+}
+
+/// An RPC response to a `TimeoutNowRequest` message.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TimeoutNowResponse {
+    /// The current term of the responding node, for the leader to update itself.
+    ///
+    /// If the responding node has started an election, this will be the term of that election.
+    pub term: u64,
+}
+
 //////////////////////////////////////////////////////////////////////////////////////////////////
 // InstallSnapshotRequest ////////////////////////////////////////////////////////////////////////
 
@@ -314,6 +513,14 @@ pub struct ClientPayload<D: AppData, R: AppDataResponse, E: AppError> {
     pub(crate) entry: EntryPayload<D>,
     /// The response mode needed by this request.
     pub(crate) response_mode: ResponseMode,
+    /// The client session of this request, if any.
+    #[serde(default)]
+    pub(crate) session: Option<ClientSession>,
+    /// A flag indicating if this payload was generated by Raft itself, rather than by a client.
+    ///
+    /// Such payloads are never rejected because of the `client_queue_capacity` limit.
+    #[serde(skip)]
+    pub(crate) is_internal: bool,
     #[serde(skip)]
     marker0: std::marker::PhantomData<R>,
     #[serde(skip)]
@@ -328,19 +535,40 @@ impl<D: AppData, R: AppDataResponse, E: AppError> ClientPayload<D, R, E> {
 
     /// Create a new instance.
     pub(crate) fn new_base(entry: EntryPayload<D>, response_mode: ResponseMode) -> Self {
-        Self{entry, response_mode, marker0: std::marker::PhantomData, marker1: std::marker::PhantomData}
+        Self{entry, response_mode, session: None, is_internal: false, marker0: std::marker::PhantomData, marker1: std::marker::PhantomData}
+    }
+
+    /// Tag this payload with the session of the client which is proposing it.
+    ///
+    /// The session is recorded on the log entry of this payload, so that the request is appended
+    /// to the log only once, no matter how many times the client retries it. A client must use a
+    /// new, higher `sequence` number for each new request, and must reuse the same `sequence`
+    /// number when retrying a request.
+    ///
+    /// A retried request is answered with the outcome of its original entry, once that entry has
+    /// been applied. With `ResponseMode::Applied`, the original response is returned if the leader
+    /// still has it cached, else a `ClientPayloadResponse::Committed` with the original index is
+    /// returned. A request older than the client's latest request is rejected with
+    /// `ClientError::AlreadyAcknowledged`. See `storage::ClientSessions`.
+    pub fn with_session(mut self, client_id: u64, sequence: u64) -> Self {
+        self.session = Some(ClientSession{client_id, sequence});
+        self
     }
 
     /// Generate a new payload holding a config change.
     pub(crate) fn new_config(membership: MembershipConfig) -> Self {
-        Self::new_base(EntryPayload::ConfigChange(EntryConfigChange{membership}), ResponseMode::Committed)
+        let mut payload = Self::new_base(EntryPayload::ConfigChange(EntryConfigChange{membership}), ResponseMode::Committed);
+        payload.is_internal = true;
+        payload
     }
 
     /// Generate a new blank payload.
     ///
     /// This is used by new leaders when first coming to power.
     pub(crate) fn new_blank_payload() -> Self {
-        Self::new_base(EntryPayload::Blank, ResponseMode::Committed)
+        let mut payload = Self::new_base(EntryPayload::Blank, ResponseMode::Committed);
+        payload.is_internal = true;
+        payload
     }
 }
 
@@ -349,6 +577,23 @@ impl<D: AppData, R: AppDataResponse, E: AppError> Message for ClientPayload<D, R
     type Result = Result<ClientPayloadResponse<R>, ClientError<D, R, E>>;
 }
 
+/// The session of a client request, used to apply each client request exactly once.
+///
+/// This follows the design of client sessions described in §6.3 of the Raft dissertation. Each
+/// client is assigned a unique `client_id`, and numbers its requests with an increasing
+/// `sequence`. As each node applies the log, Raft records the latest `sequence` applied for each
+/// client. When a request is retried, the leader recognizes it as a duplicate before it is
+/// appended to the log, & answers it with the outcome of the original request instead.
+///
+/// The session table is owned by Raft & is included in snapshots, see `storage::ClientSessions`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientSession {
+    /// The unique ID of the client.
+    pub client_id: u64,
+    /// The sequence number of the request within the client's session.
+    pub sequence: u64,
+}
+
 /// The desired response mode for a client request.
 ///
 /// This value specifies when a client request desires to receive its response from Raft. When
@@ -417,6 +662,7 @@ pub enum ClientError<D: AppData, R: AppDataResponse, E: AppError> {
     ///
     /// The process of electing a new leader is usually a very fast process in Raft, so buffering
     /// the client payload until the new leader is known should not cause a lot of overhead.
+    /// Followers configured with `forward_client_payloads` will do this on the client's behalf.
     #[serde(bound="D: AppData, R: AppDataResponse, E: AppError")]
     ForwardToLeader {
         /// The original payload which this error is associated with.
@@ -424,6 +670,32 @@ pub enum ClientError<D: AppData, R: AppDataResponse, E: AppError> {
         /// The ID of the current Raft leader, if known.
         leader: Option<NodeId>,
     },
+    /// The Raft leader is holding as many client requests as its `client_queue_capacity` allows.
+    ///
+    /// The payload was not appended to the log. It is safe to retry the payload once the leader
+    /// has worked through its backlog, typically after a short backoff.
+    Overloaded,
+    /// The payload was appended to the log, but the leader stepped down before it was known to
+    /// be committed.
+    ///
+    /// The payload's entry may still be committed by the next leader, or it may be overwritten.
+    /// Retrying the payload could see it applied twice, unless it carries a client session. Use
+    /// a `CommitStateRequest` with the given `index` & `term` to find out what became of it.
+    Indeterminate {
+        /// The log index of the payload's entry.
+        index: u64,
+        /// The term of the payload's entry.
+        term: u64,
+    },
+    /// The payload's client session is older than the latest request of the client.
+    ///
+    /// Clients have only one request outstanding at a time, so the client has already received
+    /// the response to this request. The payload was not appended to the log. The given
+    /// `sequence` is the sequence number of the client's latest request.
+    AlreadyAcknowledged {
+        /// The sequence number of the latest request of the client.
+        sequence: u64,
+    },
 }
 
 impl<D: AppData, R: AppDataResponse, E: AppError> std::fmt::Display for ClientError<D, R, E> {
@@ -432,9 +704,320 @@ impl<D: AppData, R: AppDataResponse, E: AppError> std::fmt::Display for ClientEr
             ClientError::Internal => write!(f, "An internal error was encountered in Raft."),
             ClientError::Application(err) => write!(f, "{}", &err),
             ClientError::ForwardToLeader{..} => write!(f, "The client payload must be forwarded to the Raft leader for processing."),
+            ClientError::Overloaded => write!(f, "The Raft leader is overloaded & is not accepting new client payloads."),
+            ClientError::Indeterminate{..} => write!(f, "The Raft leader stepped down before the client payload was known to be committed."),
+            ClientError::AlreadyAcknowledged{..} => write!(f, "The client payload is older than the latest request of its client session."),
         }
     }
 }
 
 impl<D: AppData, R: AppDataResponse, E: AppError> std::error::Error for ClientError<D, R, E> {}
 
+//////////////////////////////////////////////////////////////////////////////////////////////////
+// ForwardClientPayload //////////////////////////////////////////////////////////////////////////
+
+/// An RPC invoked by a follower to forward a client payload to the Raft leader.
+///
+/// This RPC is only used when the follower is configured with `forward_client_payloads`. The
+/// follower waits for the leader's response to the payload & relays it back to the client, so
+/// that applications do not need to build their own forwarding layer. Batches of client payloads
+/// are never forwarded.
+///
+/// ### actix::Message
+/// Applications using this Raft implementation are responsible for implementing the
+/// networking/transport layer which must move RPCs between nodes. Once the application instance
+/// recieves a Raft RPC, it must send the RPC to the Raft node via its `actix::Addr` and then
+/// return the response to the original sender.
+///
+/// The result type of calling the Raft actor with this message type is
+/// `Result<ClientPayloadResponse, ClientError>`, which the follower returns to its client as is.
+/// If the RPC can not be delivered to the leader, the network should respond with
+/// `ClientError::ForwardToLeader`, so that the payload is handed back to the client. A leader
+/// which receives this RPC after stepping down will do the same.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ForwardClientPayload<D: AppData, R: AppDataResponse, E: AppError> {
+    /// A non-standard field, this is the ID of the intended recipient of this RPC.
+    pub target: NodeId,
+    /// The ID of the follower which is forwarding the payload.
+    pub follower_id: NodeId,
+    /// The client payload being forwarded.
+    #[serde(bound="D: AppData, R: AppDataResponse, E: AppError")]
+    pub payload: ClientPayload<D, R, E>,
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError> Message for ForwardClientPayload<D, R, E> {
+    /// The result type of this message.
+    type Result = Result<ClientPayloadResponse<R>, ClientError<D, R, E>>;
+}
+
+
+//////////////////////////////////////////////////////////////////////////////////////////////////
+// ClientPayloadBatch ////////////////////////////////////////////////////////////////////////////
+
+/// A payload with a batch of entries coming from a client request.
+///
+/// The entries of this payload will be assigned contiguous indices, appended to the Raft log in a
+/// single storage call and replicated to the cluster as one unit. Applications which produce many
+/// small writes should prefer this over submitting each entry as its own `ClientPayload`.
+///
+/// ### actix::Message
+/// Applications using this Raft implementation are responsible for implementing the
+/// networking/transport layer which must move RPCs between nodes. Once the application instance
+/// recieves a Raft RPC, it must send the RPC to the Raft node via its `actix::Addr` and then
+/// return the response to the original sender.
+///
+/// The result type of calling the Raft actor with this message type is
+/// `Result<Vec<ClientPayloadResponse>, ClientBatchError>`. On success, there will be one response
+/// per entry, in the same order as the entries of the batch. As the batch is appended to the log
+/// as one unit, an error applies to the batch as a whole.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClientPayloadBatch<D: AppData, R: AppDataResponse, E: AppError> {
+    /// The application specific contents of this client request.
+    #[serde(bound="D: AppData")]
+    pub(crate) entries: Vec<EntryPayload<D>>,
+    /// The response mode needed by this request.
+    pub(crate) response_mode: ResponseMode,
+    /// The client session of the first entry of this request, if any.
+    #[serde(default)]
+    pub(crate) session: Option<ClientSession>,
+    #[serde(skip)]
+    marker0: std::marker::PhantomData<R>,
+    #[serde(skip)]
+    marker1: std::marker::PhantomData<E>,
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError> ClientPayloadBatch<D, R, E> {
+    /// Create a new client payload batch instance with normal entry types.
+    pub fn new(entries: Vec<EntryNormal<D>>, response_mode: ResponseMode) -> Self {
+        Self::new_base(entries.into_iter().map(EntryPayload::Normal).collect(), response_mode)
+    }
+
+    /// Create a new instance.
+    pub(crate) fn new_base(entries: Vec<EntryPayload<D>>, response_mode: ResponseMode) -> Self {
+        Self{entries, response_mode, session: None, marker0: std::marker::PhantomData, marker1: std::marker::PhantomData}
+    }
+
+    /// Tag this batch with the session of the client which is proposing it.
+    ///
+    /// Each entry of the batch is treated as its own request of the session, numbered
+    /// consecutively from `first_sequence`. The same numbering must be used when retrying the
+    /// batch. See `ClientPayload::with_session`.
+    pub fn with_session(mut self, client_id: u64, first_sequence: u64) -> Self {
+        self.session = Some(ClientSession{client_id, sequence: first_sequence});
+        self
+    }
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError> From<ClientPayload<D, R, E>> for ClientPayloadBatch<D, R, E> {
+    fn from(payload: ClientPayload<D, R, E>) -> Self {
+        let mut batch = Self::new_base(vec![payload.entry], payload.response_mode);
+        batch.session = payload.session;
+        batch
+    }
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError> Message for ClientPayloadBatch<D, R, E> {
+    /// The result type of this message.
+    type Result = Result<Vec<ClientPayloadResponse<R>>, ClientBatchError<D, R, E>>;
+}
+
+/// Error variants which may arise while handling client batch requests.
+///
+/// These are the same as the variants of `ClientError`, except that the forwarding variant
+/// carries the original batch.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag="type")]
+pub enum ClientBatchError<D: AppData, R: AppDataResponse, E: AppError> {
+    /// Some error which has taken place internally in Raft.
+    Internal,
+    /// An application specific error.
+    #[serde(bound="E: AppError")]
+    Application(E),
+    /// The Raft node returning this error is not the Raft leader.
+    ///
+    /// See `ClientError::ForwardToLeader` for details.
+    #[serde(bound="D: AppData, R: AppDataResponse, E: AppError")]
+    ForwardToLeader {
+        /// The original batch which this error is associated with.
+        payload: ClientPayloadBatch<D, R, E>,
+        /// The ID of the current Raft leader, if known.
+        leader: Option<NodeId>,
+    },
+    /// The Raft leader is holding as many client requests as its `client_queue_capacity` allows.
+    ///
+    /// See `ClientError::Overloaded` for details.
+    Overloaded,
+    /// The batch was appended to the log, but the leader stepped down before it was known to be
+    /// committed.
+    ///
+    /// The entries of the batch have contiguous indices, ending with the given `index`, all in
+    /// the given `term`. See `ClientError::Indeterminate` for details.
+    Indeterminate {
+        /// The log index of the last entry of the batch.
+        index: u64,
+        /// The term of the entries of the batch.
+        term: u64,
+    },
+    /// The batch's client session is older than the latest request of the client.
+    ///
+    /// See `ClientError::AlreadyAcknowledged` for details.
+    AlreadyAcknowledged {
+        /// The sequence number of the latest request of the client.
+        sequence: u64,
+    },
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError> From<ClientError<D, R, E>> for ClientBatchError<D, R, E> {
+    fn from(err: ClientError<D, R, E>) -> Self {
+        match err {
+            ClientError::Internal => ClientBatchError::Internal,
+            ClientError::Application(err) => ClientBatchError::Application(err),
+            ClientError::ForwardToLeader{payload, leader} => ClientBatchError::ForwardToLeader{payload: payload.into(), leader},
+            ClientError::Overloaded => ClientBatchError::Overloaded,
+            ClientError::Indeterminate{index, term} => ClientBatchError::Indeterminate{index, term},
+            ClientError::AlreadyAcknowledged{sequence} => ClientBatchError::AlreadyAcknowledged{sequence},
+        }
+    }
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError> std::fmt::Display for ClientBatchError<D, R, E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientBatchError::Internal => write!(f, "An internal error was encountered in Raft."),
+            ClientBatchError::Application(err) => write!(f, "{}", &err),
+            ClientBatchError::ForwardToLeader{..} => write!(f, "The client payload batch must be forwarded to the Raft leader for processing."),
+            ClientBatchError::Overloaded => write!(f, "The Raft leader is overloaded & is not accepting new client payloads."),
+            ClientBatchError::Indeterminate{..} => write!(f, "The Raft leader stepped down before the client payload batch was known to be committed."),
+            ClientBatchError::AlreadyAcknowledged{..} => write!(f, "The client payload batch is older than the latest request of its client session."),
+        }
+    }
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError> std::error::Error for ClientBatchError<D, R, E> {}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////
+// ClientReadRequest /////////////////////////////////////////////////////////////////////////////
+
+/// A request from a client to perform a linearizable read (§8).
+///
+/// This message does not carry any application data, and nothing is appended to the Raft log.
+/// Instead, the Raft leader will record its current commit index as the request's _read index_,
+/// confirm that it is still the cluster leader by exchanging a round of heartbeats with a
+/// majority of the cluster, and will then wait for its state machine to catch up to the read
+/// index. Once the response has been received, the application may read directly from its state
+/// machine and the data observed is guaranteed to be linearizable.
+///
+/// ### actix::Message
+/// Applications using this Raft implementation are responsible for implementing the
+/// networking/transport layer which must move RPCs between nodes. Once the application instance
+/// recieves a Raft RPC, it must send the RPC to the Raft node via its `actix::Addr` and then
+/// return the response to the original sender.
+///
+/// The result type of calling the Raft actor with this message type is
+/// `Result<ClientReadResponse, ClientReadError>`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ClientReadRequest;
+
+impl ClientReadRequest {
+    /// Create a new instance.
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Message for ClientReadRequest {
+    /// The result type of this message.
+    type Result = Result<ClientReadResponse, ClientReadError>;
+}
+
+/// A response to a `ClientReadRequest`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClientReadResponse {
+    /// The read index of the request.
+    ///
+    /// The leader's state machine has applied at least through this index, so any read
+    /// performed against the state machine after receiving this response is linearizable.
+    pub index: u64,
+}
+
+/// Error variants which may arise while handling client read requests.
+///
+/// These are also used for `CommitStateRequest`s, which read the state of the Raft log.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag="type")]
+pub enum ClientReadError {
+    /// Some error which has taken place internally in Raft.
+    Internal,
+    /// The Raft node returning this error is not the Raft leader, or it was not able to confirm
+    /// that it is still the Raft leader.
+    ///
+    /// The request should be sent to the specified leader. If the leader is unknown, it is up to
+    /// the application to determine how to handle.
+    ForwardToLeader {
+        /// The ID of the current Raft leader, if known.
+        leader: Option<NodeId>,
+    },
+}
+
+impl std::fmt::Display for ClientReadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientReadError::Internal => write!(f, "An internal error was encountered in Raft."),
+            ClientReadError::ForwardToLeader{..} => write!(f, "The client read request must be forwarded to the Raft leader for processing."),
+        }
+    }
+}
+
+impl std::error::Error for ClientReadError {}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////
+// CommitStateRequest ////////////////////////////////////////////////////////////////////////////
+
+/// A request from a client to check whether the log entry with the given index & term was committed.
+///
+/// This is typically used to resolve a `ClientError::Indeterminate` error. The request must be
+/// sent to the Raft leader, as only the leader is certain of which entries of its log are
+/// committed. Once the leader's commit index has reached `index`, the entry at `index` will never
+/// change, so the answer is final. Until then, the answer is `CommitState::Pending`, and the
+/// request should be retried later.
+///
+/// ### actix::Message
+/// Applications using this Raft implementation are responsible for implementing the
+/// networking/transport layer which must move RPCs between nodes. Once the application instance
+/// recieves a Raft RPC, it must send the RPC to the Raft node via its `actix::Addr` and then
+/// return the response to the original sender.
+///
+/// The result type of calling the Raft actor with this message type is
+/// `Result<CommitState, ClientReadError>`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommitStateRequest {
+    /// The log index of the entry to check.
+    pub index: u64,
+    /// The term of the entry to check.
+    pub term: u64,
+}
+
+impl CommitStateRequest {
+    /// Create a new instance.
+    pub fn new(index: u64, term: u64) -> Self {
+        Self{index, term}
+    }
+}
+
+impl Message for CommitStateRequest {
+    /// The result type of this message.
+    type Result = Result<CommitState, ClientReadError>;
+}
+
+/// The commit state of a log entry, as reported in response to a `CommitStateRequest`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CommitState {
+    /// The entry has been committed.
+    Committed,
+    /// A different entry has been committed at the entry's index, so the entry will never be committed.
+    Aborted,
+    /// The leader has not yet committed any entry at the entry's index.
+    Pending,
+    /// The entry's index has been compacted into a snapshot, so its term is no longer known.
+    Compacted,
+}
@@ -6,7 +6,9 @@
 //!
 //! The `RaftMetrics` type holds the baseline metrics on the state of the Raft node the metrics
 //! are coming from, its current role in the cluster, its current membership config, as well as
-//! information on the Raft log and the last index to be applied to the state machine.
+//! information on the Raft log and the last index to be applied to the state machine. While a
+//! config change is syncing new nodes, the leader also reports the catch-up progress of each
+//! new node.
 //!
 //! Applications may use this data in whatever way is needed. The obvious use cases are to expose
 //! these metrics to a metrics collection system like Prometheus or Influx. Applications may also
@@ -17,6 +19,8 @@
 //! value, but will also emit a new metrics record any time the `state` of the Raft node changes,
 //! the `membership_config` changes, or the `current_leader` changes.
 
+use std::collections::BTreeMap;
+
 use actix::prelude::*;
 
 use crate::{
@@ -57,4 +61,11 @@ pub struct RaftMetrics {
     pub current_leader: Option<NodeId>,
     /// The current membership config of the cluster.
     pub membership_config: MembershipConfig,
+    /// The match index of each new node the leader is syncing as part of a config change.
+    ///
+    /// This is only populated on the leader, while the cluster is in joint consensus & new nodes
+    /// have not yet caught up. A new node has caught up once its match index has reached the
+    /// leader's `last_log_index`. If `Config.catch_up_timeout` is set, the change will be rolled
+    /// back should a new node not catch up in time.
+    pub catch_up_progress: BTreeMap<NodeId, u64>,
 }
@@ -6,10 +6,13 @@ use actix::{
 };
 
 use crate::{
-    AppData,
+    AppData, AppDataResponse, AppError,
     messages::{
         AppendEntriesRequest,
+        ForwardClientPayload,
         InstallSnapshotRequest,
+        PreVoteRequest,
+        TimeoutNowRequest,
         VoteRequest,
     },
 };
@@ -18,9 +21,11 @@ use crate::{
 ///
 /// See the [network chapter of the guide](https://railgun-rs.github.io/actix-raft/network.html)
 /// for details and discussion on this trait and how to implement it.
-pub trait RaftNetwork<D>
+pub trait RaftNetwork<D, R, E>
     where
         D: AppData,
+        R: AppDataResponse,
+        E: AppError,
         Self: Actor<Context=Context<Self>>,
 
         Self: Handler<AppendEntriesRequest<D>>,
@@ -31,4 +36,13 @@ pub trait RaftNetwork<D>
 
         Self: Handler<VoteRequest>,
         Self::Context: ToEnvelope<Self, VoteRequest>,
+
+        Self: Handler<PreVoteRequest>,
+        Self::Context: ToEnvelope<Self, PreVoteRequest>,
+
+        Self: Handler<TimeoutNowRequest>,
+        Self::Context: ToEnvelope<Self, TimeoutNowRequest>,
+
+        Self: Handler<ForwardClientPayload<D, R, E>>,
+        Self::Context: ToEnvelope<Self, ForwardClientPayload<D, R, E>>,
 {}
@@ -1,19 +1,28 @@
+use std::{collections::BTreeSet, time::{Duration, Instant}};
+
 use actix::prelude::*;
+use futures::sync::oneshot;
 use log::{error, info, warn};
+use tokio_timer::Timeout;
 
 use crate::{
     AppData, AppDataResponse, AppError,
-    admin::{InitWithConfig, InitWithConfigError, ProposeConfigChange, ProposeConfigChangeError},
-    common::UpdateCurrentLeader,
+    NodeId,
+    admin::{
+        AbortConfigChange, AbortConfigChangeError, AddNonVoter, AddNonVoterError, ChangeMembership, ChangeMembershipError, InitWithConfig, InitWithConfigError, ProposeConfigChange, ProposeConfigChangeError,
+        RemoveNonVoter, RemoveNonVoterError, TransferLeadership, TransferLeadershipError,
+    },
+    common::{CLIENT_RPC_RX_ERR, UpdateCurrentLeader},
+    config::MembershipChangeMode,
     messages::{ClientPayload, ClientPayloadResponse, MembershipConfig},
     network::RaftNetwork,
-    raft::{RaftState, Raft, ReplicationState, state::ConsensusState},
-    replication::{ReplicationStream},
+    raft::{RaftState, Raft, ReplicationState, state::{ConsensusState, LeadershipTransfer}},
+    replication::ReplicationStream,
     storage::{GetLogEntries, RaftStorage},
 };
 
 
-impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> Handler<InitWithConfig> for Raft<D, R, E, N, S> {
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Handler<InitWithConfig> for Raft<D, R, E, N, S> {
     type Result = ResponseActFuture<Self, (), InitWithConfigError>;
 
     /// An admin message handler invoked exclusively for cluster formation.
@@ -52,7 +61,8 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
 
         // Build a new membership config from given init data & assign it as the new cluster
         // membership config in memory only.
-        self.membership = MembershipConfig{is_in_joint_consensus: false, members: msg.members, non_voters: vec![], removing: vec![]};
+        self.membership = MembershipConfig{is_in_joint_consensus: false, members: msg.members, non_voters: vec![], removing: vec![], learners: vec![], witnesses: vec![]};
+        self.membership_history.insert(0, self.membership.clone());
 
         // Become a candidate and start campaigning for leadership. If this node is the only node
         // in the cluster, then become leader without holding an election.
@@ -60,7 +70,8 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
             self.current_term += 1;
             self.voted_for = Some(self.id);
             self.become_leader(ctx);
-            self.save_hard_state(ctx);
+            let f = self.save_hard_state_async(ctx);
+            ctx.wait(f);
         } else {
             self.become_candidate(ctx);
         }
@@ -73,16 +84,23 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
 //////////////////////////////////////////////////////////////////////////////////////////////////
 // ProposeConfigChange ///////////////////////////////////////////////////////////////////////////
 
-impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> Handler<ProposeConfigChange<D, R, E>> for Raft<D, R, E, N, S> {
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Handler<ProposeConfigChange<D, R, E>> for Raft<D, R, E, N, S> {
     type Result = ResponseActFuture<Self, (), ProposeConfigChangeError<D, R, E>>;
 
     /// An admin message handler invoked to trigger dynamic cluster configuration changes. See §6.
     fn handle(&mut self, msg: ProposeConfigChange<D, R, E>, ctx: &mut Self::Context) -> Self::Result {
-        // Ensure the node is currently the cluster leader.
-        let leader_state = match &mut self.state {
-            RaftState::Leader(state) => state,
+        // Ensure the node is currently the cluster leader, & that no `ChangeMembership` command is
+        // in progress, as its final set of members would otherwise differ from the one requested.
+        match &self.state {
+            RaftState::Leader(state) if !state.awaiting_uniform_config.is_empty() => return Box::new(fut::err(ProposeConfigChangeError::ChangeInProgress)),
+            RaftState::Leader(_) => (),
             _ => return Box::new(fut::err(ProposeConfigChangeError::NodeNotLeader(self.current_leader.clone()))),
-        };
+        }
+
+        // Apply the change one node at a time, if so configured.
+        if let MembershipChangeMode::SingleServer = self.config.membership_change_mode {
+            return self.propose_single_server_change(ctx, msg);
+        }
 
         // Normalize the proposed config to ensure everything is valid.
         let msg = match normalize_proposed_config(msg, &self.membership) {
@@ -90,26 +108,132 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
             Err(err) => return Box::new(fut::err(err)),
         };
 
+        // Any non-voters added via `AddNonVoter` are promoted to voting members.
+        self.membership.learners.retain(|id| !msg.add_members.contains(id));
+
+        // Enter joint consensus & propose the config change to cluster.
+        self.begin_joint_consensus(ctx, msg.add_members, msg.remove_members);
+        Box::new(fut::wrap_future(ctx.address().send(ClientPayload::new_config(self.membership.clone())))
+            .map_err(|_, _: &mut Self, _| ProposeConfigChangeError::Internal)
+            .and_then(|res, _, _| fut::result(res.map_err(|err| ProposeConfigChangeError::ClientError(err))))
+            .map(|_, act, ctx| act.handle_newly_committed_cluster_config(ctx))
+        )
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////
+// ChangeMembership //////////////////////////////////////////////////////////////////////////////
+
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Handler<ChangeMembership<D, R, E>> for Raft<D, R, E, N, S> {
+    type Result = ResponseActFuture<Self, (), ChangeMembershipError<D, R, E>>;
+
+    /// An admin message handler invoked to change the set of voting members of the cluster. See §6.
+    ///
+    /// The nodes to be added & removed are derived from the current config, after which the
+    /// cluster enters joint consensus exactly as it would for a `ProposeConfigChange` command.
+    /// This handler resolves once the final uniform config has been committed. If only the set
+    /// of witnesses is changing, the new config is committed directly.
+    fn handle(&mut self, msg: ChangeMembership<D, R, E>, ctx: &mut Self::Context) -> Self::Result {
+        // Ensure the node is currently the cluster leader, & that no change is in progress.
+        let leader_state = match &mut self.state {
+            RaftState::Leader(state) => state,
+            _ => return Box::new(fut::err(ChangeMembershipError::NodeNotLeader(self.current_leader))),
+        };
+        match leader_state.consensus_state {
+            ConsensusState::Uniform if !self.membership.is_in_joint_consensus => (),
+            _ => return Box::new(fut::err(ChangeMembershipError::ChangeInProgress)),
+        }
+        if msg.members.len() < 2 {
+            return Box::new(fut::err(ChangeMembershipError::InoperableConfig));
+        }
+        if !is_valid_witness_set(&msg, &self.membership, self.id) {
+            return Box::new(fut::err(ChangeMembershipError::InvalidWitnesses));
+        }
+
+        // Determine the nodes to add & remove based on the current voting members.
+        let current = &self.membership.members;
+        let add_members: Vec<_> = msg.members.iter().filter(|id| !current.contains(id)).cloned().collect();
+        let remove_members: Vec<_> = current.iter().filter(|id| !msg.members.contains(id)).cloned().collect();
+        if add_members.is_empty() && remove_members.is_empty() {
+            let current_witnesses: BTreeSet<_> = self.membership.witnesses.iter().cloned().collect();
+            if current_witnesses == msg.witnesses {
+                return Box::new(fut::err(ChangeMembershipError::Noop));
+            }
+
+            // As the set of voting members is unchanged, the new witnesses are committed directly.
+            self.membership.witnesses = msg.witnesses.into_iter().collect();
+            self.report_metrics(ctx);
+            return Box::new(fut::wrap_future(ctx.address().send(ClientPayload::new_config(self.membership.clone())))
+                .map_err(|_, _: &mut Self, _| ChangeMembershipError::Internal)
+                .and_then(|res, _, _| fut::result(res.map(|_| ()).map_err(ChangeMembershipError::ClientError))));
+        }
+        let (tx, rx) = oneshot::channel();
+        leader_state.awaiting_uniform_config.push(tx);
+
+        // Any non-voters which are part of the new set are promoted to voting members. Witnesses
+        // which are being removed remain witnesses until joint consensus is finalized.
+        self.membership.learners.retain(|id| !msg.members.contains(id));
+        let removed_witnesses: Vec<_> = self.membership.witnesses.iter().filter(|id| !msg.members.contains(id)).cloned().collect();
+        self.membership.witnesses = msg.witnesses.into_iter().chain(removed_witnesses).collect();
+
+        // Enter joint consensus & propose the config change to cluster. Once the joint config is
+        // committed, await the final uniform config being committed.
+        self.begin_joint_consensus(ctx, add_members, remove_members);
+        Box::new(fut::wrap_future(ctx.address().send(ClientPayload::new_config(self.membership.clone())))
+            .map_err(|_, _: &mut Self, _| ChangeMembershipError::Internal)
+            .and_then(|res, _, _| fut::result(res.map_err(ChangeMembershipError::ClientError)))
+            .map(|_, act, ctx| act.handle_newly_committed_cluster_config(ctx))
+            .and_then(|_, _, _| fut::wrap_future(rx)
+                .map_err(|_, act: &mut Self, _| ChangeMembershipError::NodeNotLeader(act.current_leader))
+                .and_then(|res, _, _| fut::result(res))))
+    }
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Raft<D, R, E, N, S> {
+    /// Transition the cluster into a joint consensus state which adds & removes the given nodes.
+    ///
+    /// The given changes must have already been validated against the current config. The caller
+    /// is responsible for proposing the updated config to the cluster.
+    ///
+    /// NOTE: this routine will only behave as intended when in leader state.
+    fn begin_joint_consensus(&mut self, ctx: &mut Context<Self>, add_members: Vec<NodeId>, remove_members: Vec<NodeId>) {
+        let leader_state = match &mut self.state {
+            RaftState::Leader(state) => state,
+            _ => return,
+        };
+
+        // Nodes which already have a replication stream, such as non-voters being promoted, only
+        // need to be synced if they are not already replicating at line rate.
+        let new_nodes: Vec<_> = add_members.iter()
+            .filter(|id| leader_state.nodes.get(id).map(|rs| !rs.is_at_line_rate).unwrap_or(true))
+            .cloned().collect();
+
         // Update consensus state, for use in finalizing joint consensus.
         match &mut leader_state.consensus_state {
             // Merge with any current consensus state.
-            ConsensusState::Joint{new_nodes, is_committed} => {
-                new_nodes.extend_from_slice(msg.add_members.as_slice());
+            ConsensusState::Joint{new_nodes: current, is_committed} => {
+                current.extend_from_slice(new_nodes.as_slice());
                 *is_committed = false;
             }
             _ => {
-                leader_state.consensus_state = ConsensusState::Joint{new_nodes: msg.add_members.clone(), is_committed: false};
+                leader_state.consensus_state = ConsensusState::Joint{new_nodes, is_committed: false};
             }
         }
 
         // Update current config.
         self.membership.is_in_joint_consensus = true;
-        self.membership.non_voters.extend_from_slice(msg.add_members.as_slice());
-        self.membership.removing.extend_from_slice(msg.remove_members.as_slice());
+        self.membership.non_voters.extend_from_slice(add_members.as_slice());
+        self.membership.removing.extend_from_slice(remove_members.as_slice());
 
         // Spawn new replication streams for new members. Track state as non voters so that they
         // can be updated to be normal members once all non-voters have been brought up-to-date.
-        for target in msg.add_members {
+        // Their match index starts at 0, so that their progress is reported accurately.
+        // A node which was recently dropped may still have a stream awaiting removal, which is kept.
+        for target in add_members {
+            if let Some(replstate) = leader_state.nodes.get_mut(&target) {
+                replstate.remove_after_commit = None;
+                continue;
+            }
             // Build the replication stream for the target member.
             let rs = ReplicationStream::new(
                 self.id, target, self.current_term, self.config.clone(),
@@ -120,38 +244,143 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
 
             // Retain the addr of the replication stream.
             let state = ReplicationState{
-                addr, match_index: self.last_log_index, remove_after_commit: None,
+                addr, match_index: 0, remove_after_commit: None, last_heartbeat_ack: None,
                 is_at_line_rate: true, // Line rate is always initialize to true.
             };
             leader_state.nodes.insert(target, state);
         }
 
         // For any nodes being removed which are currently non-voters, immediately remove them.
-        for node in msg.remove_members {
+        for node in remove_members {
             if let Some((idx, _)) = self.membership.non_voters.iter().enumerate().find(|(_, e)| *e == &node) {
                 leader_state.nodes.remove(&node); // Dropping the replication stream's addr will kill it.
                 self.membership.non_voters.remove(idx);
             }
         }
 
+        // Give the new nodes until the configured deadline to catch up.
+        self.schedule_catch_up_deadline(ctx);
+
         // Report metrics.
         self.report_metrics(ctx);
+    }
 
-        // Propose the config change to cluster.
-        Box::new(fut::wrap_future(ctx.address().send(ClientPayload::new_config(self.membership.clone())))
-            .map_err(|_, _: &mut Self, _| ProposeConfigChangeError::Internal)
-            .and_then(|res, _, _| fut::result(res.map_err(|err| ProposeConfigChangeError::ClientError(err))))
-            .and_then(|res, act, ctx| act.handle_newly_committed_cluster_config(ctx, res))
-        )
+    /// Schedule the abortion of the current joint consensus config change, if so configured.
+    ///
+    /// Any previously scheduled deadline is cancelled, as changes proposed while in joint
+    /// consensus are merged into the current one. Nothing is scheduled if there are no new nodes
+    /// to be synced.
+    pub(super) fn schedule_catch_up_deadline(&mut self, ctx: &mut Context<Self>) {
+        let leader_state = match &mut self.state {
+            RaftState::Leader(state) => state,
+            _ => return,
+        };
+        if let Some(handle) = leader_state.catch_up_deadline.take() {
+            ctx.cancel_future(handle);
+        }
+        let timeout = match self.config.catch_up_timeout {
+            Some(timeout) => timeout,
+            None => return,
+        };
+        match &leader_state.consensus_state {
+            ConsensusState::Joint{new_nodes, ..} if !new_nodes.is_empty() => (),
+            _ => return,
+        }
+        leader_state.catch_up_deadline = Some(ctx.run_later(timeout, |act, ctx| act.abort_stalled_joint_consensus(ctx)));
     }
-}
 
-impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> Raft<D, R, E, N, S> {
-    /// Handle response from a newly committed cluster config.
-    pub(super) fn handle_newly_committed_cluster_config(&mut self, ctx: &mut Context<Self>, _: ClientPayloadResponse<R>) -> impl ActorFuture<Actor=Self, Item=(), Error=ProposeConfigChangeError<D, R, E>> {
+    /// Abort the current joint consensus config change, as its new nodes have not caught up in time.
+    fn abort_stalled_joint_consensus(&mut self, ctx: &mut Context<Self>) {
         let leader_state = match &mut self.state {
             RaftState::Leader(state) => state,
-            _ => return fut::ok(()),
+            _ => return,
+        };
+        leader_state.catch_up_deadline = None;
+        match &leader_state.consensus_state {
+            ConsensusState::Joint{new_nodes, ..} if !new_nodes.is_empty() => {
+                warn!("Node {} is aborting a config change, as nodes {:?} did not catch up in time.", self.id, new_nodes);
+            }
+            _ => return,
+        }
+        let dropped = self.revert_joint_consensus(ctx, || ChangeMembershipError::CatchUpTimeout);
+
+        // Commit the reverted config to the cluster.
+        ctx.spawn(fut::wrap_future(ctx.address().send(ClientPayload::new_config(self.membership.clone())))
+            .map_err(|err, _, _| error!("Messaging error submitting client payload to abort joint consensus. {:?}", err))
+            .and_then(|res, _, _| fut::result(res
+                .map_err(|err| error!("Error from submitting client payload to abort joint consensus. {:?}", err))))
+            .map(move |res, act: &mut Self, _| act.remove_dropped_replication_streams(dropped, res.index()))
+        );
+    }
+
+    /// Revert the current joint consensus config to the previous set of voting members.
+    ///
+    /// Every node being added is dropped, while the nodes being removed are kept. As the joint
+    /// config contains both the old & the new set of voting members, it is as safe to leave joint
+    /// consensus this way as it is to finalize the change. Any `ChangeMembership` commands
+    /// awaiting the final config are sent the given error. The caller is responsible for
+    /// committing the reverted config to the cluster, after which the replication streams of the
+    /// returned nodes may be removed, so that the dropped nodes learn of the reverted config.
+    ///
+    /// NOTE: this routine will only behave as intended when in leader state.
+    fn revert_joint_consensus(&mut self, ctx: &mut Context<Self>, err: impl Fn() -> ChangeMembershipError<D, R, E>) -> Vec<NodeId> {
+        let leader_state = match &mut self.state {
+            RaftState::Leader(state) => state,
+            _ => return vec![],
+        };
+        if let Some(handle) = leader_state.catch_up_deadline.take() {
+            ctx.cancel_future(handle);
+        }
+
+        // Revert the current config.
+        let dropped: Vec<_> = self.membership.non_voters.drain(..).collect();
+        let MembershipConfig{members, learners, witnesses, removing, ..} = &mut self.membership;
+        witnesses.retain(|id| members.contains(id) || learners.contains(id));
+        removing.clear();
+        self.membership.is_in_joint_consensus = false;
+        leader_state.consensus_state = ConsensusState::Uniform;
+
+        // Notify any `ChangeMembership` commands awaiting the final config.
+        for tx in leader_state.awaiting_uniform_config.drain(..) {
+            let _ = tx.send(Err(err()));
+        }
+        self.report_metrics(ctx);
+        dropped
+    }
+
+    /// Drop the replication streams of the given nodes, once they have replicated the reverted config at the given index.
+    fn remove_dropped_replication_streams(&mut self, dropped: Vec<NodeId>, index: u64) {
+        for node in dropped {
+            self.remove_replication_stream_after_commit(node, index);
+        }
+    }
+
+    /// Handle a new node of the current joint consensus config having caught up with this node's log.
+    ///
+    /// Once all new nodes have caught up & the joint config has been committed, the cluster
+    /// leaves joint consensus.
+    pub(super) fn handle_caught_up_node(&mut self, ctx: &mut Context<Self>, target: NodeId) {
+        let leader_state = match &mut self.state {
+            RaftState::Leader(state) => state,
+            _ => return,
+        };
+        let (new_nodes, is_committed) = match &mut leader_state.consensus_state {
+            ConsensusState::Joint{new_nodes, is_committed} if new_nodes.contains(&target) => (new_nodes, *is_committed),
+            _ => return,
+        };
+        new_nodes.retain(|id| id != &target);
+        if is_committed && new_nodes.is_empty() && self.membership.is_in_joint_consensus {
+            self.finalize_joint_consensus(ctx);
+        } else {
+            self.report_metrics(ctx);
+        }
+    }
+
+    /// Handle a newly committed joint consensus config.
+    pub(super) fn handle_newly_committed_cluster_config(&mut self, ctx: &mut Context<Self>) {
+        let leader_state = match &mut self.state {
+            RaftState::Leader(state) => state,
+            _ => return,
         };
 
         match &mut leader_state.consensus_state {
@@ -163,8 +392,6 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
             }
             _ => (),
         }
-
-        fut::ok(())
     }
 
     /// Transition the cluster out of a joint consensus state.
@@ -189,9 +416,13 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
             if let Some((idx, _)) = self.membership.members.iter().enumerate().find(|(_, e)| *e == &node) {
                 self.membership.members.remove(idx);
             }
+            self.membership.witnesses.retain(|id| id != &node);
         }
         self.membership.is_in_joint_consensus = false;
         leader_state.consensus_state = ConsensusState::Uniform;
+        if let Some(handle) = leader_state.catch_up_deadline.take() {
+            ctx.cancel_future(handle);
+        }
 
         // Committ new config to cluster.
         //
@@ -220,6 +451,11 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
             _ => return fut::ok(()),
         };
 
+        // Notify any `ChangeMembership` commands awaiting the final config.
+        for tx in leader_state.awaiting_uniform_config.drain(..) {
+            let _ = tx.send(Ok(()));
+        }
+
         // Step down if needed.
         if !self.membership.contains(&self.id) {
             info!("Node {} is stepping down.", self.id);
@@ -250,9 +486,367 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
     }
 }
 
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Raft<D, R, E, N, S> {
+    /// Propose a config change which adds or removes a single node, per §4.1 of the Raft thesis.
+    ///
+    /// The new config takes effect as soon as it is appended to the log, without going through
+    /// joint consensus. Only one such change may be uncommitted at a time. A newly elected leader
+    /// must also commit an entry from its own term first, as that guarantees that any change
+    /// appended by a previous leader has been committed.
+    fn propose_single_server_change(&mut self, ctx: &mut Context<Self>, msg: ProposeConfigChange<D, R, E>) -> ResponseActFuture<Self, (), ProposeConfigChangeError<D, R, E>> {
+        // Normalize the proposed config to ensure everything is valid.
+        let msg = match normalize_single_server_change(msg, &self.membership) {
+            Ok(msg) => msg,
+            Err(err) => return Box::new(fut::err(*err)),
+        };
+
+        // Ensure there is no uncommitted change.
+        let leader_state = match &mut self.state {
+            RaftState::Leader(state) => state,
+            _ => return Box::new(fut::err(ProposeConfigChangeError::NodeNotLeader(self.current_leader))),
+        };
+        let is_change_pending = match leader_state.consensus_state {
+            ConsensusState::Uniform => self.membership.is_in_joint_consensus || self.commit_index < leader_state.term_start_index,
+            _ => true,
+        };
+        if is_change_pending {
+            return Box::new(fut::err(ProposeConfigChangeError::ChangeInProgress));
+        }
+        leader_state.consensus_state = ConsensusState::SingleServer;
+
+        // Update current config. A non-voter added via `AddNonVoter` already has a replication
+        // stream, so it is simply promoted. Any other new node needs a new replication stream,
+        // whose match index starts at 0, as the node has not yet confirmed any entries.
+        let prev = self.membership.clone();
+        for target in msg.add_members.iter().cloned() {
+            if self.membership.learners.contains(&target) {
+                self.membership.learners.retain(|id| id != &target);
+            } else {
+                let rs = ReplicationStream::new(
+                    self.id, target, self.current_term, self.config.clone(),
+                    self.last_log_index, self.last_log_term, self.commit_index,
+                    ctx.address(), self.network.clone(), self.storage.clone().recipient::<GetLogEntries<D, E>>(),
+                );
+                let addr = rs.start(); // Start the actor on the same thread.
+                let state = ReplicationState{
+                    addr, match_index: 0, remove_after_commit: None, last_heartbeat_ack: None,
+                    is_at_line_rate: true, // Line rate is always initialize to true.
+                };
+                leader_state.nodes.insert(target, state);
+            }
+            self.membership.members.push(target);
+        }
+        for node in msg.remove_members.iter() {
+            self.membership.members.retain(|id| id != node);
+            self.membership.witnesses.retain(|id| id != node);
+        }
+        let proposed = self.membership.clone();
+
+        // Report metrics.
+        self.report_metrics(ctx);
+
+        // Propose the config change to cluster.
+        Box::new(fut::wrap_future(ctx.address().send(ClientPayload::new_config(proposed.clone())))
+            .map_err(|_, _: &mut Self, _| ProposeConfigChangeError::Internal)
+            .and_then(|res, _, _| fut::result(res.map_err(ProposeConfigChangeError::ClientError)))
+            .then(move |res, act, ctx| act.handle_single_server_change_result(ctx, res, prev, proposed)))
+    }
+
+    /// Handle the result of committing a single-server config change.
+    ///
+    /// If the change could not be committed, the previous config is restored, unless the config
+    /// has been updated again in the mean time.
+    fn handle_single_server_change_result(
+        &mut self, ctx: &mut Context<Self>, res: Result<ClientPayloadResponse<R>, ProposeConfigChangeError<D, R, E>>,
+        prev: MembershipConfig, proposed: MembershipConfig,
+    ) -> impl ActorFuture<Actor=Self, Item=(), Error=ProposeConfigChangeError<D, R, E>> {
+        let leader_state = match &mut self.state {
+            RaftState::Leader(state) => state,
+            _ => return fut::result(res.map(|_| ())),
+        };
+        leader_state.consensus_state = ConsensusState::Uniform;
+
+        let res = match res {
+            Ok(res) => res,
+            Err(err) => {
+                if self.membership == proposed {
+                    for node in proposed.all_nodes().filter(|id| !prev.contains(id)) {
+                        leader_state.nodes.remove(node); // Dropping the replication stream's addr will kill it.
+                    }
+                    self.membership = prev;
+                    self.report_metrics(ctx);
+                }
+                return fut::err(err);
+            }
+        };
+
+        // Step down if this node has been removed from the cluster.
+        if !self.membership.members.contains(&self.id) {
+            info!("Node {} is stepping down.", self.id);
+            self.become_non_voter(ctx);
+            self.update_current_leader(ctx, UpdateCurrentLeader::Unknown);
+            return fut::ok(());
+        }
+
+        // Drop the replication streams of any removed nodes, once they have replicated the new config.
+        let membership = &self.membership;
+        let removed: Vec<_> = leader_state.nodes.keys().filter(|id| !membership.contains(id)).cloned().collect();
+        for node in removed {
+            self.remove_replication_stream_after_commit(node, res.index());
+        }
+        fut::ok(())
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////
+// AbortConfigChange /////////////////////////////////////////////////////////////////////////////
+
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Handler<AbortConfigChange<D, R, E>> for Raft<D, R, E, N, S> {
+    type Result = ResponseActFuture<Self, (), AbortConfigChangeError<D, R, E>>;
+
+    /// An admin message handler invoked to abort the joint consensus config change in progress.
+    ///
+    /// The config is reverted to the previous set of voting members, which is then committed to
+    /// the cluster. This handler resolves once the reverted config has been committed.
+    fn handle(&mut self, _: AbortConfigChange<D, R, E>, ctx: &mut Self::Context) -> Self::Result {
+        // Ensure the node is currently the cluster leader, & that the cluster is in joint consensus.
+        match &self.state {
+            RaftState::Leader(state) => match state.consensus_state {
+                ConsensusState::Joint{..} if self.membership.is_in_joint_consensus => (),
+                _ => return Box::new(fut::err(AbortConfigChangeError::NotInJointConsensus)),
+            },
+            _ => return Box::new(fut::err(AbortConfigChangeError::NodeNotLeader(self.current_leader))),
+        }
+
+        info!("Node {} is aborting the config change in progress.", self.id);
+        let dropped = self.revert_joint_consensus(ctx, || ChangeMembershipError::Aborted);
+
+        // Commit the reverted config to the cluster.
+        Box::new(fut::wrap_future(ctx.address().send(ClientPayload::new_config(self.membership.clone())))
+            .map_err(|_, _: &mut Self, _| AbortConfigChangeError::Internal)
+            .and_then(|res, _, _| fut::result(res.map_err(AbortConfigChangeError::ClientError)))
+            .map(move |res, act, _| act.remove_dropped_replication_streams(dropped, res.index())))
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////
+// TransferLeadership ////////////////////////////////////////////////////////////////////////////
+
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Handler<TransferLeadership> for Raft<D, R, E, N, S> {
+    type Result = ResponseActFuture<Self, (), TransferLeadershipError>;
+
+    /// An admin message handler invoked to transfer leadership to another node (§3.10).
+    ///
+    /// The leader stops accepting new client payloads, and waits for the target's replication
+    /// stream to bring the target fully up-to-date. The target is then sent a `TimeoutNowRequest`
+    /// RPC, which causes it to start an election right away. As its log is up-to-date, it is
+    /// expected to win the election.
+    ///
+    /// This command resolves once this node has observed the newer term & stepped down. If that
+    /// does not happen within an election timeout, the transfer is aborted, this node resumes
+    /// accepting client payloads & an error is returned.
+    fn handle(&mut self, msg: TransferLeadership, ctx: &mut Self::Context) -> Self::Result {
+        // Ensure the node is currently the cluster leader.
+        let leader_state = match &mut self.state {
+            RaftState::Leader(state) => state,
+            _ => return Box::new(fut::err(TransferLeadershipError::NodeNotLeader(self.current_leader))),
+        };
+        if leader_state.leadership_transfer.is_some() {
+            return Box::new(fut::err(TransferLeadershipError::InProgress));
+        }
+
+        // Only a voting member which is staying in the cluster, & which is not a witness, may become the next leader.
+        let is_valid_target = msg.target != self.id
+            && self.membership.members.contains(&msg.target)
+            && !self.membership.removing.contains(&msg.target)
+            && !self.membership.witnesses.contains(&msg.target);
+        if !is_valid_target {
+            return Box::new(fut::err(TransferLeadershipError::InvalidTarget));
+        }
+
+        info!("Node {} is transferring leadership to node {}.", self.id, msg.target);
+        let (tx, rx) = oneshot::channel();
+        leader_state.leadership_transfer = Some(LeadershipTransfer{target: msg.target, term: self.current_term, timeout_now_sent: false, tx});
+
+        // The target may already be up-to-date.
+        self.progress_leadership_transfer(ctx);
+
+        let timeout = Duration::from_millis(self.config.election_timeout_millis);
+        Box::new(fut::wrap_future(Timeout::new(rx, timeout))
+            .then(|res, act: &mut Self, _| match res {
+                Ok(res) => fut::result(res),
+                Err(err) => {
+                    if !err.is_elapsed() {
+                        error!("{}", CLIENT_RPC_RX_ERR);
+                        return fut::err(TransferLeadershipError::Internal);
+                    }
+                    // The transfer has timed out, so resume normal operation.
+                    if let RaftState::Leader(state) = &mut act.state {
+                        warn!("Node {} failed to transfer leadership within an election timeout.", act.id);
+                        state.leadership_transfer = None;
+                    }
+                    fut::err(TransferLeadershipError::Timeout)
+                }
+            }))
+    }
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Raft<D, R, E, N, S> {
+    /// Send a `TimeoutNowRequest` to the target of the current leadership transfer, if it is ready.
+    ///
+    /// The target is ready once its replication stream has replicated all of the entries in this
+    /// node's log, including the first entry of this leader's term. This is called whenever the
+    /// target's match index is updated.
+    pub(super) fn progress_leadership_transfer(&mut self, ctx: &mut Context<Self>) {
+        let leader_state = match &mut self.state {
+            RaftState::Leader(state) => state,
+            _ => return,
+        };
+        let transfer = match &mut leader_state.leadership_transfer {
+            Some(transfer) if !transfer.timeout_now_sent => transfer,
+            _ => return,
+        };
+
+        // Wait until no more entries are being appended & the target has all of them.
+        let up_to = std::cmp::max(self.last_log_index, leader_state.term_start_index);
+        let is_up_to_date = leader_state.nodes.get(&transfer.target)
+            .map(|rs| rs.match_index >= up_to).unwrap_or(false);
+        if self.is_appending_logs || !is_up_to_date {
+            return;
+        }
+
+        transfer.timeout_now_sent = true;
+        let target = transfer.target;
+        leader_state.timeout_now_sent_at = Some(Instant::now());
+        let f = self.send_timeout_now(ctx, target);
+        ctx.spawn(f);
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////
+// AddNonVoter ///////////////////////////////////////////////////////////////////////////////////
+
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Handler<AddNonVoter<D, R, E>> for Raft<D, R, E, N, S> {
+    type Result = ResponseActFuture<Self, (), AddNonVoterError<D, R, E>>;
+
+    /// An admin message handler invoked to add a permanent non-voter to the cluster.
+    ///
+    /// As the set of voting members does not change, the new config is committed directly,
+    /// without going through joint consensus. A replication stream is spawned for the new
+    /// non-voter right away, so that it may begin catching up while the config is committed.
+    fn handle(&mut self, msg: AddNonVoter<D, R, E>, ctx: &mut Self::Context) -> Self::Result {
+        // Ensure the node is currently the cluster leader.
+        let leader_state = match &mut self.state {
+            RaftState::Leader(state) => state,
+            _ => return Box::new(fut::err(AddNonVoterError::NodeNotLeader(self.current_leader))),
+        };
+        if self.membership.contains(&msg.id) {
+            return Box::new(fut::err(AddNonVoterError::AlreadyMember));
+        }
+
+        // Build the replication stream for the new non-voter.
+        let rs = ReplicationStream::new(
+            self.id, msg.id, self.current_term, self.config.clone(),
+            self.last_log_index, self.last_log_term, self.commit_index,
+            ctx.address(), self.network.clone(), self.storage.clone().recipient::<GetLogEntries<D, E>>(),
+        );
+        let addr = rs.start(); // Start the actor on the same thread.
+
+        // Retain the addr of the replication stream. Its match index starts at 0, as the new
+        // non-voter may be promoted to a voting member before it has confirmed any entries.
+        let state = ReplicationState{
+            addr, match_index: 0, remove_after_commit: None, last_heartbeat_ack: None,
+            is_at_line_rate: true, // Line rate is always initialize to true.
+        };
+        leader_state.nodes.insert(msg.id, state);
+
+        // Update current config & report metrics.
+        self.membership.learners.push(msg.id);
+        self.report_metrics(ctx);
+
+        // Commit the new config to the cluster.
+        Box::new(fut::wrap_future(ctx.address().send(ClientPayload::new_config(self.membership.clone())))
+            .map_err(|_, _: &mut Self, _| AddNonVoterError::Internal)
+            .and_then(|res, _, _| fut::result(res.map(|_| ()).map_err(AddNonVoterError::ClientError))))
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////
+// RemoveNonVoter ////////////////////////////////////////////////////////////////////////////////
+
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Handler<RemoveNonVoter<D, R, E>> for Raft<D, R, E, N, S> {
+    type Result = ResponseActFuture<Self, (), RemoveNonVoterError<D, R, E>>;
+
+    /// An admin message handler invoked to remove a permanent non-voter from the cluster.
+    ///
+    /// As the set of voting members does not change, the new config is committed directly,
+    /// without going through joint consensus. Once committed, the target's replication stream
+    /// will be dropped as soon as the target has replicated the new config.
+    fn handle(&mut self, msg: RemoveNonVoter<D, R, E>, ctx: &mut Self::Context) -> Self::Result {
+        // Ensure the node is currently the cluster leader.
+        match &self.state {
+            RaftState::Leader(_) => (),
+            _ => return Box::new(fut::err(RemoveNonVoterError::NodeNotLeader(self.current_leader))),
+        }
+        if !self.membership.learners.contains(&msg.id) {
+            return Box::new(fut::err(RemoveNonVoterError::NotNonVoter));
+        }
+
+        // Update current config & report metrics.
+        self.membership.learners.retain(|id| id != &msg.id);
+        self.report_metrics(ctx);
+
+        // Commit the new config to the cluster.
+        let target = msg.id;
+        Box::new(fut::wrap_future(ctx.address().send(ClientPayload::new_config(self.membership.clone())))
+            .map_err(|_, _: &mut Self, _| RemoveNonVoterError::Internal)
+            .and_then(|res, _, _| fut::result(res.map_err(RemoveNonVoterError::ClientError)))
+            .map(move |res, act, _| act.remove_replication_stream_after_commit(target, res.index())))
+    }
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Raft<D, R, E, N, S> {
+    /// Drop the replication stream of a node which is no longer a cluster member, once it has replicated the given index.
+    ///
+    /// If the target has been added back to the cluster in the mean time, this is a no-op.
+    fn remove_replication_stream_after_commit(&mut self, target: NodeId, index: u64) {
+        let leader_state = match &mut self.state {
+            RaftState::Leader(state) => state,
+            _ => return,
+        };
+        if self.membership.contains(&target) {
+            return;
+        }
+
+        let is_replicated = match leader_state.nodes.get_mut(&target) {
+            Some(replstate) if replstate.match_index >= index => true,
+            Some(replstate) => {
+                replstate.remove_after_commit = Some(index);
+                false
+            }
+            None => return,
+        };
+        if is_replicated {
+            leader_state.nodes.remove(&target);
+        }
+    }
+}
+
 //////////////////////////////////////////////////////////////////////////////////////////////////
 // Utilities /////////////////////////////////////////////////////////////////////////////////////
 
+/// Check that the witnesses of the given command are valid for its members & the current config.
+///
+/// Witnesses must be members of the new config, must not include the leader, and must leave at
+/// least one full member. A current witness which stays in the cluster must remain a witness, as
+/// it holds no application data.
+fn is_valid_witness_set<D: AppData, R: AppDataResponse, E: AppError>(msg: &ChangeMembership<D, R, E>, current: &MembershipConfig, leader: NodeId) -> bool {
+    msg.witnesses.is_subset(&msg.members)
+        && !msg.witnesses.contains(&leader)
+        && msg.witnesses.len() < msg.members.len()
+        && current.witnesses.iter().all(|id| !msg.members.contains(id) || msg.witnesses.contains(id))
+}
+
 // Ensure given config is normalized and ready for use in the cluster.
 fn normalize_init_config(msg: InitWithConfig) -> InitWithConfig {
     let mut nodes = vec![];
@@ -265,24 +859,66 @@ fn normalize_init_config(msg: InitWithConfig) -> InitWithConfig {
     InitWithConfig{members: nodes}
 }
 
+/// The result of normalizing a proposed config change.
+type NormalizedConfigChange<D, R, E> = Result<ProposeConfigChange<D, R, E>, Box<ProposeConfigChangeError<D, R, E>>>;
+
+/// Check a proposed single-server config change with the current config to ensure it is valid.
+///
+/// Only voting members are taken into account, as a non-voter added via `AddNonVoter` may be
+/// promoted to a voting member. See the documentation on `ProposeConfigChangeError` for the
+/// conditions which will cause errors to be returned.
+fn normalize_single_server_change<D: AppData, R: AppDataResponse, E: AppError>(mut msg: ProposeConfigChange<D, R, E>, current: &MembershipConfig) -> NormalizedConfigChange<D, R, E> {
+    let mut new_nodes = vec![];
+    for node in msg.add_members {
+        if !current.members.contains(&node) && !msg.remove_members.contains(&node) && !new_nodes.contains(&node) {
+            new_nodes.push(node);
+        }
+    }
+    let mut remove_nodes = vec![];
+    for node in msg.remove_members {
+        if current.members.contains(&node) && !remove_nodes.contains(&node) {
+            remove_nodes.push(node);
+        }
+    }
+
+    // Account for noop & ensure only a single node is being changed.
+    let changes = new_nodes.len() + remove_nodes.len();
+    if changes == 0 {
+        return Err(Box::new(ProposeConfigChangeError::Noop));
+    } else if changes > 1 {
+        return Err(Box::new(ProposeConfigChangeError::MultipleChanges));
+    }
+
+    // Ensure cluster will have at least two nodes.
+    if current.members.len() + new_nodes.len() - remove_nodes.len() < 2 {
+        return Err(Box::new(ProposeConfigChangeError::InoperableConfig));
+    }
+
+    msg.add_members = new_nodes;
+    msg.remove_members = remove_nodes;
+    Ok(msg)
+}
+
 /// Check the proposed config changes with the current config to ensure changes are valid.
 ///
 /// See the documentation on on `ProposeConfigChangeError` for the conditions which will cause
 /// errors to be returned.
 fn normalize_proposed_config<D: AppData, R: AppDataResponse, E: AppError>(mut msg: ProposeConfigChange<D, R, E>, current: &MembershipConfig) -> Result<ProposeConfigChange<D, R, E>, ProposeConfigChangeError<D, R, E>> {
     // Ensure no duplicates in adding new nodes & ensure the new
-    // node is not also be requested for removal.
+    // node is not also be requested for removal. Non-voters added via `AddNonVoter` are promoted.
     let mut new_nodes = vec![];
     for node in msg.add_members {
-        if !current.contains(&node) && !msg.remove_members.contains(&node) {
+        let is_new = !current.contains(&node) || current.learners.contains(&node);
+        if is_new && !msg.remove_members.contains(&node) {
             new_nodes.push(node);
         }
     }
 
-    // Ensure targets to remove exist in current config.
+    // Ensure targets to remove exist in current config. Non-voters added via `AddNonVoter` must
+    // be removed via `RemoveNonVoter`.
     let mut remove_nodes = vec![];
     for node in msg.remove_members {
-        if current.contains(&node) && !current.removing.contains(&node) {
+        if current.contains(&node) && !current.removing.contains(&node) && !current.learners.contains(&node) {
             remove_nodes.push(node);
         }
     }
@@ -1,17 +1,19 @@
 use std::sync::Arc;
 
 use actix::prelude::*;
+use futures::sync::oneshot;
+use log::info;
 
 use crate::{
     AppData, AppDataResponse, AppError,
-    common::{ApplyLogsTask, DependencyAddr, UpdateCurrentLeader},
+    common::{AppendEntriesWithChan, ApplyLogsTask, DependencyAddr, UpdateCurrentLeader},
     network::RaftNetwork,
-    messages::{AppendEntriesRequest, AppendEntriesResponse, ConflictOpt, Entry, EntryPayload},
-    raft::{RaftState, Raft, SnapshotState},
+    messages::{AppendEntriesRequest, AppendEntriesResponse, ConflictOpt, Entry},
+    raft::{RaftState, Raft, SnapshotState, client_sessions::record_pending_client_sessions, record_config_entries, truncate_config_entries},
     storage::{GetLogEntries, RaftStorage, ReplicateToLog},
 };
 
-impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> Handler<AppendEntriesRequest<D>> for Raft<D, R, E, N, S> {
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Handler<AppendEntriesRequest<D>> for Raft<D, R, E, N, S> {
     type Result = ResponseActFuture<Self, AppendEntriesResponse, ()>;
 
     /// An RPC invoked by the leader to replicate log entries (§5.3); also used as heartbeat (§5.2).
@@ -59,6 +61,18 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
     /// not replicated to a majority of followers, and the new leader will proceeed to overwrite
     /// the inconsistent entries.
     fn handle(&mut self, msg: AppendEntriesRequest<D>, ctx: &mut Self::Context) -> Self::Result {
+        // Respond only once any change to this node's term is durable.
+        let (term, voted_for) = (self.current_term, self.voted_for);
+        let res = self.handle_append_entries_request(ctx, msg);
+        self.respond_once_durable(ctx, term, voted_for, res)
+    }
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Raft<D, R, E, N, S> {
+    /// Business logic of handling an `AppendEntriesRequest` RPC.
+    fn handle_append_entries_request(
+        &mut self, ctx: &mut Context<Self>, msg: AppendEntriesRequest<D>,
+    ) -> Box<dyn ActorFuture<Actor=Self, Item=AppendEntriesResponse, Error=()> + 'static> {
         // Only handle requests if actor has finished initialization.
         if let &RaftState::Initializing = &self.state {
             return Box::new(fut::err(()));
@@ -75,7 +89,6 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
         // Update current term if needed.
         if self.current_term != msg.term {
             self.update_current_term(msg.term, None);
-            self.save_hard_state(ctx);
         }
 
         // Update current leader if needed.
@@ -96,24 +109,66 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
             _ => self.become_follower(ctx),
         }
 
-        // Kick off process of applying logs to state machine based on `msg.leader_commit`.
-        self.commit_index = msg.leader_commit; // The value for `self.commit_index` is only updated here when not the leader.
-        if &self.commit_index > &self.last_applied {
-            let _ = self.apply_logs_pipeline.unbounded_send(ApplyLogsTask::Outstanding);
-        }
-
-        // If this is just a heartbeat, then respond.
+        // If this is just a heartbeat, then respond. The commit index may only advance if the
+        // heartbeat's previous log info matches the end of this node's log.
         if msg.entries.len() == 0 {
+            if msg.prev_log_index == self.last_log_index && msg.prev_log_term == self.last_log_term {
+                self.update_follower_commit_index(msg.leader_commit, msg.prev_log_index);
+            }
             return Box::new(fut::ok(AppendEntriesResponse{term: self.current_term, success: true, conflict_opt: None}));
         }
 
+        // Requests carrying entries are processed strictly one at a time, in the order in which
+        // they were received, as the leader may pipeline multiple requests to this node. Other
+        // messages, such as votes & heartbeats, continue to be handled in the mean time.
+        let (tx, rx) = oneshot::channel();
+        self.append_entries_queue.push_back(AppendEntriesWithChan{msg, tx});
+        self.drain_append_entries_queue(ctx);
+        Box::new(fut::wrap_future(rx)
+            .map_err(|_, _: &mut Self, _| ())
+            .and_then(|res, _, _| fut::result(res)))
+    }
+
+    /// Process the next queued AppendEntries RPC, if no other is being processed.
+    ///
+    /// Once it has been processed, the next one is processed, until the queue is empty. A request
+    /// from an older term than this node's current term, which may have been observed since the
+    /// request was received, is rejected.
+    fn drain_append_entries_queue(&mut self, ctx: &mut Context<Self>) {
+        if self.is_draining_append_entries {
+            return;
+        }
+        let AppendEntriesWithChan{msg, tx} = match self.append_entries_queue.pop_front() {
+            Some(queued) => queued,
+            None => return,
+        };
+        self.is_draining_append_entries = true;
+        let f = if msg.term < self.current_term {
+            fut::Either::A(fut::ok(AppendEntriesResponse{term: self.current_term, success: false, conflict_opt: None}))
+        } else {
+            fut::Either::B(self.handle_append_entries_payload(ctx, msg))
+        };
+        ctx.spawn(f.then(move |res, act: &mut Self, ctx| {
+            let _ = tx.send(res);
+            act.is_draining_append_entries = false;
+            act.drain_append_entries_queue(ctx);
+            fut::ok(())
+        }));
+    }
+
+    /// Handle the entries of an AppendEntries RPC, checking log consistency & appending them to the log.
+    fn handle_append_entries_payload(
+        &mut self, ctx: &mut Context<Self>, msg: AppendEntriesRequest<D>,
+    ) -> Box<dyn ActorFuture<Actor=Self, Item=AppendEntriesResponse, Error=()> + 'static> {
         // If RPC's `prev_log_index` is 0, or the RPC's previous log info matches the local
         // log info, then replication is g2g.
         let (term, msg_prev_index, msg_prev_term) = (self.current_term, msg.prev_log_index, msg.prev_log_term);
+        let (leader_commit, last_index) = (msg.leader_commit, msg.entries.last().map(|entry| entry.index).unwrap_or(msg_prev_index));
         let has_prev_log_match = &msg.prev_log_index == &u64::min_value() || (&msg_prev_index == &self.last_log_index && &msg_prev_term == &self.last_log_term);
         if has_prev_log_match {
             return Box::new(self.append_log_entries(ctx, Arc::new(msg.entries))
-                .map(move |_, _, _| {
+                .map(move |_, act, _| {
+                    act.update_follower_commit_index(leader_commit, last_index);
                     AppendEntriesResponse{term, success: true, conflict_opt: None}
                 }));
         }
@@ -129,15 +184,29 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
                 }
                 None => {
                     fut::Either::B(act.append_log_entries(ctx, Arc::new(msg.entries))
-                        .map(move |_, _, _| {
+                        .map(move |_, act, _| {
+                            act.update_follower_commit_index(leader_commit, last_index);
                             AppendEntriesResponse{term, success: true, conflict_opt: None}
                         }))
                 }
             }))
     }
-}
 
-impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> Raft<D, R, E, N, S> {
+    /// Advance the commit index based on the leader's commit index, & apply any newly committed entries.
+    ///
+    /// The commit index only advances through `matched`, the last index known to match the
+    /// leader's log, as any entries beyond it may yet conflict with the leader's log (§5.3). The
+    /// value for `self.commit_index` is only updated here when not the leader.
+    fn update_follower_commit_index(&mut self, leader_commit: u64, matched: u64) {
+        let commit_index = std::cmp::min(leader_commit, matched);
+        if commit_index > self.commit_index {
+            self.commit_index = commit_index;
+        }
+        if self.commit_index > self.last_applied {
+            self.queue_apply_logs_task(ApplyLogsTask::Outstanding);
+        }
+    }
+
     /// Append the given entries to the log.
     ///
     /// This routine also encapsulates all logic which must be performed related to appending log
@@ -161,15 +230,25 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
             return fut::Either::A(fut::err(()));
         }
 
-        // Check the given entries for any config changes and take the most recent.
-        let last_conf_change = entries.iter().filter_map(|ent| match &ent.payload {
-            EntryPayload::ConfigChange(conf) => Some(conf),
+        // If the given entries overwrite any config entries of the log, revert to the latest
+        // remaining config. Then check the given entries for any config changes and take the
+        // most recent.
+        let reverted_conf = match entries.first() {
+            Some(first) if first.index <= self.last_log_index => truncate_config_entries(&mut self.membership_history, first.index),
             _ => None,
-        }).last();
+        };
+        if reverted_conf.is_some() {
+            info!("Node {} is reverting its membership config, as uncommitted config entries are being truncated.", &self.id);
+        }
+        if let Some(first) = entries.first() {
+            self.pending_client_sessions.split_off(&first.index);
+        }
+        record_pending_client_sessions(&mut self.pending_client_sessions, entries.iter());
+        let last_conf_change = record_config_entries(&mut self.membership_history, entries.iter(), self.commit_index).or(reverted_conf);
         let f = match last_conf_change {
             Some(conf) => {
                 // Update membership info & apply hard state.
-                fut::Either::A(self.update_membership(ctx, conf.membership.clone()))
+                fut::Either::A(self.update_membership(ctx, conf))
             }
             None => fut::Either::B(fut::ok(())),
         };
@@ -194,63 +273,46 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
 
     /// Perform the AppendEntries RPC consistency check.
     ///
-    /// If the log entry at the specified index does not exist, the most recent entry in the log
-    /// will be used to build and return a `ConflictOpt` struct to be sent back to the leader.
+    /// If the log has no entry at the specified index, a `ConflictOpt` with a term of `0` and the
+    /// index just past the end of the log is returned, so that the leader resumes replication
+    /// from the end of this node's log.
     ///
-    /// If The log entry at the specified index does exist, but the terms to no match up, this
-    /// implementation will fetch the last 50 entries from the given index, and will use the
-    /// earliest entry from the log which is still in the given term to build a `ConflictOpt`
-    /// struct to be sent back to the leader.
+    /// If the log entry at the specified index does exist, but its term does not match, then the
+    /// conflicting term is returned along with the first index of the log in that term, so that
+    /// the leader may skip over every conflicting entry of that term in a single round trip
+    /// (§5.3). Only the entries after this node's commit index, and at most
+    /// `max_payload_entries` of them, are searched, as committed entries can never conflict.
     ///
     /// If everyhing checks out, a `None` value will be returned and log replication may continue.
     fn log_consistency_check(
         &mut self, _: &mut Context<Self>, index: u64, term: u64,
     ) -> impl ActorFuture<Actor=Self, Item=Option<ConflictOpt>, Error=()> {
-        let storage = self.storage.clone();
-        fut::wrap_future(self.storage.send::<GetLogEntries<D, E>>(GetLogEntries::new(index, index)))
+        // The target entry does not exist yet, so the leader should resume from the end of the log.
+        if index > self.last_log_index {
+            return fut::Either::A(fut::ok(Some(ConflictOpt{term: 0, index: self.last_log_index + 1})));
+        }
+
+        // Fetch the target entry, along with the entries before it which may be in the same term.
+        let floor = std::cmp::max(self.commit_index, self.last_applied) + 1;
+        let start = std::cmp::min(std::cmp::max(floor, (index + 1).saturating_sub(self.config.max_payload_entries)), index);
+        fut::Either::B(fut::wrap_future(self.storage.send::<GetLogEntries<D, E>>(GetLogEntries::new(start, index + 1)))
             .map_err(|err, act: &mut Self, ctx| act.map_fatal_actix_messaging_error(ctx, err, DependencyAddr::RaftStorage))
             .and_then(|res, act, ctx| act.map_fatal_storage_result(ctx, res))
-            .and_then(move |res, act, _| {
-                match res.last() {
-                    // The target entry was not found. This can only mean that we don't have the
-                    // specified index yet. Use the last known index & term.
-                    None => {
-                        fut::Either::A(fut::ok(Some(ConflictOpt{term: act.last_log_term, index: act.last_log_index})))
-                    }
-                    // The target entry was found. Compare its term with target term to ensure
-                    // everything is consistent.
-                    Some(target_entry) => {
-                        let (target_entry_index, target_entry_term) = (target_entry.index, target_entry.term);
-                        if &target_entry_index == &index && &target_entry_term == &term {
-                            // Everything checks out. We're g2g.
-                            fut::Either::A(fut::ok(None))
-                        } else {
-                            // Logs are inconsistent. Fetch the last 50 logs, and use the last
-                            // entry of that payload which is still in the target term for
-                            // conflict optimization.
-                            let start = if index >= 50 { index - 50 } else { 0 };
-                            fut::Either::B(fut::wrap_future(storage.send::<GetLogEntries<D, E>>(GetLogEntries::new(start, index)))
-                                .map_err(|err, act: &mut Self, ctx| act.map_fatal_actix_messaging_error(ctx, err, DependencyAddr::RaftStorage))
-                                .and_then(|res, act, ctx| act.map_fatal_storage_result(ctx, res))
-                                .and_then(move |res, act, _| {
-                                    match res.iter().find(|entry| entry.term == term) {
-                                        Some(entry) => {
-                                            fut::ok(Some(ConflictOpt{
-                                                term: entry.term,
-                                                index: entry.index,
-                                            }))
-                                        }
-                                        None => {
-                                            fut::ok(Some(ConflictOpt{
-                                                term: act.last_log_term,
-                                                index: act.last_log_index,
-                                            }))
-                                        }
-                                    }
-                                }))
-                        }
+            .map(move |entries, act, _| {
+                match entries.last() {
+                    // The target entry was found & its term matches. We're g2g.
+                    Some(target) if target.index == index && target.term == term => None,
+                    // The target entry was found, but its term conflicts. Find the first index of
+                    // the conflicting term among the fetched entries.
+                    Some(target) if target.index == index => {
+                        let first = entries.iter().rev()
+                            .take_while(|entry| entry.term == target.term)
+                            .last().map(|entry| entry.index).unwrap_or(index);
+                        Some(ConflictOpt{term: target.term, index: first})
                     }
+                    // The target entry could not be found, so resume from the end of the log.
+                    _ => Some(ConflictOpt{term: 0, index: act.last_log_index + 1}),
                 }
-            })
+            }))
     }
 }
@@ -1,39 +1,46 @@
 use std::sync::Arc;
 
 use actix::prelude::*;
-use futures::sync::oneshot;
+use futures::stream;
 use log::{error};
 
 use crate::{
     AppData, AppDataResponse, AppError,
-    common::{CLIENT_RPC_TX_ERR, ApplyLogsTask, DependencyAddr},
+    common::{CLIENT_RPC_TX_ERR, ApplyLogsTask, ClientTx, DependencyAddr},
     messages::{ClientPayloadResponse, ClientError, Entry},
     network::RaftNetwork,
     raft::Raft,
     storage::{ApplyEntryToStateMachine, ReplicateToStateMachine, GetLogEntries, RaftStorage},
 };
 
-impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> Raft<D, R, E, N, S> {
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Raft<D, R, E, N, S> {
+    /// Queue the given task in the pipeline for applying logs to the state machine.
+    pub(super) fn queue_apply_logs_task(&mut self, task: ApplyLogsTask<D, R, E>) {
+        if self.apply_logs_pipeline.unbounded_send(task).is_ok() {
+            self.apply_logs_pending += 1;
+        }
+    }
+
     /// Process tasks for applying logs to the state machine.
     ///
     /// **NOTE WELL:** these operations are strictly pipelined to ensure that these operations
     /// happen in strict order for guaranteed linearizability.
     pub(super) fn process_apply_logs_task(&mut self, ctx: &mut Context<Self>, msg: ApplyLogsTask<D, R, E>) -> impl ActorFuture<Actor=Self, Item=(), Error=()> {
         match msg {
-            ApplyLogsTask::Entry{entry, chan} => fut::Either::A(self.process_apply_logs_task_with_entries(ctx, entry, chan)),
+            ApplyLogsTask::Entries{entries, chan} => fut::Either::A(self.process_apply_logs_task_with_entries(ctx, entries, chan)),
             ApplyLogsTask::Outstanding => fut::Either::B(self.process_apply_logs_task_outstanding(ctx)),
         }
     }
 
     /// Apply the given payload of log entries to the state machine.
     fn process_apply_logs_task_with_entries(
-        &mut self, _: &mut Context<Self>, entry: Arc<Entry<D>>, chan: Option<oneshot::Sender<Result<ClientPayloadResponse<R>, ClientError<D, R, E>>>>,
+        &mut self, _: &mut Context<Self>, entries: Vec<Arc<Entry<D>>>, chan: Option<ClientTx<D, R, E>>,
     ) -> impl ActorFuture<Actor=Self, Item=(), Error=()> {
         // PREVIOUS TERM UNCOMMITTED LOGS CHECK:
         // Here we are checking to see if there are any logs from the previous term which were
         // outstanding, but which are now safe to apply due to being covered by a new definitive
         // commit index from this term.
-        let entry_index = entry.index;
+        let entry_index = entries.first().map(|entry| entry.index).unwrap_or(self.last_applied + 1);
         let f = if (self.last_applied + 1) != entry_index {
             fut::Either::A(fut::wrap_future(self.storage.send::<GetLogEntries<D, E>>(GetLogEntries::new(self.last_applied + 1, entry_index)))
                 .map_err(|err, act: &mut Self, ctx| {
@@ -52,7 +59,7 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
                         .and_then(move |_, act, _| {
                             // Update state after a success operation on the state machine.
                             if let Some(index) = line_index {
-                                act.last_applied = index;
+                                act.update_last_applied(index);
                             }
                             fut::ok(())
                         })
@@ -63,31 +70,38 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
         };
 
         // Resume with the normal flow of logic. Here we are simply taking the payload of entries
-        // to be applied to the state machine, applying and then responding as needed.
-        let line_index = entry.index;
-        f.and_then(move |_, act, _| {
-            fut::wrap_future(act.storage.send::<ApplyEntryToStateMachine<D, R, E>>(ApplyEntryToStateMachine::new(entry)))
-                .map_err(|err, act: &mut Self, ctx| {
-                    act.map_fatal_actix_messaging_error(ctx, err, DependencyAddr::RaftStorage);
-                    ClientError::Internal
+        // to be applied to the state machine, applying each in order and then responding as needed.
+        f.and_then(move |_, _, _| {
+            fut::wrap_stream::<_, Self>(stream::iter_ok(entries))
+                .fold(Vec::new(), |mut responses, entry, act, _| {
+                    let (line_index, session) = (entry.index, entry.session().cloned());
+                    fut::wrap_future(act.storage.send::<ApplyEntryToStateMachine<D, R, E>>(ApplyEntryToStateMachine::new(entry)))
+                        .map_err(|err, act: &mut Self, ctx| {
+                            act.map_fatal_actix_messaging_error(ctx, err, DependencyAddr::RaftStorage);
+                            ClientError::Internal
+                        })
+                        .and_then(|res, act, ctx| act.map_fatal_storage_result(ctx, res)
+                            .map_err(|_, _, _| ClientError::Internal))
+                        .map(move |data, act, _| {
+                            // Update state after a success operation on the state machine.
+                            act.cache_client_session_response(session, line_index, &data);
+                            act.update_last_applied(line_index);
+                            responses.push(ClientPayloadResponse::Applied{index: line_index, data});
+                            responses
+                        })
                 })
-                .and_then(|res, act, ctx| act.map_fatal_storage_result(ctx, res)
-                    .map_err(|_, _, _| ClientError::Internal))
         })
 
-            .then(move |res, act, _| match res {
-                Ok(data) => {
-                    // Update state after a success operation on the state machine.
-                    act.last_applied = line_index;
-
+            .then(move |res, _, _| match res {
+                Ok(responses) => {
                     if let Some(tx) = chan {
-                        let _ = tx.send(Ok(ClientPayloadResponse::Applied{index: line_index, data})).map_err(|err| error!("{} {:?}", CLIENT_RPC_TX_ERR, err));
+                        let _ = tx.send(Ok(responses)).map_err(|_| error!("{}", CLIENT_RPC_TX_ERR));
                     }
                     fut::ok(())
                 }
                 Err(err) => {
                     if let Some(tx) = chan {
-                        let _ = tx.send(Err(err)).map_err(|err| error!("{} {:?}", CLIENT_RPC_TX_ERR, err));
+                        let _ = tx.send(Err(err)).map_err(|_| error!("{}", CLIENT_RPC_TX_ERR));
                     }
                     fut::err(())
                 }
@@ -121,7 +135,7 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
             // Update self to reflect progress on applying logs to the state machine.
             .and_then(move |line_index, act, _| {
                 if let Some(index) = line_index {
-                    act.last_applied = index;
+                    act.update_last_applied(index);
                 }
                 fut::ok(())
             }))
@@ -1,40 +1,30 @@
+use std::{sync::Arc, time::Instant};
+
 use actix::prelude::*;
+use futures::{future, stream, sync::{mpsc, oneshot}, Async, Stream};
 use log::{error};
-use futures::sync::oneshot;
 
 use crate::{
-    AppData, AppDataResponse, AppError,
-    common::{CLIENT_RPC_RX_ERR, CLIENT_RPC_TX_ERR, ApplyLogsTask, ClientPayloadWithChan, DependencyAddr},
+    AppData, AppDataResponse, AppError, NodeId,
+    common::{CLIENT_RPC_RX_ERR, CLIENT_RPC_TX_ERR, ApplyLogsTask, ClientPayloadWithChan, ClientPayloadWithIndex, DependencyAddr, ForwardedClientPayload},
     network::RaftNetwork,
-    messages::{ClientError, ClientPayload, ClientPayloadResponse, ResponseMode},
-    raft::{RaftState, Raft},
+    messages::{ClientBatchError, ClientError, ClientPayload, ClientPayloadBatch, ClientPayloadResponse, Entry, ForwardClientPayload, ResponseMode},
+    raft::{RaftState, Raft, record_config_entries},
     replication::RSReplicate,
-    storage::{AppendEntryToLog, RaftStorage},
+    storage::{AppendEntriesToLog, AppendEntryToLog, RaftStorage},
 };
 
-impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> Handler<ClientPayload<D, R, E>> for Raft<D, R, E, N, S> {
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Handler<ClientPayload<D, R, E>> for Raft<D, R, E, N, S> {
     type Result = ResponseActFuture<Self, ClientPayloadResponse<R>, ClientError<D, R, E>>;
 
     /// Handle client requests.
-    fn handle(&mut self, msg: ClientPayload<D, R, E>, _: &mut Self::Context) -> Self::Result {
-        // Queue the message for processing or forward it along to the leader.
-        let response_chan = match &mut self.state {
-            RaftState::Leader(state) => {
-                // Wrap the given message for async processing.
-                let (tx, rx) = oneshot::channel();
-                let with_chan = ClientPayloadWithChan{tx, rpc: msg};
-                let _ = state.client_request_queue.unbounded_send(with_chan).map_err(|_| {
-                    error!("Unexpected error while queueing client request for processing.")
-                });
-                rx
-            },
-            _ => {
-                return Box::new(fut::err(ClientError::ForwardToLeader{payload: msg, leader: self.current_leader}));
-            },
-        };
+    fn handle(&mut self, msg: ClientPayload<D, R, E>, ctx: &mut Self::Context) -> Self::Result {
+        // Wrap the given message for async processing.
+        let (tx, rx) = oneshot::channel();
+        self.queue_client_payload(ctx, ClientPayloadWithChan::new_single(tx, msg));
 
         // Build a response from the message's channel.
-        Box::new(fut::wrap_future(response_chan)
+        Box::new(fut::wrap_future(rx)
             .map_err(|_, _: &mut Self, _| {
                 error!("{}", CLIENT_RPC_RX_ERR);
                 ClientError::Internal
@@ -43,99 +33,478 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
     }
 }
 
-impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> Raft<D, R, E, N, S> {
-    /// Process the given client RPC, appending it to the log and committing it to the cluster.
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Handler<ClientPayloadBatch<D, R, E>> for Raft<D, R, E, N, S> {
+    type Result = ResponseActFuture<Self, Vec<ClientPayloadResponse<R>>, ClientBatchError<D, R, E>>;
+
+    /// Handle client batch requests.
+    fn handle(&mut self, msg: ClientPayloadBatch<D, R, E>, ctx: &mut Self::Context) -> Self::Result {
+        // An empty batch has nothing to append.
+        if msg.entries.is_empty() {
+            return Box::new(fut::ok(vec![]));
+        }
+
+        // Wrap the given message for async processing.
+        let (tx, rx) = oneshot::channel();
+        self.queue_client_payload(ctx, ClientPayloadWithChan::new_batch(tx, msg));
+
+        // Build a response from the message's channel.
+        Box::new(fut::wrap_future(rx)
+            .map_err(|_, _: &mut Self, _| {
+                error!("{}", CLIENT_RPC_RX_ERR);
+                ClientBatchError::Internal
+            })
+            .and_then(|res, _, _| fut::result(res)))
+    }
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Handler<ForwardClientPayload<D, R, E>> for Raft<D, R, E, N, S> {
+    type Result = ResponseActFuture<Self, ClientPayloadResponse<R>, ClientError<D, R, E>>;
+
+    /// Handle client requests forwarded by a follower.
     ///
-    /// This function takes the given RPC, appends its entries to the log, sends the entries out
-    /// to the replication streams to be replicated to the cluster followers, after half of the
-    /// cluster members have successfully replicated the entries this routine will proceed with
-    /// applying the entries to the state machine. Then the next RPC is processed.
-    pub(super) fn process_client_rpc(&mut self, _: &mut Context<Self>, msg: ClientPayloadWithChan<D, R, E>) -> impl ActorFuture<Actor=Self, Item=(), Error=()> {
+    /// Forwarded payloads are never forwarded a second time. If this node is not the leader, the
+    /// payload is returned with a forwarding error, for the follower to relay to its client.
+    fn handle(&mut self, msg: ForwardClientPayload<D, R, E>, ctx: &mut Self::Context) -> Self::Result {
+        if !self.state.is_leader() {
+            return Box::new(fut::err(ClientError::ForwardToLeader{payload: msg.payload, leader: self.current_leader}));
+        }
+        Handler::<ClientPayload<D, R, E>>::handle(self, msg.payload, ctx)
+    }
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Raft<D, R, E, N, S> {
+    /// Queue the given client payload for processing or forward it along to the leader.
+    fn queue_client_payload(&mut self, ctx: &mut Context<Self>, msg: ClientPayloadWithChan<D, R, E>) {
+        match &mut self.state {
+            // New payloads are not accepted while leadership is being transferred to another node.
+            RaftState::Leader(state) if state.leadership_transfer.is_some() => {
+                let _ = msg.forward_to_leader(None)
+                    .map_err(|_| error!("{} Error while forwarding to leader during a leadership transfer.", CLIENT_RPC_RX_ERR));
+            }
+            // Payloads generated by Raft itself, such as config changes, must never be rejected, as
+            // the leader's state may already reflect them. A new sender always has room for one
+            // message, so the payload is queued even when the queue is at capacity.
+            RaftState::Leader(state) if msg.is_internal => {
+                if state.client_request_queue.clone().try_send(msg).is_err() {
+                    error!("Unexpected error while queueing internal payload for processing.");
+                }
+            }
+            // New payloads are rejected while the leader's backlog of requests is at capacity.
+            RaftState::Leader(state) if state.awaiting_committed.len() as u64 + self.apply_logs_pending >= self.config.client_queue_capacity => {
+                let _ = msg.tx.send(Err(ClientError::Overloaded)).map_err(|_| error!("{}", CLIENT_RPC_RX_ERR));
+            }
+            RaftState::Leader(state) => match state.client_request_queue.try_send(msg) {
+                Ok(_) => (),
+                // The queue is full, so the payload is rejected.
+                Err(err) if err.is_full() => {
+                    let _ = err.into_inner().tx.send(Err(ClientError::Overloaded)).map_err(|_| error!("{}", CLIENT_RPC_RX_ERR));
+                }
+                Err(_) => error!("Unexpected error while queueing client request for processing."),
+            },
+            _ => self.forward_client_payload(ctx, msg),
+        }
+    }
+
+    /// Forward the given client payload to the leader, or respond with a forwarding error.
+    ///
+    /// If `forward_client_payloads` is configured, single payloads are sent to the leader over the
+    /// `RaftNetwork`. When the leader is not known, the payload waits for one to be elected, up
+    /// to the configured amount of time. Otherwise, the payload is returned to the client.
+    fn forward_client_payload(&mut self, ctx: &mut Context<Self>, msg: ClientPayloadWithChan<D, R, E>) {
+        let forwarded = match self.config.forward_client_payloads {
+            Some(wait) => msg.into_forwarded(Instant::now() + wait),
+            None => Err(msg),
+        };
+        let forwarded = match forwarded {
+            Ok(forwarded) => forwarded,
+            Err(msg) => {
+                let _ = msg.forward_to_leader(self.current_leader)
+                    .map_err(|_| error!("{} Error while forwarding to leader.", CLIENT_RPC_RX_ERR));
+                return;
+            }
+        };
+
+        match self.current_leader {
+            Some(leader) if leader != self.id => self.send_forwarded_payload(ctx, leader, forwarded),
+            _ => {
+                let wait = forwarded.deadline.saturating_duration_since(Instant::now());
+                ctx.run_later(wait, |act, _| act.expire_awaiting_leader());
+                self.awaiting_leader.push(forwarded);
+            }
+        }
+    }
+
+    /// Send the given client payload to the leader, and relay the leader's response to the client.
+    fn send_forwarded_payload(&mut self, ctx: &mut Context<Self>, leader: NodeId, forwarded: ForwardedClientPayload<D, R, E>) {
+        let ForwardedClientPayload{tx, payload, ..} = forwarded;
+        let rpc = ForwardClientPayload{target: leader, follower_id: self.id, payload};
+        ctx.spawn(fut::wrap_future(self.network.send(rpc))
+            .map_err(|err, act: &mut Self, ctx| act.map_fatal_actix_messaging_error(ctx, err, DependencyAddr::RaftNetwork))
+            .then(move |res, _, _| {
+                let res = match res {
+                    Ok(res) => res,
+                    Err(_) => Err(ClientError::Internal),
+                };
+                let _ = tx.send(res).map_err(|_| error!("{} Error while relaying the response of the leader.", CLIENT_RPC_RX_ERR));
+                fut::ok(())
+            }));
+    }
+
+    /// Forward any client payloads which have been waiting for a leader, now that one is known.
+    pub(super) fn forward_awaiting_leader(&mut self, ctx: &mut Context<Self>) {
+        let leader = match self.current_leader {
+            Some(leader) if !self.awaiting_leader.is_empty() => leader,
+            _ => return,
+        };
+        let awaiting: Vec<_> = self.awaiting_leader.drain(..).collect();
+        for forwarded in awaiting {
+            // If this node is the new leader, the payload is processed locally.
+            if leader == self.id {
+                let ForwardedClientPayload{tx, payload, ..} = forwarded;
+                self.queue_client_payload(ctx, ClientPayloadWithChan::new_single(tx, payload));
+            } else {
+                self.send_forwarded_payload(ctx, leader, forwarded);
+            }
+        }
+    }
+
+    /// Return any client payloads which have waited too long for a leader to their clients.
+    fn expire_awaiting_leader(&mut self) {
+        let now = Instant::now();
+        let (expired, awaiting): (Vec<_>, Vec<_>) = self.awaiting_leader.drain(..).partition(|forwarded| forwarded.deadline <= now);
+        self.awaiting_leader = awaiting;
+        for forwarded in expired {
+            let _ = forwarded.forward_to_leader(self.current_leader)
+                .map_err(|_| error!("{} Error while forwarding to leader.", CLIENT_RPC_RX_ERR));
+        }
+    }
+
+    /// Process the given group of client RPCs, appending them to the log and committing them to the cluster.
+    ///
+    /// This function takes the given RPCs, appends their entries to the log, sends the entries
+    /// out to the replication streams to be replicated to the cluster followers, after half of
+    /// the cluster members have successfully replicated the entries this routine will proceed
+    /// with applying the entries to the state machine. Then the next group is processed.
+    ///
+    /// Every client RPC which was waiting in the queue is processed as part of the same group, so
+    /// that the entries of the entire group are appended to the log with a single storage call,
+    /// and replicated as one unit. This allows the storage engine to flush once per group.
+    pub(super) fn process_client_rpc(&mut self, _: &mut Context<Self>, msgs: Vec<ClientPayloadWithChan<D, R, E>>) -> impl ActorFuture<Actor=Self, Item=(), Error=()> {
         match &self.state {
+            // If leadership is being transferred, the payloads must go to the next leader.
+            RaftState::Leader(state) if state.leadership_transfer.is_some() => {
+                for msg in msgs {
+                    let _ = msg.forward_to_leader(None)
+                        .map_err(|_| error!("{} Error while forwarding to leader during a leadership transfer.", CLIENT_RPC_TX_ERR));
+                }
+                return fut::Either::A(fut::ok(()));
+            }
             // If node is still leader, continue.
             RaftState::Leader(_) => (),
-            // If node is in any other state, then forward the message to the leader.
+            // If node is in any other state, then forward the messages to the leader.
             _ => {
-                let _ = msg.tx.send(Err(ClientError::ForwardToLeader{payload: msg.rpc, leader: self.current_leader}))
-                    .map_err(|_| error!("{} Error while forwarding to leader at the start of process_client_rpc.", CLIENT_RPC_TX_ERR));
+                for msg in msgs {
+                    let _ = msg.forward_to_leader(self.current_leader)
+                        .map_err(|_| error!("{} Error while forwarding to leader at the start of process_client_rpc.", CLIENT_RPC_TX_ERR));
+                }
                 return fut::Either::A(fut::ok(()));
             }
         };
 
-        // Assign an index to the payload and prep it for storage & replication.
-        let payload = msg.upgrade(self.last_log_index + 1, self.current_term);
+        // If configured, replicate the entries while they are being appended to the log.
+        if self.config.parallel_append {
+            return fut::Either::B(fut::Either::A(self.process_client_rpc_in_parallel(msgs)));
+        }
 
-        // Send the payload over to the storage engine.
+        // Send the payloads over to the storage engine.
         self.is_appending_logs = true; // NOTE: this routine is pipelined, but we still use a semaphore in case of transition to follower.
-        fut::Either::B(fut::wrap_future(self.storage.send::<AppendEntryToLog<D, E>>(AppendEntryToLog::new(payload.entry())))
-            .map_err(|err, act: &mut Self, ctx| {
-                act.map_fatal_actix_messaging_error(ctx, err, DependencyAddr::RaftStorage);
-                ClientError::Internal
-            })
-            .and_then(|res, _, _| fut::result(res.map_err(|err| ClientError::Application(err))))
-
-            // Handle results from storage engine.
-            .then(move |res, act, _| {
+        let appended = if msgs.len() == 1 {
+            fut::Either::A(self.append_client_payloads_individually(msgs))
+        } else {
+            fut::Either::B(self.append_client_payload_group(msgs))
+        };
+        fut::Either::B(fut::Either::B(appended
+            .then(|res, act: &mut Self, _| {
                 act.is_appending_logs = false;
-                match res {
-                    Ok(_) => {
-                        act.last_log_index = payload.index;
-                        act.last_log_term = act.current_term;
-                        fut::result(Ok(payload))
-                    }
-                    Err(err) => {
-                        error!("Node {} received an error from the storage engine.", &act.id);
-                        let _ = payload.tx.send(Err(err)).map_err(|err| error!("{} {:?}", CLIENT_RPC_RX_ERR, err));
-                        fut::err(())
-                    }
-                }
+                fut::result(res)
             })
 
             // Send logs over for replication.
-            .and_then(move |payload, act, _| {
+            .and_then(move |payloads, act, _| {
+                if payloads.is_empty() {
+                    return fut::ok(());
+                }
                 let state = match &mut act.state {
                     RaftState::Leader(state) => state,
+                    // The payloads were appended before this node stepped down, so they may yet be
+                    // committed by the next leader.
                     _ => {
-                        let msg = payload.downgrade();
-                        let _ = msg.tx.send(Err(ClientError::ForwardToLeader{payload: msg.rpc, leader: act.current_leader}))
-                            .map_err(|_| error!("{} Error while forwarding to leader at the end of process_client_rpc.", CLIENT_RPC_RX_ERR));
+                        for payload in payloads {
+                            let _ = payload.respond_indeterminate()
+                                .map_err(|_| error!("{} Error while responding to a request with an indeterminate outcome at the end of process_client_rpc.", CLIENT_RPC_RX_ERR));
+                        }
                         return fut::ok(());
                     }
                 };
 
                 // If there are peer voting members to replicate to, then setup the
-                // request to await being comitted to the cluster & send payload over to
+                // requests to await being comitted to the cluster & send the entries over to
                 // each replication stream as needed.
                 let nodeid = &act.id;
                 let voting_peer_count = act.membership.members.iter().filter(|e| *e != nodeid).count();
+                let entries: Vec<_> = payloads.iter().flat_map(|payload| payload.entries()).collect();
                 if voting_peer_count > 0 {
-                    let entry = payload.entry();
-                    state.awaiting_committed.push(payload);
+                    state.awaiting_committed.extend(payloads);
                     for rs in state.nodes.values() {
-                        let _ = rs.addr.do_send(RSReplicate{entry: entry.clone(), line_commit: act.commit_index});
+                        let _ = rs.addr.do_send(RSReplicate{entries: entries.clone(), line_commit: act.commit_index});
                     }
                 } else {
                     // If there are any non-voting members, replicate to them.
-                    if act.membership.non_voters.len() > 0 {
-                        let entry = payload.entry();
+                    if act.membership.non_voters.len() > 0 || !act.membership.learners.is_empty() {
                         for rs in state.nodes.values() {
-                            let _ = rs.addr.do_send(RSReplicate{entry: entry.clone(), line_commit: act.commit_index});
+                            let _ = rs.addr.do_send(RSReplicate{entries: entries.clone(), line_commit: act.commit_index});
                         }
                     }
 
-                    // The payload is committed. Send it over to be applied to state machine.
-                    act.commit_index = payload.index;
-                    if let &ResponseMode::Committed = &payload.response_mode {
-                        // If this RPC is configured to wait only for log committed, then respond to client now.
-                        let entry = payload.entry();
-                        let _ = payload.tx.send(Ok(ClientPayloadResponse::Committed{index: payload.index})).map_err(|err| error!("{} {:?}", CLIENT_RPC_RX_ERR, err));
-                        let _ = act.apply_logs_pipeline.unbounded_send(ApplyLogsTask::Entry{entry, chan: None});
-                    } else {
-                        // Else, send it through the pipeline and it will be responded to afterwords.
-                        let _ = act.apply_logs_pipeline.unbounded_send(ApplyLogsTask::Entry{entry: payload.entry(), chan: Some(payload.tx)});
+                    // The payloads are committed. Send them over to be applied to state machine.
+                    act.commit_index = act.last_log_index;
+                    for payload in payloads {
+                        act.apply_committed_client_payload(payload);
                     }
                 }
                 fut::ok(())
+            })))
+    }
+
+    /// Process the given group of client RPCs, replicating their entries while they are being appended to the log.
+    ///
+    /// This allows the latency of the leader's disk write to overlap with the latency of the
+    /// network, per §10.2.1 of the Raft thesis. The entries are sent over to the storage engine
+    /// first, so that any replication stream which later fetches entries from storage will
+    /// observe them, and then they are sent out to the replication streams right away. The
+    /// leader's own log will only count toward commitment once the append is durable.
+    ///
+    /// As the entries have already been replicated, any error from the storage engine is fatal.
+    fn process_client_rpc_in_parallel(&mut self, msgs: Vec<ClientPayloadWithChan<D, R, E>>) -> impl ActorFuture<Actor=Self, Item=(), Error=()> {
+        // Assign contiguous indices to each payload of the group, skipping retried requests.
+        let (term, mut index) = (self.current_term, self.last_log_index + 1);
+        let mut payloads = vec![];
+        for msg in msgs {
+            if let Some(payload) = self.upgrade_client_payload(msg, index, term) {
+                index = payload.index + 1;
+                payloads.push(payload);
+            }
+        }
+        if payloads.is_empty() {
+            return fut::Either::A(fut::ok(()));
+        }
+        let entries: Vec<_> = payloads.iter().flat_map(|payload| payload.entries()).collect();
+        let last_index = index - 1;
+
+        // Send the entries over to the storage engine.
+        let mut appended_entries = entries.clone();
+        let append = if appended_entries.len() == 1 {
+            future::Either::A(self.storage.send::<AppendEntryToLog<D, E>>(AppendEntryToLog::new(appended_entries.remove(0))))
+        } else {
+            future::Either::B(self.storage.send::<AppendEntriesToLog<D, E>>(AppendEntriesToLog::new(appended_entries)))
+        };
+        self.is_appending_logs = true;
+        self.last_log_index = last_index;
+        self.last_log_term = term;
+
+        // Send the entries over for replication while the append is in flight. If there are peer
+        // voting members, the payloads will await being committed to the cluster, else they are
+        // committed as soon as the append is durable.
+        let nodeid = &self.id;
+        let voting_peer_count = self.membership.members.iter().filter(|e| *e != nodeid).count();
+        let mut awaiting_durable = vec![];
+        if let RaftState::Leader(state) = &mut self.state {
+            for rs in state.nodes.values() {
+                rs.addr.do_send(RSReplicate{entries: entries.clone(), line_commit: self.commit_index});
+            }
+            if voting_peer_count > 0 {
+                state.awaiting_committed.extend(payloads);
+            } else {
+                awaiting_durable = payloads;
+            }
+        }
+
+        fut::Either::B(fut::wrap_future(append)
+            .map_err(|err, act: &mut Self, ctx| act.map_fatal_actix_messaging_error(ctx, err, DependencyAddr::RaftStorage))
+            .and_then(|res, act, ctx| act.map_fatal_storage_result(ctx, res))
+            .then(move |res, act, ctx| {
+                act.is_appending_logs = false;
+                if res.is_ok() {
+                    act.update_durable_index(last_index);
+                    act.record_appended_config_entries(ctx, &entries);
+                    if !awaiting_durable.is_empty() {
+                        act.commit_index = last_index;
+                        for payload in awaiting_durable {
+                            act.apply_committed_client_payload(payload);
+                        }
+                    }
+                    act.update_commit_index();
+                }
+                fut::result(res)
+            }))
+    }
+
+    /// Record that the entries of this leader's log are durable through the given index.
+    fn update_durable_index(&mut self, index: u64) {
+        if let RaftState::Leader(state) = &mut self.state {
+            state.durable_index = index;
+        }
+    }
+
+    /// Record any config entries among the given entries, which have been appended to the log.
+    ///
+    /// The hard state is saved if any were found, so that the config entries may be reverted even
+    /// after a restart, should they be truncated by a new leader. The replication streams are
+    /// updated with the witness status of their targets per the new config.
+    fn record_appended_config_entries(&mut self, ctx: &mut Context<Self>, entries: &[Arc<Entry<D>>]) {
+        if record_config_entries(&mut self.membership_history, entries.iter().map(|entry| &**entry), self.commit_index).is_some() {
+            self.update_witness_streams();
+            let f = self.save_hard_state_async(ctx);
+            ctx.wait(f);
+        }
+    }
+
+    /// Append the entries of the given group of client payloads to the log with a single storage call.
+    ///
+    /// The payloads are assigned contiguous indices. As `AppendEntriesToLog` is atomic, if the
+    /// storage engine rejects the group with an application error, then nothing was written, and
+    /// the payloads are appended one at a time so that each error reaches the client it belongs to.
+    fn append_client_payload_group(&mut self, msgs: Vec<ClientPayloadWithChan<D, R, E>>) -> impl ActorFuture<Actor=Self, Item=Vec<ClientPayloadWithIndex<D, R, E>>, Error=()> {
+        // Assign contiguous indices to each payload of the group, skipping retried requests.
+        let (term, mut index) = (self.current_term, self.last_log_index + 1);
+        let mut payloads = vec![];
+        for msg in msgs {
+            if let Some(payload) = self.upgrade_client_payload(msg, index, term) {
+                index = payload.index + 1;
+                payloads.push(payload);
+            }
+        }
+        if payloads.is_empty() {
+            return fut::Either::A(fut::ok(vec![]));
+        }
+        let entries: Vec<_> = payloads.iter().flat_map(|payload| payload.entries()).collect();
+
+        fut::Either::B(fut::wrap_future(self.storage.send::<AppendEntriesToLog<D, E>>(AppendEntriesToLog::new(entries)))
+            .then(move |res, act: &mut Self, ctx| match res {
+                Ok(Ok(_)) => {
+                    act.last_log_index = index - 1;
+                    act.last_log_term = term;
+                    act.update_durable_index(index - 1);
+                    let entries: Vec<_> = payloads.iter().flat_map(|payload| payload.entries()).collect();
+                    act.record_appended_config_entries(ctx, &entries);
+                    fut::Either::A(fut::ok(payloads))
+                }
+                Ok(Err(_)) => {
+                    let msgs = payloads.into_iter().map(|payload| payload.downgrade()).collect();
+                    fut::Either::B(act.append_client_payloads_individually(msgs))
+                }
+                Err(err) => {
+                    act.map_fatal_actix_messaging_error(ctx, err, DependencyAddr::RaftStorage);
+                    for payload in payloads {
+                        let _ = payload.tx.send(Err(ClientError::Internal)).map_err(|_| error!("{}", CLIENT_RPC_RX_ERR));
+                    }
+                    fut::Either::A(fut::ok(vec![]))
+                }
+            }))
+    }
+
+    /// Append the entries of each of the given client payloads to the log, one payload at a time.
+    ///
+    /// Payloads rejected by the storage engine are responded to with the error, and the payloads
+    /// which were appended are returned. Indices are assigned as each payload is appended, so
+    /// rejected payloads do not leave gaps in the log.
+    fn append_client_payloads_individually(&mut self, msgs: Vec<ClientPayloadWithChan<D, R, E>>) -> impl ActorFuture<Actor=Self, Item=Vec<ClientPayloadWithIndex<D, R, E>>, Error=()> {
+        fut::wrap_stream::<_, Self>(stream::iter_ok(msgs))
+            .fold(vec![], |mut appended, msg, act, _| {
+                act.append_client_payload(msg).map(move |res, _, _| {
+                    appended.extend(res);
+                    appended
+                })
+            })
+    }
+
+    /// Append the entries of the given client payload to the log.
+    ///
+    /// If the storage engine rejects the payload, the client is responded to with the error.
+    fn append_client_payload(&mut self, msg: ClientPayloadWithChan<D, R, E>) -> impl ActorFuture<Actor=Self, Item=Option<ClientPayloadWithIndex<D, R, E>>, Error=()> {
+        // Assign indices to the payload and prep it for storage & replication, unless it is a retried request.
+        let payload = match self.upgrade_client_payload(msg, self.last_log_index + 1, self.current_term) {
+            Some(payload) => payload,
+            None => return fut::Either::A(fut::ok(None)),
+        };
+
+        // Send the payload over to the storage engine. Batches are appended in a single call.
+        let mut entries = payload.entries();
+        let append = if entries.len() == 1 {
+            future::Either::A(self.storage.send::<AppendEntryToLog<D, E>>(AppendEntryToLog::new(entries.remove(0))))
+        } else {
+            future::Either::B(self.storage.send::<AppendEntriesToLog<D, E>>(AppendEntriesToLog::new(entries)))
+        };
+        fut::Either::B(fut::wrap_future(append)
+            .then(move |res, act: &mut Self, ctx| {
+                let err = match res {
+                    Ok(Ok(_)) => {
+                        act.last_log_index = payload.index;
+                        act.last_log_term = payload.term;
+                        act.update_durable_index(payload.index);
+                        act.record_appended_config_entries(ctx, &payload.entries());
+                        return fut::ok(Some(payload));
+                    }
+                    Ok(Err(err)) => {
+                        error!("Node {} received an error from the storage engine.", &act.id);
+                        ClientError::Application(err)
+                    }
+                    Err(err) => {
+                        act.map_fatal_actix_messaging_error(ctx, err, DependencyAddr::RaftStorage);
+                        ClientError::Internal
+                    }
+                };
+                let _ = payload.tx.send(Err(err)).map_err(|_| error!("{}", CLIENT_RPC_RX_ERR));
+                fut::ok(None)
             }))
     }
+
+    /// Send the given committed client payload over to be applied to the state machine.
+    ///
+    /// If the payload is configured to wait only for its entries to be committed, the client is
+    /// responded to now, else it will be responded to once its entries have been applied.
+    pub(super) fn apply_committed_client_payload(&mut self, payload: ClientPayloadWithIndex<D, R, E>) {
+        if let ResponseMode::Committed = &payload.response_mode {
+            let entries = payload.entries();
+            let responses = payload.committed_responses();
+            let _ = payload.tx.send(Ok(responses)).map_err(|_| error!("{}", CLIENT_RPC_TX_ERR));
+            self.queue_apply_logs_task(ApplyLogsTask::Entries{entries, chan: None});
+        } else {
+            self.queue_apply_logs_task(ApplyLogsTask::Entries{entries: payload.entries(), chan: Some(payload.tx)});
+        }
+    }
+}
+
+/// Build a stream of groups of client payloads from the given client request queue.
+///
+/// Each group holds every payload which was waiting in the queue when the group was polled, up
+/// to the given maximum number of entries, and always at least one payload.
+pub(super) fn client_payload_groups<D: AppData, R: AppDataResponse, E: AppError>(
+    mut rx: mpsc::Receiver<ClientPayloadWithChan<D, R, E>>, max_entries: u64,
+) -> impl Stream<Item=Vec<ClientPayloadWithChan<D, R, E>>, Error=()> {
+    stream::poll_fn(move || {
+        let (mut group, mut entries) = (vec![], 0u64);
+        while group.is_empty() || entries < max_entries {
+            match rx.poll()? {
+                Async::Ready(Some(msg)) => {
+                    entries += msg.entries.len() as u64;
+                    group.push(msg);
+                }
+                Async::Ready(None) if group.is_empty() => return Ok(Async::Ready(None)),
+                Async::NotReady if group.is_empty() => return Ok(Async::NotReady),
+                Async::Ready(None) | Async::NotReady => break,
+            }
+        }
+        Ok(Async::Ready(Some(group)))
+    })
 }
@@ -0,0 +1,152 @@
+use std::{
+    collections::BTreeSet,
+    time::{Duration, Instant},
+};
+
+use actix::prelude::*;
+use futures::{stream, sync::oneshot, Future, Stream};
+use log::{error};
+use tokio_timer::Timeout;
+
+use crate::{
+    AppData, AppDataResponse, AppError, NodeId,
+    common::{CLIENT_RPC_RX_ERR, ClientReadWithIndex},
+    network::RaftNetwork,
+    messages::{ClientReadError, ClientReadRequest, ClientReadResponse},
+    raft::{RaftState, Raft},
+    replication::RSConfirmLeadership,
+    storage::RaftStorage,
+};
+
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Handler<ClientReadRequest> for Raft<D, R, E, N, S> {
+    type Result = ResponseActFuture<Self, ClientReadResponse, ClientReadError>;
+
+    /// Handle client read requests using the ReadIndex protocol (§8).
+    ///
+    /// The leader records its current commit index as the read index, confirms that it is still
+    /// the leader of the cluster by exchanging a round of heartbeats with a majority of the
+    /// cluster, and then waits for its state machine to advance to the read index. Only after
+    /// all of that is done will the response be returned, at which point the application may
+    /// serve the read from its state machine.
+    ///
+    /// If lease reads are enabled and the leader holds a valid lease, the round of heartbeats is
+    /// skipped and the read is served as soon as the state machine has reached the read index.
+    fn handle(&mut self, _: ClientReadRequest, ctx: &mut Self::Context) -> Self::Result {
+        // A read index may only be established by the leader. The leader may not know which
+        // entries from previous terms are committed until it has committed an entry of its own
+        // term, so the read index is never less than the index of that first entry.
+        let read_index = match &self.state {
+            RaftState::Leader(state) => std::cmp::max(self.commit_index, state.term_start_index),
+            _ => return Box::new(fut::err(ClientReadError::ForwardToLeader{leader: self.current_leader})),
+        };
+
+        let confirmed = if self.config.lease_reads && self.has_valid_lease() {
+            fut::Either::A(fut::ok(()))
+        } else {
+            fut::Either::B(self.confirm_leadership(ctx))
+        };
+
+        Box::new(confirmed
+            .then(move |res, act: &mut Self, _| match res {
+                Ok(_) => fut::ok(act.await_applied(read_index)),
+                Err(_) => fut::err(ClientReadError::ForwardToLeader{leader: act.current_leader}),
+            })
+            .and_then(|rx, _, _| fut::wrap_future(rx)
+                .map_err(|_, _, _| {
+                    error!("{}", CLIENT_RPC_RX_ERR);
+                    ClientReadError::Internal
+                })
+                .and_then(|res, _, _| fut::result(res))))
+    }
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Raft<D, R, E, N, S> {
+    /// Confirm that this node is still the leader of the cluster.
+    ///
+    /// A heartbeat is sent to each voting member of the cluster, and this routine will resolve
+    /// successfully once a majority of the cluster has acknowledged this node's leadership. In
+    /// joint consensus, separate majorities of the old & the new config are required. If a
+    /// majority does not respond within an election timeout, this routine will resolve with an
+    /// error.
+    fn confirm_leadership(&mut self, _: &mut Context<Self>) -> impl ActorFuture<Actor=Self, Item=(), Error=()> {
+        let state = match &self.state {
+            RaftState::Leader(state) => state,
+            _ => return fut::Either::A(fut::err(())),
+        };
+
+        // This node counts towards the majority of each config which it is a member of.
+        let mut acked: BTreeSet<NodeId> = vec![self.id].into_iter().collect();
+        if self.membership.is_majority(|id| acked.contains(id)) {
+            return fut::Either::A(fut::ok(()));
+        }
+
+        let membership = self.membership.clone();
+        let voters: BTreeSet<_> = membership.voting_sets().into_iter().flatten().collect();
+        let acks = voters.into_iter()
+            .filter_map(|id| state.nodes.get(&id).map(|rs| (id, rs)))
+            .map(|(id, rs)| rs.addr.send(RSConfirmLeadership).then(move |res| Ok::<_, ()>(res.ok().and_then(|res| res.ok()).map(|_| id))));
+        let confirmed = stream::futures_unordered(acks)
+            .filter_map(|acked| acked)
+            .skip_while(move |id| {
+                acked.insert(*id);
+                Ok(!membership.is_majority(|id| acked.contains(id)))
+            })
+            .into_future()
+            .map_err(|_| ())
+            .and_then(|(majority, _)| majority.map(|_| ()).ok_or(()));
+        let timeout = Duration::from_millis(self.config.election_timeout_millis);
+        fut::Either::B(fut::wrap_future(Timeout::new(confirmed, timeout).map_err(|_| ())))
+    }
+
+    /// Check if this node holds a valid leader lease.
+    ///
+    /// The lease starts at the time the heartbeat acknowledged by a majority of the cluster was
+    /// sent, and lasts for the minimum election timeout minus the configured drift margin. No
+    /// other node can become leader during this window, as a majority of the cluster has heard
+    /// from this leader within its election timeout, which is never shorter than the minimum. In
+    /// joint consensus, the lease starts at the earlier of the times at which the old & the new
+    /// config were each acknowledged by a majority.
+    ///
+    /// The lease is given up as soon as a leadership transfer begins, as the target of the
+    /// transfer may be elected without waiting for the lease to expire. For the same reason,
+    /// heartbeats acknowledged before a `TimeoutNowRequest` was sent are never counted again, even
+    /// if the transfer times out.
+    fn has_valid_lease(&self) -> bool {
+        let state = match &self.state {
+            RaftState::Leader(state) if state.leadership_transfer.is_none() => state,
+            _ => return false,
+        };
+
+        // Find the most recent time at which a majority of each config acknowledged a heartbeat.
+        // This node counts towards the majority of each config which it is a member of.
+        let now = Instant::now();
+        let mut starts = vec![];
+        for set in self.membership.voting_sets() {
+            let mut acks: Vec<_> = set.iter()
+                .filter_map(|id| if id == &self.id { Some(now) } else { state.nodes.get(id).and_then(|rs| rs.last_heartbeat_ack) })
+                .filter(|stamp| state.timeout_now_sent_at.map(|sent_at| stamp > &sent_at).unwrap_or(true))
+                .collect();
+            let needed = set.len() / 2 + 1;
+            if acks.len() < needed {
+                return false;
+            }
+            acks.sort_unstable_by(|a, b| b.cmp(a));
+            starts.push(acks[needed - 1]);
+        }
+        let lease = Duration::from_millis(self.config.election_timeout_min.saturating_sub(self.config.lease_drift_margin));
+        starts.into_iter().min().map(|start| now < start + lease).unwrap_or(false)
+    }
+
+    /// Register a read at the given read index to be resolved once it has been applied.
+    ///
+    /// If the state machine has already reached the read index, the read is resolved immediately.
+    fn await_applied(&mut self, index: u64) -> oneshot::Receiver<Result<ClientReadResponse, ClientReadError>> {
+        let (tx, rx) = oneshot::channel();
+        if self.last_applied >= index {
+            let _ = tx.send(Ok(ClientReadResponse{index})).map_err(|err| error!("{} {:?}", CLIENT_RPC_RX_ERR, err));
+        } else {
+            self.awaiting_applied.push(ClientReadWithIndex{index, tx});
+        }
+        rx
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::BTreeMap;
+
+use actix::prelude::*;
+use log::{error};
+
+use crate::{
+    AppData, AppDataResponse, AppError,
+    common::{CLIENT_RPC_TX_ERR, ClientPayloadWithChan, ClientPayloadWithIndex, DependencyAddr},
+    messages::{ClientError, ClientPayloadResponse, ClientSession, Entry, ResponseMode},
+    network::RaftNetwork,
+    raft::{RaftState, Raft},
+    storage::{ClientSessions, GetCurrentSnapshot, GetLogEntries, RaftStorage},
+};
+
+/// The outcome of checking the client session of a client payload against the session table.
+enum ClientSessionStatus {
+    /// The payload is a new request of its client, or carries no session.
+    New,
+    /// The payload is a retry of the client's latest request, whose last entry is at the given index.
+    Duplicate(u64),
+    /// The payload is older than the client's latest request, which has the given sequence number.
+    AlreadyAcknowledged(u64),
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Raft<D, R, E, N, S> {
+    /// Assign the given client payload its indices for appending to the log, unless it is a retried request.
+    ///
+    /// Retried requests are never appended to the log a second time. They are answered with the
+    /// outcome of the original request once it has been applied, or with an
+    /// `AlreadyAcknowledged` error if the client has since moved on to a newer request.
+    ///
+    /// The sessions of the payload's entries are recorded as pending, so that a retry of the
+    /// payload which arrives before it has been applied is also recognized. Any pending sessions
+    /// at or beyond `index` were recorded for an append which failed, and are dropped first.
+    pub(super) fn upgrade_client_payload(&mut self, msg: ClientPayloadWithChan<D, R, E>, index: u64, term: u64) -> Option<ClientPayloadWithIndex<D, R, E>> {
+        self.pending_client_sessions.split_off(&index);
+        let msg = self.handle_client_session(msg)?;
+        let payload = msg.upgrade(index, term);
+        record_pending_client_sessions(&mut self.pending_client_sessions, payload.entries().iter().map(|entry| &**entry));
+        Some(payload)
+    }
+
+    /// Handle the client session of the given payload, returning the payload only if it is a new request.
+    fn handle_client_session(&mut self, msg: ClientPayloadWithChan<D, R, E>) -> Option<ClientPayloadWithChan<D, R, E>> {
+        match self.check_client_session(&msg) {
+            ClientSessionStatus::New => Some(msg),
+            ClientSessionStatus::Duplicate(index) if index <= self.last_applied => {
+                self.respond_to_duplicate(msg, index);
+                None
+            }
+            ClientSessionStatus::Duplicate(index) => {
+                match &mut self.state {
+                    RaftState::Leader(state) => state.awaiting_duplicates.push((index, msg)),
+                    _ => {
+                        let _ = msg.forward_to_leader(self.current_leader)
+                            .map_err(|_| error!("{} Error while forwarding a retried request to the leader.", CLIENT_RPC_TX_ERR));
+                    }
+                }
+                None
+            }
+            ClientSessionStatus::AlreadyAcknowledged(sequence) => {
+                let _ = msg.tx.send(Err(ClientError::AlreadyAcknowledged{sequence})).map_err(|_| error!("{}", CLIENT_RPC_TX_ERR));
+                None
+            }
+        }
+    }
+
+    /// Check the client session of the given payload against the session table & the unapplied entries of the log.
+    fn check_client_session(&self, msg: &ClientPayloadWithChan<D, R, E>) -> ClientSessionStatus {
+        let session = match &msg.session {
+            Some(session) => session,
+            None => return ClientSessionStatus::New,
+        };
+        let last_sequence = session.sequence + (msg.entries.len() as u64).saturating_sub(1);
+        let latest = self.pending_client_sessions.iter().rev()
+            .find(|(_, pending)| pending.client_id == session.client_id)
+            .map(|(index, pending)| (pending.sequence, *index))
+            .or_else(|| self.client_sessions.latest(session.client_id));
+        match latest {
+            Some((sequence, index)) if sequence == last_sequence => ClientSessionStatus::Duplicate(index),
+            Some((sequence, _)) if session.sequence <= sequence => ClientSessionStatus::AlreadyAcknowledged(sequence),
+            _ => ClientSessionStatus::New,
+        }
+    }
+
+    /// Respond to a retried client request, whose original request's last entry has been applied at the given index.
+    ///
+    /// The entries of the original request have contiguous indices. The last entry is answered
+    /// with the response cached by this leader when the original request was applied, if the
+    /// client wants it & it is still available, else each entry is answered with its index.
+    fn respond_to_duplicate(&mut self, msg: ClientPayloadWithChan<D, R, E>, index: u64) {
+        let cached = match (&msg.response_mode, &msg.session, &self.state) {
+            (ResponseMode::Applied, Some(session), RaftState::Leader(state)) => state.session_responses.get(&session.client_id)
+                .filter(|(response_index, _)| *response_index == index)
+                .map(|(_, data)| data.clone()),
+            _ => None,
+        };
+        let first = (index + 1).saturating_sub(msg.entries.len() as u64);
+        let mut responses: Vec<_> = (first..index).map(|index| ClientPayloadResponse::Committed{index}).collect();
+        responses.push(match cached {
+            Some(data) => ClientPayloadResponse::Applied{index, data},
+            None => ClientPayloadResponse::Committed{index},
+        });
+        let _ = msg.tx.send(Ok(responses)).map_err(|_| error!("{}", CLIENT_RPC_TX_ERR));
+    }
+
+    /// Record the sessions of the entries which have been applied through the given index.
+    ///
+    /// Any retried client requests which were waiting on these entries are responded to.
+    pub(super) fn record_applied_client_sessions(&mut self, index: u64) {
+        let pending = self.pending_client_sessions.split_off(&(index + 1));
+        let applied = std::mem::replace(&mut self.pending_client_sessions, pending);
+        for (index, session) in applied {
+            self.client_sessions.record(&session, index);
+        }
+
+        let awaiting = match &mut self.state {
+            RaftState::Leader(state) if !state.awaiting_duplicates.is_empty() => std::mem::take(&mut state.awaiting_duplicates),
+            _ => return,
+        };
+        let (ready, awaiting): (Vec<_>, Vec<_>) = awaiting.into_iter().partition(|(awaited, _)| *awaited <= index);
+        if let RaftState::Leader(state) = &mut self.state {
+            state.awaiting_duplicates = awaiting;
+        }
+        for (_, msg) in ready {
+            // The original request is checked again, as its entry may have been re-assigned a new
+            // index. If it is no longer in the log at all, the client must retry it.
+            if let Some(msg) = self.handle_client_session(msg) {
+                let _ = msg.forward_to_leader(self.current_leader)
+                    .map_err(|_| error!("{} Error while forwarding a retried request to the leader.", CLIENT_RPC_TX_ERR));
+            }
+        }
+    }
+
+    /// Cache the response of the given applied entry, for answering retries of its request.
+    pub(super) fn cache_client_session_response(&mut self, session: Option<ClientSession>, index: u64, data: &R) {
+        if let (Some(session), RaftState::Leader(state)) = (session, &mut self.state) {
+            state.session_responses.insert(session.client_id, (index, data.clone()));
+        }
+    }
+
+    /// The client sessions of the entries through the given index, to be included in a snapshot covering them.
+    pub(super) fn client_sessions_through(&self, index: u64) -> ClientSessions {
+        let mut sessions = self.client_sessions.clone();
+        for (index, session) in self.pending_client_sessions.range(..=index) {
+            sessions.record(session, *index);
+        }
+        sessions
+    }
+
+    /// Merge the client sessions of the snapshot which has just been installed at the given index.
+    pub(super) fn rebase_client_sessions(&mut self, index: u64, sessions: ClientSessions) {
+        self.pending_client_sessions = self.pending_client_sessions.split_off(&(index + 1));
+        self.client_sessions.merge(sessions);
+    }
+
+    /// Load the client sessions of this node from its current snapshot & its log.
+    ///
+    /// The sessions of the entries which follow the snapshot are rebuilt from the log, as the
+    /// session table is only persisted as part of snapshots.
+    pub(super) fn load_client_sessions(&mut self, last_log_index: u64, last_applied: u64) -> impl ActorFuture<Actor=Self, Item=(), Error=()> {
+        fut::wrap_future(self.storage.send::<GetCurrentSnapshot<E>>(GetCurrentSnapshot::new()))
+            .map_err(|err, act: &mut Self, ctx| act.map_fatal_actix_messaging_error(ctx, err, DependencyAddr::RaftStorage))
+            .and_then(|res, act, ctx| act.map_fatal_storage_result(ctx, res))
+            .and_then(move |snapshot, act, _| {
+                let start = match snapshot {
+                    Some(snapshot) => {
+                        act.client_sessions = snapshot.client_sessions;
+                        snapshot.index + 1
+                    }
+                    None => 1,
+                };
+                if start > last_log_index {
+                    return fut::Either::A(fut::ok(()));
+                }
+                fut::Either::B(fut::wrap_future(act.storage.send::<GetLogEntries<D, E>>(GetLogEntries::new(start, last_log_index + 1)))
+                    .map_err(|err, act: &mut Self, ctx| act.map_fatal_actix_messaging_error(ctx, err, DependencyAddr::RaftStorage))
+                    .and_then(|res, act, ctx| act.map_fatal_storage_result(ctx, res))
+                    .map(move |entries, act, _| {
+                        record_pending_client_sessions(&mut act.pending_client_sessions, entries.iter());
+                        act.record_applied_client_sessions(last_applied);
+                    }))
+            })
+    }
+}
+
+/// Record the client sessions of the given entries, which have been appended to the log, keyed by log index.
+pub(super) fn record_pending_client_sessions<'a, D: AppData>(pending: &mut BTreeMap<u64, ClientSession>, entries: impl Iterator<Item=&'a Entry<D>>) {
+    for entry in entries {
+        if let Some(session) = entry.session() {
+            pending.insert(entry.index, *session);
+        }
+    }
+}
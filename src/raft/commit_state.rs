@@ -0,0 +1,42 @@
+use actix::prelude::*;
+
+use crate::{
+    AppData, AppDataResponse, AppError,
+    common::DependencyAddr,
+    messages::{ClientReadError, CommitState, CommitStateRequest},
+    network::RaftNetwork,
+    raft::Raft,
+    storage::{GetLogEntries, RaftStorage},
+};
+
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Handler<CommitStateRequest> for Raft<D, R, E, N, S> {
+    type Result = ResponseActFuture<Self, CommitState, ClientReadError>;
+
+    /// Handle requests to check whether the log entry with the given index & term was committed.
+    ///
+    /// Every entry of the leader's log through its commit index is committed. So once the commit
+    /// index has reached the requested index, the entry is committed only if the leader's entry
+    /// at that index has the requested term. If that entry has been compacted into a snapshot,
+    /// its term is no longer known, unless it is the last entry covered by the snapshot, which is
+    /// replaced by a snapshot pointer carrying the same index & term.
+    fn handle(&mut self, msg: CommitStateRequest, _: &mut Self::Context) -> Self::Result {
+        if !self.state.is_leader() {
+            return Box::new(fut::err(ClientReadError::ForwardToLeader{leader: self.current_leader}));
+        }
+        if msg.index > self.commit_index {
+            return Box::new(fut::ok(CommitState::Pending));
+        }
+
+        Box::new(fut::wrap_future(self.storage.send::<GetLogEntries<D, E>>(GetLogEntries::new(msg.index, msg.index + 1)))
+            .map_err(|err, act: &mut Self, ctx| {
+                act.map_fatal_actix_messaging_error(ctx, err, DependencyAddr::RaftStorage);
+                ClientReadError::Internal
+            })
+            .and_then(|res, act, ctx| act.map_fatal_storage_result(ctx, res).map_err(|_, _, _| ClientReadError::Internal))
+            .map(move |entries, _, _| match entries.into_iter().find(|entry| entry.index == msg.index) {
+                Some(ref entry) if entry.term == msg.term => CommitState::Committed,
+                Some(_) => CommitState::Aborted,
+                None => CommitState::Compacted,
+            }))
+    }
+}
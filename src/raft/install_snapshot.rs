@@ -7,11 +7,11 @@ use crate::{
     common::{DependencyAddr, UpdateCurrentLeader},
     network::RaftNetwork,
     messages::{InstallSnapshotRequest, InstallSnapshotResponse},
-    raft::{RaftState, Raft, SnapshotState},
-    storage::{InstallSnapshot, InstallSnapshotChunk, RaftStorage},
+    raft::{RaftState, Raft, SnapshotState, rebase_config_entries},
+    storage::{GetCurrentSnapshot, InstallSnapshot, InstallSnapshotChunk, RaftStorage},
 };
 
-impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> Handler<InstallSnapshotRequest> for Raft<D, R, E, N, S> {
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Handler<InstallSnapshotRequest> for Raft<D, R, E, N, S> {
     type Result = ResponseActFuture<Self, InstallSnapshotResponse, ()>;
 
     /// Invoked by leader to send chunks of a snapshot to a follower (§7).
@@ -22,6 +22,18 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
     ///
     /// See the `storage::InstallSnapshot` type for implementaion details.
     fn handle(&mut self, msg: InstallSnapshotRequest, ctx: &mut Self::Context) -> Self::Result {
+        // Respond only once any change to this node's term is durable.
+        let (term, voted_for) = (self.current_term, self.voted_for);
+        let res = self.handle_install_snapshot_request(ctx, msg);
+        self.respond_once_durable(ctx, term, voted_for, res)
+    }
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Raft<D, R, E, N, S> {
+    /// Business logic of handling an `InstallSnapshotRequest` RPC.
+    fn handle_install_snapshot_request(
+        &mut self, ctx: &mut Context<Self>, msg: InstallSnapshotRequest,
+    ) -> Box<dyn ActorFuture<Actor=Self, Item=InstallSnapshotResponse, Error=()> + 'static> {
         // Only handle requests if actor has finished initialization.
         if let &RaftState::Initializing = &self.state {
             return Box::new(fut::err(()));
@@ -43,7 +55,6 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
         // Update current term if needed.
         if self.current_term != msg.term {
             self.update_current_term(msg.term, None);
-            self.save_hard_state(ctx);
         }
 
         // Update current leader if needed.
@@ -87,9 +98,7 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
             SnapshotState::Streaming(_, _) => Box::new(fut::err(())),
         }
     }
-}
 
-impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> Raft<D, R, E, N, S> {
     // Install a new snapshot which was small enough to fit into a single frame.
     fn handle_mini_snapshot(&mut self, ctx: &mut Context<Self>, msg: InstallSnapshotRequest) -> Box<dyn ActorFuture<Actor=Self, Item=InstallSnapshotResponse, Error=()>> {
         let (tx, rx) = mpsc::unbounded();
@@ -127,9 +136,11 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
                         debug!("Finished installing snapshot. Update index & term to {} & {}.", snap_index, snap_term);
                         state.snapshot_state = SnapshotState::Idle;
                         if act.last_log_index < snap_index {
+                            // The entire log has been replaced by the snapshot.
+                            act.pending_client_sessions.clear();
                             act.last_log_index = snap_index;
                             act.last_log_term = snap_term;
-                            act.last_applied = snap_index;
+                            act.update_last_applied(snap_index);
                         }
                         fut::ok(InstallSnapshotResponse{term: act.current_term})
                     }
@@ -139,7 +150,8 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
                     error!("Error awaiting response from storage engine for final snapshot chunk. Channel was closed.");
                     fut::err(())
                 }
-            }))
+            })
+            .and_then(move |res, act, ctx| act.rebase_onto_snapshot(ctx, snap_index).map(move |_, _, _| res)))
     }
 
     fn handle_snapshot_stream(&mut self, ctx: &mut Context<Self>, msg: InstallSnapshotRequest) -> Box<dyn ActorFuture<Actor=Self, Item=InstallSnapshotResponse, Error=()>> {
@@ -208,9 +220,11 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
                         debug!("Finished installing snapshot. Update index & term to {} & {}.", snap_index, snap_term);
                         state.snapshot_state = SnapshotState::Idle;
                         if act.last_log_index < snap_index {
+                            // The entire log has been replaced by the snapshot.
+                            act.pending_client_sessions.clear();
                             act.last_log_index = snap_index;
                             act.last_log_term = snap_term;
-                            act.last_applied = snap_index;
+                            act.update_last_applied(snap_index);
                         }
                         fut::ok(InstallSnapshotResponse{term: act.current_term})
                     }
@@ -220,7 +234,8 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
                     error!("Error awaiting response from storage engine for final snapshot chunk. Channel was closed.");
                     fut::err(())
                 }
-            }))
+            })
+            .and_then(move |res, act, ctx| act.rebase_onto_snapshot(ctx, snap_index).map(move |_, _, _| res)))
     }
 
     fn handle_snapshot_chunk(
@@ -246,4 +261,23 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
                 }
             }))
     }
+
+    /// Rebase the membership history & the client sessions onto the snapshot which has just been installed.
+    ///
+    /// Without this, truncating the entries which follow the snapshot could revert this node to
+    /// a config from before the snapshot, and the client sessions of the entries covered by the
+    /// snapshot would be unknown to this node.
+    fn rebase_onto_snapshot(&mut self, _: &mut Context<Self>, snap_index: u64) -> impl ActorFuture<Actor=Self, Item=(), Error=()> {
+        fut::wrap_future(self.storage.send::<GetCurrentSnapshot<E>>(GetCurrentSnapshot::new()))
+            .map_err(|err, act: &mut Self, ctx| act.map_fatal_actix_messaging_error(ctx, err, DependencyAddr::RaftStorage))
+            .and_then(|res, act, ctx| act.map_fatal_storage_result(ctx, res))
+            .and_then(move |snapshot, act, ctx| match snapshot {
+                Some(snapshot) if snapshot.index == snap_index => {
+                    rebase_config_entries(&mut act.membership_history, snapshot.index, snapshot.membership);
+                    act.rebase_client_sessions(snapshot.index, snapshot.client_sessions);
+                    fut::Either::A(act.save_hard_state_async(ctx))
+                }
+                _ => fut::Either::B(fut::ok(())),
+            })
+    }
 }
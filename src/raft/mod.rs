@@ -4,31 +4,36 @@ mod admin;
 mod append_entries;
 mod apply_logs;
 mod client;
+mod client_read;
+mod client_sessions;
+mod commit_state;
 mod install_snapshot;
 mod replication;
 mod state;
+mod timeout_now;
 mod vote;
 
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, VecDeque},
     sync::Arc,
     time::{Duration, Instant},
 };
 
 use actix::prelude::*;
-use futures::sync::{mpsc};
-use log::{error};
+use futures::sync::{mpsc, oneshot};
+use log::{error, warn};
 
 use crate::{
     AppData, AppDataResponse, AppError, NodeId,
-    common::{ApplyLogsTask, DependencyAddr, UpdateCurrentLeader},
+    admin::TransferLeadershipError,
+    common::{CLIENT_RPC_RX_ERR, AppendEntriesWithChan, ApplyLogsTask, ClientReadWithIndex, DependencyAddr, ForwardedClientPayload, UpdateCurrentLeader},
     config::Config,
-    messages::{ClientPayload, MembershipConfig},
+    messages::{ClientPayload, ClientReadResponse, ClientSession, Entry, EntryPayload, MembershipConfig},
     metrics::{RaftMetrics, State},
     network::RaftNetwork,
-    raft::state::{CandidateState, FollowerState, LeaderState, RaftState, ReplicationState, SnapshotState},
+    raft::state::{CandidateState, ConsensusState, FollowerState, LeaderState, RaftState, ReplicationState, SnapshotState},
     replication::{ReplicationStream, RSTerminate},
-    storage::{GetInitialState, GetLogEntries, HardState, InitialState, RaftStorage, SaveHardState},
+    storage::{ClientSessions, GetInitialState, GetLogEntries, HardState, InitialState, RaftStorage, SaveHardState},
 };
 
 const FATAL_ACTIX_MAILBOX_ERR: &str = "Fatal actix MailboxError while communicating with Raft dependency. Raft is shutting down.";
@@ -59,9 +64,9 @@ const FATAL_STORAGE_ERR: &str = "Fatal storage error encountered which can not b
 ///
 /// ##### raft rpc messages
 /// These are Raft request PRCs coming from other nodes of the cluster. They are defined in the
-/// `messages` module of this crate. They are `AppendEntriesRequest`, `VoteRequest` &
-/// `InstallSnapshotRequest`. This actor will use the `RaftNetwork` impl of the parent application
-/// to send RPCs to other nodes.
+/// `messages` module of this crate. They are `AppendEntriesRequest`, `VoteRequest`,
+/// `PreVoteRequest`, `TimeoutNowRequest` & `InstallSnapshotRequest`. This actor will use the
+/// `RaftNetwork` impl of the parent application to send RPCs to other nodes.
 ///
 /// The application's networking layer must decode these message types and pass them over to the
 /// appropriate handler on this type, await the response, and then send the response back over the
@@ -74,6 +79,12 @@ const FATAL_STORAGE_ERR: &str = "Fatal storage error encountered which can not b
 /// should ever need to go through Raft. The contentsof these messages are entirely specific to
 /// your application.
 ///
+/// Many entries may be submitted at once with the `messages::ClientPayloadBatch` type. They will
+/// be appended to the log with a single storage call & replicated as one unit.
+///
+/// Applications which need linearizable reads may use the `messages::ClientReadRequest` type.
+/// Once its future resolves, the application may serve the read from its state machine.
+///
 /// #### storage
 /// The storage interface is typically going to be the most involved as this is where your
 /// application really exists. SQL, NoSQL, mutable, immutable, KV, append only ... whatever your
@@ -85,15 +96,31 @@ const FATAL_STORAGE_ERR: &str = "Fatal storage error encountered which can not b
 ///
 /// #### admin
 /// These are admin commands which may be issued to a Raft node in order to influence it in ways
-/// outside of the normal Raft lifecycle. Dynamic membership changes and cluster initialization
-/// are the main commands of this layer.
-pub struct Raft<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> {
+/// outside of the normal Raft lifecycle. Dynamic membership changes, cluster initialization and
+/// leadership transfer are the main commands of this layer.
+pub struct Raft<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> {
     /// This node's ID.
     id: NodeId,
     /// This node's runtime config.
     config: Arc<Config>,
     /// The cluster's current membership configuration.
     membership: MembershipConfig,
+    /// The config entries of this node's log which may still be overwritten, keyed by log index.
+    ///
+    /// This holds the latest config known to be committed, keyed by index `0` if it did not come
+    /// from the log, followed by each config entry appended after it. If any of these entries are
+    /// truncated from the log, membership reverts to the latest remaining config. This is saved
+    /// as part of the node's hard state, so that it survives restarts.
+    membership_history: BTreeMap<u64, MembershipConfig>,
+    /// The client sessions of the entries which have been applied to the state machine.
+    ///
+    /// This is included in snapshots, and the sessions of the entries which follow the snapshot
+    /// are rebuilt from the log on startup.
+    client_sessions: ClientSessions,
+    /// The client sessions of the entries of this node's log which have not yet been applied, keyed by log index.
+    ///
+    /// Just as `membership_history`, these are dropped if their entries are truncated from the log.
+    pending_client_sessions: BTreeMap<u64, ClientSession>,
     /// The current state of this Raft node.
     state: RaftState<D, R, E, N, S>,
     /// The address of the actor responsible for implementing the `RaftNetwork` interface.
@@ -145,10 +172,23 @@ pub struct Raft<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>,
 
     /// A flag to indicate if this system is currently appending logs.
     is_appending_logs: bool,
+    /// A queue of AppendEntries RPCs carrying entries, which are appended one at a time in the order received.
+    append_entries_queue: VecDeque<AppendEntriesWithChan<D>>,
+    /// A flag to indicate if the AppendEntries RPCs of `append_entries_queue` are being processed.
+    is_draining_append_entries: bool,
     /// The entrypoint to the pipeline of logs which need to be applied to the state machine.
     apply_logs_pipeline: mpsc::UnboundedSender<ApplyLogsTask<D, R, E>>,
     /// The receiving end of the pipeline for applying logs. This is moved out and spawned when Raft starts.
     _apply_logs_pipeline_receiver: Option<mpsc::UnboundedReceiver<ApplyLogsTask<D, R, E>>>,
+    /// The number of tasks in the pipeline for applying logs which have not yet been completed.
+    ///
+    /// While the leader has as many tasks pending as its `client_queue_capacity` allows, new
+    /// client requests are rejected, which bounds the growth of the pipeline.
+    apply_logs_pending: u64,
+    /// A buffer of client read requests which are awaiting the state machine to reach their read index.
+    awaiting_applied: Vec<ClientReadWithIndex>,
+    /// A buffer of client payloads which are waiting for a leader to be known, so that they can be forwarded to it.
+    awaiting_leader: Vec<ForwardedClientPayload<D, R, E>>,
 
     /// A handle to the election timeout callback.
     election_timeout: Option<actix::SpawnHandle>,
@@ -156,7 +196,7 @@ pub struct Raft<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>,
     election_timeout_stamp: Option<Instant>,
 }
 
-impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> Raft<D, R, E, N, S> {
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Raft<D, R, E, N, S> {
     /// Create a new Raft instance.
     ///
     /// This actor will need to be started after instantiation, which must be done within a
@@ -165,14 +205,16 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
         let state = RaftState::Initializing;
         let config = Arc::new(config);
         let (tx, rx) = mpsc::unbounded();
-        let membership = MembershipConfig{is_in_joint_consensus: false, members: vec![id], non_voters: vec![], removing: vec![]};
+        let membership = MembershipConfig{is_in_joint_consensus: false, members: vec![id], non_voters: vec![], removing: vec![], learners: vec![], witnesses: vec![]};
         Self{
-            id, config, membership, state, network, storage, metrics,
+            id, config, membership, membership_history: BTreeMap::new(), state, network, storage, metrics,
+            client_sessions: ClientSessions::new(), pending_client_sessions: BTreeMap::new(),
             commit_index: 0, last_applied: 0,
             current_term: 0, current_leader: None, voted_for: None,
             last_log_index: 0, last_log_term: 0,
-            is_appending_logs: false,
-            apply_logs_pipeline: tx, _apply_logs_pipeline_receiver: Some(rx),
+            is_appending_logs: false, append_entries_queue: VecDeque::new(), is_draining_append_entries: false,
+            apply_logs_pipeline: tx, _apply_logs_pipeline_receiver: Some(rx), apply_logs_pending: 0,
+            awaiting_applied: vec![], awaiting_leader: vec![],
             election_timeout: None, election_timeout_stamp: None,
         }
     }
@@ -193,7 +235,7 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
     /// Transition to the Raft follower state.
     fn become_follower(&mut self, ctx: &mut Context<Self>) {
         // Don't transition to follower state if the cluster has this node configured as a non-voter.
-        if !self.membership.contains(&self.id) || self.membership.non_voters.contains(&self.id) {
+        if !self.membership.members.contains(&self.id) {
             return;
         }
 
@@ -239,7 +281,51 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
     /// candidate obtains a majority. When this happens, each candidate will time out and start a
     /// new election by incrementing its term and initiating another round of RequestVote RPCs.
     /// The randomization of election timeouts per node helps to avoid this issue.
+    ///
+    /// If Pre-Vote is enabled, the node will first hold a Pre-Vote round (§9.6), and will only
+    /// start a real election once a majority of the cluster indicates that it could win.
+    ///
+    /// Witnesses never campaign, as they do not store the application data needed to lead.
     fn become_candidate(&mut self, ctx: &mut Context<Self>) {
+        if self.membership.witnesses.contains(&self.id) {
+            return;
+        }
+        if self.config.pre_vote {
+            self.start_pre_vote(ctx);
+        } else {
+            self.start_election(ctx, false);
+        }
+    }
+
+    /// Start a Pre-Vote round, without incrementing the current term (§9.6).
+    fn start_pre_vote(&mut self, ctx: &mut Context<Self>) {
+        // Cleanup previous state.
+        self.cleanup_state(ctx);
+
+        // Send RPCs to all members in parallel.
+        let mut requests = BTreeMap::new();
+        let peers = self.membership.members.iter().filter(|member| *member != &self.id).copied().collect::<Vec<_>>();
+        for member in peers {
+            let f = self.request_pre_vote(ctx, member);
+            let handle = ctx.spawn(f);
+            requests.insert(member, handle);
+        }
+
+        // Update the election timeout. If the Pre-Vote round fails, a new one will be started.
+        self.update_election_timeout(ctx);
+
+        // Update Raft state as candidate.
+        let votes_granted = 1; // We would vote for ourselves.
+        let votes_needed = ((self.membership.members.len() / 2) + 1) as u64; // Just need a majority.
+        self.state = RaftState::Candidate(CandidateState{requests, votes_granted, votes_needed, is_pre_vote: true});
+        self.report_metrics(ctx);
+    }
+
+    /// Start a new election by incrementing the current term and requesting votes (§5.2).
+    ///
+    /// If `disrupt_leader` is true, the election is part of a leadership transfer, and peers will
+    /// grant their votes even if they have recently heard from the current leader.
+    fn start_election(&mut self, ctx: &mut Context<Self>, disrupt_leader: bool) {
         // Cleanup previous state.
         self.cleanup_state(ctx);
 
@@ -247,13 +333,16 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
         self.current_term += 1;
         self.voted_for = Some(self.id);
         self.update_current_leader(ctx, UpdateCurrentLeader::Unknown);
-        self.save_hard_state(ctx);
+        let f = self.save_hard_state_async(ctx);
+        ctx.wait(f);
 
-        // Send RPCs to all members in parallel.
+        // Send RPCs to all members in parallel. None of the spawned requests will be polled until
+        // this node's vote for itself is durable, so they are built lazily to ensure that they
+        // are only sent once it is.
         let mut requests = BTreeMap::new();
         let peers = self.membership.members.iter().filter(|member| *member != &self.id).map(|e| *e).collect::<Vec<_>>();
         for member in peers {
-            let f = self.request_vote(ctx, member);
+            let f = fut::ok(()).and_then(move |_, act: &mut Self, ctx| act.request_vote(ctx, member, disrupt_leader));
             let handle = ctx.spawn(f);
             requests.insert(member, handle);
         }
@@ -264,7 +353,7 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
         // Update Raft state as candidate.
         let votes_granted = 1; // We must vote for ourselves per the Raft spec.
         let votes_needed = ((self.membership.members.len() / 2) + 1) as u64; // Just need a majority.
-        self.state = RaftState::Candidate(CandidateState{requests, votes_granted, votes_needed});
+        self.state = RaftState::Candidate(CandidateState{requests, votes_granted, votes_needed, is_pre_vote: false});
         self.report_metrics(ctx);
     }
 
@@ -289,18 +378,19 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
         self.cancel_election_timeout(ctx);
 
         // Prep new leader state.
-        let (client_request_queue, client_request_receiver) = mpsc::unbounded();
-        let mut new_state = LeaderState::new(client_request_queue, &self.membership);
+        // The channel's capacity includes one slot for its single sender.
+        let (client_request_queue, client_request_receiver) = mpsc::channel(self.config.client_queue_capacity as usize - 1);
+        let mut new_state = LeaderState::new(client_request_queue, &self.membership, self.last_log_index + 1);
 
         // Spawn stream which consumes client RPCs.
-        ctx.spawn(fut::wrap_stream(client_request_receiver)
-            .and_then(|msg, act: &mut Self, ctx| act.process_client_rpc(ctx, msg))
+        ctx.spawn(fut::wrap_stream(client::client_payload_groups(client_request_receiver, self.config.max_payload_entries))
+            .and_then(|msgs, act: &mut Self, ctx| act.process_client_rpc(ctx, msgs))
             .then(|_, _, _| fut::ok(())) // Ensure errors don't cause the stream to close.
             .finish());
 
         // Spawn new replication stream actors.
         let targets = self.membership.members.iter().filter(|elem| *elem != &self.id)
-            .chain(self.membership.non_voters.iter());
+            .chain(self.membership.non_voters.iter()).chain(self.membership.learners.iter());
         for target in targets {
             // Build the replication stream for the target member.
             let rs = ReplicationStream::new(
@@ -311,12 +401,19 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
             let addr = rs.start(); // Start the actor on the same thread.
 
             // Retain the addr of the replication stream.
-            let state = ReplicationState{match_index: self.last_log_index, is_at_line_rate: true, addr, remove_after_commit: None};
+            let state = ReplicationState{match_index: self.last_log_index, is_at_line_rate: true, addr, remove_after_commit: None, last_heartbeat_ack: None};
             new_state.nodes.insert(*target, state);
         }
 
-        // Initialize new state as leader.
+        // Periodically ensure that this node is still in contact with a majority of the cluster.
+        let check_interval = Duration::from_millis(self.config.election_timeout_millis);
+        new_state.check_quorum = Some(ctx.run_interval(check_interval, |act, ctx| act.check_quorum(ctx)));
+
+        // Initialize new state as leader. If a config change is in progress, its new nodes are
+        // given until the configured deadline to catch up.
         self.state = RaftState::Leader(new_state);
+        self.update_witness_streams();
+        self.schedule_catch_up_deadline(ctx);
         self.update_current_leader(ctx, UpdateCurrentLeader::ThisNode);
         self.report_metrics(ctx);
 
@@ -353,6 +450,30 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
                 inner.nodes.values().for_each(|rsstate| {
                     let _ = rsstate.addr.do_send(RSTerminate);
                 });
+                if let Some(handle) = inner.check_quorum.take() {
+                    ctx.cancel_future(handle);
+                }
+                if let Some(handle) = inner.catch_up_deadline.take() {
+                    ctx.cancel_future(handle);
+                }
+                // Requests awaiting commitment have been appended, and may yet be committed by
+                // the next leader, so their outcome is indeterminate.
+                for request in inner.awaiting_committed.drain(..) {
+                    let _ = request.respond_indeterminate()
+                        .map_err(|_| error!("{} Error while responding to a request with an indeterminate outcome.", CLIENT_RPC_RX_ERR));
+                }
+                // Retried requests awaiting their original request were never appended, so they
+                // must be retried with the next leader.
+                for (_, request) in inner.awaiting_duplicates.drain(..) {
+                    let _ = request.forward_to_leader(None)
+                        .map_err(|_| error!("{} Error while forwarding a retried request to the leader.", CLIENT_RPC_RX_ERR));
+                }
+                // If a leadership transfer is in progress, it is complete only if a newer term
+                // has been observed; any other reason for stepping down means it has failed.
+                if let Some(transfer) = inner.leadership_transfer.take() {
+                    let res = if self.current_term > transfer.term { Ok(()) } else { Err(TransferLeadershipError::NodeNotLeader(None)) };
+                    let _ = transfer.tx.send(res);
+                }
             }
             _ => (),
         }
@@ -391,6 +512,10 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
         self.current_term = state.hard_state.current_term;
         self.voted_for = state.hard_state.voted_for;
         self.membership = state.hard_state.membership;
+        self.membership_history = state.hard_state.membership_history;
+        if self.membership_history.is_empty() {
+            self.membership_history.insert(0, self.membership.clone());
+        }
         self.last_applied = state.last_applied_log;
         // NOTE: this is repeated here for clarity. It is unsafe to initialize the node's commit
         // index to any other value. The commit index must be determined by a leader after
@@ -400,7 +525,11 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
         // Spawn the stream for applying logs to the state machine. This will always be `Some` here, never after.
         if let Some(rx) = self._apply_logs_pipeline_receiver.take() {
             ctx.spawn(fut::wrap_stream(rx)
-                .and_then(|msg, act: &mut Self, ctx| act.process_apply_logs_task(ctx, msg))
+                .and_then(|msg, act: &mut Self, ctx| act.process_apply_logs_task(ctx, msg)
+                    .then(|res, act, _| {
+                        act.apply_logs_pending -= 1;
+                        fut::result(res)
+                    }))
                 .finish());
         }
 
@@ -414,8 +543,9 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
         if is_only_configured_member && &self.last_log_index != &u64::min_value() {
             self.become_leader(ctx);
         }
-        // Else if there are other members, that can only mean that state was recovered. Become follower.
-        else if !is_only_configured_member {
+        // Else if there are other members, that can only mean that state was recovered. Become
+        // follower, unless this node is configured as a permanent non-voter.
+        else if !is_only_configured_member && !self.membership.learners.contains(&self.id) {
             self.state = RaftState::Follower(FollowerState::default());
             self.update_election_timeout(ctx);
         }
@@ -457,37 +587,73 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
             RaftState::Leader(_) => State::Leader,
             _ => return,
         };
+
+        // While in joint consensus, the leader reports the match index of each new node it is syncing.
+        let catch_up_progress = match &self.state {
+            RaftState::Leader(state) => match &state.consensus_state {
+                ConsensusState::Joint{new_nodes, ..} => new_nodes.iter()
+                    .filter_map(|id| state.nodes.get(id).map(|rs| (*id, rs.match_index)))
+                    .collect(),
+                _ => BTreeMap::new(),
+            },
+            _ => BTreeMap::new(),
+        };
         let _ = self.metrics.do_send(RaftMetrics{
             id: self.id, state, current_term: self.current_term,
             last_log_index: self.last_log_index,
             last_applied: self.last_applied,
             current_leader: self.current_leader,
             membership_config: self.membership.clone(),
+            catch_up_progress,
         }).map_err(|err| {
             error!("Error reporting metrics. {}", err);
         });
     }
 
-    /// Save the Raft node's current hard state to disk.
-    ///
-    /// DEPRECATED: use `save_hard_state_async`.
-    fn save_hard_state(&mut self, ctx: &mut Context<Self>) {
-        let hs = HardState{current_term: self.current_term, voted_for: self.voted_for, membership: self.membership.clone()};
-        let f = fut::wrap_future(self.storage.send::<SaveHardState<E>>(SaveHardState::new(hs)))
-            .map_err(|err, act: &mut Self, ctx| act.map_fatal_actix_messaging_error(ctx, err, DependencyAddr::RaftStorage))
-            .and_then(|res, act, ctx| act.map_fatal_storage_result(ctx, res));
-
-        ctx.spawn(f);
-    }
-
     /// Save the Raft node's current hard state to disk.
     fn save_hard_state_async(&mut self, _: &mut Context<Self>) -> impl ActorFuture<Actor=Self, Item=(), Error=()> {
-        let hs = HardState{current_term: self.current_term, voted_for: self.voted_for, membership: self.membership.clone()};
+        let hs = HardState{
+            current_term: self.current_term, voted_for: self.voted_for,
+            membership: self.membership.clone(), membership_history: self.membership_history.clone(),
+        };
         fut::wrap_future(self.storage.send::<SaveHardState<E>>(SaveHardState::new(hs)))
             .map_err(|err, act: &mut Self, ctx| act.map_fatal_actix_messaging_error(ctx, err, DependencyAddr::RaftStorage))
             .and_then(|res, act, ctx| act.map_fatal_storage_result(ctx, res))
     }
 
+    /// Save the Raft node's current hard state to disk, handling nothing else until it is durable.
+    ///
+    /// No other messages will be handled, and none of this actor's other futures will make
+    /// progress, until the save has completed. RPC responses which depend upon a change to the
+    /// hard state must be chained onto the returned future, as a crash before the change is
+    /// durable could otherwise cause this node to vote twice in the same term.
+    fn save_hard_state_and_wait(&mut self, ctx: &mut Context<Self>) -> impl ActorFuture<Actor=Self, Item=(), Error=()> {
+        let (tx, rx) = oneshot::channel();
+        let f = self.save_hard_state_async(ctx)
+            .then(move |res, _, _| {
+                let _ = tx.send(res);
+                fut::ok(())
+            });
+        ctx.wait(f);
+        fut::wrap_future(rx)
+            .map_err(|_, _: &mut Self, _| ())
+            .and_then(|res, _, _| fut::result(res))
+    }
+
+    /// Respond to an RPC with the given response once any change it made to the hard state is durable.
+    ///
+    /// The given term & vote are those of this node from before the RPC was handled. If either
+    /// has since changed, the response is delayed until the new hard state has been saved.
+    fn respond_once_durable<T: 'static>(
+        &mut self, ctx: &mut Context<Self>, term: u64, voted_for: Option<NodeId>,
+        res: impl ActorFuture<Actor=Self, Item=T, Error=()> + 'static,
+    ) -> Box<dyn ActorFuture<Actor=Self, Item=T, Error=()> + 'static> {
+        if self.current_term == term && self.voted_for == voted_for {
+            return Box::new(res);
+        }
+        Box::new(self.save_hard_state_and_wait(ctx).and_then(move |_, _, _| res))
+    }
+
     /// Update the value of the `current_leader` property.
     ///
     /// NOTE WELL: there was previously a bit of log encapsulated here related to forwarding
@@ -495,13 +661,15 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
     /// to determine how they want to handle forwarding client requests to leaders, that logic was
     /// removed and this handler has thus been greatly simplified. We are keeping it as is in case
     /// we need to add some additional logic here.
-    fn update_current_leader(&mut self, _: &mut Context<Self>, update: UpdateCurrentLeader) {
+    fn update_current_leader(&mut self, ctx: &mut Context<Self>, update: UpdateCurrentLeader) {
         match update {
             UpdateCurrentLeader::ThisNode => {
                 self.current_leader = Some(self.id);
+                self.forward_awaiting_leader(ctx);
             }
             UpdateCurrentLeader::OtherNode(target) => {
                 self.current_leader = Some(target);
+                self.forward_awaiting_leader(ctx);
             }
             UpdateCurrentLeader::Unknown => {
                 self.current_leader = None;
@@ -517,6 +685,26 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
         }
     }
 
+    /// Update the index of the last log applied to the state machine.
+    ///
+    /// Any client read requests which have been waiting on the state machine to reach their read
+    /// index will be responded to, as will any retried client requests which were waiting on the
+    /// entries of their original request.
+    fn update_last_applied(&mut self, index: u64) {
+        self.last_applied = index;
+        self.record_applied_client_sessions(index);
+        if self.awaiting_applied.is_empty() {
+            return;
+        }
+        let last_applied = self.last_applied;
+        let (ready, pending) = self.awaiting_applied.drain(..).partition(|read| read.index <= last_applied);
+        self.awaiting_applied = pending;
+        for read in ready {
+            let ClientReadWithIndex{index, tx} = read;
+            let _ = tx.send(Ok(ClientReadResponse{index})).map_err(|err| error!("{} {:?}", CLIENT_RPC_RX_ERR, err));
+        }
+    }
+
     /// Update the election timeout process.
     ///
     /// This will schedule a new interval job based on the configured election timeout. The
@@ -528,7 +716,7 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
     /// valid vote request.
     fn update_election_timeout(&mut self, ctx: &mut Context<Self>) {
         // Don't update if the cluster has this node configured as a non-voter.
-        if !self.membership.contains(&self.id) || self.membership.non_voters.contains(&self.id) {
+        if !self.membership.members.contains(&self.id) {
             return;
         }
 
@@ -559,6 +747,39 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
         }
     }
 
+    /// Check that this node, as leader, is still in contact with a majority of the cluster.
+    ///
+    /// If a majority of the cluster, or of either config while in joint consensus, has not
+    /// acknowledged a heartbeat from this node within an election timeout, then this node may be
+    /// in a minority partition and another leader may already have been elected. In such a case,
+    /// this node will step down, and all client requests awaiting commitment will receive an
+    /// `Indeterminate` error.
+    fn check_quorum(&mut self, ctx: &mut Context<Self>) {
+        let state = match &mut self.state {
+            RaftState::Leader(state) => state,
+            _ => return,
+        };
+
+        // This node counts towards the majority of each config which it is a member of. In joint
+        // consensus, this node must be in contact with a majority of both the old & the new config.
+        let (id, timeout) = (self.id, Duration::from_millis(self.config.election_timeout_millis));
+        let in_contact = self.membership.is_majority(|target| target == &id || state.nodes.get(target)
+            .and_then(|rs| rs.last_heartbeat_ack)
+            .map(|stamp| stamp.elapsed() <= timeout)
+            .unwrap_or(false));
+        if in_contact {
+            return;
+        }
+
+        warn!("Node {} has lost contact with a majority of the cluster. Stepping down.", self.id);
+        for request in state.awaiting_committed.drain(..) {
+            let _ = request.respond_indeterminate()
+                .map_err(|_| error!("{} Error while responding to a request with an indeterminate outcome after losing quorum.", CLIENT_RPC_RX_ERR));
+        }
+        self.update_current_leader(ctx, UpdateCurrentLeader::Unknown);
+        self.become_follower(ctx);
+    }
+
     /// Update the node's current membership config.
     ///
     /// NOTE WELL: if a leader is stepping down, it should not call this method, as it will cause
@@ -584,7 +805,7 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
     }
 }
 
-impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> Actor for Raft<D, R, E, N, S> {
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Actor for Raft<D, R, E, N, S> {
     type Context = Context<Self>;
 
     /// The initialization routine for this actor.
@@ -593,7 +814,149 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
         let f = fut::wrap_future(self.storage.send::<GetInitialState<E>>(GetInitialState::new()))
             .map_err(|err, act: &mut Self, ctx| act.map_fatal_actix_messaging_error(ctx, err, DependencyAddr::RaftStorage))
             .and_then(|res, act, ctx| act.map_fatal_storage_result(ctx, res))
+            .and_then(|state, act, _| act.load_client_sessions(state.last_log_index, state.last_applied_log).map(move |_, _, _| state))
             .map(|state, act, ctx| act.initialize(ctx, state));
         ctx.spawn(f);
     }
 }
+
+/// Record the config entries among the given entries, which have been appended to the log.
+///
+/// Recorded configs which have been superseded by a config known to be committed are pruned, as
+/// they can never be reverted to. Returns the most recent of the given configs, if any.
+fn record_config_entries<'a, D: AppData>(
+    history: &mut BTreeMap<u64, MembershipConfig>, entries: impl Iterator<Item=&'a Entry<D>>, commit_index: u64,
+) -> Option<MembershipConfig> {
+    let mut last_conf = None;
+    for entry in entries {
+        if let EntryPayload::ConfigChange(conf) = &entry.payload {
+            history.insert(entry.index, conf.membership.clone());
+            last_conf = Some(conf.membership.clone());
+        }
+    }
+
+    let committed = history.range(..=commit_index).next_back().map(|(idx, _)| *idx);
+    if let Some(idx) = committed {
+        *history = history.split_off(&idx);
+    }
+    last_conf
+}
+
+/// Drop the recorded config entries at or beyond the given index, as the log is being truncated.
+///
+/// If any config entries were dropped, the latest remaining config is returned, which is the
+/// config which should now be in effect.
+fn truncate_config_entries(history: &mut BTreeMap<u64, MembershipConfig>, index: u64) -> Option<MembershipConfig> {
+    if history.split_off(&index).is_empty() {
+        return None;
+    }
+    history.values().next_back().cloned()
+}
+
+/// Rebase the recorded config entries onto a snapshot which has just been installed.
+///
+/// Configs covered by the snapshot are replaced by the snapshot's own config, as the entries
+/// which they came from are no longer in the log, & so can never be reverted to.
+fn rebase_config_entries(history: &mut BTreeMap<u64, MembershipConfig>, index: u64, membership: MembershipConfig) {
+    *history = history.split_off(&(index + 1));
+    history.insert(index, membership);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Serialize, Deserialize};
+    use crate::messages::EntryConfigChange;
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    struct TestData;
+
+    impl AppData for TestData {
+        fn encoded_len(&self) -> u64 {
+            0
+        }
+    }
+
+    fn config(members: Vec<NodeId>) -> MembershipConfig {
+        MembershipConfig{is_in_joint_consensus: false, members, non_voters: vec![], removing: vec![], learners: vec![], witnesses: vec![]}
+    }
+
+    fn config_entry(index: u64, members: Vec<NodeId>) -> Entry<TestData> {
+        Entry::new(EntryPayload::ConfigChange(EntryConfigChange{membership: config(members)}), index, 1)
+    }
+
+    fn blank_entry(index: u64) -> Entry<TestData> {
+        Entry::new(EntryPayload::Blank, index, 1)
+    }
+
+    //////////////////////////////////////////////////////////////////////////
+    // record_config_entries /////////////////////////////////////////////////
+
+    mod record_config_entries {
+        use super::*;
+
+        #[test]
+        fn records_config_entries_by_index_and_returns_the_latest() {
+            let mut history: BTreeMap<_, _> = vec![(0, config(vec![0]))].into_iter().collect();
+            let entries = [blank_entry(1), config_entry(2, vec![0, 1]), blank_entry(3), config_entry(4, vec![0, 1, 2])];
+            let output = record_config_entries(&mut history, entries.iter(), 0);
+            assert_eq!(output, Some(config(vec![0, 1, 2])));
+            assert_eq!(history.keys().cloned().collect::<Vec<_>>(), vec![0, 2, 4]);
+        }
+
+        #[test]
+        fn returns_none_without_config_entries() {
+            let mut history: BTreeMap<_, _> = vec![(0, config(vec![0]))].into_iter().collect();
+            let entries = [blank_entry(1), blank_entry(2)];
+            let output = record_config_entries(&mut history, entries.iter(), 0);
+            assert_eq!(output, None);
+            assert_eq!(history.keys().cloned().collect::<Vec<_>>(), vec![0]);
+        }
+
+        #[test]
+        fn prunes_configs_superseded_by_a_committed_config() {
+            let mut history: BTreeMap<_, _> = vec![(0, config(vec![0])), (2, config(vec![0, 1])), (4, config(vec![0, 1, 2]))].into_iter().collect();
+            let entries = [config_entry(6, vec![0, 1, 2, 3])];
+            record_config_entries(&mut history, entries.iter(), 5);
+            assert_eq!(history.keys().cloned().collect::<Vec<_>>(), vec![4, 6]);
+        }
+    }
+
+    //////////////////////////////////////////////////////////////////////////
+    // truncate_config_entries ///////////////////////////////////////////////
+
+    mod truncate_config_entries {
+        use super::*;
+
+        #[test]
+        fn reverts_to_the_latest_remaining_config() {
+            let mut history: BTreeMap<_, _> = vec![(0, config(vec![0])), (2, config(vec![0, 1])), (4, config(vec![0, 1, 2]))].into_iter().collect();
+            let output = truncate_config_entries(&mut history, 3);
+            assert_eq!(output, Some(config(vec![0, 1])));
+            assert_eq!(history.keys().cloned().collect::<Vec<_>>(), vec![0, 2]);
+        }
+
+        #[test]
+        fn returns_none_when_no_config_entries_are_truncated() {
+            let mut history: BTreeMap<_, _> = vec![(0, config(vec![0])), (2, config(vec![0, 1]))].into_iter().collect();
+            let output = truncate_config_entries(&mut history, 3);
+            assert_eq!(output, None);
+            assert_eq!(history.keys().cloned().collect::<Vec<_>>(), vec![0, 2]);
+        }
+    }
+
+    //////////////////////////////////////////////////////////////////////////
+    // rebase_config_entries /////////////////////////////////////////////////
+
+    mod rebase_config_entries {
+        use super::*;
+
+        #[test]
+        fn replaces_configs_covered_by_the_snapshot() {
+            let mut history: BTreeMap<_, _> = vec![(0, config(vec![0])), (2, config(vec![0, 1])), (6, config(vec![0, 1, 2, 3]))].into_iter().collect();
+            rebase_config_entries(&mut history, 4, config(vec![0, 1, 2]));
+            assert_eq!(history.keys().cloned().collect::<Vec<_>>(), vec![4, 6]);
+            assert_eq!(truncate_config_entries(&mut history, 5), Some(config(vec![0, 1, 2])));
+        }
+    }
+}
@@ -1,17 +1,16 @@
 use actix::prelude::*;
-use log::{debug, error, warn};
+use log::{debug, warn};
 
 use crate::{
     AppData, AppDataResponse, AppError,
-    common::{CLIENT_RPC_TX_ERR, ApplyLogsTask, DependencyAddr, UpdateCurrentLeader},
+    common::{DependencyAddr, UpdateCurrentLeader},
     config::SnapshotPolicy,
-    messages::{ClientPayloadResponse, ResponseMode},
     network::RaftNetwork,
-    raft::{Raft, RaftState, state::ConsensusState},
+    raft::{Raft, RaftState},
     replication::{
         RSFatalActixMessagingError, RSFatalStorageError,
         RSNeedsSnapshot, RSNeedsSnapshotResponse,
-        RSRateUpdate, RSUpdateLineCommit, RSRevertToFollower, RSUpdateMatchIndex,
+        RSHeartbeatAck, RSRateUpdate, RSUpdateLineCommit, RSUpdateWitness, RSRevertToFollower, RSUpdateMatchIndex,
     },
     storage::{CreateSnapshot, GetCurrentSnapshot, CurrentSnapshotData, RaftStorage},
 };
@@ -19,7 +18,7 @@ use crate::{
 //////////////////////////////////////////////////////////////////////////////////////////////////
 // RSFatalActixMessagingError ////////////////////////////////////////////////////////////////////
 
-impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> Handler<RSFatalActixMessagingError> for Raft<D, R, E, N, S> {
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Handler<RSFatalActixMessagingError> for Raft<D, R, E, N, S> {
     type Result = ();
 
     /// Handle events from replication streams reporting errors.
@@ -31,7 +30,7 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
 //////////////////////////////////////////////////////////////////////////////////////////////////
 // RSFatalStorageError ///////////////////////////////////////////////////////////////////////////
 
-impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> Handler<RSFatalStorageError<E>> for Raft<D, R, E, N, S> {
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Handler<RSFatalStorageError<E>> for Raft<D, R, E, N, S> {
     type Result = ();
 
     /// Handle events from replication streams reporting errors.
@@ -44,33 +43,46 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
 //////////////////////////////////////////////////////////////////////////////////////////////////
 // RSRateUpdate //////////////////////////////////////////////////////////////////////////////////
 
-impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> Handler<RSRateUpdate> for Raft<D, R, E, N, S> {
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Handler<RSRateUpdate> for Raft<D, R, E, N, S> {
     type Result = ();
 
     /// Handle events from replication streams updating their replication rate tracker.
-    fn handle(&mut self, msg: RSRateUpdate, ctx: &mut Self::Context) {
+    fn handle(&mut self, msg: RSRateUpdate, _: &mut Self::Context) {
         // Extract leader state, else do nothing.
         let state = match &mut self.state {
             RaftState::Leader(state) => state,
             _ => return,
         };
 
-        // Get a handle the target's replication stat & update it as needed.
-        match state.nodes.get_mut(&msg.target) {
-            Some(repl_state) => {
-                repl_state.is_at_line_rate = msg.is_line_rate;
-                // If in joint consensus, and the target node was one of the new nodes, update
-                // the joint consensus state to indicate that the target is up-to-date.
-                if let ConsensusState::Joint{new_nodes, is_committed} = &mut state.consensus_state {
-                    if let Some((idx, _)) = new_nodes.iter().enumerate().find(|(_, e)| *e == &msg.target) {
-                        new_nodes.remove(idx);
-                    }
-                    if *is_committed && new_nodes.len() == 0 && self.membership.is_in_joint_consensus {
-                        self.finalize_joint_consensus(ctx);
-                    }
-                }
-            },
-            _ => (),
+        // Get a handle the target's replication stat & update it as needed. Whether a new node
+        // has caught up is determined by its match index, as a stream reports that it is at line
+        // rate just before sending its last batch of outstanding entries.
+        if let Some(repl_state) = state.nodes.get_mut(&msg.target) {
+            repl_state.is_at_line_rate = msg.is_line_rate;
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////
+// RSHeartbeatAck ////////////////////////////////////////////////////////////////////////////////
+
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Handler<RSHeartbeatAck> for Raft<D, R, E, N, S> {
+    type Result = ();
+
+    /// Handle events from replication streams indicating that a heartbeat has been acknowledged.
+    fn handle(&mut self, msg: RSHeartbeatAck, _: &mut Self::Context) {
+        // Extract leader state, else do nothing.
+        let state = match &mut self.state {
+            RaftState::Leader(state) => state,
+            _ => return,
+        };
+
+        // Heartbeat responses may arrive out of order, so only ever move the ack stamp forward.
+        if let Some(repl_state) = state.nodes.get_mut(&msg.target) {
+            match repl_state.last_heartbeat_ack {
+                Some(stamp) if stamp >= msg.sent_at => (),
+                _ => repl_state.last_heartbeat_ack = Some(msg.sent_at),
+            }
         }
     }
 }
@@ -78,7 +90,7 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
 //////////////////////////////////////////////////////////////////////////////////////////////////
 // RSNeedsSnapshot ///////////////////////////////////////////////////////////////////////////////
 
-impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> Handler<RSNeedsSnapshot> for Raft<D, R, E, N, S> {
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Handler<RSNeedsSnapshot> for Raft<D, R, E, N, S> {
     type Result = ResponseActFuture<Self, RSNeedsSnapshotResponse, ()>;
 
     /// Handle events from replication streams requesting for snapshot info.
@@ -107,17 +119,17 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
                     // If snapshot exists, ensure its distance from the leader's last log index is <= half
                     // of the configured snapshot threshold, else create a new snapshot.
                     if snapshot_is_within_half_of_threshold(&meta, act.last_log_index, threshold) {
-                        let CurrentSnapshotData{index, term, membership, pointer} = meta;
+                        let CurrentSnapshotData{index, term, membership, pointer, ..} = meta;
                         return fut::Either::A(fut::ok(RSNeedsSnapshotResponse{index, term, membership, pointer}));
                     }
                 }
                 // If snapshot is not within half of threshold, or if snapshot does not exist, create a new snapshot.
                 // Create a new snapshot up through the committed index (to avoid jitter).
-                fut::Either::B(fut::wrap_future(act.storage.send::<CreateSnapshot<E>>(CreateSnapshot::new(act.commit_index)))
+                fut::Either::B(fut::wrap_future(act.storage.send::<CreateSnapshot<E>>(CreateSnapshot::new(act.commit_index, act.client_sessions_through(act.commit_index))))
                     .map_err(|err, act: &mut Self, ctx| act.map_fatal_actix_messaging_error(ctx, err, DependencyAddr::RaftStorage))
                     .and_then(|res, act, ctx| act.map_fatal_storage_result(ctx, res))
                     .and_then(|res, _, _| {
-                        let CurrentSnapshotData{index, term, membership, pointer} = res;
+                        let CurrentSnapshotData{index, term, membership, pointer, ..} = res;
                         fut::ok(RSNeedsSnapshotResponse{index, term, membership, pointer})
                     }))
             }))
@@ -127,14 +139,15 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
 //////////////////////////////////////////////////////////////////////////////////////////////////
 // RSRevertToFollower ////////////////////////////////////////////////////////////////////////////
 
-impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> Handler<RSRevertToFollower> for Raft<D, R, E, N, S> {
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Handler<RSRevertToFollower> for Raft<D, R, E, N, S> {
     type Result = ();
 
     /// Handle events from replication streams for when this node needs to revert to follower state.
     fn handle(&mut self, msg: RSRevertToFollower, ctx: &mut Self::Context) {
         if &msg.term > &self.current_term {
             self.update_current_term(msg.term, None);
-            self.save_hard_state(ctx);
+            let f = self.save_hard_state_async(ctx);
+            ctx.wait(f);
             self.update_current_leader(ctx, UpdateCurrentLeader::Unknown);
             self.become_follower(ctx);
         }
@@ -144,11 +157,11 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
 //////////////////////////////////////////////////////////////////////////////////////////////////
 // RSUpdateMatchIndex ////////////////////////////////////////////////////////////////////////////
 
-impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> Handler<RSUpdateMatchIndex> for Raft<D, R, E, N, S> {
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Handler<RSUpdateMatchIndex> for Raft<D, R, E, N, S> {
     type Result = ();
 
     /// Handle events from a replication stream which updates the target node's match index.
-    fn handle(&mut self, msg: RSUpdateMatchIndex, _: &mut Self::Context) {
+    fn handle(&mut self, msg: RSUpdateMatchIndex, ctx: &mut Self::Context) {
         // Extract leader state, else do nothing.
         let state = match &mut self.state {
             RaftState::Leader(state) => state,
@@ -174,11 +187,42 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
             state.nodes.remove(&msg.target);
         }
 
+        self.update_commit_index();
+
+        // If in joint consensus, and the target node was one of the new nodes, it is up-to-date
+        // once it has replicated all of this node's log.
+        if msg.match_index >= self.last_log_index {
+            self.handle_caught_up_node(ctx, msg.target);
+        }
+
+        // If leadership is being transferred to the target, it may now be up-to-date.
+        self.progress_leadership_transfer(ctx);
+    }
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Raft<D, R, E, N, S> {
+    /// Update the leader's commit index based on the match indices of the cluster.
+    ///
+    /// Client requests which are awaiting commitment will be sent over to be applied to the
+    /// state machine as the commit index advances.
+    pub(super) fn update_commit_index(&mut self) {
+        // Extract leader state, else do nothing.
+        let state = match &mut self.state {
+            RaftState::Leader(state) => state,
+            _ => return,
+        };
+
         // Parse through each targets' match index, and update the value of `commit_index` based
         // on the highest value which has been replicated to a majority of the cluster
-        // including the leader which created the entry.
-        let mut indices: Vec<_> = state.nodes.values().map(|elem| elem.match_index).collect();
-        indices.push(self.last_log_index);
+        // including the leader which created the entry, once it is durable in the leader's log.
+        // Permanent non-voters & nodes which have been removed never count toward commitment.
+        let membership = &self.membership;
+        let mut indices: Vec<_> = state.nodes.iter()
+            .filter(|(id, _)| membership.members.contains(id) || membership.non_voters.contains(id))
+            .map(|(_, elem)| elem.match_index).collect();
+        if membership.members.contains(&self.id) {
+            indices.push(state.durable_index);
+        }
         let new_commit_index = calculate_new_commit_index(indices, self.commit_index);
         let has_new_commit_index = new_commit_index > self.commit_index;
 
@@ -197,20 +241,30 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
                 .map(|(idx, _)| idx);
             if let Some(offset) = filter {
                 // Build a new ApplyLogsTask from each of the given client requests.
-                for request in state.awaiting_committed.drain(..=offset) {
-                    if let &ResponseMode::Committed = &request.response_mode {
-                        // If this RPC is configured to wait only for log committed, then respond to client now.
-                        let entry = request.entry();
-                        let _ = request.tx.send(Ok(ClientPayloadResponse::Committed{index: request.index})).map_err(|err| error!("{} {:?}", CLIENT_RPC_TX_ERR, err));
-                        let _ = self.apply_logs_pipeline.unbounded_send(ApplyLogsTask::Entry{entry, chan: None});
-                    } else {
-                        // Else, send it through the pipeline and it will be responded to afterwords.
-                        let _ = self.apply_logs_pipeline.unbounded_send(ApplyLogsTask::Entry{entry: request.entry(), chan: Some(request.tx)});
-                    }
+                let requests: Vec<_> = state.awaiting_committed.drain(..=offset).collect();
+                for request in requests {
+                    self.apply_committed_client_payload(request);
                 }
             }
         }
     }
+
+    /// Update each replication stream with its target's witness status, per this node's current config.
+    ///
+    /// This must be called whenever this node's config changes while it is leader, so that a
+    /// target named a witness by a config which is later truncated or reverted is no longer
+    /// treated as one.
+    pub(super) fn update_witness_streams(&mut self) {
+        let state = match &mut self.state {
+            RaftState::Leader(state) => state,
+            _ => return,
+        };
+        let config_index = self.membership_history.keys().next_back().cloned().unwrap_or(0);
+        for (target, node) in state.nodes.iter() {
+            let witness_index = if self.membership.witnesses.contains(target) { Some(config_index) } else { None };
+            node.addr.do_send(RSUpdateWitness(witness_index));
+        }
+    }
 }
 
 /// Determine the value for `current_commit` based on all known indicies of the cluster members.
@@ -257,7 +311,7 @@ fn snapshot_is_within_half_of_threshold(data: &CurrentSnapshotData, last_log_ind
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::messages::{EntrySnapshotPointer, MembershipConfig};
+    use crate::{messages::{EntrySnapshotPointer, MembershipConfig}, storage::ClientSessions};
 
     //////////////////////////////////////////////////////////////////////////
     // snapshot_is_within_half_of_threshold //////////////////////////////////
@@ -278,8 +332,8 @@ mod tests {
         test_snapshot_is_within_half_of_threshold!({
             test=>happy_path_true_when_within_half_threshold,
             data=>&CurrentSnapshotData{
-                term: 1, index: 50, membership: MembershipConfig{members: vec![], non_voters: vec![], removing: vec![], is_in_joint_consensus: false},
-                pointer: EntrySnapshotPointer{path: String::new()},
+                term: 1, index: 50, membership: MembershipConfig{members: vec![], non_voters: vec![], removing: vec![], learners: vec![], witnesses: vec![], is_in_joint_consensus: false},
+                client_sessions: ClientSessions::new(), pointer: EntrySnapshotPointer{path: String::new()},
             },
             last_log_index=>100, threshold=>500, expected=>true
         });
@@ -287,8 +341,8 @@ mod tests {
         test_snapshot_is_within_half_of_threshold!({
             test=>happy_path_false_when_above_half_threshold,
             data=>&CurrentSnapshotData{
-                term: 1, index: 1, membership: MembershipConfig{members: vec![], non_voters: vec![], removing: vec![], is_in_joint_consensus: false},
-                pointer: EntrySnapshotPointer{path: String::new()},
+                term: 1, index: 1, membership: MembershipConfig{members: vec![], non_voters: vec![], removing: vec![], learners: vec![], witnesses: vec![], is_in_joint_consensus: false},
+                client_sessions: ClientSessions::new(), pointer: EntrySnapshotPointer{path: String::new()},
             },
             last_log_index=>500, threshold=>100, expected=>false
         });
@@ -296,8 +350,8 @@ mod tests {
         test_snapshot_is_within_half_of_threshold!({
             test=>guards_against_underflow,
             data=>&CurrentSnapshotData{
-                term: 1, index: 200, membership: MembershipConfig{members: vec![], non_voters: vec![], removing: vec![], is_in_joint_consensus: false},
-                pointer: EntrySnapshotPointer{path: String::new()},
+                term: 1, index: 200, membership: MembershipConfig{members: vec![], non_voters: vec![], removing: vec![], learners: vec![], witnesses: vec![], is_in_joint_consensus: false},
+                client_sessions: ClientSessions::new(), pointer: EntrySnapshotPointer{path: String::new()},
             },
             last_log_index=>100, threshold=>500, expected=>true
         });
@@ -2,6 +2,7 @@
 use std::{
     collections::BTreeMap,
     fmt,
+    time::Instant,
 };
 
 use actix::prelude::*;
@@ -9,6 +10,7 @@ use futures::sync::{mpsc, oneshot};
 
 use crate::{
     AppData, AppDataResponse, AppError, NodeId,
+    admin::{ChangeMembershipError, TransferLeadershipError},
     common::{ClientPayloadWithIndex, ClientPayloadWithChan},
     messages::{MembershipConfig},
     network::RaftNetwork,
@@ -18,7 +20,9 @@ use crate::{
 
 
 /// The state of the Raft node.
-pub(crate) enum RaftState<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> {
+// Not boxed, as there is only ever one instance of this state per Raft node.
+#[allow(clippy::large_enum_variant)]
+pub(crate) enum RaftState<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> {
     /// A non-standard Raft state indicating that the node is initializing.
     Initializing,
     /// The node is completely passive; replicating entries, but not voting or timing out.
@@ -28,7 +32,8 @@ pub(crate) enum RaftState<D: AppData, R: AppDataResponse, E: AppError, N: RaftNe
     /// calculating replication majority, and it is not solicited for votes.
     ///
     /// A node will only be in this state when it first comes online without any existing config
-    /// on disk, or when it has been removed from an existing cluster.
+    /// on disk, when it has been removed from an existing cluster, or when it has been added to
+    /// the cluster as a permanent non-voter via the `AddNonVoter` command.
     ///
     /// In such a state, the parent application may have this new node added to an already running
     /// cluster, may have the node start as the leader of a new standalone cluster, may have the
@@ -52,7 +57,7 @@ pub(crate) enum RaftState<D: AppData, R: AppDataResponse, E: AppError, N: RaftNe
     Leader(LeaderState<D, R, E, N, S>),
 }
 
-impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> RaftState<D, R, E, N, S> {
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> RaftState<D, R, E, N, S> {
     /// Check if currently in follower state.
     pub fn is_follower(&self) -> bool {
         match self {
@@ -62,7 +67,6 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
     }
 
     /// Check if currently in leader state.
-    #[allow(dead_code)]
     pub fn is_leader(&self) -> bool {
         match self {
             RaftState::Leader(_) => true,
@@ -79,7 +83,7 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
     }
 }
 
-impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> fmt::Display for RaftState<D, R, E, N, S> {
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> fmt::Display for RaftState<D, R, E, N, S> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let state = match self {
             RaftState::Initializing => "Initializing",
@@ -92,23 +96,60 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
     }
 }
 
+/// The response channel of a `ChangeMembership` command awaiting a uniform cluster config.
+pub(crate) type UniformConfigTx<D, R, E> = oneshot::Sender<Result<(), ChangeMembershipError<D, R, E>>>;
+
 /// Volatile state specific to the Raft leader.
 ///
 /// This state is reinitialized after an election.
-pub(crate) struct LeaderState<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> {
+pub(crate) struct LeaderState<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> {
     /// A mapping of node IDs the replication state of the target node.
     pub nodes: BTreeMap<NodeId, ReplicationState<D, R, E, N, S>>,
-    /// A queue of client requests to be processed.
-    pub client_request_queue: mpsc::UnboundedSender<ClientPayloadWithChan<D, R, E>>,
+    /// A queue of client requests to be processed, bounded by the configured `client_queue_capacity`.
+    pub client_request_queue: mpsc::Sender<ClientPayloadWithChan<D, R, E>>,
     /// A buffer of client requests which have been appended locally and are awaiting to be committed to the cluster.
     pub awaiting_committed: Vec<ClientPayloadWithIndex<D, R, E>>,
     /// A field tracking the cluster's current consensus state, which is used for dynamic membership.
     pub consensus_state: ConsensusState,
+    /// Channels used to notify `ChangeMembership` commands once the cluster has left joint consensus.
+    pub awaiting_uniform_config: Vec<UniformConfigTx<D, R, E>>,
+    /// The handle of the task which aborts the current joint consensus config change, if its new
+    /// nodes have not caught up by the configured `catch_up_timeout`.
+    pub catch_up_deadline: Option<SpawnHandle>,
+    /// The index of the last entry known to be durable in this leader's own log.
+    ///
+    /// This is the leader's own match index, which counts toward commitment. When parallel append
+    /// is enabled, it may trail the leader's last log index while a local append is in flight.
+    pub durable_index: u64,
+    /// The index of the first entry appended by this leader in its term.
+    ///
+    /// This is the blank (or initial config) entry appended upon election. Until it has been
+    /// committed, the leader can not know which entries from previous terms are committed (§8).
+    pub term_start_index: u64,
+    /// The handle of the recurring task which checks that the leader still has contact with a quorum.
+    pub check_quorum: Option<SpawnHandle>,
+    /// The state of the current leadership transfer, if one is in progress.
+    ///
+    /// While a transfer is in progress, new client payloads will not be accepted.
+    pub leadership_transfer: Option<LeadershipTransfer>,
+    /// The time at which this leader last sent a `TimeoutNowRequest`, if ever.
+    ///
+    /// Heartbeats acknowledged before then no longer count toward the leader's lease, even once
+    /// the transfer has timed out, as the target may still be elected without waiting for it.
+    pub timeout_now_sent_at: Option<Instant>,
+    /// The latest response produced by this leader's state machine for each client session,
+    /// keyed by client ID, along with the index of its entry.
+    ///
+    /// This is used to answer retried client requests with the response of the original request.
+    pub session_responses: BTreeMap<u64, (u64, R)>,
+    /// Retried client requests which are awaiting the last entry of their original request, at
+    /// the given index, to be applied.
+    pub awaiting_duplicates: Vec<(u64, ClientPayloadWithChan<D, R, E>)>,
 }
 
-impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> LeaderState<D, R, E, N, S> {
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> LeaderState<D, R, E, N, S> {
     /// Create a new instance.
-    pub fn new(tx: mpsc::UnboundedSender<ClientPayloadWithChan<D, R, E>>, membership: &MembershipConfig) -> Self {
+    pub fn new(tx: mpsc::Sender<ClientPayloadWithChan<D, R, E>>, membership: &MembershipConfig, term_start_index: u64) -> Self {
         let consensus_state = if membership.is_in_joint_consensus {
             ConsensusState::Joint{
                 new_nodes: membership.non_voters.clone(),
@@ -117,15 +158,34 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
         } else {
             ConsensusState::Uniform
         };
-        Self{nodes: Default::default(), client_request_queue: tx, awaiting_committed: vec![], consensus_state}
+        Self{
+            nodes: Default::default(), client_request_queue: tx, awaiting_committed: vec![], consensus_state, awaiting_uniform_config: vec![],
+            durable_index: term_start_index - 1, term_start_index, check_quorum: None, catch_up_deadline: None, leadership_transfer: None,
+            timeout_now_sent_at: None, session_responses: BTreeMap::new(), awaiting_duplicates: vec![],
+        }
     }
 }
 
+/// A struct tracking the state of a leadership transfer (§3.10).
+pub(crate) struct LeadershipTransfer {
+    /// The ID of the node which leadership is being transferred to.
+    pub target: NodeId,
+    /// The term in which the transfer was started.
+    ///
+    /// Once this node observes a newer term and steps down, the transfer is complete.
+    pub term: u64,
+    /// A flag indicating if the `TimeoutNowRequest` RPC has been sent to the target.
+    pub timeout_now_sent: bool,
+    /// The channel used to respond to the `TransferLeadership` command.
+    pub tx: oneshot::Sender<Result<(), TransferLeadershipError>>,
+}
+
 /// A struct tracking the state of a replication stream from the perspective of the Raft actor.
-pub(crate) struct ReplicationState<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> {
+pub(crate) struct ReplicationState<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> {
     pub match_index: u64,
     pub is_at_line_rate: bool,
     pub remove_after_commit: Option<u64>,
+    pub last_heartbeat_ack: Option<Instant>,
     pub addr: Addr<ReplicationStream<D, R, E, N, S>>,
 }
 
@@ -141,7 +201,11 @@ pub(crate) enum ConsensusState {
         /// NOTE: when a new leader is elected, it will initialize this value to false, and then
         /// update this value to true once the new leader's blank payload has been committed.
         is_committed: bool,
-    }
+    },
+    /// A single-server membership change has been appended & is awaiting commitment.
+    ///
+    /// Only one such change may be uncommitted at a time, per §4.1 of the Raft thesis.
+    SingleServer,
 }
 
 /// Volatile state specific to a Raft node in candidate state.
@@ -152,6 +216,11 @@ pub(crate) struct CandidateState {
     pub(crate) votes_granted: u64,
     /// The number of votes needed in order to become the Raft leader.
     pub(crate) votes_needed: u64,
+    /// A flag indicating if this campaign is a Pre-Vote round (§9.6).
+    ///
+    /// During a Pre-Vote round the node's term has not been incremented. Once enough votes have
+    /// been granted, the node will start a real election.
+    pub(crate) is_pre_vote: bool,
 }
 
 impl CandidateState {
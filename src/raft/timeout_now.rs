@@ -0,0 +1,65 @@
+use actix::prelude::*;
+use log::info;
+
+use crate::{
+    AppData, AppDataResponse, AppError, NodeId,
+    common::{DependencyAddr, UpdateCurrentLeader},
+    messages::{TimeoutNowRequest, TimeoutNowResponse},
+    network::RaftNetwork,
+    raft::{RaftState, Raft},
+    storage::RaftStorage,
+};
+
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Handler<TimeoutNowRequest> for Raft<D, R, E, N, S> {
+    type Result = ResponseActFuture<Self, TimeoutNowResponse, ()>;
+
+    /// An RPC invoked by the leader to have this node start an election immediately (§3.10).
+    ///
+    /// Receiver implementation:
+    ///
+    /// 1. Reply with the current term if `term` is less than receiver's current `term` (§5.1).
+    /// 2. If the receiver is a follower, skip the Pre-Vote round & start a new election, flagging
+    ///    the election as part of a leadership transfer.
+    fn handle(&mut self, msg: TimeoutNowRequest, ctx: &mut Self::Context) -> Self::Result {
+        // Only handle requests if actor has finished initialization.
+        if let &RaftState::Initializing = &self.state {
+            return Box::new(fut::err(()));
+        }
+
+        // Ignore requests from stale leaders.
+        if msg.term < self.current_term {
+            return Box::new(fut::ok(TimeoutNowResponse{term: self.current_term}));
+        }
+
+        // Only followers which are not witnesses may campaign. Non-voters are never in the follower state.
+        if !self.state.is_follower() || self.membership.witnesses.contains(&self.id) {
+            return Box::new(fut::ok(TimeoutNowResponse{term: self.current_term}));
+        }
+
+        info!("Node {} received a TimeoutNow request from leader {}. Starting an election.", self.id, msg.leader_id);
+        self.update_current_term(msg.term, None);
+        self.start_election(ctx, true);
+        Box::new(fut::ok(TimeoutNowResponse{term: self.current_term}))
+    }
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Raft<D, R, E, N, S> {
+    /// Instruct the target peer to start an election immediately.
+    pub(super) fn send_timeout_now(&mut self, _: &mut Context<Self>, target: NodeId) -> impl ActorFuture<Actor=Self, Item=(), Error=()> {
+        let rpc = TimeoutNowRequest::new(target, self.current_term, self.id);
+        fut::wrap_future(self.network.send(rpc))
+            .map_err(|err, act: &mut Self, ctx| act.map_fatal_actix_messaging_error(ctx, err, DependencyAddr::RaftNetwork))
+            .and_then(|res, _, _| fut::result(res))
+            .and_then(|res, act, ctx| {
+                // If the target has started its election, then step down, which completes the transfer.
+                if res.term > act.current_term {
+                    act.update_current_term(res.term, None);
+                    let f = act.save_hard_state_async(ctx);
+                    ctx.wait(f);
+                    act.update_current_leader(ctx, UpdateCurrentLeader::Unknown);
+                    act.become_follower(ctx);
+                }
+                fut::ok(())
+            })
+    }
+}
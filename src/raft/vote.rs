@@ -1,15 +1,17 @@
+use std::time::Instant;
+
 use actix::prelude::*;
 
 use crate::{
     AppData, AppDataResponse, AppError, NodeId,
     common::{DependencyAddr, UpdateCurrentLeader},
-    messages::{VoteRequest, VoteResponse},
+    messages::{PreVoteRequest, PreVoteResponse, VoteRequest, VoteResponse},
     network::RaftNetwork,
     raft::{RaftState, Raft},
     storage::RaftStorage,
 };
 
-impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> Handler<VoteRequest> for Raft<D, R, E, N, S> {
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Handler<VoteRequest> for Raft<D, R, E, N, S> {
     type Result = ResponseActFuture<Self, VoteResponse, ()>;
 
     /// An RPC invoked by candidates to gather votes (§5.2).
@@ -25,11 +27,58 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
             return Box::new(fut::err(()));
         }
 
-        Box::new(fut::result(self.handle_vote_request(ctx, msg)))
+        // Respond only once any change to this node's term or vote is durable.
+        let (term, voted_for) = (self.current_term, self.voted_for);
+        let res = fut::result(self.handle_vote_request(ctx, msg));
+        self.respond_once_durable(ctx, term, voted_for, res)
+    }
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Handler<PreVoteRequest> for Raft<D, R, E, N, S> {
+    type Result = ResponseActFuture<Self, PreVoteResponse, ()>;
+
+    /// An RPC invoked by prospective candidates to check if they could win an election (§9.6).
+    ///
+    /// Receiver implementation:
+    ///
+    /// 1. Reply `false` if `term` is less than receiver's current `term` (§5.1).
+    /// 2. Reply `false` if the receiver is the leader, or if it has heard from the current leader
+    ///    within its election timeout.
+    /// 3. If candidate’s log is atleast as up-to-date as receiver’s log, reply `true` (§5.4).
+    ///
+    /// The receiver's term & vote are never updated as part of handling this RPC.
+    fn handle(&mut self, msg: PreVoteRequest, _: &mut Self::Context) -> Self::Result {
+        // Only handle requests if actor has finished initialization.
+        if let &RaftState::Initializing = &self.state {
+            return Box::new(fut::err(()));
+        }
+
+        Box::new(fut::ok(self.handle_pre_vote_request(msg)))
     }
 }
 
-impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> Raft<D, R, E, N, S> {
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Raft<D, R, E, N, S> {
+    /// Business logic of handling a `PreVoteRequest` RPC.
+    fn handle_pre_vote_request(&mut self, msg: PreVoteRequest) -> PreVoteResponse {
+        // Don't interact with non-cluster members.
+        if !self.membership.contains(&msg.candidate_id) {
+            return PreVoteResponse{term: self.current_term, vote_granted: false, is_candidate_unknown: true};
+        }
+
+        // If candidate's prospective term is less than this nodes current term, reject.
+        if msg.term < self.current_term {
+            return PreVoteResponse{term: self.current_term, vote_granted: false, is_candidate_unknown: false};
+        }
+
+        // If there is a live leader, the candidate would only disrupt the cluster, so reject.
+        if self.state.is_leader() || self.has_current_leader_contact() {
+            return PreVoteResponse{term: self.current_term, vote_granted: false, is_candidate_unknown: false};
+        }
+
+        let vote_granted = self.candidate_log_is_uptodate(msg.last_log_index, msg.last_log_term);
+        PreVoteResponse{term: self.current_term, vote_granted, is_candidate_unknown: false}
+    }
+
     /// Business logic of handling a `VoteRequest` RPC.
     fn handle_vote_request(&mut self, ctx: &mut Context<Self>, msg: VoteRequest) -> Result<VoteResponse, ()> {
         // Don't interact with non-cluster members.
@@ -42,17 +91,23 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
             return Ok(VoteResponse{term: self.current_term, vote_granted: false, is_candidate_unknown: false});
         }
 
+        // If lease reads are enabled, and this node has heard from the current leader within its
+        // election timeout, then reject the request without updating the term (§4.2.3). The
+        // leader's lease depends upon this. Candidates started by a leadership transfer are exempt,
+        // as the leader gives up its lease before asking the candidate to take over.
+        if self.config.lease_reads && !msg.disrupt_leader && self.has_current_leader_contact() {
+            return Ok(VoteResponse{term: self.current_term, vote_granted: false, is_candidate_unknown: false});
+        }
+
         // Per spec, if we observe a term greater than our own, we must update
         // term & immediately become follower, we still need to do vote checking after this.
         if &msg.term > &self.current_term {
             self.update_current_term(msg.term, None);
-            self.save_hard_state(ctx);
         }
 
         // Check if candidate's log is at least as up-to-date as this node's.
         // If candidate's log is not at least as up-to-date as this node, then reject.
-        let client_is_uptodate = (&msg.last_log_term >= &self.last_log_term) && (&msg.last_log_index >= &self.last_log_index);
-        if !client_is_uptodate {
+        if !self.candidate_log_is_uptodate(msg.last_log_index, msg.last_log_term) {
             return Ok(VoteResponse{term: self.current_term, vote_granted: false, is_candidate_unknown: false});
         }
 
@@ -67,7 +122,6 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
             // This node has not already voted, so vote for the candidate.
             None => {
                 self.voted_for = Some(msg.candidate_id);
-                self.save_hard_state(ctx);
                 self.update_election_timeout_stamp();
                 self.become_follower(ctx);
                 Ok(VoteResponse{term: self.current_term, vote_granted: true, is_candidate_unknown: false})
@@ -75,9 +129,29 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
         }
     }
 
+    /// Check if a candidate's log is at least as up-to-date as this node's log (§5.4.1).
+    ///
+    /// If the logs have last entries with different terms, then the log with the later term is
+    /// more up-to-date, regardless of its length. If the logs end with the same term, then
+    /// whichever log is longer is more up-to-date.
+    fn candidate_log_is_uptodate(&self, last_log_index: u64, last_log_term: u64) -> bool {
+        last_log_term > self.last_log_term || (last_log_term == self.last_log_term && last_log_index >= self.last_log_index)
+    }
+
+    /// Check if this node is a follower which has heard from the current leader within its election timeout.
+    fn has_current_leader_contact(&self) -> bool {
+        if !self.state.is_follower() || self.current_leader.is_none() {
+            return false;
+        }
+        match &self.election_timeout_stamp {
+            Some(stamp) => Instant::now() < *stamp,
+            None => false,
+        }
+    }
+
     /// Request a vote from the the target peer.
-    pub(super) fn request_vote(&mut self, _: &mut Context<Self>, target: NodeId) -> impl ActorFuture<Actor=Self, Item=(), Error=()> {
-        let rpc = VoteRequest::new(target, self.current_term, self.id, self.last_log_index, self.last_log_term);
+    pub(super) fn request_vote(&mut self, _: &mut Context<Self>, target: NodeId, disrupt_leader: bool) -> impl ActorFuture<Actor=Self, Item=(), Error=()> {
+        let rpc = VoteRequest::new(target, self.current_term, self.id, self.last_log_index, self.last_log_term, disrupt_leader);
         fut::wrap_future(self.network.send(rpc))
             .map_err(|err, act: &mut Self, ctx| act.map_fatal_actix_messaging_error(ctx, err, DependencyAddr::RaftNetwork))
             .and_then(|res, _, _| fut::result(res))
@@ -103,7 +177,8 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
                     act.update_current_term(res.term, None);
                     act.update_current_leader(ctx, UpdateCurrentLeader::Unknown);
                     act.become_follower(ctx);
-                    act.save_hard_state(ctx);
+                    let f = act.save_hard_state_async(ctx);
+                    ctx.wait(f);
                     return fut::ok(());
                 }
 
@@ -119,4 +194,50 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
                 fut::ok(())
             })
     }
+
+    /// Request a pre-vote from the the target peer (§9.6).
+    pub(super) fn request_pre_vote(&mut self, _: &mut Context<Self>, target: NodeId) -> impl ActorFuture<Actor=Self, Item=(), Error=()> {
+        let rpc = PreVoteRequest::new(target, self.current_term + 1, self.id, self.last_log_index, self.last_log_term);
+        fut::wrap_future(self.network.send(rpc))
+            .map_err(|err, act: &mut Self, ctx| act.map_fatal_actix_messaging_error(ctx, err, DependencyAddr::RaftNetwork))
+            .and_then(|res, _, _| fut::result(res))
+            .and_then(move |res, act, ctx| {
+                // Ensure the node is still campaigning in a Pre-Vote round.
+                let state = match &mut act.state {
+                    RaftState::Candidate(state) if state.is_pre_vote => state,
+                    _ => {
+                        return fut::ok(());
+                    }
+                };
+
+                // If responding node sees this node as being unknown to the cluster, and this
+                // node has been active, then go into NonVoter state, as this typically means this
+                // node is being removed from the cluster.
+                if res.is_candidate_unknown && act.last_log_index > 0 {
+                    act.become_non_voter(ctx);
+                    return fut::ok(());
+                }
+
+                // If peer's term is greater than current term, revert to follower state.
+                if res.term > act.current_term {
+                    act.update_current_term(res.term, None);
+                    act.update_current_leader(ctx, UpdateCurrentLeader::Unknown);
+                    act.become_follower(ctx);
+                    let f = act.save_hard_state_async(ctx);
+                    ctx.wait(f);
+                    return fut::ok(());
+                }
+
+                // If peer granted its pre-vote, then update campaign state.
+                if res.vote_granted {
+                    state.votes_granted += 1;
+                    if state.votes_granted >= state.votes_needed {
+                        // A majority would grant their votes, so start a real election.
+                        act.start_election(ctx, false);
+                    }
+                }
+
+                fut::ok(())
+            })
+    }
 }
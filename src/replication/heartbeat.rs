@@ -1,3 +1,5 @@
+use std::time::Instant;
+
 use actix::prelude::*;
 
 use crate::{
@@ -7,26 +9,31 @@ use crate::{
         AppendEntriesRequest, AppendEntriesResponse,
     },
     network::RaftNetwork,
-    replication::{ReplicationStream, RSRevertToFollower},
+    replication::{ReplicationStream, RSConfirmLeadership, RSHeartbeatAck, RSRevertToFollower},
     storage::{RaftStorage},
 };
 
-impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> ReplicationStream<D, R, E, N, S> {
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> ReplicationStream<D, R, E, N, S> {
 
     /// Handle heartbeat responses.
     ///
     /// For heartbeat responses, we really only care about checking for more recent terms. We
     /// don't do any conflict resolution or anything like that with heartbeats.
-    fn handle_heartbeat_response(&mut self, _: &mut Context<Self>, res: AppendEntriesResponse) -> impl ActorFuture<Actor=Self, Item=(), Error=()> {
+    ///
+    /// If a more recent term is observed, an error will be returned, as the target node does not
+    /// recognize this node's leadership. Otherwise, the Raft node will be notified that the target
+    /// acknowledged the heartbeat sent at `sent_at`.
+    fn handle_heartbeat_response(&mut self, _: &mut Context<Self>, res: AppendEntriesResponse, sent_at: Instant) -> impl ActorFuture<Actor=Self, Item=(), Error=()> {
         // Replication was not successful, if a newer term has been returned, revert to follower.
         if &res.term > &self.term {
             fut::Either::A(fut::wrap_future(self.raftnode.send(RSRevertToFollower{target: self.target, term: res.term}))
                 .map_err(|err, act: &mut Self, ctx| act.map_fatal_actix_messaging_error(ctx, err, DependencyAddr::RaftInternal))
-                .then(|res, _, ctx| {
+                .then(|_, _, ctx| {
                     ctx.terminate(); // Terminate this replication stream.
-                    fut::result(res)
+                    fut::err(())
                 }))
         } else {
+            self.raftnode.do_send(RSHeartbeatAck{target: self.target, sent_at});
             fut::Either::B(fut::ok(()))
         }
     }
@@ -51,9 +58,22 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
         };
 
         // Send the payload.
+        let sent_at = Instant::now();
         fut::wrap_future(self.network.send(payload))
             .map_err(|err, act: &mut Self, ctx| act.map_fatal_actix_messaging_error(ctx, err, DependencyAddr::RaftNetwork))
             .and_then(|res, _, _| fut::result(res))
-            .and_then(|res, act, ctx| act.handle_heartbeat_response(ctx, res))
+            .and_then(move |res, act, ctx| act.handle_heartbeat_response(ctx, res, sent_at))
+    }
+}
+
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Handler<RSConfirmLeadership> for ReplicationStream<D, R, E, N, S> {
+    type Result = ResponseActFuture<Self, (), ()>;
+
+    /// Handle a request to confirm the leadership of the Raft node with the target.
+    ///
+    /// A heartbeat is sent immediately, independent of the heartbeat interval. This will resolve
+    /// successfully only if the target responds and recognizes this node's current term.
+    fn handle(&mut self, _: RSConfirmLeadership, ctx: &mut Self::Context) -> Self::Result {
+        Box::new(self.heartbeat_send(ctx))
     }
 }
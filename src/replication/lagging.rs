@@ -10,11 +10,11 @@ use crate::{
     config::SnapshotPolicy,
     messages::{AppendEntriesRequest, EntryPayload},
     network::RaftNetwork,
-    replication::{ReplicationStream, RSRateUpdate, RSState},
+    replication::{ReplicationStream, RSRateUpdate, RSState, payload_len},
     storage::{RaftStorage, GetLogEntries},
 };
 
-impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> ReplicationStream<D, R, E, N, S> {
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> ReplicationStream<D, R, E, N, S> {
     /// Drive the replication stream forward when it is in state `Lagging`.
     pub(super) fn drive_state_lagging(&mut self, ctx: &mut Context<Self>) {
         let state = match &mut self.state {
@@ -26,7 +26,7 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
         };
 
         // A few values to be moved into future closures.
-        let (prev_log_index, prev_log_term) = (self.match_index, self.match_term);
+        let (prev_log_index, prev_log_term, line_index) = (self.match_index, self.match_term, self.line_index);
         let start = self.next_index;
         let batch_will_reach_line = (self.next_index > self.line_index) || ((self.line_index - self.next_index) < self.config.max_payload_entries);
 
@@ -72,7 +72,7 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
             .and_then(|res, act, ctx| act.map_fatal_storage_result(ctx, res))
 
             // We have a successful payload of entries, send it to the target.
-            .and_then(move |entries, act, ctx| {
+            .and_then(move |mut entries, act, ctx| {
                 // If a snapshot pointer is included in the payload, then we need to transition to snapshotting state.
                 for entry in entries.iter() {
                     match entry.payload {
@@ -84,6 +84,20 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
                     }
                 }
 
+                // Keep the payload within the configured size limit. Any entries which are cut off
+                // will be sent as part of the next payload.
+                let len = payload_len(entries.iter(), act.config.max_payload_entries, act.config.max_payload_bytes);
+                entries.truncate(len);
+
+                // When the leader appends in parallel, the storage engine may not yet have all
+                // entries through the line index, & the payload may have been cut off by its size
+                // limit. If so, the stream is not yet ready for line rate.
+                if let RSState::Lagging(inner) = &mut act.state {
+                    if entries.last().map(|elem| elem.index).unwrap_or(prev_log_index) < line_index {
+                        inner.is_ready_for_line_rate = false;
+                    }
+                }
+
                 let last_log_and_index = entries.last().map(|elem| (elem.index, elem.term));
                 let payload = AppendEntriesRequest{
                     target: act.target, term: act.term, leader_id: act.id,
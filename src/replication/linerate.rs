@@ -1,16 +1,22 @@
-
 use actix::prelude::*;
 
 use crate::{
     AppData, AppDataResponse, AppError,
-    messages::{AppendEntriesRequest},
+    messages::{AppendEntriesRequest, AppendEntriesResponse},
     network::RaftNetwork,
-    replication::{ReplicationStream, RSState},
+    replication::{ReplicationStream, RSState, payload_len},
     storage::{RaftStorage},
 };
 
-impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> ReplicationStream<D, R, E, N, S> {
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> ReplicationStream<D, R, E, N, S> {
     /// Drive the replication stream forward when it is in state `LineRate`.
+    ///
+    /// Requests are pipelined to the target. This routine does not wait for the response to the
+    /// request it sends, so the state loop may send the next payload as soon as entries are
+    /// buffered, as long as the number of in-flight requests is within the configured window.
+    ///
+    /// Each payload is limited per `max_payload_entries` & `max_payload_bytes`. Any entries which
+    /// do not fit remain buffered for the next payload.
     pub(super) fn drive_state_line_rate(&mut self, ctx: &mut Context<Self>) {
         let state = match &mut self.state {
             RSState::LineRate(state) => state,
@@ -20,42 +26,81 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
             },
         };
 
-        // If there is a buffered payload, send it, else nothing to do.
-        if state.buffered_outbound.len() > 0 {
-            let entries: Vec<_> = state.buffered_outbound.drain(..).map(|elem| (*elem).clone()).collect();
-            let last_index_and_term = entries.last().map(|e| (e.index, e.term));
+        // If there is a buffered payload & room in the in-flight window, send it, else nothing to do.
+        let is_window_full = state.in_flight.len() as u64 >= self.config.max_inflight_payloads;
+        if !state.buffered_outbound.is_empty() && !is_window_full {
+            // The next request builds upon the last in-flight request, if any.
+            let (prev_log_index, prev_log_term) = state.in_flight.back().cloned().unwrap_or((self.match_index, self.match_term));
+            let len = payload_len(state.buffered_outbound.iter().map(|elem| elem.as_ref()), self.config.max_payload_entries, self.config.max_payload_bytes);
+            let entries: Vec<_> = state.buffered_outbound.drain(..len).map(|elem| (*elem).clone()).collect();
+            state.buffered_bytes -= entries.iter().map(|entry| entry.encoded_len()).sum::<u64>();
+            let last_index_and_term = entries.last().map(|e| (e.index, e.term)).unwrap_or((prev_log_index, prev_log_term));
+            state.in_flight.push_back(last_index_and_term);
             let payload = AppendEntriesRequest{
                 target: self.target, term: self.term, leader_id: self.id,
-                prev_log_index: self.match_index,
-                prev_log_term: self.match_term,
+                prev_log_index, prev_log_term,
                 entries, leader_commit: self.line_commit,
             };
 
             // Send the payload.
             let f = self.send_append_entries(ctx, payload)
                 // Process the response.
-                .and_then(move |res, act, ctx| act.handle_append_entries_response(ctx, res, last_index_and_term))
+                .and_then(move |res, act, ctx| act.handle_line_rate_response(ctx, res, last_index_and_term))
 
                 // Drive state forward regardless of outcome.
-                .then(|res, act, ctx| {
-                    act.is_driving_state = false;
-                    match res {
-                        Ok(_) => {
-                            act.drive_state(ctx);
-                            fut::Either::A(fut::result(res))
-                        }
-                        Err(_) => {
-                            fut::Either::B(act.transition_to_lagging(ctx)
-                                .then(|res, act, ctx| {
-                                    act.drive_state(ctx);
-                                    fut::result(res)
-                                }))
-                        }
+                .then(move |res, act, ctx| match res {
+                    // If the request is no longer in-flight, the stream has already left line rate.
+                    Err(_) if act.is_in_flight(last_index_and_term) => {
+                        fut::Either::B(act.transition_to_lagging(ctx)
+                            .then(|res, act, ctx| {
+                                act.drive_state(ctx);
+                                fut::result(res)
+                            }))
+                    }
+                    _ => {
+                        act.drive_state(ctx);
+                        fut::Either::A(fut::result(res))
                     }
                 });
             ctx.spawn(f);
-        } else {
-            self.is_driving_state = false;
+
+            // If entries remain buffered, send them on as the next payload.
+            if let RSState::LineRate(state) = &self.state {
+                if !state.buffered_outbound.is_empty() {
+                    self.is_driving_state = false;
+                    return self.drive_state(ctx);
+                }
+            }
+        }
+        self.is_driving_state = false;
+    }
+
+    /// Handle the response to an AppendEntries RPC which was pipelined at line rate.
+    ///
+    /// The response is matched to its request by the index & term of the request's last entry.
+    /// If the request is no longer in-flight, then the stream has left line rate since the request
+    /// was sent, and the response is ignored, unless it carries a newer term.
+    fn handle_line_rate_response(
+        &mut self, ctx: &mut Context<Self>, res: AppendEntriesResponse, last_index_and_term: (u64, u64),
+    ) -> Box<dyn ActorFuture<Actor=Self, Item=(), Error=()> + 'static> {
+        if !self.is_in_flight(last_index_and_term) && res.term <= self.term {
+            return Box::new(fut::ok(()));
+        }
+
+        // Stop tracking the acknowledged request, along with any which were sent before it.
+        if let (true, RSState::LineRate(state)) = (res.success, &mut self.state) {
+            while state.in_flight.front().map(|(index, _)| index <= &last_index_and_term.0).unwrap_or(false) {
+                state.in_flight.pop_front();
+            }
+        }
+        self.handle_append_entries_response(ctx, res, Some(last_index_and_term))
+    }
+
+    /// Check if a request with the given last index & term is in-flight at line rate.
+    fn is_in_flight(&self, last_index_and_term: (u64, u64)) -> bool {
+        match &self.state {
+            RSState::LineRate(state) => state.in_flight.contains(&last_index_and_term),
+            _ => false,
         }
     }
 }
@@ -8,17 +8,22 @@ mod lagging;
 mod linerate;
 mod snapshot;
 
-use std::sync::Arc;
+use std::{
+    collections::VecDeque,
+    sync::Arc,
+    time::Instant,
+};
 
 use actix::prelude::*;
+use log::{debug};
 
 use crate::{
     AppData, AppDataResponse, AppError, NodeId,
     common::DependencyAddr,
     config::{Config, SnapshotPolicy},
     messages::{
-        AppendEntriesRequest, AppendEntriesResponse,
-        Entry, EntrySnapshotPointer, MembershipConfig,
+        AppendEntriesRequest, AppendEntriesResponse, ConflictOpt,
+        Entry, EntryPayload, EntrySnapshotPointer, MembershipConfig,
     },
     network::RaftNetwork,
     raft::{Raft},
@@ -43,13 +48,21 @@ struct LineRateState<D: AppData> {
     /// A buffer of data to replicate to the target follower.
     ///
     /// The buffered payload here will be expanded as more replication commands come in from the
-    /// Raft node while there is a buffered instance here.
+    /// Raft node while there is a buffered instance here. It is bounded per the configured
+    /// `max_buffered_entries` & `max_buffered_bytes`.
     buffered_outbound: Vec<Arc<Entry<D>>>,
+    /// The encoded size of the entries in `buffered_outbound`.
+    buffered_bytes: u64,
+    /// The index & term of the last entry of each in-flight AppendEntries request, in the order sent.
+    ///
+    /// Responses are matched to their requests by these values. The last element is used as the
+    /// previous log index & term of the next request to be pipelined to the target.
+    in_flight: VecDeque<(u64, u64)>,
 }
 
 impl<D: AppData> Default for LineRateState<D> {
     fn default() -> Self {
-        Self{buffered_outbound: vec![]}
+        Self{buffered_outbound: vec![], buffered_bytes: 0, in_flight: VecDeque::new()}
     }
 }
 
@@ -62,11 +75,13 @@ struct LaggingState<D: AppData> {
     /// This is identical to `LineRateState`'s buffer, and will be trasferred over to its buffer
     /// during state transition.
     buffered_outbound: Vec<Arc<Entry<D>>>,
+    /// The encoded size of the entries in `buffered_outbound`.
+    buffered_bytes: u64,
 }
 
 impl<D: AppData> Default for LaggingState<D> {
     fn default() -> Self {
-        Self{is_ready_for_line_rate: false, buffered_outbound: vec![]}
+        Self{is_ready_for_line_rate: false, buffered_outbound: vec![], buffered_bytes: 0}
     }
 }
 
@@ -89,23 +104,28 @@ struct SnapshottingState;
 /// an `RSReplicate` payload of the log entries which were just appended to the log. With the
 /// information in the payload, replication streams can stay at line rate a majority of the time.
 ///
-/// When running at line rate, replication requests will be buffered when there is an outstanding
-/// request, and all buffered entries will be sent over in the next request as one larger payload.
+/// When running at line rate, replication requests are pipelined: up to `max_inflight_payloads`
+/// requests may be outstanding at once, each one building upon the entries of the request sent
+/// before it. Replication requests will be buffered while the window is full, and all buffered
+/// entries will be sent over in the next request as one larger payload. Responses are matched to
+/// their requests by the index of the last entry of the request.
 ///
 /// ### lagging replication
 /// When a replication request fails (typically due to target being new to the cluster, the target
 /// having been offline for some time, or any such reason), the replication stream will enter the
 /// state `RSState::Lagging`, indicating that the target needs to be brought up-to-speed and that
 /// it is no longer running at line rate. When such an event takes place, any buffered replication
-/// payload will be purged, and the replication stream will begin the process of bringing the
-/// target up-to-speed.
+/// payload will be purged, the responses of any other in-flight requests will be ignored, and the
+/// replication stream will begin the process of bringing the target up-to-speed.
 ///
 /// #### bringing target up-to-date
 /// When the replication stream enters the `RSState::Lagging`, the replication stream will attempt
 /// to bring the target up-to-date based on the type of failure which triggered the state change.
 /// This Raft implementation uses a _conflict optimization_ algorithm as described in §5.3. As
 /// such, a `ConflictOpt` struct should always be present to help determine the last index to
-/// resume replication from.
+/// resume replication from. The target reports the term of its conflicting entry along with the
+/// first index of that term in its log, and the replication stream uses its own log to skip over
+/// every conflicting entry of that term at once.
 ///
 /// If the node is far enough behind, based on the Raft's configuration, the target may need to be
 /// sent an InstallSnapshot RPC. When this needs to take place, the replication stream will
@@ -120,9 +140,9 @@ struct SnapshottingState;
 ///
 /// ----
 ///
-/// NOTE: we do not stack replication requests to targets because this could result in
-/// out-of-order delivery.
-pub(crate) struct ReplicationStream<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> {
+/// NOTE: pipelined requests rely upon in-order delivery to the target. If a request is delivered
+/// out of order, the target will reject it, and the stream will recover via `RSState::Lagging`.
+pub(crate) struct ReplicationStream<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> {
     //////////////////////////////////////////////////////////////////////////
     // Static Fields /////////////////////////////////////////////////////////
 
@@ -155,6 +175,13 @@ pub(crate) struct ReplicationStream<D: AppData, R: AppDataResponse, E: AppError,
     line_index: u64,
     /// The index of the highest log entry which is known to be committed in the cluster.
     line_commit: u64,
+    /// The index of the config entry which made the target a witness, if it is a witness per the
+    /// leader's current config.
+    ///
+    /// Witnesses are sent normal entries with their data stripped, and are sent empty snapshots.
+    /// This only begins once the config is committed, as an uncommitted config may yet be
+    /// truncated, while a witness may never become a full member again. See `is_witness`.
+    witness_index: Option<u64>,
 
     /// The index of the next log to send.
     ///
@@ -171,7 +198,8 @@ pub(crate) struct ReplicationStream<D: AppData, R: AppDataResponse, E: AppError,
     ///
     /// This Raft implementation also uses a _conflict optimization_ pattern for reducing the
     /// number of RPCs which need to be sent back and forth between a peer which is lagging
-    /// behind. This is defined in §5.3.
+    /// behind. This is defined in §5.3. Rather than decrementing `next_index` by one, it jumps
+    /// past all of the target's entries in the conflicting term, per `resolve_conflict`.
     next_index: u64,
     /// The last know index to be successfully replicated on the target.
     ///
@@ -185,7 +213,7 @@ pub(crate) struct ReplicationStream<D: AppData, R: AppDataResponse, E: AppError,
     match_term: u64,
 }
 
-impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> ReplicationStream<D, R, E, N, S> {
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> ReplicationStream<D, R, E, N, S> {
     /// Create a new instance.
     pub fn new(
         id: NodeId, target: NodeId, term: u64, config: Arc<Config>,
@@ -195,11 +223,18 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
         Self{
             id, target, term, raftnode, network, storage, config,
             state: RSState::LineRate(Default::default()), is_driving_state: false,
-            line_index, line_commit,
+            line_index, line_commit, witness_index: None,
             next_index: line_index + 1, match_index: line_index, match_term: line_term,
         }
     }
 
+    /// Check if the target is a witness whose data should be stripped.
+    ///
+    /// The target must be a witness per a config which is known to be committed.
+    fn is_witness(&self) -> bool {
+        self.witness_index.map(|index| index <= self.line_commit).unwrap_or(false)
+    }
+
     /// Drive the replication stream forward.
     ///
     /// This method will take into account the current state of the replication stream and will
@@ -247,15 +282,6 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
                 self.raftnode.do_send(RSUpdateMatchIndex{target: self.target, match_index: index});
             }
 
-            // If running at line rate, and our buffered outbound requests have accumulated too
-            // much, we need to purge and transition to a lagging state. The target is not able to
-            // replicate data fast enough.
-            if let RSState::LineRate(inner) = &self.state {
-                if inner.buffered_outbound.len() > (self.config.max_payload_entries as usize) {
-                    return Box::new(self.transition_to_lagging(ctx));
-                }
-            }
-
             // Else, this was just a heartbeat. Do nothing.
             return Box::new(fut::ok(()));
         }
@@ -280,35 +306,57 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
                 return Box::new(fut::err(()));
             }
 
-            // Check snapshot policy and handle conflict as needed.
-            match &self.config.snapshot_policy {
-                SnapshotPolicy::Disabled => {
-                    self.next_index = conflict.index + 1;
-                    self.match_index = conflict.index;
-                    self.match_term = conflict.term;
-                    return Box::new(self.transition_to_lagging(ctx));
-                }
-                SnapshotPolicy::LogsSinceLast(threshold) => {
-                    let diff = &self.line_index - &conflict.index; // NOTE WELL: underflow is guarded against above.
-                    let needs_snpshot = &diff >= threshold;
-                    self.next_index = conflict.index + 1;
-                    self.match_index = conflict.index;
-                    self.match_term = conflict.term;
-
-                    if needs_snpshot {
-                        // Follower is far behind and needs to receive an InstallSnapshot RPC.
-                        return Box::new(self.transition_to_snapshotting(ctx));
-                    }
-                    // Follower is behind, but not too far behind to receive an InstallSnapshot RPC.
-                    return Box::new(self.transition_to_lagging(ctx));
-                }
-            }
+            // Stop replicating at line rate, & find the index from which to resume replication.
+            return Box::new(self.transition_to_lagging(ctx)
+                .and_then(move |_, act, ctx| act.resolve_conflict(ctx, conflict)));
         } else {
             self.next_index = if self.next_index > 0 { self.next_index - 1} else { 0 }; // Guard against underflow.
             return Box::new(self.transition_to_lagging(ctx));
         }
     }
 
+    /// Determine the index from which to resume replication to the target, given a conflict opt.
+    ///
+    /// This implements the conflicting term optimization of §5.3 using the leader's own log. If
+    /// the leader's entry at the conflict's index is in the conflicting term, then both logs are
+    /// identical through that entry, and replication resumes just after it. Otherwise, all of the
+    /// target's entries in the conflicting term are skipped over, and replication resumes from
+    /// the conflict's index. As such, a target with a long divergent suffix will converge in one
+    /// round trip per term, rather than one per entry.
+    ///
+    /// If the leader's entry preceding the conflict's index has been compacted into a snapshot,
+    /// or if the target is far enough behind per the snapshot policy, the target will be sent a
+    /// snapshot instead.
+    fn resolve_conflict(&mut self, _: &mut Context<Self>, conflict: ConflictOpt) -> impl ActorFuture<Actor=Self, Item=(), Error=()> {
+        let start = if conflict.index > 1 { conflict.index - 1 } else { 1 };
+        fut::wrap_future(self.storage.send(GetLogEntries::new(start, conflict.index + 1)))
+            .map_err(|err, act: &mut Self, ctx| act.map_fatal_actix_messaging_error(ctx, err, DependencyAddr::RaftStorage))
+            .and_then(|res, act, ctx| act.map_fatal_storage_result(ctx, res))
+            .and_then(move |entries, act, ctx| {
+                let entry_at = |index| entries.iter().find(|entry| entry.index == index).map(|entry| (entry.index, entry.term));
+                let resume_after = match entry_at(conflict.index) {
+                    Some((index, term)) if term == conflict.term => Some((index, term)),
+                    _ if conflict.index <= 1 => Some((0, 0)),
+                    _ => entry_at(conflict.index - 1),
+                };
+                let (index, term) = match resume_after {
+                    Some(val) => val,
+                    None => return fut::Either::A(act.transition_to_snapshotting(ctx)),
+                };
+                act.next_index = index + 1;
+                act.match_index = index;
+                act.match_term = term;
+
+                // Check snapshot policy to see if the target is far enough behind to need a snapshot.
+                if let SnapshotPolicy::LogsSinceLast(threshold) = &act.config.snapshot_policy {
+                    if act.line_index.saturating_sub(index) >= *threshold {
+                        return fut::Either::A(act.transition_to_snapshotting(ctx));
+                    }
+                }
+                fut::Either::B(fut::ok(()))
+            })
+    }
+
     /// Transform and log an actix MailboxError.
     ///
     /// This method treats the error as being fatal, as Raft can not function properly if the
@@ -335,9 +383,20 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
     /// If a response successfully comes back from the target, the heartbeat timer will be
     /// updated. This routine does not perform any timeout logic. That is up to the parent
     /// application's networking layer.
+    ///
+    /// If the target is a witness, the data of any normal entries will be stripped before the
+    /// request is sent.
     fn send_append_entries(
-        &mut self, _: &mut Context<Self>, request: AppendEntriesRequest<D>,
+        &mut self, _: &mut Context<Self>, mut request: AppendEntriesRequest<D>,
     ) -> impl ActorFuture<Actor=Self, Item=AppendEntriesResponse, Error=()> {
+        if self.is_witness() {
+            for entry in request.entries.iter_mut() {
+                if let EntryPayload::Normal(_) = &entry.payload {
+                    entry.payload = EntryPayload::Stripped;
+                }
+            }
+        }
+
         // Send the payload.
         fut::wrap_future(self.network.send(request))
             .map_err(|err, act: &mut Self, ctx| act.map_fatal_actix_messaging_error(ctx, err, DependencyAddr::RaftNetwork))
@@ -363,6 +422,7 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
         match &mut self.state {
             RSState::Lagging(inner) => {
                 new_state.buffered_outbound.append(&mut inner.buffered_outbound);
+                new_state.buffered_bytes = inner.buffered_bytes;
             }
             _ => (),
         }
@@ -383,7 +443,7 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
     }
 }
 
-impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> Actor for ReplicationStream<D, R, E, N, S> {
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Actor for ReplicationStream<D, R, E, N, S> {
     type Context = Context<Self>;
 
     /// Perform actors startup routine.
@@ -408,11 +468,11 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
 /// A replication stream message indicating a new payload of entries to be replicated.
 #[derive(Clone)]
 pub(crate) struct RSReplicate<D: AppData> {
-    /// The new entry which needs to be replicated.
+    /// The new entries which need to be replicated, in order.
     ///
-    /// This entry will always be the most recent entry to have been appended to the log, so its
-    /// index is the new line index value.
-    pub entry: Arc<Entry<D>>,
+    /// The last of these entries will always be the most recent entry to have been appended to
+    /// the log, so its index is the new line index value.
+    pub entries: Vec<Arc<Entry<D>>>,
     /// The index of the highest log entry which is known to be committed in the cluster.
     pub line_commit: u64,
 }
@@ -421,7 +481,7 @@ impl<D: AppData> Message for RSReplicate<D> {
     type Result = Result<(), ()>;
 }
 
-impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> Handler<RSReplicate<D>> for ReplicationStream<D, R, E, N, S> {
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Handler<RSReplicate<D>> for ReplicationStream<D, R, E, N, S> {
     type Result = Result<(), ()>;
 
     /// Handle a request to replicate the given payload of entries.
@@ -429,19 +489,44 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
     /// If there is already an outbound request, this payload will be buffered. If there is
     /// already a buffered payload, the payloads will be combined. This helps to keep throughput
     /// as high as possible when dealing with high write load conditions.
+    ///
+    /// If the buffer grows beyond its configured bounds, the target is not able to replicate data
+    /// fast enough. The buffer is then dropped, and the stream transitions to a lagging state, so
+    /// that the target is brought up-to-date from storage, rather than from memory.
     fn handle(&mut self, msg: RSReplicate<D>, ctx: &mut Self::Context) -> Self::Result {
         // Always update line commit & index info first so that this value can be used in all AppendEntries RPCs.
         self.line_commit = msg.line_commit;
-        self.line_index = msg.entry.index;
+        if let Some(entry) = msg.entries.last() {
+            self.line_index = entry.index;
+        }
 
         // Get a mutable reference to an inner buffer if permitted by current state, else return.
-        match &mut self.state {
-            // NOTE: exceeding line rate buffer size is accounted for in the `handle_append_entries_response` handler.
-            RSState::LineRate(inner) => inner.buffered_outbound.push(msg.entry),
-            RSState::Lagging(inner) => inner.buffered_outbound.push(msg.entry),
+        let bytes: u64 = msg.entries.iter().map(|entry| entry.encoded_len()).sum();
+        let (buffered_entries, buffered_bytes) = match &mut self.state {
+            RSState::LineRate(inner) => {
+                inner.buffered_outbound.extend(msg.entries);
+                inner.buffered_bytes += bytes;
+                (inner.buffered_outbound.len() as u64, inner.buffered_bytes)
+            }
+            RSState::Lagging(inner) => {
+                inner.buffered_outbound.extend(msg.entries);
+                inner.buffered_bytes += bytes;
+                (inner.buffered_outbound.len() as u64, inner.buffered_bytes)
+            }
             _ => return Ok(()),
         };
 
+        // If the buffer has exceeded its bounds, drop it & fall back to replicating from storage.
+        if buffered_entries > self.config.max_buffered_entries || buffered_bytes > self.config.max_buffered_bytes {
+            debug!("{} has buffered too much data for {}. Transitioning to lagging.", self.id, self.target);
+            let f = self.transition_to_lagging(ctx).then(|res, act, ctx| {
+                act.drive_state(ctx);
+                fut::result(res)
+            });
+            ctx.spawn(f);
+            return Ok(());
+        }
+
         self.drive_state(ctx);
         Ok(())
     }
@@ -454,7 +539,7 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
 #[derive(Clone, Message)]
 pub(crate) struct RSUpdateLineCommit(pub u64);
 
-impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> Handler<RSUpdateLineCommit> for ReplicationStream<D, R, E, N, S> {
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Handler<RSUpdateLineCommit> for ReplicationStream<D, R, E, N, S> {
     type Result = ();
 
     /// Handle a request to update the current line commit of the leader.
@@ -463,6 +548,37 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
     }
 }
 
+//////////////////////////////////////////////////////////////////////////////////////////////////
+// RSUpdateWitness ///////////////////////////////////////////////////////////////////////////////
+
+/// A replication stream message indicating the target's witness status per the leader's current config.
+///
+/// This holds the index of the config entry which made the target a witness, if any.
+#[derive(Clone, Message)]
+pub(crate) struct RSUpdateWitness(pub Option<u64>);
+
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Handler<RSUpdateWitness> for ReplicationStream<D, R, E, N, S> {
+    type Result = ();
+
+    /// Handle a request to update the witness status of the target.
+    fn handle(&mut self, msg: RSUpdateWitness, _: &mut Self::Context) -> Self::Result {
+        self.witness_index = msg.0;
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////
+// RSConfirmLeadership ///////////////////////////////////////////////////////////////////////////
+
+/// A replication stream message requesting an immediate heartbeat to confirm leadership.
+///
+/// This is used by the Raft node when serving linearizable reads. The message will resolve with
+/// an error if the target could not be reached or if it has observed a newer term.
+pub(crate) struct RSConfirmLeadership;
+
+impl Message for RSConfirmLeadership {
+    type Result = Result<(), ()>;
+}
+
 //////////////////////////////////////////////////////////////////////////////////////////////////
 // RSTerminate ///////////////////////////////////////////////////////////////////////////////////
 
@@ -470,7 +586,7 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
 #[derive(Message)]
 pub(crate) struct RSTerminate;
 
-impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> Handler<RSTerminate> for ReplicationStream<D, R, E, N, S> {
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> Handler<RSTerminate> for ReplicationStream<D, R, E, N, S> {
     type Result = ();
 
     /// Handle a request to terminate this replication stream.
@@ -533,6 +649,25 @@ pub(crate) struct RSRevertToFollower {
     pub term: u64,
 }
 
+//////////////////////////////////////////////////////////////////////////////////////////////////
+// RSHeartbeatAck ////////////////////////////////////////////////////////////////////////////////
+
+/// An event indicating that the target node has acknowledged a heartbeat from the leader.
+///
+/// This is used by the leader to check that it is still in contact with a majority of the
+/// cluster, and to track its lease when lease reads are enabled.
+#[derive(Message)]
+pub(crate) struct RSHeartbeatAck {
+    /// The ID of the target node which acknowledged the heartbeat.
+    pub target: NodeId,
+    /// The time at which the acknowledged heartbeat was sent.
+    ///
+    /// The leader's lease is measured from when the heartbeat was sent, not from when the
+    /// response was received, as the target may have reset its election timeout at any point in
+    /// between.
+    pub sent_at: Instant,
+}
+
 //////////////////////////////////////////////////////////////////////////////////////////////////
 // RSNeedsSnapshot ///////////////////////////////////////////////////////////////////////////////
 
@@ -562,3 +697,80 @@ pub(crate) struct RSUpdateMatchIndex {
     /// The index of the most recent log known to have been successfully replicated on the target.
     pub match_index: u64,
 }
+
+/// Determine how many of the given entries, taken from the front, fit within a single payload.
+///
+/// A payload is cut off once it would exceed either the given maximum number of entries or the
+/// given maximum size in bytes, whichever is reached first. A payload always holds at least one
+/// entry, if any are given, so that entries larger than the size limit can still be replicated.
+fn payload_len<'a, D: AppData>(entries: impl Iterator<Item=&'a Entry<D>>, max_entries: u64, max_bytes: u64) -> usize {
+    let (mut len, mut bytes) = (0u64, 0u64);
+    for entry in entries {
+        bytes = bytes.saturating_add(entry.encoded_len());
+        if len > 0 && (len >= max_entries || bytes > max_bytes) {
+            break;
+        }
+        len += 1;
+    }
+    len as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Serialize, Deserialize};
+    use crate::messages::EntryNormal;
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    struct TestData(u64);
+
+    impl AppData for TestData {
+        fn encoded_len(&self) -> u64 {
+            self.0
+        }
+    }
+
+    fn entries(sizes: &[u64]) -> Vec<Entry<TestData>> {
+        sizes.iter().enumerate()
+            .map(|(idx, size)| Entry::new(EntryPayload::Normal(EntryNormal{data: TestData(*size)}), idx as u64 + 1, 1))
+            .collect()
+    }
+
+    //////////////////////////////////////////////////////////////////////////
+    // payload_len ///////////////////////////////////////////////////////////
+
+    mod payload_len {
+        use super::*;
+
+        #[test]
+        fn takes_all_entries_within_both_limits() {
+            let entries = entries(&[10, 10, 10]);
+            assert_eq!(payload_len(entries.iter(), 10, 100), 3);
+        }
+
+        #[test]
+        fn stops_at_max_entries() {
+            let entries = entries(&[10, 10, 10]);
+            assert_eq!(payload_len(entries.iter(), 2, 100), 2);
+        }
+
+        #[test]
+        fn stops_before_exceeding_max_bytes() {
+            let entries = entries(&[40, 40, 40]);
+            assert_eq!(payload_len(entries.iter(), 10, 100), 2);
+            assert_eq!(payload_len(entries.iter(), 10, 80), 2);
+        }
+
+        #[test]
+        fn always_takes_at_least_one_entry() {
+            let entries = entries(&[500, 10]);
+            assert_eq!(payload_len(entries.iter(), 10, 100), 1);
+        }
+
+        #[test]
+        fn returns_zero_without_entries() {
+            let entries = entries(&[]);
+            assert_eq!(payload_len(entries.iter(), 10, 100), 0);
+        }
+    }
+}
@@ -20,7 +20,7 @@ use crate::{
     storage::{RaftStorage},
 };
 
-impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStorage<D, R, E>> ReplicationStream<D, R, E, N, S> {
+impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D, R, E>, S: RaftStorage<D, R, E>> ReplicationStream<D, R, E, N, S> {
 
     /// Drive the replication stream forward when it is in state `Snapshotting`.
     pub(super) fn drive_state_snapshotting(&mut self, ctx: &mut Context<Self>) {
@@ -65,8 +65,13 @@ impl<D: AppData, R: AppDataResponse, E: AppError, N: RaftNetwork<D>, S: RaftStor
     fn send_snapshot(&mut self, _: &mut Context<Self>, snap: RSNeedsSnapshotResponse) -> impl ActorFuture<Actor=Self, Item=(), Error=()> {
         // Look up the snapshot on disk.
         let (snap_index, snap_term) = (snap.index, snap.term);
-        let pathbuf = PathBuf::from(snap.pointer.path);
-        let snap_stream = SnapshotStream::new(self.target, pathbuf, self.config.snapshot_max_chunk_size, self.term, self.id, snap_index, snap_term);
+        let snap_stream = if self.is_witness() {
+            // Witnesses never store application data, so they are only sent the snapshot's index & term.
+            SnapshotStream::new_empty(self.target, self.term, self.id, snap_index, snap_term)
+        } else {
+            let pathbuf = PathBuf::from(snap.pointer.path);
+            SnapshotStream::new(self.target, pathbuf, self.config.snapshot_max_chunk_size, self.term, self.id, snap_index, snap_term)
+        };
 
         fut::wrap_stream(snap_stream)
             .and_then(|res, _, _| fut::result(res))
@@ -137,6 +142,19 @@ impl SnapshotStream {
         rx
     }
 
+    /// Create a new stream of a single empty frame, which covers the given index & term.
+    pub fn new_empty(
+        target: NodeId, term: u64, leader_id: NodeId, last_included_index: u64, last_included_term: u64,
+    ) -> mpsc::Receiver<Result<InstallSnapshotRequest, ()>> {
+        let (mut tx, rx) = mpsc::channel(0);
+        let frame = InstallSnapshotRequest{
+            target, term, leader_id, last_included_index, last_included_term,
+            offset: 0, data: vec![], done: true,
+        };
+        let _ = tx.try_send(Ok(frame)); // Each sender is guaranteed one slot in the channel.
+        rx
+    }
+
     fn run(mut self) {
         // Open the target snapshot file & get a read on its length.
         let mut chan = self.chan.wait();
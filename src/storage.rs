@@ -1,6 +1,6 @@
 //! The RaftStorage interface and message types.
 
-use std::sync::Arc;
+use std::{collections::BTreeMap, sync::Arc};
 
 use actix::{
     dev::ToEnvelope,
@@ -85,13 +85,16 @@ impl<D: AppData, E: AppError> Message for GetLogEntries<D, E> {
 
 /// A request from Raft to append a new entry to the log.
 ///
-/// These requests come about via client requests, and as such, this is the only RaftStorage
-/// interface which is allowed to return errors which will not cause Raft to shutdown. Application
-/// errors coming from this interface will be sent back as-is to the call point where your
-/// application originally presented the client request to Raft.
+/// These requests come about via client requests, and as such, this and `AppendEntriesToLog` are
+/// the only RaftStorage interfaces which are allowed to return errors which will not cause Raft to
+/// shutdown. Application errors coming from this interface will be sent back as-is to the call
+/// point where your application originally presented the client request to Raft.
 ///
 /// This property of error handling allows you to keep your application logic as close to the
 /// storage layer as needed.
+///
+/// If the Raft node is configured with `parallel_append`, entries are replicated before this
+/// handler has responded, and any error returned from here will cause Raft to shutdown.
 pub struct AppendEntryToLog<D: AppData, E: AppError> {
     pub entry: Arc<messages::Entry<D>>,
     marker: std::marker::PhantomData<E>,
@@ -108,6 +111,37 @@ impl<D: AppData, E: AppError> Message for AppendEntryToLog<D, E> {
     type Result = Result<(), E>;
 }
 
+//////////////////////////////////////////////////////////////////////////////////////////////////
+// AppendEntriesToLog ////////////////////////////////////////////////////////////////////////////
+
+/// A request from Raft to append a batch of new entries to the log.
+///
+/// These requests come about via client requests which carry multiple entries, or via a group of
+/// client requests which were waiting to be processed together, and errors are handled exactly as
+/// they are for `AppendEntryToLog`. The entries are presented in order, with contiguous indices.
+/// The batch must be appended atomically: if an error is returned, none of the entries may be
+/// written to the log.
+///
+/// When a group of client requests is rejected, Raft will append each request of the group on
+/// its own, so that the error is returned only to the client whose request caused it.
+///
+/// This allows storage to persist the whole batch with a single write or fsync.
+pub struct AppendEntriesToLog<D: AppData, E: AppError> {
+    pub entries: Vec<Arc<messages::Entry<D>>>,
+    marker: std::marker::PhantomData<E>,
+}
+
+impl<D: AppData, E: AppError> AppendEntriesToLog<D, E> {
+    // Create a new instance.
+    pub fn new(entries: Vec<Arc<messages::Entry<D>>>) -> Self {
+        Self{entries, marker: std::marker::PhantomData}
+    }
+}
+
+impl<D: AppData, E: AppError> Message for AppendEntriesToLog<D, E> {
+    type Result = Result<(), E>;
+}
+
 //////////////////////////////////////////////////////////////////////////////////////////////////
 // ReplicateToLog ////////////////////////////////////////////////////////////////////////////////
 
@@ -116,11 +150,19 @@ impl<D: AppData, E: AppError> Message for AppendEntryToLog<D, E> {
 /// These requests come about via the Raft leader's replication process. An error coming from this
 /// interface will cause Raft to shutdown, as this is not where application logic should be
 /// returning application specific errors. Application specific constraints may only be enforced
-/// in the `AppendEntryToLog` handler.
+/// in the `AppendEntryToLog` & `AppendEntriesToLog` handlers.
 ///
 /// Though the entries will always be presented in order, each entry's index should be used to
 /// determine its location to be written in the log, as logs may need to be overwritten under
 /// some circumstances.
+///
+/// ### witnesses
+/// If this node is a witness, normal entries will be replicated to it with the payload
+/// `EntryPayload::Stripped`, which carries no application data. These entries must be written to
+/// the log like any other, as their index & term are needed for the log consistency check. When
+/// such entries are applied to the state machine, they should be treated as blank entries.
+///
+/// There is no separate append path for witnesses, this handler receives their entries too.
 pub struct ReplicateToLog<D: AppData, E: AppError> {
     pub entries: Arc<Vec<messages::Entry<D>>>,
     marker: std::marker::PhantomData<E>,
@@ -200,13 +242,18 @@ pub struct CreateSnapshot<E: AppError> {
     /// The new snapshot should start from entry `0` and should cover all entries through the
     /// index specified here, inclusive.
     pub through: u64,
+    /// The client sessions of the entries covered by the snapshot.
+    ///
+    /// These must be persisted as part of the snapshot, and returned as is via
+    /// `CurrentSnapshotData::client_sessions`.
+    pub client_sessions: ClientSessions,
     marker: std::marker::PhantomData<E>,
 }
 
 impl<E: AppError> CreateSnapshot<E> {
     // Create a new instance.
-    pub fn new(through: u64) -> Self {
-        Self{through, marker: std::marker::PhantomData}
+    pub fn new(through: u64, client_sessions: ClientSessions) -> Self {
+        Self{through, client_sessions, marker: std::marker::PhantomData}
     }
 }
 
@@ -219,6 +266,9 @@ impl<E: AppError> Message for CreateSnapshot<E> {
 
 /// A request from Raft to have a new snapshot written to disk and installed.
 ///
+/// If this node is a witness, the snapshot will hold no data, and will only carry the index &
+/// term which it covers. Installing such a snapshot should leave the state machine empty.
+///
 /// See the [storage chapter of the guide](https://railgun-rs.github.io/actix-raft/storage.html#InstallSnapshot)
 /// for details on how to implement this handler.
 pub struct InstallSnapshot<E: AppError> {
@@ -289,6 +339,12 @@ pub struct CurrentSnapshotData {
     pub index: u64,
     /// The latest membership configuration covered by the snapshot.
     pub membership: messages::MembershipConfig,
+    /// The client sessions covered by the snapshot.
+    ///
+    /// This is the table given via `CreateSnapshot::client_sessions` when the snapshot was
+    /// created, which must also be recovered from the snapshot when it has been installed via
+    /// `InstallSnapshot`. See `ClientSessions`.
+    pub client_sessions: ClientSessions,
     /// The snapshot entry's pointer to the snapshot file.
     pub pointer: messages::EntrySnapshotPointer,
 }
@@ -325,6 +381,81 @@ pub struct HardState {
     pub voted_for: Option<NodeId>,
     /// The cluster membership configuration.
     pub membership: messages::MembershipConfig,
+    /// The config entries of the log which may still be overwritten by a new leader, keyed by log index.
+    ///
+    /// Raft uses this to revert to the previous membership config when an uncommitted config entry
+    /// is truncated from the log. Storage engines need only persist & return it as is.
+    #[serde(default)]
+    pub membership_history: BTreeMap<u64, messages::MembershipConfig>,
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////
+// ClientSessions ////////////////////////////////////////////////////////////////////////////////
+
+/// The table of client sessions known to Raft, used to detect duplicate client requests (§6.3).
+///
+/// Client requests may be tagged with a session via `ClientPayload::with_session`, and the
+/// session is recorded on the request's log entry. As entries are applied, Raft records the
+/// latest sequence number of each client in this table, along with the index of its entry. The
+/// leader checks the session of each new client request against this table, as well as against
+/// the entries of its log which have not yet been applied, before appending it to the log. A
+/// retried request is answered with the outcome of its original entry, and is never appended to
+/// the log a second time, so state machines only ever see each request once.
+///
+/// The table is owned by Raft. It is handed to the storage engine via `CreateSnapshot`, and must
+/// be persisted as part of the snapshot & returned as is via `CurrentSnapshotData`, including for
+/// snapshots which have been installed via `InstallSnapshot`. Storage engines need not inspect it.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientSessions {
+    sessions: BTreeMap<u64, ClientSessionRecord>,
+}
+
+/// The latest applied request of a client session.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct ClientSessionRecord {
+    /// The sequence number of the latest applied request of the session.
+    sequence: u64,
+    /// The log index of the entry of the latest applied request of the session.
+    index: u64,
+}
+
+impl ClientSessions {
+    /// Create a new, empty instance.
+    pub fn new() -> Self {
+        Self{sessions: BTreeMap::new()}
+    }
+
+    /// Record the given session of an entry which has been applied at the given index.
+    ///
+    /// Sessions older than the latest recorded request of the same client are ignored.
+    pub(crate) fn record(&mut self, session: &messages::ClientSession, index: u64) {
+        let record = self.sessions.entry(session.client_id).or_insert(ClientSessionRecord{sequence: session.sequence, index});
+        if session.sequence >= record.sequence {
+            *record = ClientSessionRecord{sequence: session.sequence, index};
+        }
+    }
+
+    /// Merge the given table into this one, keeping the latest request of each client.
+    pub(crate) fn merge(&mut self, other: ClientSessions) {
+        for (client_id, record) in other.sessions {
+            self.record(&messages::ClientSession{client_id, sequence: record.sequence}, record.index);
+        }
+    }
+
+    /// Get the sequence number & log index of the latest applied request of the given client.
+    pub(crate) fn latest(&self, client_id: u64) -> Option<(u64, u64)> {
+        self.sessions.get(&client_id).map(|record| (record.sequence, record.index))
+    }
+
+    /// The number of client sessions in this table.
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// Check if this table has no client sessions.
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
 }
 
 //////////////////////////////////////////////////////////////////////////////////////////////////
@@ -346,6 +477,7 @@ pub trait RaftStorage<D, R, E>: 'static
         Handler<SaveHardState<E>> +
         Handler<GetLogEntries<D, E>> +
         Handler<AppendEntryToLog<D, E>> +
+        Handler<AppendEntriesToLog<D, E>> +
         Handler<ReplicateToLog<D, E>> +
         Handler<ApplyEntryToStateMachine<D, R, E>> +
         Handler<ReplicateToStateMachine<D, E>> +
@@ -359,6 +491,7 @@ pub trait RaftStorage<D, R, E>: 'static
         ToEnvelope<Self::Actor, SaveHardState<E>> +
         ToEnvelope<Self::Actor, GetLogEntries<D, E>> +
         ToEnvelope<Self::Actor, AppendEntryToLog<D, E>> +
+        ToEnvelope<Self::Actor, AppendEntriesToLog<D, E>> +
         ToEnvelope<Self::Actor, ReplicateToLog<D, E>> +
         ToEnvelope<Self::Actor, ApplyEntryToStateMachine<D, R, E>> +
         ToEnvelope<Self::Actor, ReplicateToStateMachine<D, E>> +
@@ -366,3 +499,36 @@ pub trait RaftStorage<D, R, E>: 'static
         ToEnvelope<Self::Actor, InstallSnapshot<E>> +
         ToEnvelope<Self::Actor, GetCurrentSnapshot<E>>;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::ClientSession;
+
+    //////////////////////////////////////////////////////////////////////////
+    // ClientSessions ////////////////////////////////////////////////////////
+
+    mod client_sessions {
+        use super::*;
+
+        #[test]
+        fn records_the_latest_request_of_each_client() {
+            let mut sessions = ClientSessions::new();
+            sessions.record(&ClientSession{client_id: 7, sequence: 1}, 10);
+            sessions.record(&ClientSession{client_id: 7, sequence: 2}, 12);
+            sessions.record(&ClientSession{client_id: 8, sequence: 1}, 11);
+            assert_eq!(sessions.latest(7), Some((2, 12)));
+            assert_eq!(sessions.latest(8), Some((1, 11)));
+            assert_eq!(sessions.latest(9), None);
+            assert_eq!(sessions.len(), 2);
+        }
+
+        #[test]
+        fn ignores_older_requests_of_a_client() {
+            let mut sessions = ClientSessions::new();
+            sessions.record(&ClientSession{client_id: 7, sequence: 2}, 12);
+            sessions.record(&ClientSession{client_id: 7, sequence: 1}, 13);
+            assert_eq!(sessions.latest(7), Some((2, 12)));
+        }
+    }
+}
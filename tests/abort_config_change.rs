@@ -0,0 +1,188 @@
+//! Test aborting joint consensus membership changes.
+
+mod fixtures;
+
+use std::time::{Duration, Instant};
+
+use actix::prelude::*;
+use actix_raft::{
+    admin::{AbortConfigChange, AbortConfigChangeError, ProposeConfigChange},
+    messages::{EntryNormal, ResponseMode},
+    metrics::State,
+};
+use tokio_timer::{Delay, Timeout};
+
+use fixtures::{
+    Payload, RaftTestController, Node, setup_logger,
+    dev::{ExecuteInRaftRouter, GetCurrentLeader, RaftRouter, Register},
+    memory_storage::MemoryStorageData,
+};
+
+/// Config change abortion tests for a three node cluster.
+///
+/// What does this test cover?
+///
+/// - An `AbortConfigChange` command sent to a follower, or while not in joint consensus, should be rejected.
+/// - A change which adds an unreachable node & removes a follower should leave the cluster in
+///   joint consensus.
+/// - Aborting the change should revert to the previous set of voting members, keeping the
+///   follower & dropping the unreachable node.
+/// - Once reachable again, the dropped node should receive the reverted config, and no entries
+///   after it.
+///
+/// `RUST_LOG=actix_raft,abort_config_change=debug cargo test abort_config_change`
+#[test]
+fn abort_config_change() {
+    setup_logger();
+    let sys = System::builder().stop_on_panic(true).name("test").build();
+
+    // Setup test dependencies.
+    let net = RaftRouter::new();
+    let network = net.start();
+    let members = vec![0, 1, 2];
+    let node0 = Node::builder(0, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 0, addr: node0.addr.clone()});
+    let node1 = Node::builder(1, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 1, addr: node1.addr.clone()});
+    let node2 = Node::builder(2, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 2, addr: node2.addr.clone()});
+
+    // Setup test controller and actions.
+    let mut ctl = RaftTestController::new(network);
+    ctl.register(0, node0.addr.clone()).register(1, node1.addr.clone()).register(2, node2.addr.clone());
+    ctl.start_with_test(10, Box::new(|act, ctx| {
+        let task = fut::wrap_future(act.network.send(GetCurrentLeader))
+            .map_err(|_, _: &mut RaftTestController, _| panic!("Failed to get current leader."))
+            .and_then(|res, _, _| fut::result(res)).and_then(|leader_opt, _, _| {
+                let leader = leader_opt.expect("Expected the cluster to have elected a leader.");
+                fut::ok(leader)
+            })
+            .and_then(|leader, act, _| act.write_data(leader).map(move |_, _, _| leader))
+
+            // Abort commands sent to a follower, or while not in joint consensus, should be rejected.
+            .and_then(|leader, act, _| {
+                let follower = act.nodes.keys().cloned().find(|id| id != &leader).expect("Expected a follower.");
+                let from_follower = act.nodes.get(&follower).expect("Expected follower to be registered.").send(AbortConfigChange::new());
+                let from_leader = act.nodes.get(&leader).expect("Expected leader to be registered.").send(AbortConfigChange::new());
+                fut::wrap_future(from_follower.join(from_leader))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |(from_follower, from_leader), _, _| {
+                        match from_follower {
+                            Err(AbortConfigChangeError::NodeNotLeader(Some(id))) => assert_eq!(id, leader, "Expected follower to report the leader."),
+                            other => panic!("Expected NodeNotLeader error from follower, got {:?}.", other),
+                        }
+                        match from_leader {
+                            Err(AbortConfigChangeError::NotInJointConsensus) => (),
+                            other => panic!("Expected NotInJointConsensus error, got {:?}.", other),
+                        }
+                        (leader, follower)
+                    })
+            })
+
+            // Add node 3 while it is unreachable, & remove a follower. The cluster should be stuck
+            // in joint consensus.
+            .and_then(|(leader, follower), act, _| {
+                let node3 = Node::builder(3, act.network.clone(), vec![3]).build();
+                act.register(3, node3.addr.clone());
+                act.network.do_send(Register{id: 3, addr: node3.addr.clone()});
+                act.network.do_send(ExecuteInRaftRouter(Box::new(|act, _| act.isolate_node(3))));
+
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                fut::wrap_future(node.send(ProposeConfigChange::new(vec![3], vec![follower])))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |res, _, _| {
+                        res.expect("Expected the joint config to be committed.");
+                        (leader, follower)
+                    })
+            })
+            .and_then(|ids, _, _| fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(2)))
+                .map_err(|_, _, _| ())
+                .map(move |_, _, _| ids))
+            .and_then(|(leader, follower), act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| {
+                    let leader = act.metrics.get(&leader).expect("Expected leader's metrics to be present.");
+                    assert!(leader.membership_config.is_in_joint_consensus, "Expected cluster to be in joint consensus.");
+                    assert_eq!(leader.membership_config.non_voters, vec![3], "Expected node 3 to be syncing.");
+                    assert_eq!(leader.membership_config.removing, vec![follower], "Expected the follower to be removing.");
+                })));
+                fut::ok((leader, follower))
+            })
+
+            // Abort the change. A second attempt should be rejected.
+            .and_then(|(leader, follower), act, _| {
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.").clone();
+                fut::wrap_future(node.send(AbortConfigChange::new()))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .and_then(move |res, _, _| {
+                        res.expect("Expected AbortConfigChange to succeed on the leader.");
+                        fut::wrap_future(node.send(AbortConfigChange::new())).map_err(|err, _, _| panic!("{}", err))
+                    })
+                    .map(move |res, _, _| {
+                        match res {
+                            Err(AbortConfigChangeError::NotInJointConsensus) => (),
+                            other => panic!("Expected NotInJointConsensus error, got {:?}.", other),
+                        }
+                        (leader, follower)
+                    })
+            })
+
+            // Restore node 3, give it time to receive the reverted config, & write more data to the leader.
+            .and_then(|ids, act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(|act, _| act.restore_node(3))));
+                fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(2)))
+                    .map_err(|_, _, _| ())
+                    .map(move |_, _, _| ids)
+            })
+            .and_then(|(leader, follower), act, _| act.write_data(leader).map(move |_, _, _| (leader, follower)))
+            .and_then(|ids, _, _| fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(2)))
+                .map_err(|_, _, _| ())
+                .map(move |_, _, _| ids))
+
+            // Assert that the previous config is in effect, & that node 3 has been dropped.
+            .and_then(|(leader, follower), act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| {
+                    let leader = act.metrics.get(&leader).expect("Expected leader's metrics to be present.");
+                    assert_eq!(leader.state, State::Leader, "Expected leader to have retained leadership.");
+                    assert_eq!(leader.membership_config.members.len(), 3, "Expected a three node cluster.");
+                    assert!(leader.membership_config.members.contains(&follower), "Expected the follower to have been kept.");
+                    assert!(!leader.membership_config.contains(&3), "Expected node 3 to have been dropped.");
+                    assert!(!leader.membership_config.is_in_joint_consensus, "Expected cluster not to be in joint consensus.");
+                    assert!(leader.membership_config.removing.is_empty(), "Expected no nodes to be removing.");
+                    for nodeid in leader.membership_config.members.iter().filter(|e| *e != &leader.id) {
+                        let node = act.metrics.get(nodeid).expect("Expected to find metrics entry for cluster member.");
+                        assert_eq!(node.state, State::Follower, "Expected all other cluster members to be followers.");
+                        assert_eq!(node.membership_config, leader.membership_config, "Expected all cluster members to have matching config.");
+                        assert_eq!(node.last_log_index, leader.last_log_index, "Expected all cluster members to have matching last log index.");
+                    }
+
+                    let node3 = act.metrics.get(&3).expect("Expected node 3's metrics to be present.");
+                    assert_eq!(node3.state, State::NonVoter, "Expected node 3 to be in state NonVoter.");
+                    assert_eq!(node3.membership_config, leader.membership_config, "Expected node 3 to have received the reverted config.");
+                    assert!(node3.last_log_index < leader.last_log_index, "Expected node 3 to receive no entries after the reverted config.");
+                    System::current().stop();
+                })));
+                fut::ok(())
+            })
+            .map_err(|err, _, _| panic!("Failure during test. {:?}", err));
+        ctx.spawn(task);
+    }));
+
+    // Run the test.
+    assert!(sys.run().is_ok(), "Error during test.");
+}
+
+impl RaftTestController {
+    /// Write 10 entries to the given leader, expecting each to be applied within a few seconds.
+    fn write_data(&mut self, leader: u64) -> impl ActorFuture<Actor=Self, Item=(), Error=()> {
+        let addr = self.nodes.get(&leader).expect("Expected leader to be registered.").clone();
+        fut::wrap_stream(futures::stream::iter_ok(0..10u64))
+            .and_then(move |data, _, _| {
+                let entry = EntryNormal{data: MemoryStorageData{data: data.to_string().into_bytes()}};
+                let payload = Payload::new(entry, ResponseMode::Applied);
+                fut::wrap_future(Timeout::new(addr.send(payload), Duration::from_secs(3)))
+                    .map_err(|err, _, _| panic!("Client request was not applied in time. {:?}", err))
+                    .map(|res, _, _| { res.expect("Expected client request to succeed."); })
+            })
+            .finish()
+    }
+}
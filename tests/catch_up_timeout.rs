@@ -0,0 +1,189 @@
+//! Test the catch-up deadline of membership changes.
+
+mod fixtures;
+
+use std::{
+    collections::BTreeSet,
+    time::{Duration, Instant},
+};
+
+use actix::prelude::*;
+use actix_raft::{
+    admin::{ChangeMembership, ChangeMembershipError},
+    messages::{EntryNormal, EntryPayload, ResponseMode},
+    metrics::State,
+};
+use tokio_timer::{Delay, Timeout};
+
+use fixtures::{
+    Payload, RaftTestController, Node, setup_logger,
+    dev::{ExecuteInRaftRouter, GetCurrentLeader, RaftRouter, Register},
+    memory_storage::{GetCurrentState, MemoryStorageData},
+};
+
+/// Catch-up deadline tests for a three node cluster.
+///
+/// What does this test cover?
+///
+/// - While a new node is being synced, the leader should report its progress in its metrics.
+/// - A change which adds a node which never catches up should be rolled back once the deadline
+///   has elapsed, & the previous set of voting members should be committed again.
+/// - Once the new node is reachable, the same change should succeed.
+/// - The first change names the new node a witness, while the second does not. As the first
+///   change was rolled back, the new node should store the data of normal entries.
+///
+/// `RUST_LOG=actix_raft,catch_up_timeout=debug cargo test catch_up_timeout`
+#[test]
+fn catch_up_timeout() {
+    setup_logger();
+    let sys = System::builder().stop_on_panic(true).name("test").build();
+
+    // Setup test dependencies.
+    let net = RaftRouter::new();
+    let network = net.start();
+    let members = vec![0, 1, 2];
+    let timeout = Duration::from_secs(3);
+    let node0 = Node::builder(0, network.clone(), members.clone()).catch_up_timeout(timeout).build();
+    network.do_send(Register{id: 0, addr: node0.addr.clone()});
+    let node1 = Node::builder(1, network.clone(), members.clone()).catch_up_timeout(timeout).build();
+    network.do_send(Register{id: 1, addr: node1.addr.clone()});
+    let node2 = Node::builder(2, network.clone(), members.clone()).catch_up_timeout(timeout).build();
+    network.do_send(Register{id: 2, addr: node2.addr.clone()});
+    let node3 = Node::builder(3, network.clone(), vec![3]).catch_up_timeout(timeout).build();
+    let storage3 = node3.storage.clone();
+
+    // Setup test controller and actions.
+    let mut ctl = RaftTestController::new(network);
+    ctl.register(0, node0.addr.clone()).register(1, node1.addr.clone()).register(2, node2.addr.clone());
+    ctl.start_with_test(10, Box::new(move |act, ctx| {
+        let task = fut::wrap_future(act.network.send(GetCurrentLeader))
+            .map_err(|_, _: &mut RaftTestController, _| panic!("Failed to get current leader."))
+            .and_then(|res, _, _| fut::result(res)).and_then(|leader_opt, _, _| {
+                let leader = leader_opt.expect("Expected the cluster to have elected a leader.");
+                fut::ok(leader)
+            })
+            .and_then(|leader, act, _| act.write_data(leader).map(move |_, _, _| leader))
+
+            // Add node 3 as a witness while it is isolated. Its progress should be reported while the
+            // change is pending, & the change should be rolled back once the deadline has elapsed.
+            .and_then(move |leader, act, _| {
+                act.register(3, node3.addr.clone());
+                act.network.do_send(Register{id: 3, addr: node3.addr.clone()});
+                act.network.do_send(ExecuteInRaftRouter(Box::new(|act, _| act.isolate_node(3))));
+
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                let change = node.send(ChangeMembership::with_witnesses(vec![0, 1, 2, 3].into_iter().collect(), vec![3].into_iter().collect()));
+                let network = act.network.clone();
+                let check = Delay::new(Instant::now() + Duration::from_millis(1500))
+                    .map_err(|err| panic!("{}", err))
+                    .and_then(move |_| network.send(ExecuteInRaftRouter(Box::new(move |act, _| {
+                        let leader = act.metrics.get(&leader).expect("Expected leader's metrics to be present.");
+                        assert!(leader.membership_config.is_in_joint_consensus, "Expected cluster to be in joint consensus.");
+                        let progress = leader.catch_up_progress.get(&3).expect("Expected the progress of node 3 to be reported.");
+                        assert!(progress < &leader.last_log_index, "Expected node 3 not to have caught up.");
+                    }))).map_err(|err| panic!("{}", err)));
+                fut::wrap_future(change.join(check))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |(res, _), _, _| {
+                        match res {
+                            Err(ChangeMembershipError::CatchUpTimeout) => (),
+                            other => panic!("Expected CatchUpTimeout error, got {:?}.", other),
+                        }
+                        leader
+                    })
+            })
+            .and_then(|leader, _, _| fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(2)))
+                .map_err(|_, _, _| ())
+                .map(move |_, _, _| leader))
+
+            // Assert that the previous set of voting members has been committed again.
+            .and_then(|leader, act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| {
+                    let leader = act.metrics.get(&leader).expect("Expected leader's metrics to be present.");
+                    assert_eq!(leader.state, State::Leader, "Expected leader to have retained leadership.");
+                    assert_eq!(leader.membership_config.members.len(), 3, "Expected a three node cluster.");
+                    assert!(!leader.membership_config.contains(&3), "Expected node 3 to have been dropped.");
+                    assert!(!leader.membership_config.is_in_joint_consensus, "Expected cluster not to be in joint consensus.");
+                    assert!(leader.catch_up_progress.is_empty(), "Expected no progress to be reported.");
+                    for nodeid in leader.membership_config.members.iter().filter(|e| *e != &leader.id) {
+                        let node = act.metrics.get(nodeid).expect("Expected to find metrics entry for cluster member.");
+                        assert_eq!(node.state, State::Follower, "Expected all other cluster members to be followers.");
+                        assert_eq!(node.membership_config, leader.membership_config, "Expected all cluster members to have matching config.");
+                    }
+                    act.restore_node(3);
+                })));
+                fut::ok(leader)
+            })
+
+            // Now that node 3 is reachable, the same change should succeed.
+            .and_then(|leader, act, _| {
+                let target: BTreeSet<_> = vec![0, 1, 2, 3].into_iter().collect();
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                fut::wrap_future(node.send(ChangeMembership::new(target)))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |res, _, _| {
+                        res.expect("Expected ChangeMembership to succeed on the leader.");
+                        leader
+                    })
+            })
+            .and_then(|leader, act, _| act.write_data(leader).map(move |_, _, _| leader))
+            .and_then(|leader, _, _| fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(2)))
+                .map_err(|_, _, _| ())
+                .map(move |_, _, _| leader))
+            .and_then(|leader, act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| {
+                    let leader = act.metrics.get(&leader).expect("Expected leader's metrics to be present.");
+                    assert_eq!(leader.membership_config.members.len(), 4, "Expected a four node cluster.");
+                    assert!(!leader.membership_config.is_in_joint_consensus, "Expected cluster not to be in joint consensus.");
+                    assert!(leader.catch_up_progress.is_empty(), "Expected no progress to be reported.");
+
+                    let node3 = act.metrics.get(&3).expect("Expected node 3's metrics to be present.");
+                    assert_eq!(node3.state, State::Follower, "Expected node 3 to be a follower.");
+                    assert_eq!(node3.last_log_index, leader.last_log_index, "Expected node 3 to have caught up.");
+                    assert_eq!(node3.membership_config, leader.membership_config, "Expected node 3 to have matching config.");
+                })));
+                fut::ok(())
+            })
+
+            // Node 3 was never a witness per a committed config, so it should store application data.
+            .and_then(move |_, _, _| {
+                fut::wrap_future(storage3.send(GetCurrentState))
+                    .map_err(|err, _: &mut RaftTestController, _| panic!("{}", err))
+                    .and_then(|res, _, _| fut::result(res))
+                    .map(|state, _, _| {
+                        let (mut stripped, mut normal) = (0, 0);
+                        for entry in state.log.values().chain(state.state_machine.values()) {
+                            match entry.payload {
+                                EntryPayload::Stripped => stripped += 1,
+                                EntryPayload::Normal(_) => normal += 1,
+                                _ => (),
+                            }
+                        }
+                        assert_eq!(stripped, 0, "Expected node 3 not to have been treated as a witness.");
+                        assert!(normal >= 20, "Expected node 3 to store the data of each normal entry.");
+                        System::current().stop();
+                    })
+            })
+            .map_err(|err, _, _| panic!("Failure during test. {:?}", err));
+        ctx.spawn(task);
+    }));
+
+    // Run the test.
+    assert!(sys.run().is_ok(), "Error during test.");
+}
+
+impl RaftTestController {
+    /// Write 10 entries to the given leader, expecting each to be applied within a few seconds.
+    fn write_data(&mut self, leader: u64) -> impl ActorFuture<Actor=Self, Item=(), Error=()> {
+        let addr = self.nodes.get(&leader).expect("Expected leader to be registered.").clone();
+        fut::wrap_stream(futures::stream::iter_ok(0..10u64))
+            .and_then(move |data, _, _| {
+                let entry = EntryNormal{data: MemoryStorageData{data: data.to_string().into_bytes()}};
+                let payload = Payload::new(entry, ResponseMode::Applied);
+                fut::wrap_future(Timeout::new(addr.send(payload), Duration::from_secs(3)))
+                    .map_err(|err, _, _| panic!("Client request was not applied in time. {:?}", err))
+                    .map(|res, _, _| { res.expect("Expected client request to succeed."); })
+            })
+            .finish()
+    }
+}
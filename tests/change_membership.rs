@@ -0,0 +1,201 @@
+//! Test declarative membership changes.
+
+mod fixtures;
+
+use std::{
+    collections::BTreeSet,
+    time::{Duration, Instant},
+};
+
+use actix::prelude::*;
+use actix_raft::{
+    admin::{AddNonVoter, ChangeMembership, ChangeMembershipError, ProposeConfigChange, ProposeConfigChangeError},
+    messages::{EntryNormal, ResponseMode},
+    metrics::State,
+};
+use tokio_timer::{Delay, Timeout};
+
+use fixtures::{
+    Payload, RaftTestController, Node, setup_logger,
+    dev::{ExecuteInRaftRouter, GetCurrentLeader, RaftRouter, Register, RemoveNodeFromCluster},
+    memory_storage::MemoryStorageData,
+};
+
+/// Declarative membership change tests for a three node cluster.
+///
+/// What does this test cover?
+///
+/// - A `ChangeMembership` command sent to a follower should be rejected.
+/// - A change to the current set of voters, or to a single node, should be rejected.
+/// - A change which promotes a non-voter, adds a new node & removes the leader plus a follower should succeed.
+/// - A `ProposeConfigChange` command sent while the change is in progress should be rejected.
+/// - Once the command resolves, the cluster should no longer be in joint consensus.
+/// - The new set of voters should elect a new leader & keep committing entries.
+///
+/// `RUST_LOG=actix_raft,change_membership=debug cargo test change_membership`
+#[test]
+fn change_membership() {
+    setup_logger();
+    let sys = System::builder().stop_on_panic(true).name("test").build();
+
+    // Setup test dependencies.
+    let net = RaftRouter::new();
+    let network = net.start();
+    let members = vec![0, 1, 2];
+    let node0 = Node::builder(0, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 0, addr: node0.addr.clone()});
+    let node1 = Node::builder(1, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 1, addr: node1.addr.clone()});
+    let node2 = Node::builder(2, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 2, addr: node2.addr.clone()});
+
+    // Setup test controller and actions.
+    let mut ctl = RaftTestController::new(network);
+    ctl.register(0, node0.addr.clone()).register(1, node1.addr.clone()).register(2, node2.addr.clone());
+    ctl.start_with_test(10, Box::new(|act, ctx| {
+        let task = fut::wrap_future(act.network.send(GetCurrentLeader))
+            .map_err(|_, _: &mut RaftTestController, _| panic!("Failed to get current leader."))
+            .and_then(|res, _, _| fut::result(res)).and_then(|leader_opt, _, _| {
+                let leader = leader_opt.expect("Expected the cluster to have elected a leader.");
+                fut::ok(leader)
+            })
+            .and_then(|leader, act, _| act.write_data(leader).map(move |_, _, _| leader))
+
+            // Send a ChangeMembership command to a follower. It should be rejected.
+            .and_then(|leader, act, _| {
+                let follower = act.nodes.keys().cloned().find(|id| id != &leader).expect("Expected a follower.");
+                let node = act.nodes.get(&follower).expect("Expected follower to be registered.");
+                fut::wrap_future(node.send(ChangeMembership::new(vec![0, 1, 3].into_iter().collect())))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |res, _, _| {
+                        match res {
+                            Err(ChangeMembershipError::NodeNotLeader(Some(id))) => assert_eq!(id, leader, "Expected follower to report the leader."),
+                            other => panic!("Expected NodeNotLeader error from follower, got {:?}.", other),
+                        }
+                        (leader, follower)
+                    })
+            })
+
+            // Changes to the current set of voters, or to a single node, should be rejected.
+            .and_then(|(leader, follower), act, _| {
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                let noop = node.send(ChangeMembership::new(vec![0, 1, 2].into_iter().collect()));
+                let inoperable = node.send(ChangeMembership::new(vec![leader].into_iter().collect()));
+                fut::wrap_future(noop.join(inoperable))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |(noop, inoperable), _, _| {
+                        match noop {
+                            Err(ChangeMembershipError::Noop) => (),
+                            other => panic!("Expected Noop error, got {:?}.", other),
+                        }
+                        match inoperable {
+                            Err(ChangeMembershipError::InoperableConfig) => (),
+                            other => panic!("Expected InoperableConfig error, got {:?}.", other),
+                        }
+                        (leader, follower)
+                    })
+            })
+
+            // Add node 3 as a non-voter, and give it a moment to catch up.
+            .and_then(|(leader, follower), act, _| {
+                let node3 = Node::builder(3, act.network.clone(), vec![3]).build();
+                act.register(3, node3.addr.clone());
+                act.network.do_send(Register{id: 3, addr: node3.addr.clone()});
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                fut::wrap_future(node.send(AddNonVoter::new(3)))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |res, _, _| {
+                        res.expect("Expected AddNonVoter to succeed on the leader.");
+                        (leader, follower)
+                    })
+            })
+            .and_then(|ids, _, _| fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(1)))
+                .map_err(|_, _, _| ())
+                .map(move |_, _, _| ids))
+
+            // Change the voters to the kept follower, node 3 & a new node 4.
+            .and_then(|(leader, follower), act, _| {
+                let node4 = Node::builder(4, act.network.clone(), vec![4]).build();
+                act.register(4, node4.addr.clone());
+                act.network.do_send(Register{id: 4, addr: node4.addr.clone()});
+
+                let target: BTreeSet<_> = vec![follower, 3, 4].into_iter().collect();
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                let change = node.send(ChangeMembership::new(target.clone()));
+                let concurrent = node.send(ProposeConfigChange::new(vec![], vec![4]));
+                fut::wrap_future(change.join(concurrent))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |(res, concurrent), _, _| {
+                        res.expect("Expected ChangeMembership to succeed on the leader.");
+                        match concurrent {
+                            Err(ProposeConfigChangeError::ChangeInProgress) => (),
+                            other => panic!("Expected ChangeInProgress error, got {:?}.", other),
+                        }
+                        (leader, target)
+                    })
+            })
+            .and_then(|ids, _, _| fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(6)))
+                .map_err(|_, _, _| ())
+                .map(move |_, _, _| ids))
+
+            // Remove the nodes which are no longer members & write more data to the new leader.
+            .and_then(|(old_leader, target), act, _| {
+                let removed: Vec<_> = act.nodes.keys().cloned().filter(|id| !target.contains(id)).collect();
+                for id in removed.iter() {
+                    act.network.do_send(RemoveNodeFromCluster{id: *id});
+                }
+                fut::wrap_future(act.network.send(GetCurrentLeader))
+                    .map_err(|_, _: &mut RaftTestController, _| panic!("Failed to get current leader."))
+                    .and_then(|res, _, _| fut::result(res))
+                    .and_then(move |leader_opt, act, _| {
+                        let leader = leader_opt.expect("Expected the cluster to have elected a new leader.");
+                        assert_ne!(leader, old_leader, "Expected a new leader to have been elected.");
+                        act.write_data(leader).map(move |_, _, _| (leader, target))
+                    })
+            })
+            .and_then(|ids, _, _| fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(2)))
+                .map_err(|_, _, _| ())
+                .map(move |_, _, _| ids))
+
+            // Assert against the state of the cluster.
+            .and_then(|(leader, target), act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| {
+                    let leader = act.metrics.get(&leader).expect("Expected leader's metrics to be present.");
+                    let members: BTreeSet<_> = leader.membership_config.members.iter().cloned().collect();
+                    assert_eq!(members, target, "Expected the voting members to match the requested set.");
+                    assert!(!leader.membership_config.is_in_joint_consensus, "Expected cluster not to be in joint consensus.");
+                    assert!(leader.membership_config.non_voters.is_empty(), "Expected no non-voters in cluster.");
+                    assert!(leader.membership_config.learners.is_empty(), "Expected node 3 to have been promoted.");
+                    for nodeid in target.iter().filter(|e| *e != &leader.id) {
+                        let node = act.metrics.get(nodeid).expect("Expected to find metrics entry for cluster member.");
+                        assert_eq!(node.state, State::Follower, "Expected all other cluster members to be followers.");
+                        assert_eq!(node.membership_config, leader.membership_config, "Expected all cluster members to have matching config.");
+                        assert_eq!(node.last_log_index, leader.last_log_index, "Expected all cluster members to have matching last log index.");
+                    }
+                    System::current().stop();
+                })));
+                fut::ok(())
+            })
+            .map_err(|err, _, _| panic!("Failure during test. {:?}", err));
+        ctx.spawn(task);
+    }));
+
+    // Run the test.
+    assert!(sys.run().is_ok(), "Error during test.");
+}
+
+impl RaftTestController {
+    /// Write 10 entries to the given leader, expecting each to be applied within a few seconds.
+    fn write_data(&mut self, leader: u64) -> impl ActorFuture<Actor=Self, Item=(), Error=()> {
+        let addr = self.nodes.get(&leader).expect("Expected leader to be registered.").clone();
+        fut::wrap_stream(futures::stream::iter_ok(0..10u64))
+            .and_then(move |data, _, _| {
+                let entry = EntryNormal{data: MemoryStorageData{data: data.to_string().into_bytes()}};
+                let payload = Payload::new(entry, ResponseMode::Applied);
+                fut::wrap_future(Timeout::new(addr.send(payload), Duration::from_secs(3)))
+                    .map_err(|err, _, _| panic!("Client request was not applied in time. {:?}", err))
+                    .map(|res, _, _| { res.expect("Expected client request to succeed."); })
+            })
+            .finish()
+    }
+}
@@ -0,0 +1,92 @@
+//! Test leader step down behavior when quorum is lost.
+
+mod fixtures;
+
+use std::time::{Duration, Instant};
+
+use actix::prelude::*;
+use actix_raft::{
+    messages::{ClientError, EntryNormal, ResponseMode},
+    metrics::{RaftMetrics, State},
+};
+use tokio_timer::Delay;
+
+use fixtures::{
+    Payload, RaftTestController, Node, setup_logger,
+    dev::{ExecuteInRaftRouter, GetCurrentLeader, RaftRouter, Register},
+    memory_storage::MemoryStorageData,
+};
+
+/// CheckQuorum tests for a three node cluster.
+///
+/// What does this test cover?
+///
+/// - A leader which has been partitioned from the rest of the cluster should step down once it
+///   has not heard from a majority of the cluster within an election timeout.
+/// - Client requests which were awaiting commitment on the old leader should receive an
+///   `Indeterminate` error, as they may still be committed by the next leader.
+///
+/// `RUST_LOG=actix_raft,check_quorum=debug cargo test check_quorum`
+#[test]
+fn check_quorum() {
+    setup_logger();
+    let sys = System::builder().stop_on_panic(true).name("test").build();
+
+    // Setup test dependencies.
+    let netarb = Arbiter::new();
+    let network = RaftRouter::start_in_arbiter(&netarb, |_| RaftRouter::new());
+    let members = vec![0, 1, 2];
+    let node0 = Node::builder(0, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 0, addr: node0.addr.clone()});
+    let node1 = Node::builder(1, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 1, addr: node1.addr.clone()});
+    let node2 = Node::builder(2, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 2, addr: node2.addr.clone()});
+
+    // Setup test controller and actions.
+    let mut ctl = RaftTestController::new(network);
+    ctl.register(0, node0.addr.clone()).register(1, node1.addr.clone()).register(2, node2.addr.clone());
+    ctl.start_with_test(10,  Box::new(|act, ctx| {
+        // Get the current leader.
+        ctx.spawn(fut::wrap_future(act.network.send(GetCurrentLeader))
+            .map_err(|_, _: &mut RaftTestController, _| panic!("Failed to get current leader."))
+            .and_then(|res, _, _| fut::result(res)).and_then(|leader_opt, _, _| {
+                let leader = leader_opt.expect("Expected the cluster to have elected a leader.");
+                fut::ok(leader)
+            })
+
+            // Isolate the leader & send it a client request which can never be committed.
+            .and_then(|leader, act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| act.isolate_node(leader))));
+                let entry = EntryNormal{data: MemoryStorageData{data: b"0".to_vec()}};
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                fut::wrap_future(node.send(Payload::new(entry, ResponseMode::Applied)))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |res, _, _| match res {
+                        Err(ClientError::Indeterminate{..}) => leader,
+                        other => panic!("Expected Indeterminate error from isolated leader, got {:?}.", other),
+                    })
+            })
+
+            // Give the metrics time to be reported, then assert that the old leader has stepped down.
+            .and_then(|leader, _, _| {
+                fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(2))).map_err(|_, _, _| ())
+                    .map(move |_, _, _| leader)
+            })
+            .and_then(|leader, act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| {
+                    let old_leader: &RaftMetrics = act.metrics.get(&leader).unwrap();
+                    assert_ne!(old_leader.state, State::Leader, "Expected isolated leader to have stepped down.");
+                })));
+                fut::ok(())
+            })
+            .and_then(|_, _, ctx| {
+                ctx.run_later(Duration::from_secs(2), |_, _| System::current().stop());
+                fut::ok(())
+            })
+            .map_err(|err, _, _| panic!("Failure during test. {:?}", err)));
+    }));
+
+    // Run the test.
+    assert!(sys.run().is_ok(), "Error during test.");
+}
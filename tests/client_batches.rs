@@ -0,0 +1,130 @@
+//! Test client payload batch behavior.
+
+mod fixtures;
+
+use std::time::{Duration, Instant};
+
+use actix::prelude::*;
+use actix_raft::messages::{ClientBatchError, ClientPayloadResponse, EntryNormal, ResponseMode};
+use tokio_timer::Delay;
+
+use fixtures::{
+    PayloadBatch, RaftTestController, Node, setup_logger,
+    dev::{GetCurrentLeader, RaftRouter, Register},
+    memory_storage::MemoryStorageData,
+};
+
+/// Build a batch of entries with the given response mode.
+fn batch(len: u64, response_mode: ResponseMode) -> PayloadBatch {
+    let entries = (0..len).map(|idx| EntryNormal{data: MemoryStorageData{data: idx.to_string().into_bytes()}}).collect();
+    PayloadBatch::new(entries, response_mode)
+}
+
+/// Assert that the given responses have contiguous indices, and return the last index.
+fn assert_contiguous(responses: &[ClientPayloadResponse<fixtures::memory_storage::MemoryStorageResponse>], len: usize) -> u64 {
+    assert_eq!(responses.len(), len, "Expected one response per entry of the batch.");
+    for pair in responses.windows(2) {
+        assert_eq!(pair[0].index() + 1, pair[1].index(), "Expected batch entries to have contiguous indices.");
+    }
+    responses.last().map(|res| res.index()).unwrap_or(0)
+}
+
+/// Client payload batch tests for a three node cluster.
+///
+/// What does this test cover?
+///
+/// - Batches sent to the leader should resolve with one response per entry, in index order.
+/// - Consecutive batches should be assigned consecutive ranges of indices.
+/// - Batches sent to a follower should hand back the original batch for forwarding.
+///
+/// `RUST_LOG=actix_raft,client_batches=debug cargo test client_batches`
+#[test]
+fn client_batches() {
+    setup_logger();
+    let sys = System::builder().stop_on_panic(true).name("test").build();
+
+    // Setup test dependencies.
+    let netarb = Arbiter::new();
+    let network = RaftRouter::start_in_arbiter(&netarb, |_| RaftRouter::new());
+    let members = vec![0, 1, 2];
+    let node0 = Node::builder(0, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 0, addr: node0.addr.clone()});
+    let node1 = Node::builder(1, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 1, addr: node1.addr.clone()});
+    let node2 = Node::builder(2, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 2, addr: node2.addr.clone()});
+
+    // Setup test controller and actions.
+    let mut ctl = RaftTestController::new(network);
+    ctl.register(0, node0.addr.clone()).register(1, node1.addr.clone()).register(2, node2.addr.clone());
+    ctl.start_with_test(10,  Box::new(|act, ctx| {
+        // Get the current leader.
+        ctx.spawn(fut::wrap_future(act.network.send(GetCurrentLeader))
+            .map_err(|_, _: &mut RaftTestController, _| panic!("Failed to get current leader."))
+            .and_then(|res, _, _| fut::result(res)).and_then(|leader_opt, _, _| {
+                let leader = leader_opt.expect("Expected the cluster to have elected a leader.");
+                fut::ok(leader)
+            })
+
+            // Send a batch to the leader which waits for its entries to be applied.
+            .and_then(|leader, act, _| {
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                fut::wrap_future(node.send(batch(10, ResponseMode::Applied)))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |res, _, _| {
+                        let responses = res.expect("Expected batch sent to leader to succeed.");
+                        assert!(responses.iter().all(|res| matches!(res, ClientPayloadResponse::Applied{..})), "Expected applied responses.");
+                        (leader, assert_contiguous(&responses, 10))
+                    })
+            })
+
+            // Send a batch to the leader which waits only for its entries to be committed.
+            .and_then(|(leader, last_index), act, _| {
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                fut::wrap_future(node.send(batch(5, ResponseMode::Committed)))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |res, _, _| {
+                        let responses = res.expect("Expected batch sent to leader to succeed.");
+                        assert!(responses.iter().all(|res| matches!(res, ClientPayloadResponse::Committed{..})), "Expected committed responses.");
+                        assert_eq!(responses[0].index(), last_index + 1, "Expected batches to be assigned consecutive indices.");
+                        assert_contiguous(&responses, 5);
+                        leader
+                    })
+            })
+
+            // Send a batch to a follower. It should hand the batch back for forwarding.
+            .and_then(|leader, act, _| {
+                let follower = act.nodes.keys().cloned().find(|id| id != &leader).expect("Expected a follower.");
+                let node = act.nodes.get(&follower).expect("Expected follower to be registered.");
+                fut::wrap_future(node.send(batch(3, ResponseMode::Applied)))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |res, _, _| match res {
+                        Err(ClientBatchError::ForwardToLeader{payload, leader: Some(id)}) => {
+                            assert_eq!(id, leader, "Expected follower to forward to the leader.");
+                            (leader, payload)
+                        }
+                        other => panic!("Expected ForwardToLeader error from follower, got {:?}.", other),
+                    })
+            })
+
+            // Forward the batch to the leader.
+            .and_then(|(leader, payload), act, _| {
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                fut::wrap_future(node.send(payload))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(|res, _, _| {
+                        let responses = res.expect("Expected forwarded batch to succeed.");
+                        assert_contiguous(&responses, 3);
+                    })
+            })
+            .and_then(|_, _, _| fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(1))).map_err(|_, _, _| ()))
+            .and_then(|_, _, _| {
+                System::current().stop();
+                fut::ok(())
+            })
+            .map_err(|err, _, _| panic!("Failure during test. {:?}", err)));
+    }));
+
+    // Run the test.
+    assert!(sys.run().is_ok(), "Error during test.");
+}
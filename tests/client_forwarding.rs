@@ -0,0 +1,149 @@
+//! Test the forwarding of client payloads from followers to the Raft leader.
+
+mod fixtures;
+
+use std::time::{Duration, Instant};
+
+use actix::prelude::*;
+use actix_raft::messages::{ClientBatchError, ClientError, ClientPayloadResponse, EntryNormal, EntryPayload, ResponseMode};
+use futures::future;
+use tokio_timer::Delay;
+
+use fixtures::{
+    Payload, PayloadBatch, RaftTestController, Node, setup_logger,
+    dev::{GetCurrentLeader, RaftRouter, Register},
+    memory_storage::{GetCurrentState, MemoryStorageData},
+};
+
+/// The longest the nodes of this test will wait for a leader when forwarding client payloads.
+const FORWARDING_WAIT: Duration = Duration::from_secs(5);
+/// The longest the lone node of this test will wait for a leader when forwarding client payloads.
+const LONE_FORWARDING_WAIT: Duration = Duration::from_secs(1);
+
+/// Client payload forwarding tests for a three node cluster.
+///
+/// What does this test cover?
+///
+/// - A client payload sent before any leader has been elected should wait for the election, and
+///   then be forwarded to the new leader & applied.
+/// - A client payload sent to a follower should be forwarded to the leader & applied, with the
+///   leader's response relayed back to the client.
+/// - A batch of client payloads sent to a follower should still be returned with
+///   `ClientBatchError::ForwardToLeader`.
+/// - A client payload sent to a node which never learns of a leader should be returned with
+///   `ClientError::ForwardToLeader` once the configured wait has elapsed.
+///
+/// `RUST_LOG=actix_raft,client_forwarding=debug cargo test client_forwarding`
+#[test]
+fn client_forwarding() {
+    setup_logger();
+    let sys = System::builder().stop_on_panic(true).name("test").build();
+
+    // Setup test dependencies.
+    let net = RaftRouter::new();
+    let network = net.start();
+    let members = vec![0, 1, 2];
+    let node0 = Node::builder(0, network.clone(), members.clone()).forward_client_payloads(FORWARDING_WAIT).build();
+    network.do_send(Register{id: 0, addr: node0.addr.clone()});
+    let node1 = Node::builder(1, network.clone(), members.clone()).forward_client_payloads(FORWARDING_WAIT).build();
+    network.do_send(Register{id: 1, addr: node1.addr.clone()});
+    let node2 = Node::builder(2, network.clone(), members.clone()).forward_client_payloads(FORWARDING_WAIT).build();
+    network.do_send(Register{id: 2, addr: node2.addr.clone()});
+    // A pristine node outside of the cluster, which will never learn of a leader.
+    let node3 = Node::builder(3, network.clone(), vec![3]).forward_client_payloads(LONE_FORWARDING_WAIT).build();
+    network.do_send(Register{id: 3, addr: node3.addr.clone()});
+    let storages = vec![node0.storage.clone(), node1.storage.clone(), node2.storage.clone()];
+
+    // Setup test controller and actions.
+    let mut ctl = RaftTestController::new(network);
+    ctl.register(0, node0.addr.clone()).register(1, node1.addr.clone()).register(2, node2.addr.clone());
+    ctl.start_with_test(0, Box::new(move |act, ctx| {
+        let storages = storages.clone();
+        let lone_node = node3.addr.clone();
+
+        // Send a client payload before a leader has been elected. It should wait for the election.
+        let node = act.nodes.get(&0).expect("Expected node 0 to be registered.");
+        let task = fut::wrap_future(node.send(payload(b"early")))
+            .map_err(|err, _: &mut RaftTestController, _| panic!("{}", err))
+            .map(|res, _, _| {
+                res.expect("Expected client payload sent before the election to be applied.");
+            })
+
+            // Give the metrics time to be reported, then get the current leader.
+            .and_then(|_, _, _| fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(2))).map_err(|_, _, _| ()))
+            .and_then(|_, act, _| fut::wrap_future(act.network.send(GetCurrentLeader))
+                .map_err(|_, _, _| panic!("Failed to get current leader."))
+                .and_then(|res, _, _| fut::result(res)))
+            .map(|leader_opt, _, _| leader_opt.expect("Expected the cluster to have elected a leader."))
+
+            // Send a client payload to a follower. It should be forwarded to the leader.
+            .and_then(|leader, act, _| {
+                let follower = (leader + 1) % 3;
+                let node = act.nodes.get(&follower).expect("Expected follower to be registered.");
+                fut::wrap_future(node.send(payload(b"forwarded")))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |res, _, _| match res {
+                        Ok(ClientPayloadResponse::Applied{..}) => leader,
+                        other => panic!("Expected forwarded client payload to be applied, got {:?}.", other),
+                    })
+            })
+
+            // Send a batch to a follower. Batches are never forwarded.
+            .and_then(|leader, act, _| {
+                let follower = (leader + 1) % 3;
+                let node = act.nodes.get(&follower).expect("Expected follower to be registered.");
+                let entries = vec![EntryNormal{data: MemoryStorageData{data: b"batched".to_vec()}}];
+                fut::wrap_future(node.send(PayloadBatch::new(entries, ResponseMode::Applied)))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |res, _, _| match res {
+                        Err(ClientBatchError::ForwardToLeader{leader: Some(id), ..}) => assert_eq!(id, leader, "Expected follower to point to the leader."),
+                        other => panic!("Expected ForwardToLeader error from follower, got {:?}.", other),
+                    })
+            })
+
+            // Send a client payload to a node which knows of no leader. It should be returned once its wait has elapsed.
+            .and_then(move |_, _, _| {
+                let start = Instant::now();
+                fut::wrap_future(lone_node.send(payload(b"stranded")))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |res, _, _| {
+                        match res {
+                            Err(ClientError::ForwardToLeader{leader: None, ..}) => (),
+                            other => panic!("Expected ForwardToLeader error from lone node, got {:?}.", other),
+                        }
+                        assert!(start.elapsed() >= LONE_FORWARDING_WAIT, "Expected lone node to wait for a leader before responding.");
+                    })
+            })
+            .and_then(|_, _, _| fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(1))).map_err(|_, _, _| ()))
+
+            // Each forwarded client payload should have been applied exactly once on every node.
+            .and_then(move |_, _, _| {
+                fut::wrap_future(future::join_all(storages.iter().map(|storage| storage.send(GetCurrentState)).collect::<Vec<_>>()))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(|states, _, _| {
+                        for state in states {
+                            let state = state.expect("Expected storage state to be returned.");
+                            for data in [b"early".to_vec(), b"forwarded".to_vec()].iter() {
+                                let applied = state.state_machine.values().filter(|entry| match &entry.payload {
+                                    EntryPayload::Normal(inner) => &inner.data.data == data,
+                                    _ => false,
+                                }).count();
+                                assert_eq!(applied, 1, "Expected forwarded client payload to be applied exactly once.");
+                            }
+                        }
+                        System::current().stop();
+                    })
+            })
+            .map_err(|err, _, _| panic!("Failure during test. {:?}", err));
+        ctx.spawn(task);
+    }));
+
+    // Run the test.
+    assert!(sys.run().is_ok(), "Error during test.");
+}
+
+/// Build a client payload with the given data.
+fn payload(data: &[u8]) -> Payload {
+    let entry = EntryNormal{data: MemoryStorageData{data: data.to_vec()}};
+    Payload::new(entry, ResponseMode::Applied)
+}
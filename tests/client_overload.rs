@@ -0,0 +1,103 @@
+//! Test the rejection of client requests by an overloaded leader.
+
+mod fixtures;
+
+use std::time::{Duration, Instant};
+
+use actix::prelude::*;
+use actix_raft::messages::{ClientError, EntryNormal, ResponseMode};
+use futures::future;
+use tokio_timer::Delay;
+
+use fixtures::{
+    Payload, RaftTestController, Node, setup_logger,
+    dev::{GetCurrentLeader, RaftRouter, Register},
+    memory_storage::MemoryStorageData,
+};
+
+/// The latency of the leader's disk writes.
+const LATENCY: Duration = Duration::from_millis(200);
+/// The capacity of the leader's client request queue.
+const CAPACITY: u64 = 5;
+
+/// Client overload tests for a three node cluster with slow disk writes.
+///
+/// What does this test cover?
+///
+/// - A burst of client requests larger than the leader's `client_queue_capacity` should see the
+///   excess requests rejected with `ClientError::Overloaded`, while the rest are applied.
+/// - Once the leader has worked through its backlog, new client requests should be accepted.
+///
+/// `RUST_LOG=actix_raft,client_overload=debug cargo test client_overload`
+#[test]
+fn client_overload() {
+    setup_logger();
+    let sys = System::builder().stop_on_panic(true).name("test").build();
+
+    // Setup test dependencies.
+    let net = RaftRouter::new();
+    let network = net.start();
+    let members = vec![0, 1, 2];
+    let node0 = Node::builder(0, network.clone(), members.clone()).append_latency(LATENCY).client_queue_capacity(CAPACITY).build();
+    network.do_send(Register{id: 0, addr: node0.addr.clone()});
+    let node1 = Node::builder(1, network.clone(), members.clone()).append_latency(LATENCY).client_queue_capacity(CAPACITY).build();
+    network.do_send(Register{id: 1, addr: node1.addr.clone()});
+    let node2 = Node::builder(2, network.clone(), members.clone()).append_latency(LATENCY).client_queue_capacity(CAPACITY).build();
+    network.do_send(Register{id: 2, addr: node2.addr.clone()});
+
+    // Setup test controller and actions.
+    let mut ctl = RaftTestController::new(network);
+    ctl.register(0, node0.addr.clone()).register(1, node1.addr.clone()).register(2, node2.addr.clone());
+    ctl.start_with_test(10, Box::new(|act, ctx| {
+        let task = fut::wrap_future(act.network.send(GetCurrentLeader))
+            .map_err(|_, _: &mut RaftTestController, _| panic!("Failed to get current leader."))
+            .and_then(|res, _, _| fut::result(res)).and_then(|leader_opt, _, _| {
+                let leader = leader_opt.expect("Expected the cluster to have elected a leader.");
+                fut::ok(leader)
+            })
+
+            // Send a burst of requests to the leader. Only some of them should be accepted.
+            .and_then(|leader, act, _| {
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                let requests: Vec<_> = (0..50u64).map(|idx| {
+                    let entry = EntryNormal{data: MemoryStorageData{data: idx.to_string().into_bytes()}};
+                    node.send(Payload::new(entry, ResponseMode::Applied))
+                }).collect();
+                fut::wrap_future(future::join_all(requests))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |results, _, _| {
+                        let (mut applied, mut overloaded) = (0, 0);
+                        for res in results {
+                            match res {
+                                Ok(_) => applied += 1,
+                                Err(ClientError::Overloaded) => overloaded += 1,
+                                Err(err) => panic!("Expected client request to be applied or rejected as overloaded, got {:?}.", err),
+                            }
+                        }
+                        assert!(applied > 0, "Expected some client requests to be applied.");
+                        assert!(overloaded > 0, "Expected some client requests to be rejected as overloaded.");
+                        leader
+                    })
+            })
+            .and_then(|leader, _, _| fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(1)))
+                .map_err(|_, _, _| ())
+                .map(move |_, _, _| leader))
+
+            // Once the backlog has been worked through, new requests should be accepted.
+            .and_then(|leader, act, _| {
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                let entry = EntryNormal{data: MemoryStorageData{data: b"after".to_vec()}};
+                fut::wrap_future(node.send(Payload::new(entry, ResponseMode::Applied)))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(|res, _, _| {
+                        res.expect("Expected client request to be accepted once the backlog has cleared.");
+                        System::current().stop();
+                    })
+            })
+            .map_err(|err, _, _| panic!("Failure during test. {:?}", err));
+        ctx.spawn(task);
+    }));
+
+    // Run the test.
+    assert!(sys.run().is_ok(), "Error during test.");
+}
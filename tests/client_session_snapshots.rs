@@ -0,0 +1,165 @@
+//! Test that client sessions are carried by snapshots.
+
+mod fixtures;
+
+use std::time::{Duration, Instant};
+
+use actix::prelude::*;
+use actix_raft::{
+    admin::TransferLeadership,
+    config::SnapshotPolicy,
+    messages::{ClientPayloadResponse, EntryNormal, EntryPayload, ResponseMode},
+};
+use futures::future;
+use tokio_timer::Delay;
+
+use fixtures::{
+    Payload, RaftTestController, Node, setup_logger,
+    dev::{ExecuteInRaftRouter, GetCurrentLeader, RaftRouter, Register},
+    memory_storage::{GetCurrentState, MemoryStorageData},
+};
+
+/// The ID of the client session used by this test.
+const CLIENT_ID: u64 = 7;
+
+/// The snapshot policy threshold used by this test.
+const SNAPSHOT_THRESHOLD: u64 = 20;
+
+/// Client session snapshot tests for a three node cluster.
+///
+/// What does this test cover?
+///
+/// - A follower which is brought up-to-speed with a snapshot should receive the client sessions
+///   of the entries covered by the snapshot, along with the snapshot.
+/// - Once that follower has become the leader, a retry of a request covered by the snapshot should
+///   be answered with the index of the original request, without being appended a second time.
+///
+/// `RUST_LOG=actix_raft,client_session_snapshots=debug cargo test client_session_snapshots`
+#[test]
+fn client_session_snapshots() {
+    setup_logger();
+    let sys = System::builder().stop_on_panic(true).name("test").build();
+
+    // Setup test dependencies.
+    let net = RaftRouter::new();
+    let network = net.start();
+    let members = vec![0, 1, 2];
+    let policy = || SnapshotPolicy::LogsSinceLast(SNAPSHOT_THRESHOLD);
+    let node0 = Node::builder(0, network.clone(), members.clone()).snapshot_policy(policy()).build();
+    network.do_send(Register{id: 0, addr: node0.addr.clone()});
+    let node1 = Node::builder(1, network.clone(), members.clone()).snapshot_policy(policy()).build();
+    network.do_send(Register{id: 1, addr: node1.addr.clone()});
+    let node2 = Node::builder(2, network.clone(), members.clone()).snapshot_policy(policy()).build();
+    network.do_send(Register{id: 2, addr: node2.addr.clone()});
+    let storages = vec![node0.storage.clone(), node1.storage.clone(), node2.storage.clone()];
+
+    // Setup test controller and actions.
+    let mut ctl = RaftTestController::new(network);
+    ctl.register(0, node0.addr.clone()).register(1, node1.addr.clone()).register(2, node2.addr.clone());
+    ctl.start_with_test(10, Box::new(move |act, ctx| {
+        let storages = storages.clone();
+        let lagging_storages = storages.clone();
+        let task = fut::wrap_future(act.network.send(GetCurrentLeader))
+            .map_err(|_, _: &mut RaftTestController, _| panic!("Failed to get current leader."))
+            .and_then(|res, _, _| fut::result(res)).and_then(|leader_opt, _, _| {
+                let leader = leader_opt.expect("Expected the cluster to have elected a leader.");
+                fut::ok(leader)
+            })
+
+            // Apply a request of the session, then isolate a follower, so that it falls behind.
+            .and_then(|leader, act, _| {
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                let lagging = (leader + 1) % 3;
+                fut::wrap_future(node.send(session_payload(1)))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |res, act: &mut RaftTestController, _| {
+                        let original = res.expect("Expected client request to be applied.").index();
+                        act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| act.isolate_node(lagging))));
+                        (leader, lagging, original)
+                    })
+            })
+
+            // Write enough requests for the lagging follower to need a snapshot, then restore it.
+            .and_then(|(leader, lagging, original), act, _| {
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.").clone();
+                fut::wrap_stream(futures::stream::iter_ok(0..SNAPSHOT_THRESHOLD * 2))
+                    .and_then(move |data, _, _| {
+                        let entry = EntryNormal{data: MemoryStorageData{data: data.to_string().into_bytes()}};
+                        fut::wrap_future(node.send(Payload::new(entry, ResponseMode::Applied)))
+                            .map_err(|err, _, _| panic!("{}", err))
+                            .map(|res, _, _| { res.expect("Expected client request to be applied."); })
+                    })
+                    .finish()
+                    .map(move |_, act: &mut RaftTestController, _| {
+                        act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| act.restore_node(lagging))));
+                        (leader, lagging, original)
+                    })
+            })
+            .and_then(|vals, _, _| fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(5))).map_err(|_, _, _| ()).map(move |_, _, _| vals))
+
+            // The lagging follower should have installed a snapshot carrying the client session.
+            .and_then(move |vals, _, _| {
+                let (_, lagging, _) = vals;
+                fut::wrap_future(lagging_storages[lagging as usize].send(GetCurrentState))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |state, _, _| {
+                        let state = state.expect("Expected storage state to be returned.");
+                        let snapshot = state.snapshot_data.expect("Expected the lagging follower to have installed a snapshot.");
+                        assert_eq!(snapshot.client_sessions.len(), 1, "Expected the snapshot to carry the client session.");
+                        vals
+                    })
+            })
+
+            // Transfer leadership to the lagging follower.
+            .and_then(|(leader, lagging, original), act, _| {
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                fut::wrap_future(node.send(TransferLeadership::new(lagging)))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |res, _, _| {
+                        res.expect("Expected leadership to be transferred to the lagging follower.");
+                        (lagging, original)
+                    })
+            })
+            .and_then(|vals, _, _| fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(1))).map_err(|_, _, _| ()).map(move |_, _, _| vals))
+
+            // A retry of the request should be answered with its original index by the new leader.
+            .and_then(|(leader, original), act, _| {
+                let node = act.nodes.get(&leader).expect("Expected new leader to be registered.");
+                fut::wrap_future(node.send(session_payload(1)))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |res, _, _| match res {
+                        Ok(ClientPayloadResponse::Committed{index}) if index == original => (),
+                        other => panic!("Expected retried client request to get its original index, got {:?}.", other),
+                    })
+            })
+            .and_then(|_, _, _| fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(1))).map_err(|_, _, _| ()))
+
+            // The request should have been applied exactly once on every node.
+            .and_then(move |_, _, _| {
+                fut::wrap_future(future::join_all(storages.iter().map(|storage| storage.send(GetCurrentState)).collect::<Vec<_>>()))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(|states, _, _| {
+                        for state in states {
+                            let state = state.expect("Expected storage state to be returned.");
+                            let applied = state.state_machine.values().filter(|entry| match &entry.payload {
+                                EntryPayload::Normal(inner) => inner.data.data == b"session".to_vec(),
+                                _ => false,
+                            }).count();
+                            assert_eq!(applied, 1, "Expected client request to be applied exactly once.");
+                        }
+                        System::current().stop();
+                    })
+            })
+            .map_err(|err, _, _| panic!("Failure during test. {:?}", err));
+        ctx.spawn(task);
+    }));
+
+    // Run the test.
+    assert!(sys.run().is_ok(), "Error during test.");
+}
+
+/// Build a client payload of this test's client session.
+fn session_payload(sequence: u64) -> Payload {
+    let entry = EntryNormal{data: MemoryStorageData{data: b"session".to_vec()}};
+    Payload::new(entry, ResponseMode::Applied).with_session(CLIENT_ID, sequence)
+}
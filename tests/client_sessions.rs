@@ -0,0 +1,188 @@
+//! Test exactly-once handling of client requests which carry a client session.
+
+mod fixtures;
+
+use std::time::{Duration, Instant};
+
+use actix::prelude::*;
+use actix_raft::messages::{ClientError, ClientPayloadResponse, Entry, EntryNormal, EntryPayload, ResponseMode};
+use futures::future;
+use tokio_timer::Delay;
+
+use fixtures::{
+    Payload, PayloadBatch, RaftTestController, Node, setup_logger,
+    dev::{GetCurrentLeader, RaftRouter, Register},
+    memory_storage::{GetCurrentState, MemoryStorageData},
+};
+
+/// The ID of the client session used by this test.
+const CLIENT_ID: u64 = 7;
+
+/// Client session tests for a three node cluster.
+///
+/// What does this test cover?
+///
+/// - A client request sent to a follower should be returned with `ClientError::ForwardToLeader`,
+///   still carrying its client session.
+/// - Once forwarded to the leader, the request should be applied.
+/// - Retrying the same request should be answered with the cached response of the original
+///   request, without the request being appended to the log a second time.
+/// - A new request of the same session should be applied as normal, after which retrying the
+///   older request should be rejected with `ClientError::AlreadyAcknowledged`.
+/// - A batch tagged with the session should have each of its entries applied exactly once, & a
+///   retry of the batch should be answered with the indices of the original entries.
+/// - A retry which arrives while the original request is still in flight should be answered
+///   once the original request has been applied, with the original response.
+///
+/// `RUST_LOG=actix_raft,client_sessions=debug cargo test client_sessions`
+#[test]
+fn client_sessions() {
+    setup_logger();
+    let sys = System::builder().stop_on_panic(true).name("test").build();
+
+    // Setup test dependencies.
+    let net = RaftRouter::new();
+    let network = net.start();
+    let members = vec![0, 1, 2];
+    let node0 = Node::builder(0, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 0, addr: node0.addr.clone()});
+    let node1 = Node::builder(1, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 1, addr: node1.addr.clone()});
+    let node2 = Node::builder(2, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 2, addr: node2.addr.clone()});
+    let storages = vec![node0.storage.clone(), node1.storage.clone(), node2.storage.clone()];
+
+    // Setup test controller and actions.
+    let mut ctl = RaftTestController::new(network);
+    ctl.register(0, node0.addr.clone()).register(1, node1.addr.clone()).register(2, node2.addr.clone());
+    ctl.start_with_test(10, Box::new(move |act, ctx| {
+        let storages = storages.clone();
+        let task = fut::wrap_future(act.network.send(GetCurrentLeader))
+            .map_err(|_, _: &mut RaftTestController, _| panic!("Failed to get current leader."))
+            .and_then(|res, _, _| fut::result(res)).and_then(|leader_opt, _, _| {
+                let leader = leader_opt.expect("Expected the cluster to have elected a leader.");
+                fut::ok(leader)
+            })
+
+            // Send the first request of the session to a follower, which should send it back for forwarding.
+            .and_then(|leader, act, _| {
+                let follower = (leader + 1) % 3;
+                let node = act.nodes.get(&follower).expect("Expected follower to be registered.");
+                fut::wrap_future(node.send(session_payload(b"once", 1)))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |res, _, _| match res {
+                        Err(ClientError::ForwardToLeader{payload, ..}) => (leader, payload),
+                        other => panic!("Expected client request to be forwarded to the leader, got {:?}.", other),
+                    })
+            })
+
+            // Forward the request to the leader, then retry it, as a client would after a timeout.
+            .and_then(|(leader, payload), act, _| {
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.").clone();
+                fut::wrap_future(node.send(payload))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .and_then(move |res, _, _| {
+                        let original = res.expect("Expected forwarded client request to be applied.").index();
+                        fut::wrap_future(node.send(session_payload(b"once", 1)))
+                            .map_err(|err, _, _| panic!("{}", err))
+                            .map(move |res, _, _| {
+                                match res {
+                                    Ok(ClientPayloadResponse::Applied{index, ..}) if index == original => (),
+                                    other => panic!("Expected retried client request to get the original response, got {:?}.", other),
+                                }
+                                leader
+                            })
+                    })
+            })
+
+            // A new request of the session should be applied as normal, after which the first
+            // request of the session has been acknowledged.
+            .and_then(|leader, act, _| {
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.").clone();
+                fut::wrap_future(node.send(session_payload(b"twice", 2)))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .and_then(move |res, _, _| {
+                        res.expect("Expected new client request of the session to be applied.");
+                        fut::wrap_future(node.send(session_payload(b"once", 1)))
+                            .map_err(|err, _, _| panic!("{}", err))
+                            .map(move |res, _, _| {
+                                match res {
+                                    Err(ClientError::AlreadyAcknowledged{sequence: 2}) => (),
+                                    other => panic!("Expected stale client request to be rejected, got {:?}.", other),
+                                }
+                                leader
+                            })
+                    })
+            })
+
+            // A batch of the session should be applied as normal, & answered with the original indices when retried.
+            .and_then(|leader, act, _| {
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.").clone();
+                fut::wrap_future(node.send(session_batch(&[b"batch-a", b"batch-b"], 3)))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .and_then(move |res, _, _| {
+                        let original: Vec<_> = res.expect("Expected client batch of the session to be applied.").iter().map(|res| res.index()).collect();
+                        fut::wrap_future(node.send(session_batch(&[b"batch-a", b"batch-b"], 3)))
+                            .map_err(|err, _, _| panic!("{}", err))
+                            .map(move |res, _, _| {
+                                let retried: Vec<_> = res.expect("Expected retried client batch to be answered.").iter().map(|res| res.index()).collect();
+                                assert_eq!(retried, original, "Expected retried client batch to be answered with the original indices.");
+                                leader
+                            })
+                    })
+            })
+
+            // A request & its retry, sent together, should both be answered with the original response.
+            .and_then(|leader, act, _| {
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                let (original, retry) = (node.send(session_payload(b"in-flight", 5)), node.send(session_payload(b"in-flight", 5)));
+                fut::wrap_future(original.join(retry))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(|(original, retry), _, _| {
+                        let original = original.expect("Expected client request to be applied.").index();
+                        match retry {
+                            Ok(ClientPayloadResponse::Applied{index, ..}) if index == original => (),
+                            other => panic!("Expected in-flight retry to get the original response, got {:?}.", other),
+                        }
+                    })
+            })
+            .and_then(|_, _, _| fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(1))).map_err(|_, _, _| ()))
+
+            // Each request should have been appended & applied exactly once on every node.
+            .and_then(move |_, _, _| {
+                fut::wrap_future(future::join_all(storages.iter().map(|storage| storage.send(GetCurrentState)).collect::<Vec<_>>()))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(|states, _, _| {
+                        for state in states {
+                            let state = state.expect("Expected storage state to be returned.");
+                            for data in [b"once".to_vec(), b"twice".to_vec(), b"batch-a".to_vec(), b"batch-b".to_vec(), b"in-flight".to_vec()].iter() {
+                                let is_request = |entry: &&Entry<MemoryStorageData>| match &entry.payload {
+                                    EntryPayload::Normal(inner) => &inner.data.data == data,
+                                    _ => false,
+                                };
+                                assert_eq!(state.log.values().filter(is_request).count(), 1, "Expected client request to be appended exactly once.");
+                                assert_eq!(state.state_machine.values().filter(is_request).count(), 1, "Expected client request to be applied exactly once.");
+                            }
+                        }
+                        System::current().stop();
+                    })
+            })
+            .map_err(|err, _, _| panic!("Failure during test. {:?}", err));
+        ctx.spawn(task);
+    }));
+
+    // Run the test.
+    assert!(sys.run().is_ok(), "Error during test.");
+}
+
+/// Build a client payload of this test's client session.
+fn session_payload(data: &[u8], sequence: u64) -> Payload {
+    let entry = EntryNormal{data: MemoryStorageData{data: data.to_vec()}};
+    Payload::new(entry, ResponseMode::Applied).with_session(CLIENT_ID, sequence)
+}
+
+/// Build a client batch of this test's client session.
+fn session_batch(data: &[&[u8]], first_sequence: u64) -> PayloadBatch {
+    let entries = data.iter().map(|data| EntryNormal{data: MemoryStorageData{data: data.to_vec()}}).collect();
+    PayloadBatch::new(entries, ResponseMode::Applied).with_session(CLIENT_ID, first_sequence)
+}
@@ -21,9 +21,9 @@ use fixtures::{
 /// - When that leader dies, and remains dead until an election timeout, a new leader will be
 /// elected by the remaininng nodes.
 /// - The new cluster should all agree upon the new leader, and should have a new term.
-/// - When the old leader comes back online, it will realize that it is no longer leader, new
-/// cluster nodes will reject its heartbeats, and the old leader will become a follower of the
-/// new leader.
+/// - While isolated, the old leader will step down as it has lost contact with a majority of the
+/// cluster.
+/// - When the old leader comes back online, it will become a follower of the new leader.
 /// - The new leader generates and commits a new blank log entry to guard against stale writes for
 /// when a previous leader had uncommitted entries replicated on some nodes. See end of §8.
 ///
@@ -97,7 +97,8 @@ fn clustering() {
 
                         // Assertions on old cluster.
                         assert_eq!(old_leader.current_term, old_leader_and_term.1, "Expected old terms match.");
-                        assert_eq!(old_leader.current_leader, Some(old_leader_and_term.0), "Expected old leader to still thinks it is leader.");
+                        assert_eq!(old_leader.current_leader, None, "Expected old leader to have stepped down.");
+                        assert!(old_leader.state != State::Leader, "Expected old leader to have stepped down.");
 
                         let _ = tx1.send((leader_id, term)).unwrap();
                     })));
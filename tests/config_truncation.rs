@@ -0,0 +1,157 @@
+//! Test the reverting of membership when uncommitted config entries are truncated.
+
+mod fixtures;
+
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use actix::prelude::*;
+use actix_raft::{
+    admin::ProposeConfigChange,
+    messages::{EntryNormal, ResponseMode},
+    metrics::State,
+};
+use tokio_timer::{Delay, Timeout};
+
+use fixtures::{
+    Payload, RaftTestController, Node, setup_logger,
+    dev::{ExecuteInRaftRouter, GetCurrentLeader, RaftRouter, Register},
+    memory_storage::{GetCurrentState, MemoryStorageData},
+};
+
+/// Config entry truncation tests for a three node cluster.
+///
+/// What does this test cover?
+///
+/// - An isolated leader which appends a config entry should switch over to the new config, & it
+///   should record the entry in its hard state.
+/// - Once the rest of the cluster has elected a new leader & the old leader is reachable again,
+///   the uncommitted config entry should be truncated from its log, & its membership should
+///   revert to the config of the rest of the cluster.
+///
+/// `RUST_LOG=actix_raft,config_truncation=debug cargo test config_truncation`
+#[test]
+fn config_truncation() {
+    setup_logger();
+    let sys = System::builder().stop_on_panic(true).name("test").build();
+
+    // Setup test dependencies.
+    let net = RaftRouter::new();
+    let network = net.start();
+    let members = vec![0, 1, 2];
+    let node0 = Node::builder(0, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 0, addr: node0.addr.clone()});
+    let node1 = Node::builder(1, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 1, addr: node1.addr.clone()});
+    let node2 = Node::builder(2, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 2, addr: node2.addr.clone()});
+    let storages: BTreeMap<_, _> = vec![(0, node0.storage.clone()), (1, node1.storage.clone()), (2, node2.storage.clone())].into_iter().collect();
+
+    // Setup test controller and actions.
+    let mut ctl = RaftTestController::new(network);
+    ctl.register(0, node0.addr.clone()).register(1, node1.addr.clone()).register(2, node2.addr.clone());
+    ctl.start_with_test(10, Box::new(move |act, ctx| {
+        let task = fut::wrap_future(act.network.send(GetCurrentLeader))
+            .map_err(|_, _: &mut RaftTestController, _| panic!("Failed to get current leader."))
+            .and_then(|res, _, _| fut::result(res)).and_then(|leader_opt, _, _| {
+                let leader = leader_opt.expect("Expected the cluster to have elected a leader.");
+                fut::ok(leader)
+            })
+            .and_then(|leader, act, _| act.write_data(leader).map(move |_, _, _| leader))
+
+            // Isolate the leader & have it propose the removal of a follower. The config entry can
+            // never be committed.
+            .and_then(|leader, act, _| {
+                let follower = act.nodes.keys().cloned().find(|id| id != &leader).expect("Expected a follower.");
+                act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| act.isolate_node(leader))));
+                act.nodes.get(&leader).expect("Expected leader to be registered.").do_send(ProposeConfigChange::new(vec![], vec![follower]));
+                fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(1)))
+                    .map_err(|_, _, _| ())
+                    .map(move |_, _, _| (leader, follower))
+            })
+            .and_then(|(leader, follower), act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| {
+                    let leader = act.metrics.get(&leader).expect("Expected old leader's metrics to be present.");
+                    assert!(leader.membership_config.is_in_joint_consensus, "Expected old leader to be in joint consensus.");
+                    assert_eq!(leader.membership_config.removing, vec![follower], "Expected the follower to be removing.");
+                })));
+                fut::ok((leader, follower))
+            })
+            .and_then(move |(leader, follower), _, _| {
+                let storage = storages.get(&leader).expect("Expected old leader's storage to be present.").clone();
+                fut::wrap_future(storage.send(GetCurrentState))
+                    .map_err(|err, _: &mut RaftTestController, _| panic!("{}", err))
+                    .and_then(|res, _, _| fut::result(res))
+                    .map(move |state, _, _| {
+                        let (_, last_conf) = state.hs.membership_history.iter().next_back().expect("Expected config entries to be recorded.");
+                        assert!(last_conf.is_in_joint_consensus, "Expected the joint config entry to be recorded in the hard state.");
+                        assert_eq!(last_conf, &state.hs.membership, "Expected the hard state to hold the joint config.");
+                        (leader, follower, storage)
+                    })
+            })
+
+            // Wait for the rest of the cluster to elect a new leader, & write data to it.
+            .and_then(|ids, _, _| fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(6)))
+                .map_err(|_, _, _| ())
+                .map(move |_, _, _| ids))
+            .and_then(|(old_leader, _, storage), act, _| {
+                fut::wrap_future(act.network.send(GetCurrentLeader))
+                    .map_err(|_, _: &mut RaftTestController, _| panic!("Failed to get current leader."))
+                    .and_then(|res, _, _| fut::result(res))
+                    .and_then(move |leader_opt, act, _| {
+                        let leader = leader_opt.expect("Expected the cluster to have elected a new leader.");
+                        assert_ne!(leader, old_leader, "Expected a new leader to have been elected.");
+                        act.write_data(leader).map(move |_, _, _| (leader, old_leader, storage))
+                    })
+            })
+
+            // Restore the old leader. Its config entry should be truncated & its config reverted.
+            .and_then(|(leader, old_leader, storage), act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| act.restore_node(old_leader))));
+                fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(3)))
+                    .map_err(|_, _, _| ())
+                    .map(move |_, _, _| (leader, old_leader, storage))
+            })
+            .and_then(|(leader, old_leader, storage), act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| {
+                    let leader = act.metrics.get(&leader).expect("Expected leader's metrics to be present.");
+                    assert!(!leader.membership_config.is_in_joint_consensus, "Expected cluster not to be in joint consensus.");
+                    let node = act.metrics.get(&old_leader).expect("Expected old leader's metrics to be present.");
+                    assert_eq!(node.state, State::Follower, "Expected old leader to be a follower.");
+                    assert_eq!(node.membership_config, leader.membership_config, "Expected old leader to have reverted its config.");
+                    assert_eq!(node.last_log_index, leader.last_log_index, "Expected old leader to have matching last log index.");
+                })));
+                fut::wrap_future(storage.send(GetCurrentState))
+                    .map_err(|err, _: &mut RaftTestController, _| panic!("{}", err))
+                    .and_then(|res, _, _| fut::result(res))
+                    .map(|state, _, _| {
+                        assert!(!state.hs.membership.is_in_joint_consensus, "Expected the hard state to hold the reverted config.");
+                        assert!(state.hs.membership_history.values().all(|conf| !conf.is_in_joint_consensus), "Expected the joint config entry to have been dropped.");
+                        System::current().stop();
+                    })
+            })
+            .map_err(|err, _, _| panic!("Failure during test. {:?}", err));
+        ctx.spawn(task);
+    }));
+
+    // Run the test.
+    assert!(sys.run().is_ok(), "Error during test.");
+}
+
+impl RaftTestController {
+    /// Write 10 entries to the given leader, expecting each to be applied within a few seconds.
+    fn write_data(&mut self, leader: u64) -> impl ActorFuture<Actor=Self, Item=(), Error=()> {
+        let addr = self.nodes.get(&leader).expect("Expected leader to be registered.").clone();
+        fut::wrap_stream(futures::stream::iter_ok(0..10u64))
+            .and_then(move |data, _, _| {
+                let entry = EntryNormal{data: MemoryStorageData{data: data.to_string().into_bytes()}};
+                let payload = Payload::new(entry, ResponseMode::Applied);
+                fut::wrap_future(Timeout::new(addr.send(payload), Duration::from_secs(3)))
+                    .map_err(|err, _, _| panic!("Client request was not applied in time. {:?}", err))
+                    .map(|res, _, _| { res.expect("Expected client request to succeed."); })
+            })
+            .finish()
+    }
+}
@@ -0,0 +1,130 @@
+//! Test the convergence of a follower whose log has a long divergent suffix.
+
+mod fixtures;
+
+use std::time::{Duration, Instant};
+
+use actix::prelude::*;
+use actix_raft::{
+    messages::{EntryNormal, ResponseMode},
+    metrics::State,
+};
+use tokio_timer::{Delay, Timeout};
+
+use fixtures::{
+    Payload, RaftTestController, Node, setup_logger,
+    dev::{ExecuteInRaftRouter, GetCurrentLeader, RaftRouter, Register},
+    memory_storage::MemoryStorageData,
+};
+
+/// Log divergence tests for a three node cluster.
+///
+/// What does this test cover?
+///
+/// - An isolated leader which keeps appending entries ends up with a long suffix of entries
+///   which can never be committed.
+/// - Once the rest of the cluster has elected a new leader, committed entries of its own, & the
+///   old leader is reachable again, the old leader's divergent suffix should be replaced with the
+///   new leader's log.
+///
+/// `RUST_LOG=actix_raft,divergent_logs=debug cargo test divergent_logs`
+#[test]
+fn divergent_logs() {
+    setup_logger();
+    let sys = System::builder().stop_on_panic(true).name("test").build();
+
+    // Setup test dependencies.
+    let net = RaftRouter::new();
+    let network = net.start();
+    let members = vec![0, 1, 2];
+    let node0 = Node::builder(0, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 0, addr: node0.addr.clone()});
+    let node1 = Node::builder(1, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 1, addr: node1.addr.clone()});
+    let node2 = Node::builder(2, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 2, addr: node2.addr.clone()});
+
+    // Setup test controller and actions.
+    let mut ctl = RaftTestController::new(network);
+    ctl.register(0, node0.addr.clone()).register(1, node1.addr.clone()).register(2, node2.addr.clone());
+    ctl.start_with_test(10, Box::new(|act, ctx| {
+        let task = fut::wrap_future(act.network.send(GetCurrentLeader))
+            .map_err(|_, _: &mut RaftTestController, _| panic!("Failed to get current leader."))
+            .and_then(|res, _, _| fut::result(res)).and_then(|leader_opt, _, _| {
+                let leader = leader_opt.expect("Expected the cluster to have elected a leader.");
+                fut::ok(leader)
+            })
+            .and_then(|leader, act, _| act.write_data(leader).map(move |_, _, _| leader))
+
+            // Isolate the leader & have it append entries which can never be committed.
+            .and_then(|leader, act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| act.isolate_node(leader))));
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                for data in 0..40u64 {
+                    let entry = EntryNormal{data: MemoryStorageData{data: data.to_string().into_bytes()}};
+                    node.do_send(Payload::new(entry, ResponseMode::Committed));
+                }
+                fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(6)))
+                    .map_err(|_, _, _| ())
+                    .map(move |_, _, _| leader)
+            })
+
+            // Write data to the new leader, then restore the old leader.
+            .and_then(|old_leader, act, _| {
+                fut::wrap_future(act.network.send(GetCurrentLeader))
+                    .map_err(|_, _: &mut RaftTestController, _| panic!("Failed to get current leader."))
+                    .and_then(|res, _, _| fut::result(res))
+                    .and_then(move |leader_opt, act: &mut RaftTestController, _| {
+                        let leader = leader_opt.expect("Expected the cluster to have elected a new leader.");
+                        assert_ne!(leader, old_leader, "Expected a new leader to have been elected.");
+                        act.write_data(leader).map(move |_, _, _| (leader, old_leader))
+                    })
+            })
+            .and_then(|(leader, old_leader), act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| {
+                    let old = act.metrics.get(&old_leader).expect("Expected old leader's metrics to be present.");
+                    let new = act.metrics.get(&leader).expect("Expected new leader's metrics to be present.");
+                    assert!(old.last_log_index > new.last_log_index, "Expected the old leader to have the longer log.");
+                    act.restore_node(old_leader);
+                })));
+                fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(3)))
+                    .map_err(|_, _, _| ())
+                    .map(move |_, _, _| (leader, old_leader))
+            })
+
+            // The old leader's log should have converged with the new leader's log.
+            .and_then(|(leader, old_leader), act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| {
+                    let leader = act.metrics.get(&leader).expect("Expected leader's metrics to be present.");
+                    let old = act.metrics.get(&old_leader).expect("Expected old leader's metrics to be present.");
+                    assert_eq!(leader.state, State::Leader, "Expected the new leader to have retained leadership.");
+                    assert_eq!(old.state, State::Follower, "Expected the old leader to be a follower.");
+                    assert_eq!(old.current_term, leader.current_term, "Expected the old leader to have adopted the new term.");
+                    assert_eq!(old.last_log_index, leader.last_log_index, "Expected the old leader's log to match the leader's log.");
+                    System::current().stop();
+                })));
+                fut::ok(())
+            })
+            .map_err(|err, _, _| panic!("Failure during test. {:?}", err));
+        ctx.spawn(task);
+    }));
+
+    // Run the test.
+    assert!(sys.run().is_ok(), "Error during test.");
+}
+
+impl RaftTestController {
+    /// Write 10 entries to the given leader, expecting each to be applied within a few seconds.
+    fn write_data(&mut self, leader: u64) -> impl ActorFuture<Actor=Self, Item=(), Error=()> {
+        let addr = self.nodes.get(&leader).expect("Expected leader to be registered.").clone();
+        fut::wrap_stream(futures::stream::iter_ok(0..10u64))
+            .and_then(move |data, _, _| {
+                let entry = EntryNormal{data: MemoryStorageData{data: data.to_string().into_bytes()}};
+                let payload = Payload::new(entry, ResponseMode::Applied);
+                fut::wrap_future(Timeout::new(addr.send(payload), Duration::from_secs(3)))
+                    .map_err(|err, _, _| panic!("Client request was not applied in time. {:?}", err))
+                    .map(|res, _, _| { res.expect("Expected client request to succeed."); })
+            })
+            .finish()
+    }
+}
@@ -0,0 +1,135 @@
+//! Test that RPC responses are only sent once the hard state they depend upon is durable.
+
+mod fixtures;
+
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use actix::prelude::*;
+use actix_raft::messages::{AppendEntriesRequest, EntryNormal, ResponseMode, VoteRequest};
+use tokio_timer::Timeout;
+
+use fixtures::{
+    Payload, RaftTestController, Node, setup_logger,
+    dev::{GetCurrentLeader, RaftRouter, Register},
+    memory_storage::{GetCurrentState, MemoryStorageData},
+};
+
+/// The latency of each hard state save.
+const LATENCY: Duration = Duration::from_millis(500);
+
+/// Durable hard state tests for a three node cluster with slow hard state saves.
+///
+/// What does this test cover?
+///
+/// - A vote should only be granted once the vote & the candidate's term are durable.
+/// - An AppendEntries RPC carrying a newer term should only be responded to once the new term is
+///   durable.
+///
+/// `RUST_LOG=actix_raft,durable_hard_state=debug cargo test durable_hard_state`
+#[test]
+fn durable_hard_state() {
+    setup_logger();
+    let sys = System::builder().stop_on_panic(true).name("test").build();
+
+    // Setup test dependencies.
+    let net = RaftRouter::new();
+    let network = net.start();
+    let members = vec![0, 1, 2];
+    let node0 = Node::builder(0, network.clone(), members.clone()).hard_state_latency(LATENCY).build();
+    network.do_send(Register{id: 0, addr: node0.addr.clone()});
+    let node1 = Node::builder(1, network.clone(), members.clone()).hard_state_latency(LATENCY).build();
+    network.do_send(Register{id: 1, addr: node1.addr.clone()});
+    let node2 = Node::builder(2, network.clone(), members.clone()).hard_state_latency(LATENCY).build();
+    network.do_send(Register{id: 2, addr: node2.addr.clone()});
+    let storages: BTreeMap<_, _> = vec![(0, node0.storage.clone()), (1, node1.storage.clone()), (2, node2.storage.clone())].into_iter().collect();
+
+    // Setup test controller and actions.
+    let mut ctl = RaftTestController::new(network);
+    ctl.register(0, node0.addr.clone()).register(1, node1.addr.clone()).register(2, node2.addr.clone());
+    ctl.start_with_test(10, Box::new(move |act, ctx| {
+        let task = fut::wrap_future(act.network.send(GetCurrentLeader))
+            .map_err(|_, _: &mut RaftTestController, _| panic!("Failed to get current leader."))
+            .and_then(|res, _, _| fut::result(res)).and_then(|leader_opt, _, _| {
+                let leader = leader_opt.expect("Expected the cluster to have elected a leader.");
+                fut::ok(leader)
+            })
+            .and_then(|leader, act, _| act.write_data(leader).map(move |_, _, _| leader))
+
+            // Fetch the term of one of the followers.
+            .and_then(move |leader, act, _| {
+                let mut followers = act.nodes.keys().cloned().filter(|id| id != &leader);
+                let (follower, candidate) = (followers.next().expect("Expected a follower."), followers.next().expect("Expected a follower."));
+                let storage = storages.get(&follower).expect("Expected follower's storage to be present.").clone();
+                fut::wrap_future(storage.send(GetCurrentState))
+                    .map_err(|err, _: &mut RaftTestController, _| panic!("{}", err))
+                    .and_then(|res, _, _| fut::result(res))
+                    .map(move |state, _, _| (follower, candidate, storage, state.hs.current_term))
+            })
+
+            // Request the follower's vote in a newer term. The vote should only be granted once it is durable.
+            .and_then(|(follower, candidate, storage, term), act, _| {
+                let node = act.nodes.get(&follower).expect("Expected follower to be registered.").clone();
+                let (term, start) = (term + 10, Instant::now());
+                fut::wrap_future(node.send(VoteRequest::new(follower, term, candidate, u64::MAX, term, true)))
+                    .map_err(|err, _: &mut RaftTestController, _| panic!("{}", err))
+                    .and_then(|res, _, _| fut::result(res))
+                    .and_then(move |res, _, _| {
+                        assert!(res.vote_granted, "Expected the follower to grant its vote.");
+                        assert!(start.elapsed() >= LATENCY, "Expected the vote to be granted only after the hard state was saved.");
+                        fut::wrap_future(storage.send(GetCurrentState))
+                            .map_err(|err, _: &mut RaftTestController, _| panic!("{}", err))
+                            .and_then(|res, _, _| fut::result(res))
+                            .map(move |state, _, _| {
+                                assert_eq!(state.hs.current_term, term, "Expected the candidate's term to be durable.");
+                                assert_eq!(state.hs.voted_for, Some(candidate), "Expected the vote to be durable.");
+                                (node, follower, candidate, storage, term)
+                            })
+                    })
+            })
+
+            // Send the follower a heartbeat in an even newer term, which should only be responded
+            // to once the new term is durable.
+            .and_then(|(node, follower, candidate, storage, term), _, _| {
+                let term = term + 10;
+                let rpc = AppendEntriesRequest{target: follower, term, leader_id: candidate, prev_log_index: 0, prev_log_term: 0, entries: vec![], leader_commit: 0};
+                fut::wrap_future(node.send(rpc))
+                    .map_err(|err, _: &mut RaftTestController, _| panic!("{}", err))
+                    .and_then(|res, _, _| fut::result(res))
+                    .and_then(move |res, _, _| {
+                        assert_eq!(res.term, term, "Expected the follower to have adopted the new term.");
+                        fut::wrap_future(storage.send(GetCurrentState))
+                            .map_err(|err, _: &mut RaftTestController, _| panic!("{}", err))
+                            .and_then(|res, _, _| fut::result(res))
+                            .map(move |state, _, _| {
+                                assert_eq!(state.hs.current_term, term, "Expected the new term to be durable.");
+                                assert_eq!(state.hs.voted_for, None, "Expected no vote to have been cast in the new term.");
+                                System::current().stop();
+                            })
+                    })
+            })
+            .map_err(|err, _, _| panic!("Failure during test. {:?}", err));
+        ctx.spawn(task);
+    }));
+
+    // Run the test.
+    assert!(sys.run().is_ok(), "Error during test.");
+}
+
+impl RaftTestController {
+    /// Write 10 entries to the given leader, expecting each to be applied within a few seconds.
+    fn write_data(&mut self, leader: u64) -> impl ActorFuture<Actor=Self, Item=(), Error=()> {
+        let addr = self.nodes.get(&leader).expect("Expected leader to be registered.").clone();
+        fut::wrap_stream(futures::stream::iter_ok(0..10u64))
+            .and_then(move |data, _, _| {
+                let entry = EntryNormal{data: MemoryStorageData{data: data.to_string().into_bytes()}};
+                let payload = Payload::new(entry, ResponseMode::Applied);
+                fut::wrap_future(Timeout::new(addr.send(payload), Duration::from_secs(3)))
+                    .map_err(|err, _, _| panic!("Client request was not applied in time. {:?}", err))
+                    .map(|res, _, _| { res.expect("Expected client request to succeed."); })
+            })
+            .finish()
+    }
+}
@@ -2,21 +2,25 @@
 
 use std::{
     collections::BTreeMap,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use actix::prelude::*;
 use actix_raft::{
-    Raft, NodeId,
+    AppData, Raft, NodeId,
     messages::{
-        AppendEntriesRequest, AppendEntriesResponse,
+        AppendEntriesRequest, AppendEntriesResponse, ClientError, ClientPayloadResponse, EntryPayload,
+        ForwardClientPayload,
         InstallSnapshotRequest, InstallSnapshotResponse,
+        PreVoteRequest, PreVoteResponse,
+        TimeoutNowRequest, TimeoutNowResponse,
         VoteRequest, VoteResponse,
     },
     network::RaftNetwork,
     metrics::{RaftMetrics, State},
 };
 use log::{debug};
+use tokio_timer::Delay;
 
 use crate::fixtures::memory_storage::{MemoryStorage, MemoryStorageData, MemoryStorageError, MemoryStorageResponse};
 
@@ -37,6 +41,18 @@ pub struct RaftRouter {
     isolated_nodes: Vec<NodeId>,
     /// The count of all messages which have passed through this system.
     routed: (u64, u64, u64, u64), // AppendEntries, Vote, InstallSnapshot, Other.
+    /// The latency added to the responses of AppendEntries RPCs which carry entries.
+    ///
+    /// Requests are delivered right away, so that delivery order is preserved.
+    append_entries_latency: Option<Duration>,
+    /// The number of AppendEntries RPCs carrying entries which are in-flight to each node.
+    append_entries_in_flight: BTreeMap<NodeId, u64>,
+    /// The highest number of AppendEntries RPCs carrying entries observed in-flight to any one node.
+    pub max_append_entries_in_flight: u64,
+    /// The largest encoded size of the entries of any one AppendEntries RPC observed.
+    pub max_append_entries_bytes: u64,
+    /// The number of AppendEntries RPCs rejected by each node, as its log did not match.
+    pub rejected_append_entries: BTreeMap<NodeId, u64>,
 }
 
 impl RaftRouter {
@@ -51,6 +67,19 @@ impl RaftRouter {
         self.isolated_nodes.push(id);
     }
 
+    /// Add the given latency to the responses of AppendEntries RPCs which carry entries.
+    pub fn set_append_entries_latency(&mut self, val: Duration) {
+        self.append_entries_latency = Some(val);
+    }
+
+    /// Record the given response to an AppendEntries RPC sent to the target node.
+    fn record_append_entries_response(&mut self, target: NodeId, res: AppendEntriesResponse) -> AppendEntriesResponse {
+        if !res.success {
+            *self.rejected_append_entries.entry(target).or_insert(0) += 1;
+        }
+        res
+    }
+
     /// Restore the network of the specified node.
     pub fn restore_node(&mut self, id: NodeId) {
         if let Some((idx, _)) = self.isolated_nodes.iter().enumerate().find(|(_, e)| *e == &id) {
@@ -73,7 +102,7 @@ impl Actor for RaftRouter {
 //////////////////////////////////////////////////////////////////////////////
 // Impl RaftNetwork //////////////////////////////////////////////////////////
 
-impl RaftNetwork<MemoryStorageData> for RaftRouter {}
+impl RaftNetwork<MemoryStorageData, MemoryStorageResponse, MemoryStorageError> for RaftRouter {}
 
 impl Handler<AppendEntriesRequest<MemoryStorageData>> for RaftRouter {
     type Result = ResponseActFuture<Self, AppendEntriesResponse, ()>;
@@ -84,9 +113,43 @@ impl Handler<AppendEntriesRequest<MemoryStorageData>> for RaftRouter {
         if self.isolated_nodes.contains(&msg.target) || self.isolated_nodes.contains(&msg.leader_id) {
             return Box::new(fut::err(()));
         }
+
+        // Heartbeats are routed right away.
+        let target = msg.target;
+        if msg.entries.is_empty() {
+            return Box::new(fut::wrap_future(addr.send(msg))
+                .map_err(|_, _, _| panic!("{}", ERR_ROUTING_FAILURE))
+                .and_then(|res, _, _| fut::result(res))
+                .map(move |res, act: &mut Self, _| act.record_append_entries_response(target, res)));
+        }
+
+        // Track the size of payloads, & the in-flight requests carrying entries. Delay their
+        // responses as configured.
+        let bytes = msg.entries.iter().map(|entry| match &entry.payload {
+            EntryPayload::Normal(inner) => inner.data.encoded_len(),
+            _ => 0,
+        }).sum();
+        if bytes > self.max_append_entries_bytes {
+            self.max_append_entries_bytes = bytes;
+        }
+        let in_flight = self.append_entries_in_flight.entry(target).or_insert(0);
+        *in_flight += 1;
+        if *in_flight > self.max_append_entries_in_flight {
+            self.max_append_entries_in_flight = *in_flight;
+        }
+        let latency = self.append_entries_latency.unwrap_or(Duration::from_millis(0));
         Box::new(fut::wrap_future(addr.send(msg))
             .map_err(|_, _, _| panic!(ERR_ROUTING_FAILURE))
-            .and_then(|res, _, _| fut::result(res)))
+            .and_then(move |res, _, _| fut::wrap_future(Delay::new(Instant::now() + latency))
+                .map_err(|_, _, _| ())
+                .map(move |_, _, _| res))
+            .then(move |res, act: &mut Self, _| {
+                if let Some(in_flight) = act.append_entries_in_flight.get_mut(&target) {
+                    *in_flight -= 1;
+                }
+                fut::result(res.and_then(|res| res))
+            })
+            .map(move |res, act: &mut Self, _| act.record_append_entries_response(target, res)))
     }
 }
 
@@ -105,6 +168,51 @@ impl Handler<VoteRequest> for RaftRouter {
     }
 }
 
+impl Handler<PreVoteRequest> for RaftRouter {
+    type Result = ResponseActFuture<Self, PreVoteResponse, ()>;
+
+    fn handle(&mut self, msg: PreVoteRequest, _: &mut Self::Context) -> Self::Result {
+        self.routed.1 += 1;
+        let addr = self.routing_table.get(&msg.target).unwrap();
+        if self.isolated_nodes.contains(&msg.target) || self.isolated_nodes.contains(&msg.candidate_id) {
+            return Box::new(fut::err(()));
+        }
+        Box::new(fut::wrap_future(addr.send(msg))
+            .map_err(|_, _, _| panic!("{}", ERR_ROUTING_FAILURE))
+            .and_then(|res, _, _| fut::result(res)))
+    }
+}
+
+impl Handler<TimeoutNowRequest> for RaftRouter {
+    type Result = ResponseActFuture<Self, TimeoutNowResponse, ()>;
+
+    fn handle(&mut self, msg: TimeoutNowRequest, _: &mut Self::Context) -> Self::Result {
+        self.routed.3 += 1;
+        let addr = self.routing_table.get(&msg.target).unwrap();
+        if self.isolated_nodes.contains(&msg.target) || self.isolated_nodes.contains(&msg.leader_id) {
+            return Box::new(fut::err(()));
+        }
+        Box::new(fut::wrap_future(addr.send(msg))
+            .map_err(|_, _, _| panic!("{}", ERR_ROUTING_FAILURE))
+            .and_then(|res, _, _| fut::result(res)))
+    }
+}
+
+impl Handler<ForwardClientPayload<MemoryStorageData, MemoryStorageResponse, MemoryStorageError>> for RaftRouter {
+    type Result = ResponseActFuture<Self, ClientPayloadResponse<MemoryStorageResponse>, ClientError<MemoryStorageData, MemoryStorageResponse, MemoryStorageError>>;
+
+    fn handle(&mut self, msg: ForwardClientPayload<MemoryStorageData, MemoryStorageResponse, MemoryStorageError>, _: &mut Self::Context) -> Self::Result {
+        self.routed.3 += 1;
+        let addr = self.routing_table.get(&msg.target).unwrap();
+        if self.isolated_nodes.contains(&msg.target) || self.isolated_nodes.contains(&msg.follower_id) {
+            return Box::new(fut::err(ClientError::ForwardToLeader{payload: msg.payload, leader: Some(msg.target)}));
+        }
+        Box::new(fut::wrap_future(addr.send(msg))
+            .map_err(|_, _, _| panic!("{}", ERR_ROUTING_FAILURE))
+            .and_then(|res, _, _| fut::result(res)))
+    }
+}
+
 impl Handler<InstallSnapshotRequest> for RaftRouter {
     type Result = ResponseActFuture<Self, InstallSnapshotResponse, ()>;
 
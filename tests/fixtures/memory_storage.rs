@@ -3,18 +3,22 @@ use std::{
     io::{Seek, SeekFrom, Write},
     fs::{self, File},
     path::PathBuf,
+    time::{Duration, Instant},
 };
 
 use actix::prelude::*;
 use log::{debug, error};
 use serde::{Serialize, Deserialize};
 use rmp_serde as rmps;
+use tokio_timer::Delay;
 
 use actix_raft::{
     AppData, AppDataResponse, AppError, NodeId,
-    messages::{Entry as RaftEntry, EntrySnapshotPointer, MembershipConfig},
+    messages::{Entry as RaftEntry, EntryPayload, EntrySnapshotPointer, MembershipConfig},
     storage::{
         AppendEntryToLog,
+        AppendEntriesToLog,
+        ClientSessions,
         ReplicateToLog,
         ApplyEntryToStateMachine,
         ReplicateToStateMachine,
@@ -33,13 +37,28 @@ use actix_raft::{
 
 type Entry = RaftEntry<MemoryStorageData>;
 
+/// Entries carrying this data will be rejected by the `MemoryStorage` system with an application error.
+pub const REJECTED_DATA: &[u8] = b"rejected";
+
+/// Check if the given entry is to be rejected with an application error.
+fn is_rejected(entry: &Entry) -> bool {
+    match &entry.payload {
+        EntryPayload::Normal(inner) => inner.data.data.as_slice() == REJECTED_DATA,
+        _ => false,
+    }
+}
+
 /// The concrete data type used by the `MemoryStorage` system.
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct MemoryStorageData {
     pub data: Vec<u8>,
 }
 
-impl AppData for MemoryStorageData {}
+impl AppData for MemoryStorageData {
+    fn encoded_len(&self) -> u64 {
+        self.data.len() as u64
+    }
+}
 
 /// The concrete data type used for responding from the storage engine when applying logs to the state machine.
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
@@ -76,19 +95,45 @@ pub struct MemoryStorage {
     snapshot_dir: String,
     state_machine: BTreeMap<u64, Entry>,
     snapshot_actor: Addr<SnapshotActor>,
+    /// The latency added to client appends, emulating the time taken to flush to disk.
+    append_latency: Option<Duration>,
+    /// The latency added to hard state saves, emulating the time taken to flush to disk.
+    hard_state_latency: Option<Duration>,
 }
 
 impl MemoryStorage {
     /// Create a new instance.
     pub fn new(members: Vec<NodeId>, snapshot_dir: String) -> Self {
         let snapshot_dir_pathbuf = std::path::PathBuf::from(snapshot_dir.clone());
-        let membership = MembershipConfig{members, non_voters: vec![], removing: vec![], is_in_joint_consensus: false};
+        let membership = MembershipConfig{members, non_voters: vec![], removing: vec![], learners: vec![], witnesses: vec![], is_in_joint_consensus: false};
         Self{
-            hs: HardState{current_term: 0, voted_for: None, membership},
+            hs: HardState{current_term: 0, voted_for: None, membership, membership_history: Default::default()},
             log: Default::default(),
             snapshot_data: None, snapshot_dir,
             state_machine: Default::default(),
             snapshot_actor: SyncArbiter::start(1, move || SnapshotActor(snapshot_dir_pathbuf.clone())),
+            append_latency: None,
+            hard_state_latency: None,
+        }
+    }
+
+    /// Add the given latency to each `AppendEntryToLog` & `AppendEntriesToLog` call.
+    pub fn with_append_latency(mut self, val: Duration) -> Self {
+        self.append_latency = Some(val);
+        self
+    }
+
+    /// Add the given latency to each `SaveHardState` call. The hard state is only updated once it has elapsed.
+    pub fn with_hard_state_latency(mut self, val: Duration) -> Self {
+        self.hard_state_latency = Some(val);
+        self
+    }
+
+    /// Respond to a client append, after the configured append latency if any.
+    fn respond_to_append(&self) -> ResponseActFuture<Self, (), MemoryStorageError> {
+        match self.append_latency {
+            Some(latency) => Box::new(fut::wrap_future(Delay::new(Instant::now() + latency)).map_err(|_, _, _| MemoryStorageError)),
+            None => Box::new(fut::ok(())),
         }
     }
 }
@@ -122,8 +167,18 @@ impl Handler<SaveHardState<MemoryStorageError>> for MemoryStorage {
     type Result = ResponseActFuture<Self, (), MemoryStorageError>;
 
     fn handle(&mut self, msg: SaveHardState<MemoryStorageError>, _: &mut Self::Context) -> Self::Result {
-        self.hs = msg.hs;
-        Box::new(fut::ok(()))
+        match self.hard_state_latency {
+            Some(latency) => {
+                let hs = msg.hs;
+                Box::new(fut::wrap_future(Delay::new(Instant::now() + latency))
+                    .map_err(|_, _, _| MemoryStorageError)
+                    .map(move |_, act: &mut Self, _| act.hs = hs))
+            }
+            None => {
+                self.hs = msg.hs;
+                Box::new(fut::ok(()))
+            }
+        }
     }
 }
 
@@ -139,8 +194,25 @@ impl Handler<AppendEntryToLog<MemoryStorageData, MemoryStorageError>> for Memory
     type Result = ResponseActFuture<Self, (), MemoryStorageError>;
 
     fn handle(&mut self, msg: AppendEntryToLog<MemoryStorageData, MemoryStorageError>, _: &mut Self::Context) -> Self::Result {
+        if is_rejected(&msg.entry) {
+            return Box::new(fut::err(MemoryStorageError));
+        }
         self.log.insert(msg.entry.index, (*msg.entry).clone());
-        Box::new(fut::ok(()))
+        self.respond_to_append()
+    }
+}
+
+impl Handler<AppendEntriesToLog<MemoryStorageData, MemoryStorageError>> for MemoryStorage {
+    type Result = ResponseActFuture<Self, (), MemoryStorageError>;
+
+    fn handle(&mut self, msg: AppendEntriesToLog<MemoryStorageData, MemoryStorageError>, _: &mut Self::Context) -> Self::Result {
+        if msg.entries.iter().any(|e| is_rejected(e)) {
+            return Box::new(fut::err(MemoryStorageError));
+        }
+        msg.entries.iter().for_each(|e| {
+            self.log.insert(e.index, (**e).clone());
+        });
+        self.respond_to_append()
     }
 }
 
@@ -194,7 +266,8 @@ impl Handler<CreateSnapshot<MemoryStorageError>> for MemoryStorage {
         let entries = self.log.range(0u64..=through).map(|(_, v)| v.clone()).collect::<Vec<_>>();
         debug!("Creating snapshot with {} entries.", entries.len());
         let (index, term) = entries.last().map(|e| (e.index, e.term)).unwrap_or((0, 0));
-        let snapdata = match rmps::to_vec(&entries) {
+        let snapshot = MemorySnapshot{entries, client_sessions: msg.client_sessions};
+        let snapdata = match rmps::to_vec(&snapshot) {
             Ok(snapdata) => snapdata,
             Err(err) => {
                 error!("Error serializing log for creating a snapshot. {}", err);
@@ -218,7 +291,7 @@ impl Handler<CreateSnapshot<MemoryStorageError>> for MemoryStorage {
                 act.log.insert(through, entry);
 
                 // Cache the most recent snapshot data.
-                let current_snap_data = CurrentSnapshotData{term, index, membership: act.hs.membership.clone(), pointer};
+                let current_snap_data = CurrentSnapshotData{term, index, membership: act.hs.membership.clone(), client_sessions: snapshot.client_sessions, pointer};
                 act.snapshot_data = Some(current_snap_data.clone());
 
                 fut::ok(current_snap_data)
@@ -235,10 +308,20 @@ impl Handler<InstallSnapshot<MemoryStorageError>> for MemoryStorage {
             .map_err(|err, _, _| panic!("Error communicating with snapshot actor. {}", err))
             .and_then(|res, _, _| fut::result(res))
 
-            // Snapshot file has been created. Perform final steps of this algorithm.
-            .and_then(move |pointer, act: &mut Self, ctx| {
+            // Snapshot file has been created. Read it back for the client sessions it holds.
+            .and_then(|pointer, act: &mut Self, _| {
+                fut::wrap_future(act.snapshot_actor.send(DeserializeSnapshot(PathBuf::from(pointer.path.clone()))))
+                    .map_err(|err, _, _| panic!("Error communicating with snapshot actor. {}", err))
+                    .and_then(|res, _, _| fut::result(res))
+                    .map(move |snapshot, _, _| (pointer, snapshot))
+            })
+
+            // Perform final steps of this algorithm.
+            .and_then(move |(pointer, snapshot), act: &mut Self, ctx| {
                 // Cache the most recent snapshot data.
-                act.snapshot_data = Some(CurrentSnapshotData{index, term, membership: act.hs.membership.clone(), pointer: pointer.clone()});
+                act.snapshot_data = Some(CurrentSnapshotData{
+                    index, term, membership: act.hs.membership.clone(), client_sessions: snapshot.client_sessions, pointer: pointer.clone(),
+                });
 
                 // Update target index with the new snapshot pointer.
                 let entry = Entry::new_snapshot_pointer(pointer.clone(), index, term);
@@ -279,9 +362,9 @@ impl MemoryStorage {
             .map_err(|err, _, _| panic!("Error communicating with snapshot actor. {}", err))
             .and_then(|res, _, _| fut::result(res))
             // Rebuild state machine from the deserialized data.
-            .and_then(|entries, act: &mut Self, _| {
+            .and_then(|snapshot, act: &mut Self, _| {
                 act.state_machine.clear();
-                act.state_machine.extend(entries.into_iter().map(|e| (e.index, e)));
+                act.state_machine.extend(snapshot.entries.into_iter().map(|e| (e.index, e)));
                 fut::ok(())
             })
             .map(|_, _, _| debug!("Finished rebuilding statemachine from snapshot successfully."))
@@ -294,6 +377,15 @@ impl MemoryStorage {
 /// A simple synchronous actor for interfacing with the filesystem for snapshots.
 struct SnapshotActor(std::path::PathBuf);
 
+/// The contents of a snapshot file.
+#[derive(Serialize, Deserialize)]
+struct MemorySnapshot {
+    /// The log entries covered by the snapshot.
+    entries: Vec<Entry>,
+    /// The client sessions covered by the snapshot, which are owned by Raft.
+    client_sessions: ClientSessions,
+}
+
 impl Actor for SnapshotActor {
     type Context = SyncContext<Self>;
 }
@@ -326,11 +418,11 @@ impl Handler<CreateSnapshotWithData> for SnapshotActor {
 struct DeserializeSnapshot(PathBuf);
 
 impl Message for DeserializeSnapshot {
-    type Result = Result<Vec<Entry>, MemoryStorageError>;
+    type Result = Result<MemorySnapshot, MemoryStorageError>;
 }
 
 impl Handler<DeserializeSnapshot> for SnapshotActor {
-    type Result = Result<Vec<Entry>, MemoryStorageError>;
+    type Result = Result<MemorySnapshot, MemoryStorageError>;
 
     fn handle(&mut self, msg: DeserializeSnapshot, _: &mut Self::Context) -> Self::Result {
         fs::read(msg.0)
@@ -338,9 +430,12 @@ impl Handler<DeserializeSnapshot> for SnapshotActor {
                 error!("Error reading contents of snapshot file. {}", err);
                 MemoryStorageError
             })
-            // Deserialize the data of the snapshot file.
+            // Deserialize the data of the snapshot file. Witnesses are sent empty snapshots.
             .and_then(|snapdata| {
-                rmps::from_slice::<Vec<Entry>>(snapdata.as_slice()).map_err(|err| {
+                if snapdata.is_empty() {
+                    return Ok(MemorySnapshot{entries: vec![], client_sessions: ClientSessions::new()});
+                }
+                rmps::from_slice::<MemorySnapshot>(snapdata.as_slice()).map_err(|err| {
                     error!("Error deserializing snapshot contents. {}", err);
                     MemoryStorageError
                 })
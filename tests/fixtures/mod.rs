@@ -12,8 +12,8 @@ use std::{
 use actix::prelude::*;
 use actix_raft::{
     NodeId, Raft,
-    config::{Config, SnapshotPolicy},
-    messages::{ClientPayload, ClientError, EntryNormal, ResponseMode},
+    config::{Config, MembershipChangeMode, SnapshotPolicy},
+    messages::{ClientPayload, ClientPayloadBatch, ClientError, EntryNormal, ResponseMode},
 };
 use async_log;
 use env_logger;
@@ -27,6 +27,7 @@ use crate::fixtures::{
 };
 
 pub type Payload = ClientPayload<MemoryStorageData, MemoryStorageResponse, MemoryStorageError>;
+pub type PayloadBatch = ClientPayloadBatch<MemoryStorageData, MemoryStorageResponse, MemoryStorageError>;
 
 pub fn setup_logger() {
     let logger = env_logger::Builder::from_default_env().build();
@@ -98,7 +99,7 @@ impl Handler<ClientRequest> for RaftTestController {
                         fut::ok(())
                     },
                     Err(err) => match err {
-                        ClientError::Internal => {
+                        ClientError::Internal | ClientError::Overloaded | ClientError::Indeterminate{..} => {
                             debug!("TEST: resending client request.");
                             ctx.notify(msg);
                             fut::ok(())
@@ -106,6 +107,9 @@ impl Handler<ClientRequest> for RaftTestController {
                         ClientError::Application(err) => {
                             panic!("Unexpected application error from client request: {:?}", err)
                         }
+                        ClientError::AlreadyAcknowledged{..} => {
+                            panic!("Unexpected client session error from client request without a session.")
+                        }
                         ClientError::ForwardToLeader{leader, ..} => {
                             debug!("TEST: received ForwardToLeader error. Updating leader and forwarding.");
                             msg.current_leader = leader;
@@ -150,7 +154,7 @@ pub struct Node {
 impl Node {
     /// Start building a new node.
     pub fn builder(id: NodeId, network: Addr<RaftRouter>, members: Vec<NodeId>) -> NodeBuilder {
-        NodeBuilder{id, network, members, metrics_rate: None, snapshot_policy: None}
+        NodeBuilder{id, network, members, metrics_rate: None, snapshot_policy: None, lease_reads: None, parallel_append: None, append_latency: None, hard_state_latency: None, membership_change_mode: None, catch_up_timeout: None, max_payload_bytes: None, max_buffered_entries: None, client_queue_capacity: None, forward_client_payloads: None, max_inflight_payloads: None}
     }
 }
 
@@ -160,6 +164,17 @@ pub struct NodeBuilder {
     members: Vec<NodeId>,
     metrics_rate: Option<u64>,
     snapshot_policy: Option<SnapshotPolicy>,
+    lease_reads: Option<bool>,
+    parallel_append: Option<bool>,
+    append_latency: Option<Duration>,
+    hard_state_latency: Option<Duration>,
+    membership_change_mode: Option<MembershipChangeMode>,
+    catch_up_timeout: Option<Duration>,
+    max_payload_bytes: Option<u64>,
+    max_buffered_entries: Option<u64>,
+    client_queue_capacity: Option<u64>,
+    forward_client_payloads: Option<Duration>,
+    max_inflight_payloads: Option<u64>,
 }
 
 impl NodeBuilder {
@@ -167,20 +182,54 @@ impl NodeBuilder {
     pub fn build(self) -> Node {
         let metrics_rate = self.metrics_rate.unwrap_or(1);
         let snapshot_policy = self.snapshot_policy.unwrap_or(SnapshotPolicy::default());
+        let lease_reads = self.lease_reads.unwrap_or(false);
+        let parallel_append = self.parallel_append.unwrap_or(false);
+        let (append_latency, hard_state_latency) = (self.append_latency, self.hard_state_latency);
+        let membership_change_mode = self.membership_change_mode.unwrap_or_default();
         let id = self.id;
         let members = self.members;
         let network = self.network;
 
         let temp_dir = tempdir_in("/tmp").expect("Tempdir to be created without error.");
         let snapshot_dir = temp_dir.path().to_string_lossy().to_string();
-        let config = Config::build(snapshot_dir.clone())
+        let mut config = Config::build(snapshot_dir.clone())
             .election_timeout_min(1500).election_timeout_max(2000).heartbeat_interval(150)
+            .lease_reads(lease_reads)
+            .parallel_append(parallel_append)
+            .membership_change_mode(membership_change_mode)
             .metrics_rate(Duration::from_secs(metrics_rate))
-            .snapshot_policy(snapshot_policy).snapshot_max_chunk_size(10000)
-            .validate().expect("Raft config to be created without error.");
+            .snapshot_policy(snapshot_policy).snapshot_max_chunk_size(10000);
+        if let Some(timeout) = self.catch_up_timeout {
+            config = config.catch_up_timeout(timeout);
+        }
+        if let Some(bytes) = self.max_payload_bytes {
+            config = config.max_payload_bytes(bytes);
+        }
+        if let Some(entries) = self.max_buffered_entries {
+            config = config.max_buffered_entries(entries);
+        }
+        if let Some(capacity) = self.client_queue_capacity {
+            config = config.client_queue_capacity(capacity);
+        }
+        if let Some(wait) = self.forward_client_payloads {
+            config = config.forward_client_payloads(wait);
+        }
+        if let Some(inflight) = self.max_inflight_payloads {
+            config = config.max_inflight_payloads(inflight);
+        }
+        let config = config.validate().expect("Raft config to be created without error.");
 
         let (storage_arb, raft_arb) = (Arbiter::new(), Arbiter::new());
-        let storage = MemoryStorage::start_in_arbiter(&storage_arb, |_| MemoryStorage::new(members, snapshot_dir));
+        let storage = MemoryStorage::start_in_arbiter(&storage_arb, move |_| {
+            let mut storage = MemoryStorage::new(members, snapshot_dir);
+            if let Some(latency) = append_latency {
+                storage = storage.with_append_latency(latency);
+            }
+            if let Some(latency) = hard_state_latency {
+                storage = storage.with_hard_state_latency(latency);
+            }
+            storage
+        });
         let storage_addr = storage.clone();
         let addr = Raft::start_in_arbiter(&raft_arb, move |_| {
             Raft::new(id, config, network.clone(), storage.clone(), network.recipient())
@@ -199,6 +248,72 @@ impl NodeBuilder {
         self.snapshot_policy = Some(val);
         self
     }
+
+    /// Configure the node to serve lease based reads, defaults to `false`.
+    pub fn lease_reads(mut self, val: bool) -> Self {
+        self.lease_reads = Some(val);
+        self
+    }
+
+    /// Configure the node to replicate entries while appending them locally, defaults to `false`.
+    pub fn parallel_append(mut self, val: bool) -> Self {
+        self.parallel_append = Some(val);
+        self
+    }
+
+    /// Configure the latency of client appends in the node's storage, defaults to none.
+    pub fn append_latency(mut self, val: Duration) -> Self {
+        self.append_latency = Some(val);
+        self
+    }
+
+    /// Configure the latency of hard state saves in the node's storage, defaults to none.
+    pub fn hard_state_latency(mut self, val: Duration) -> Self {
+        self.hard_state_latency = Some(val);
+        self
+    }
+
+    /// Configure the node's membership change mode, defaults to `MembershipChangeMode::JointConsensus`.
+    pub fn membership_change_mode(mut self, val: MembershipChangeMode) -> Self {
+        self.membership_change_mode = Some(val);
+        self
+    }
+
+    /// Configure the deadline for new nodes to catch up during a config change, defaults to none.
+    pub fn catch_up_timeout(mut self, val: Duration) -> Self {
+        self.catch_up_timeout = Some(val);
+        self
+    }
+
+    /// Configure the maximum size of replication payloads in bytes, defaults to the config default.
+    pub fn max_payload_bytes(mut self, val: u64) -> Self {
+        self.max_payload_bytes = Some(val);
+        self
+    }
+
+    /// Configure the maximum number of entries buffered per replication stream, defaults to the config default.
+    pub fn max_buffered_entries(mut self, val: u64) -> Self {
+        self.max_buffered_entries = Some(val);
+        self
+    }
+
+    /// Configure the capacity of the leader's client request queue, defaults to the config default.
+    pub fn client_queue_capacity(mut self, val: u64) -> Self {
+        self.client_queue_capacity = Some(val);
+        self
+    }
+
+    /// Configure the node to forward client payloads to the leader, waiting up to the given time for one, defaults to none.
+    pub fn forward_client_payloads(mut self, val: Duration) -> Self {
+        self.forward_client_payloads = Some(val);
+        self
+    }
+
+    /// Configure the maximum number of in-flight payloads per replication stream, defaults to the config default.
+    pub fn max_inflight_payloads(mut self, val: u64) -> Self {
+        self.max_inflight_payloads = Some(val);
+        self
+    }
 }
 
 /// Create a new Raft node for testing purposes.
@@ -0,0 +1,87 @@
+//! Test group commit of client requests.
+
+mod fixtures;
+
+use std::time::{Duration, Instant};
+
+use actix::prelude::*;
+use actix_raft::messages::{ClientError, EntryNormal, ResponseMode};
+use futures::future;
+use tokio_timer::Delay;
+
+use fixtures::{
+    Payload, RaftTestController, Node, setup_logger,
+    dev::{GetCurrentLeader, RaftRouter, Register},
+    memory_storage::{MemoryStorageData, REJECTED_DATA},
+};
+
+/// Group commit tests for a three node cluster.
+///
+/// What does this test cover?
+///
+/// - Many client requests sent to the leader at once should all be appended & applied.
+/// - Each appended request should be assigned its own index, with no gaps in the log.
+/// - A request rejected by the storage engine should only fail the client which sent it.
+///
+/// `RUST_LOG=actix_raft,group_commit=debug cargo test group_commit`
+#[test]
+fn group_commit() {
+    setup_logger();
+    let sys = System::builder().stop_on_panic(true).name("test").build();
+
+    // Setup test dependencies.
+    let netarb = Arbiter::new();
+    let network = RaftRouter::start_in_arbiter(&netarb, |_| RaftRouter::new());
+    let members = vec![0, 1, 2];
+    let node0 = Node::builder(0, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 0, addr: node0.addr.clone()});
+    let node1 = Node::builder(1, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 1, addr: node1.addr.clone()});
+    let node2 = Node::builder(2, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 2, addr: node2.addr.clone()});
+
+    // Setup test controller and actions.
+    let mut ctl = RaftTestController::new(network);
+    ctl.register(0, node0.addr.clone()).register(1, node1.addr.clone()).register(2, node2.addr.clone());
+    ctl.start_with_test(10,  Box::new(|act, ctx| {
+        // Get the current leader.
+        ctx.spawn(fut::wrap_future(act.network.send(GetCurrentLeader))
+            .map_err(|_, _: &mut RaftTestController, _| panic!("Failed to get current leader."))
+            .and_then(|res, _, _| fut::result(res)).and_then(|leader_opt, _, _| {
+                let leader = leader_opt.expect("Expected the cluster to have elected a leader.");
+                fut::ok(leader)
+            })
+
+            // Send 50 requests to the leader all at once, one of which will be rejected by storage.
+            .and_then(|leader, act, _| {
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                let requests: Vec<_> = (0..50u64).map(|idx| {
+                    let data = if idx == 25 { REJECTED_DATA.to_vec() } else { idx.to_string().into_bytes() };
+                    node.send(Payload::new(EntryNormal{data: MemoryStorageData{data}}, ResponseMode::Applied))
+                }).collect();
+                fut::wrap_future(future::join_all(requests))
+                    .map_err(|err, _, _| panic!("{}", err))
+            })
+            .map(|results, _, _| {
+                let mut indices = vec![];
+                for (idx, res) in results.into_iter().enumerate() {
+                    match res {
+                        Ok(res) if idx != 25 => indices.push(res.index()),
+                        Err(ClientError::Application(_)) if idx == 25 => (),
+                        other => panic!("Unexpected response for request {}: {:?}.", idx, other),
+                    }
+                }
+                assert_eq!(indices.len(), 49, "Expected every other request to be appended.");
+                assert!(indices.windows(2).all(|pair| pair[0] + 1 == pair[1]), "Expected requests to be appended in order, with no gaps in the log.");
+            })
+            .and_then(|_, _, _| fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(1))).map_err(|_, _, _| ()))
+            .and_then(|_, _, _| {
+                System::current().stop();
+                fut::ok(())
+            })
+            .map_err(|err, _, _| panic!("Failure during test. {:?}", err)));
+    }));
+
+    // Run the test.
+    assert!(sys.run().is_ok(), "Error during test.");
+}
@@ -0,0 +1,140 @@
+//! Test the indeterminate outcome of client requests which are in flight as the leader steps down.
+
+mod fixtures;
+
+use std::time::{Duration, Instant};
+
+use actix::prelude::*;
+use actix_raft::messages::{ClientError, ClientReadError, CommitState, CommitStateRequest, EntryNormal, ResponseMode};
+use tokio_timer::{Delay, Timeout};
+
+use fixtures::{
+    Payload, RaftTestController, Node, setup_logger,
+    dev::{ExecuteInRaftRouter, GetCurrentLeader, RaftRouter, Register},
+    memory_storage::{GetCurrentState, MemoryStorageData},
+};
+
+/// Indeterminate outcome tests for a three node cluster.
+///
+/// What does this test cover?
+///
+/// - A client request appended by a leader which then loses contact with the cluster should be
+///   answered with `ClientError::Indeterminate`, carrying the index & term of its entry.
+/// - Once a new leader has been elected, a `CommitStateRequest` for that entry should report it
+///   as `CommitState::Aborted`, as the new leader has committed a different entry at its index.
+/// - A `CommitStateRequest` for an entry applied by the new leader should report it as
+///   `CommitState::Committed`, and one for an index beyond the commit index as `Pending`.
+/// - Followers should answer `CommitStateRequest`s with `ClientReadError::ForwardToLeader`.
+///
+/// `RUST_LOG=actix_raft,indeterminate_outcome=debug cargo test indeterminate_outcome`
+#[test]
+fn indeterminate_outcome() {
+    setup_logger();
+    let sys = System::builder().stop_on_panic(true).name("test").build();
+
+    // Setup test dependencies.
+    let net = RaftRouter::new();
+    let network = net.start();
+    let members = vec![0, 1, 2];
+    let node0 = Node::builder(0, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 0, addr: node0.addr.clone()});
+    let node1 = Node::builder(1, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 1, addr: node1.addr.clone()});
+    let node2 = Node::builder(2, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 2, addr: node2.addr.clone()});
+    let storages = vec![node0.storage.clone(), node1.storage.clone(), node2.storage.clone()];
+
+    // Setup test controller and actions.
+    let mut ctl = RaftTestController::new(network);
+    ctl.register(0, node0.addr.clone()).register(1, node1.addr.clone()).register(2, node2.addr.clone());
+    ctl.start_with_test(10, Box::new(move |act, ctx| {
+        let storages = storages.clone();
+        let task = fut::wrap_future(act.network.send(GetCurrentLeader))
+            .map_err(|_, _: &mut RaftTestController, _| panic!("Failed to get current leader."))
+            .and_then(|res, _, _| fut::result(res)).and_then(|leader_opt, _, _| {
+                let leader = leader_opt.expect("Expected the cluster to have elected a leader.");
+                fut::ok(leader)
+            })
+
+            // Isolate the leader, then send it a request which can never be committed.
+            .and_then(|leader, act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| act.isolate_node(leader))));
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                let entry = EntryNormal{data: MemoryStorageData{data: b"isolated".to_vec()}};
+                fut::wrap_future(Timeout::new(node.send(Payload::new(entry, ResponseMode::Committed)), Duration::from_secs(5)))
+                    .map_err(|err, _, _| panic!("Client request was not answered in time. {:?}", err))
+                    .map(move |res, _, _| match res {
+                        Err(ClientError::Indeterminate{index, term}) => (leader, index, term),
+                        other => panic!("Expected client request to have an indeterminate outcome, got {:?}.", other),
+                    })
+            })
+            .and_then(|(old_leader, index, term), _, _| fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(3)))
+                .map_err(|_, _, _| ())
+                .map(move |_, _, _| (old_leader, index, term)))
+
+            // The new leader will have committed a different entry at the request's index.
+            .and_then(|(old_leader, index, term), act, _| {
+                fut::wrap_future(act.network.send(GetCurrentLeader))
+                    .map_err(|_, _: &mut RaftTestController, _| panic!("Failed to get current leader."))
+                    .and_then(|res, _, _| fut::result(res))
+                    .and_then(move |leader_opt, act: &mut RaftTestController, _| {
+                        let leader = leader_opt.expect("Expected the cluster to have elected a new leader.");
+                        assert_ne!(leader, old_leader, "Expected a new leader to have been elected.");
+                        let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                        fut::wrap_future(node.send(CommitStateRequest::new(index, term)))
+                            .map_err(|err, _, _| panic!("{}", err))
+                            .map(move |res, _, _| {
+                                assert_eq!(res.expect("Expected commit state to be returned."), CommitState::Aborted);
+                                (leader, old_leader)
+                            })
+                    })
+            })
+
+            // An entry applied by the new leader should be reported as committed.
+            .and_then(move |(leader, old_leader), act, _| {
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.").clone();
+                let storage = storages[leader as usize].clone();
+                let entry = EntryNormal{data: MemoryStorageData{data: b"committed".to_vec()}};
+                fut::wrap_future(node.send(Payload::new(entry, ResponseMode::Applied)))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(|res, _, _| res.expect("Expected client request to be applied.").index())
+                    .and_then(move |index, _, _| fut::wrap_future(storage.send(GetCurrentState))
+                        .map_err(|err, _, _| panic!("{}", err))
+                        .map(move |state, _, _| {
+                            let state = state.expect("Expected storage state to be returned.");
+                            let term = state.log.get(&index).expect("Expected applied entry to be in the log.").term;
+                            (index, term)
+                        }))
+                    .and_then(move |(index, term), _, _| fut::wrap_future(node.send(CommitStateRequest::new(index, term)))
+                        .map_err(|err, _, _| panic!("{}", err))
+                        .and_then(move |res, _, _| {
+                            assert_eq!(res.expect("Expected commit state to be returned."), CommitState::Committed);
+                            fut::wrap_future(node.send(CommitStateRequest::new(index + 1, term))).map_err(|err, _, _| panic!("{}", err))
+                        })
+                        .map(move |res, _, _| {
+                            assert_eq!(res.expect("Expected commit state to be returned."), CommitState::Pending);
+                            (leader, old_leader, index, term)
+                        }))
+            })
+
+            // Followers should send the request to the leader.
+            .and_then(|(leader, old_leader, index, term), act, _| {
+                let follower = (0..3u64).find(|id| *id != leader && *id != old_leader).expect("Expected a third node.");
+                let node = act.nodes.get(&follower).expect("Expected follower to be registered.");
+                fut::wrap_future(node.send(CommitStateRequest::new(index, term)))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |res, _, _| {
+                        match res {
+                            Err(ClientReadError::ForwardToLeader{leader: Some(id)}) => assert_eq!(id, leader),
+                            other => panic!("Expected follower to forward the request to the leader, got {:?}.", other),
+                        }
+                        System::current().stop();
+                    })
+            })
+            .map_err(|err, _, _| panic!("Failure during test. {:?}", err));
+        ctx.spawn(task);
+    }));
+
+    // Run the test.
+    assert!(sys.run().is_ok(), "Error during test.");
+}
@@ -0,0 +1,99 @@
+//! Test leader step down behavior when quorum of the new config is lost during joint consensus.
+
+mod fixtures;
+
+use std::{
+    sync::{Arc, atomic::{AtomicU64, Ordering}},
+    time::{Duration, Instant},
+};
+
+use actix::prelude::*;
+use actix_raft::admin::ProposeConfigChange;
+use tokio_timer::Delay;
+
+use fixtures::{
+    RaftTestController, Node, setup_logger,
+    dev::{ExecuteInRaftRouter, GetCurrentLeader, RaftRouter, Register},
+};
+
+/// CheckQuorum tests for a three node cluster in joint consensus.
+///
+/// What does this test cover?
+///
+/// - A leader in joint consensus which is in contact with a majority of the old config, but not
+///   of the new config, should step down. Here, two unreachable nodes are added & a follower is
+///   removed, so only the old config has a majority of reachable nodes. The step down is
+///   observed as the cluster's term advancing, as the old config may elect the same leader again.
+///
+/// `RUST_LOG=actix_raft,joint_check_quorum=debug cargo test joint_check_quorum`
+#[test]
+fn joint_check_quorum() {
+    setup_logger();
+    let sys = System::builder().stop_on_panic(true).name("test").build();
+
+    // Setup test dependencies.
+    let net = RaftRouter::new();
+    let network = net.start();
+    let members = vec![0, 1, 2];
+    let node0 = Node::builder(0, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 0, addr: node0.addr.clone()});
+    let node1 = Node::builder(1, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 1, addr: node1.addr.clone()});
+    let node2 = Node::builder(2, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 2, addr: node2.addr.clone()});
+    let node3 = Node::builder(3, network.clone(), vec![3]).build();
+    network.do_send(Register{id: 3, addr: node3.addr.clone()});
+    let node4 = Node::builder(4, network.clone(), vec![4]).build();
+    network.do_send(Register{id: 4, addr: node4.addr.clone()});
+
+    // Setup test controller and actions.
+    let mut ctl = RaftTestController::new(network);
+    ctl.register(0, node0.addr.clone()).register(1, node1.addr.clone()).register(2, node2.addr.clone());
+    ctl.start_with_test(10,  Box::new(|act, ctx| {
+        let initial_term = Arc::new(AtomicU64::new(0));
+        let final_term = initial_term.clone();
+
+        // Get the current leader.
+        let task = fut::wrap_future(act.network.send(GetCurrentLeader))
+            .map_err(|_, _: &mut RaftTestController, _| panic!("Failed to get current leader."))
+            .and_then(|res, _, _| fut::result(res)).and_then(|leader_opt, _, _| {
+                let leader = leader_opt.expect("Expected the cluster to have elected a leader.");
+                fut::ok(leader)
+            })
+
+            // Record the current term, isolate nodes 3 & 4, then add them to the cluster & remove a follower.
+            .and_then(move |leader, act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| {
+                    let metrics = act.metrics.get(&leader).expect("Expected leader's metrics to be present.");
+                    initial_term.store(metrics.current_term, Ordering::SeqCst);
+                    act.isolate_node(3);
+                    act.isolate_node(4);
+                })));
+                let removed = (leader + 1) % 3;
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                fut::wrap_future(node.send(ProposeConfigChange::new(vec![3, 4], vec![removed])))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .and_then(|res, _, _| fut::result(res).map_err(|err, _, _| panic!("{:?}", err)))
+                    .map(move |_, _, _| leader)
+            })
+
+            // Give the leader time to notice the lost quorum, then assert that the term has advanced.
+            .and_then(|leader, _, _| {
+                fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(8))).map_err(|_, _, _| ())
+                    .map(move |_, _, _| leader)
+            })
+            .and_then(move |leader, act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| {
+                    let metrics = act.metrics.get(&leader).expect("Expected old leader's metrics to be present.");
+                    assert!(metrics.current_term > final_term.load(Ordering::SeqCst), "Expected old leader to have stepped down.");
+                    System::current().stop();
+                })));
+                fut::ok(())
+            })
+            .map_err(|err, _, _| panic!("Failure during test. {:?}", err));
+        ctx.spawn(task);
+    }));
+
+    // Run the test.
+    assert!(sys.run().is_ok(), "Error during test.");
+}
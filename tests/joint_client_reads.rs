@@ -0,0 +1,94 @@
+//! Test linearizable client read behavior during joint consensus.
+
+mod fixtures;
+
+use std::time::{Duration, Instant};
+
+use actix::prelude::*;
+use actix_raft::{
+    admin::ProposeConfigChange,
+    messages::{ClientReadError, ClientReadRequest},
+};
+use tokio_timer::Delay;
+
+use fixtures::{
+    RaftTestController, Node, setup_logger,
+    dev::{ExecuteInRaftRouter, GetCurrentLeader, RaftRouter, Register},
+};
+
+/// Linearizable read tests for a three node cluster in joint consensus.
+///
+/// What does this test cover?
+///
+/// - A leader in joint consensus must not serve reads confirmed by a majority of the old config
+///   alone. Here, two unreachable nodes are added & a follower is removed, so the old config
+///   still has a majority of reachable nodes while the new config does not.
+/// - Likewise, a leader in joint consensus must not hold a lease acknowledged by a majority of
+///   the old config alone.
+///
+/// `RUST_LOG=actix_raft,joint_client_reads=debug cargo test joint_client_reads`
+#[test]
+fn joint_client_reads() {
+    setup_logger();
+    let sys = System::builder().stop_on_panic(true).name("test").build();
+
+    // Setup test dependencies.
+    let net = RaftRouter::new();
+    let network = net.start();
+    let members = vec![0, 1, 2];
+    let node0 = Node::builder(0, network.clone(), members.clone()).lease_reads(true).build();
+    network.do_send(Register{id: 0, addr: node0.addr.clone()});
+    let node1 = Node::builder(1, network.clone(), members.clone()).lease_reads(true).build();
+    network.do_send(Register{id: 1, addr: node1.addr.clone()});
+    let node2 = Node::builder(2, network.clone(), members.clone()).lease_reads(true).build();
+    network.do_send(Register{id: 2, addr: node2.addr.clone()});
+    let node3 = Node::builder(3, network.clone(), vec![3]).lease_reads(true).build();
+    network.do_send(Register{id: 3, addr: node3.addr.clone()});
+    let node4 = Node::builder(4, network.clone(), vec![4]).lease_reads(true).build();
+    network.do_send(Register{id: 4, addr: node4.addr.clone()});
+
+    // Setup test controller and actions.
+    let mut ctl = RaftTestController::new(network);
+    ctl.register(0, node0.addr.clone()).register(1, node1.addr.clone()).register(2, node2.addr.clone());
+    ctl.start_with_test(10,  Box::new(|act, ctx| {
+        // Get the current leader.
+        let task = fut::wrap_future(act.network.send(GetCurrentLeader))
+            .map_err(|_, _: &mut RaftTestController, _| panic!("Failed to get current leader."))
+            .and_then(|res, _, _| fut::result(res)).and_then(|leader_opt, _, _| {
+                let leader = leader_opt.expect("Expected the cluster to have elected a leader.");
+                fut::ok(leader)
+            })
+
+            // Isolate nodes 3 & 4, then add them to the cluster & remove a follower.
+            .and_then(|leader, act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(|act, _| {
+                    act.isolate_node(3);
+                    act.isolate_node(4);
+                })));
+                let removed = (leader + 1) % 3;
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                fut::wrap_future(node.send(ProposeConfigChange::new(vec![3, 4], vec![removed])))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .and_then(|res, _, _| fut::result(res).map_err(|err, _, _| panic!("{:?}", err)))
+                    .map(move |_, _, _| leader)
+            })
+
+            // Read from the leader. Only the old config can confirm its leadership.
+            .and_then(|leader, act, _| {
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                fut::wrap_future(node.send(ClientReadRequest::new()))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(|res, _, _| match res {
+                        Err(ClientReadError::ForwardToLeader{..}) => (),
+                        other => panic!("Expected ForwardToLeader error from leader without a majority of the new config, got {:?}.", other),
+                    })
+            })
+            .and_then(|_, _, _| fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(1))).map_err(|_, _, _| ()))
+            .map(|_, _, _| System::current().stop())
+            .map_err(|err, _, _| panic!("Failure during test. {:?}", err));
+        ctx.spawn(task);
+    }));
+
+    // Run the test.
+    assert!(sys.run().is_ok(), "Error during test.");
+}
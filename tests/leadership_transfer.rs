@@ -0,0 +1,121 @@
+//! Test leadership transfer behavior.
+
+mod fixtures;
+
+use std::time::{Duration, Instant};
+
+use actix::prelude::*;
+use actix_raft::admin::{TransferLeadership, TransferLeadershipError};
+use tokio_timer::Delay;
+
+use fixtures::{
+    ClientRequest, RaftTestController, Node, setup_logger,
+    dev::{GetCurrentLeader, RaftRouter, Register},
+};
+
+/// Leadership transfer tests for a three node cluster.
+///
+/// What does this test cover?
+///
+/// - Transfer commands sent to a follower should be rejected.
+/// - Transfer commands which target a node outside of the cluster should be rejected.
+/// - A transfer sent to the leader should resolve successfully, after which the target should
+///   be the new leader of the cluster.
+///
+/// `RUST_LOG=actix_raft,leadership_transfer=debug cargo test leadership_transfer`
+#[test]
+fn leadership_transfer() {
+    setup_logger();
+    let sys = System::builder().stop_on_panic(true).name("test").build();
+
+    // Setup test dependencies.
+    let netarb = Arbiter::new();
+    let network = RaftRouter::start_in_arbiter(&netarb, |_| RaftRouter::new());
+    let members = vec![0, 1, 2];
+    let node0 = Node::builder(0, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 0, addr: node0.addr.clone()});
+    let node1 = Node::builder(1, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 1, addr: node1.addr.clone()});
+    let node2 = Node::builder(2, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 2, addr: node2.addr.clone()});
+
+    // Setup test controller and actions.
+    let mut ctl = RaftTestController::new(network);
+    ctl.register(0, node0.addr.clone()).register(1, node1.addr.clone()).register(2, node2.addr.clone());
+    ctl.start_with_test(10,  Box::new(|act, ctx| {
+        // Get the current leader.
+        ctx.spawn(fut::wrap_future(act.network.send(GetCurrentLeader))
+            .map_err(|_, _: &mut RaftTestController, _| panic!("Failed to get current leader."))
+            .and_then(|res, _, _| fut::result(res)).and_then(|leader_opt, _, _| {
+                let leader = leader_opt.expect("Expected the cluster to have elected a leader.");
+                fut::ok(leader)
+            })
+            // Send 10 requests to the current leader & give the cluster 1 second to do work.
+            .and_then(|leader, _, ctx| {
+                for idx in 0..10 {
+                    ctx.notify(ClientRequest{payload: idx, current_leader: Some(leader), cb: None});
+                }
+                fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(1))).map_err(|_, _, _| ())
+                    .map(move |_, _, _| leader)
+            })
+
+            // Send a transfer to a follower. It should be rejected.
+            .and_then(|leader, act, _| {
+                let target = act.nodes.keys().cloned().find(|id| id != &leader).expect("Expected a follower.");
+                let node = act.nodes.get(&target).expect("Expected follower to be registered.");
+                fut::wrap_future(node.send(TransferLeadership::new(leader)))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |res, _, _| {
+                        match res {
+                            Err(TransferLeadershipError::NodeNotLeader(Some(id))) => assert_eq!(id, leader, "Expected follower to report the leader."),
+                            other => panic!("Expected NodeNotLeader error from follower, got {:?}.", other),
+                        }
+                        (leader, target)
+                    })
+            })
+
+            // Send a transfer which targets a node outside of the cluster. It should be rejected.
+            .and_then(|(leader, target), act, _| {
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                fut::wrap_future(node.send(TransferLeadership::new(99)))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |res, _, _| {
+                        match res {
+                            Err(TransferLeadershipError::InvalidTarget) => (),
+                            other => panic!("Expected InvalidTarget error from leader, got {:?}.", other),
+                        }
+                        (leader, target)
+                    })
+            })
+
+            // Transfer leadership to the follower & give the cluster 2 seconds to report metrics.
+            .and_then(|(leader, target), act, _| {
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                fut::wrap_future(node.send(TransferLeadership::new(target)))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .and_then(|res, _, _| {
+                        res.expect("Expected leadership transfer to succeed.");
+                        fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(2))).map_err(|_, _, _| ())
+                    })
+                    .map(move |_, _, _| target)
+            })
+
+            // Assert that the target is now the leader of the cluster.
+            .and_then(|target, act, _| {
+                fut::wrap_future(act.network.send(GetCurrentLeader))
+                    .map_err(|_, _, _| panic!("Failed to get current leader."))
+                    .and_then(|res, _, _| fut::result(res))
+                    .map(move |leader_opt, _, _| {
+                        assert_eq!(leader_opt, Some(target), "Expected the target of the transfer to be the new leader.");
+                    })
+            })
+            .and_then(|_, _, _| {
+                System::current().stop();
+                fut::ok(())
+            })
+            .map_err(|err, _, _| panic!("Failure during test. {:?}", err)));
+    }));
+
+    // Run the test.
+    assert!(sys.run().is_ok(), "Error during test.");
+}
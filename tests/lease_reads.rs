@@ -0,0 +1,114 @@
+//! Test lease based client read behavior.
+
+mod fixtures;
+
+use std::time::{Duration, Instant};
+
+use actix::prelude::*;
+use actix_raft::messages::{ClientReadError, ClientReadRequest};
+use tokio_timer::Delay;
+
+use fixtures::{
+    ClientRequest, RaftTestController, Node, setup_logger,
+    dev::{ExecuteInRaftRouter, GetCurrentLeader, RaftRouter, Register},
+};
+
+/// Lease read tests for a three node cluster.
+///
+/// What does this test cover?
+///
+/// - Read requests sent to a leader holding a lease should resolve with a read index which
+///   covers all previously applied client writes.
+/// - A leader which has been partitioned from the rest of the cluster must not serve reads once
+///   its lease has expired.
+/// - The rest of the cluster should elect a new leader once the old leader's lease has expired.
+///
+/// `RUST_LOG=actix_raft,lease_reads=debug cargo test lease_reads`
+#[test]
+fn lease_reads() {
+    setup_logger();
+    let sys = System::builder().stop_on_panic(true).name("test").build();
+
+    // Setup test dependencies.
+    let netarb = Arbiter::new();
+    let network = RaftRouter::start_in_arbiter(&netarb, |_| RaftRouter::new());
+    let members = vec![0, 1, 2];
+    let node0 = Node::builder(0, network.clone(), members.clone()).lease_reads(true).build();
+    network.do_send(Register{id: 0, addr: node0.addr.clone()});
+    let node1 = Node::builder(1, network.clone(), members.clone()).lease_reads(true).build();
+    network.do_send(Register{id: 1, addr: node1.addr.clone()});
+    let node2 = Node::builder(2, network.clone(), members.clone()).lease_reads(true).build();
+    network.do_send(Register{id: 2, addr: node2.addr.clone()});
+
+    // Setup test controller and actions.
+    let mut ctl = RaftTestController::new(network);
+    ctl.register(0, node0.addr.clone()).register(1, node1.addr.clone()).register(2, node2.addr.clone());
+    ctl.start_with_test(10,  Box::new(|act, ctx| {
+        // Get the current leader.
+        ctx.spawn(fut::wrap_future(act.network.send(GetCurrentLeader))
+            .map_err(|_, _: &mut RaftTestController, _| panic!("Failed to get current leader."))
+            .and_then(|res, _, _| fut::result(res)).and_then(|leader_opt, _, _| {
+                let leader = leader_opt.expect("Expected the cluster to have elected a leader.");
+                fut::ok(leader)
+            })
+            // Send 10 requests to the current leader & give the cluster 1 second to do work.
+            .and_then(|leader, _, ctx| {
+                for idx in 0..10 {
+                    ctx.notify(ClientRequest{payload: idx, current_leader: Some(leader), cb: None});
+                }
+                fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(1))).map_err(|_, _, _| ())
+                    .map(move |_, _, _| leader)
+            })
+
+            // Read from the leader. The read index must cover all of the writes.
+            .and_then(|leader, act, _| {
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                fut::wrap_future(node.send(ClientReadRequest::new()))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |res, _, _| {
+                        let res = res.expect("Expected read from leader to succeed.");
+                        assert!(res.index >= 10, "Expected read index to cover all writes, got {}.", res.index);
+                        leader
+                    })
+            })
+
+            // Isolate the leader & wait for its lease to expire. It must no longer serve reads.
+            .and_then(|leader, act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| act.isolate_node(leader))));
+                fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(3))).map_err(|_, _, _| ())
+                    .map(move |_, _, _| leader)
+            })
+            .and_then(|leader, act, _| {
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                fut::wrap_future(node.send(ClientReadRequest::new()))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |res, _, _| match res {
+                        Err(ClientReadError::ForwardToLeader{..}) => leader,
+                        other => panic!("Expected ForwardToLeader error from isolated leader, got {:?}.", other),
+                    })
+            })
+
+            // Give the rest of the cluster time to elect a new leader & assert on it.
+            .and_then(|old_leader, _, _| {
+                fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(5))).map_err(|_, _, _| ())
+                    .map(move |_, _, _| old_leader)
+            })
+            .and_then(|old_leader, act, _| {
+                fut::wrap_future(act.network.send(GetCurrentLeader))
+                    .map_err(|_, _: &mut RaftTestController, _| panic!("Failed to get current leader."))
+                    .and_then(|res, _, _| fut::result(res))
+                    .map(move |leader_opt, _, _| {
+                        let leader = leader_opt.expect("Expected the cluster to have elected a new leader.");
+                        assert_ne!(leader, old_leader, "Expected a new leader to have been elected.");
+                    })
+            })
+            .and_then(|_, _, ctx| {
+                ctx.run_later(Duration::from_secs(2), |_, _| System::current().stop());
+                fut::ok(())
+            })
+            .map_err(|err, _, _| panic!("Failure during test. {:?}", err)));
+    }));
+
+    // Run the test.
+    assert!(sys.run().is_ok(), "Error during test.");
+}
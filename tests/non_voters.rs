@@ -0,0 +1,247 @@
+//! Test permanent non-voter management.
+
+mod fixtures;
+
+use std::time::{Duration, Instant};
+
+use actix::prelude::*;
+use actix_raft::{
+    admin::{AddNonVoter, AddNonVoterError, ProposeConfigChange, RemoveNonVoter, RemoveNonVoterError},
+    messages::{EntryNormal, ResponseMode},
+    metrics::State,
+};
+use tokio_timer::{Delay, Timeout};
+
+use fixtures::{
+    Payload, RaftTestController, Node, setup_logger,
+    dev::{ExecuteInRaftRouter, GetCurrentLeader, RaftRouter, Register},
+    memory_storage::MemoryStorageData,
+};
+
+/// Permanent non-voter tests for a three node cluster.
+///
+/// What does this test cover?
+///
+/// - An `AddNonVoter` command sent to a follower should be rejected.
+/// - An `AddNonVoter` command sent to the leader should add the node as a permanent non-voter.
+/// - Adding a node which is already a member should be rejected.
+/// - The leader should commit entries while the non-voter and one follower are isolated.
+/// - The non-voter should catch up, should stay a non-voter & should never campaign.
+/// - A `RemoveNonVoter` command should remove the node, and repeating it should be rejected.
+/// - A non-voter added via a `ProposeConfigChange` command should be promoted to a voting member
+///   through joint consensus.
+///
+/// `RUST_LOG=actix_raft,non_voters=debug cargo test non_voters`
+#[test]
+fn non_voters() {
+    setup_logger();
+    let sys = System::builder().stop_on_panic(true).name("test").build();
+
+    // Setup test dependencies.
+    let net = RaftRouter::new();
+    let network = net.start();
+    let members = vec![0, 1, 2];
+    let node0 = Node::builder(0, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 0, addr: node0.addr.clone()});
+    let node1 = Node::builder(1, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 1, addr: node1.addr.clone()});
+    let node2 = Node::builder(2, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 2, addr: node2.addr.clone()});
+
+    // Setup test controller and actions.
+    let mut ctl = RaftTestController::new(network);
+    ctl.register(0, node0.addr.clone()).register(1, node1.addr.clone()).register(2, node2.addr.clone());
+    ctl.start_with_test(10, Box::new(|act, ctx| {
+        let task = fut::wrap_future(act.network.send(GetCurrentLeader))
+            .map_err(|_, _: &mut RaftTestController, _| panic!("Failed to get current leader."))
+            .and_then(|res, _, _| fut::result(res)).and_then(|leader_opt, _, _| {
+                let leader = leader_opt.expect("Expected the cluster to have elected a leader.");
+                fut::ok(leader)
+            })
+            .and_then(|leader, act, _| act.write_data(leader).map(move |_, _, _| leader))
+
+            // Send an AddNonVoter command to a follower. It should be rejected.
+            .and_then(|leader, act, _| {
+                let follower = act.nodes.keys().cloned().find(|id| id != &leader).expect("Expected a follower.");
+                let node = act.nodes.get(&follower).expect("Expected follower to be registered.");
+                fut::wrap_future(node.send(AddNonVoter::new(3)))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |res, _, _| {
+                        match res {
+                            Err(AddNonVoterError::NodeNotLeader(Some(id))) => assert_eq!(id, leader, "Expected follower to report the leader."),
+                            other => panic!("Expected NodeNotLeader error from follower, got {:?}.", other),
+                        }
+                        (leader, follower)
+                    })
+            })
+
+            // Add a new node to the cluster as a non-voter via the leader.
+            .and_then(|(leader, follower), act, _| {
+                let node3 = Node::builder(3, act.network.clone(), vec![3]).build();
+                act.register(3, node3.addr.clone());
+                act.network.do_send(Register{id: 3, addr: node3.addr.clone()});
+
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                fut::wrap_future(node.send(AddNonVoter::new(3)))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |res, _, _| {
+                        res.expect("Expected AddNonVoter to succeed on the leader.");
+                        (leader, follower)
+                    })
+            })
+
+            // Adding a node which is already a member should be rejected.
+            .and_then(|(leader, follower), act, _| {
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                fut::wrap_future(node.send(AddNonVoter::new(follower)))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |res, _, _| {
+                        match res {
+                            Err(AddNonVoterError::AlreadyMember) => (),
+                            other => panic!("Expected AlreadyMember error, got {:?}.", other),
+                        }
+                        (leader, follower)
+                    })
+            })
+
+            // Isolate the non-voter & a follower. The leader must still be able to commit, as the
+            // non-voter does not count toward commitment.
+            .and_then(|(leader, follower), act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| {
+                    act.isolate_node(3);
+                    act.isolate_node(follower);
+                })));
+                act.write_data(leader).map(move |_, _, _| (leader, follower))
+            })
+
+            // Wait for longer than an election timeout, then restore the isolated nodes.
+            .and_then(|(leader, follower), _, _| fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(3)))
+                .map_err(|_, _, _| ())
+                .map(move |_, _, _| (leader, follower)))
+            .and_then(|(leader, follower), act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| {
+                    act.restore_node(3);
+                    act.restore_node(follower);
+                })));
+                fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(3)))
+                    .map_err(|_, _, _| ())
+                    .map(move |_, _, _| leader)
+            })
+
+            // Assert that the non-voter has caught up & has never voted or campaigned.
+            .and_then(|leader, act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| {
+                    let leader = act.metrics.get(&leader).expect("Expected leader's metrics to be present.");
+                    assert_eq!(leader.state, State::Leader, "Expected leader to have retained leadership.");
+                    assert_eq!(leader.membership_config.members.len(), 3, "Expected three voting members.");
+                    assert_eq!(leader.membership_config.learners, vec![3], "Expected node 3 to be a permanent non-voter.");
+                    assert!(leader.membership_config.non_voters.is_empty(), "Expected no catching-up non-voters.");
+                    assert!(!leader.membership_config.is_in_joint_consensus, "Expected cluster not to be in joint consensus.");
+
+                    let node3 = act.metrics.get(&3).expect("Expected non-voter's metrics to be present.");
+                    assert_eq!(node3.state, State::NonVoter, "Expected node 3 to be in state NonVoter.");
+                    assert_eq!(node3.current_term, leader.current_term, "Expected node 3 to never have campaigned.");
+                    assert_eq!(node3.last_log_index, leader.last_log_index, "Expected node 3 to have caught up.");
+                    assert_eq!(node3.membership_config, leader.membership_config, "Expected node 3 to have matching config.");
+                })));
+                fut::ok(leader)
+            })
+
+            // Remove the non-voter. Repeating the removal should be rejected.
+            .and_then(|leader, act, _| {
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.").clone();
+                fut::wrap_future(node.send(RemoveNonVoter::new(3)))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .and_then(move |res, _, _| {
+                        res.expect("Expected RemoveNonVoter to succeed on the leader.");
+                        fut::wrap_future(node.send(RemoveNonVoter::new(3))).map_err(|err, _, _| panic!("{}", err))
+                    })
+                    .map(move |res, _, _| {
+                        match res {
+                            Err(RemoveNonVoterError::NotNonVoter) => (),
+                            other => panic!("Expected NotNonVoter error, got {:?}.", other),
+                        }
+                        leader
+                    })
+            })
+            .and_then(|leader, act, _| act.write_data(leader).map(move |_, _, _| leader))
+            .and_then(|leader, _, _| fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(2)))
+                .map_err(|_, _, _| ())
+                .map(move |_, _, _| leader))
+
+            // Assert that the non-voter has been removed & no longer receives entries.
+            .and_then(|leader, act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| {
+                    let leader = act.metrics.get(&leader).expect("Expected leader's metrics to be present.");
+                    assert!(leader.membership_config.learners.is_empty(), "Expected node 3 to have been removed.");
+
+                    let node3 = act.metrics.get(&3).expect("Expected non-voter's metrics to be present.");
+                    assert_eq!(node3.state, State::NonVoter, "Expected node 3 to be in state NonVoter.");
+                    assert!(!node3.membership_config.contains(&3), "Expected node 3 to have replicated its removal.");
+                    assert!(node3.last_log_index < leader.last_log_index, "Expected node 3 to no longer receive entries.");
+                })));
+                fut::ok(leader)
+            })
+
+            // Add another non-voter, then promote it via a `ProposeConfigChange` command.
+            .and_then(|leader, act, _| {
+                let node4 = Node::builder(4, act.network.clone(), vec![4]).build();
+                act.register(4, node4.addr.clone());
+                act.network.do_send(Register{id: 4, addr: node4.addr.clone()});
+
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.").clone();
+                fut::wrap_future(node.send(AddNonVoter::new(4)))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .and_then(move |res, _, _| {
+                        res.expect("Expected AddNonVoter to succeed on the leader.");
+                        fut::wrap_future(node.send(ProposeConfigChange::new(vec![4], vec![]))).map_err(|err, _, _| panic!("{}", err))
+                    })
+                    .map(move |res, _, _| {
+                        res.expect("Expected the non-voter to be promoted.");
+                        leader
+                    })
+            })
+            .and_then(|leader, _, _| fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(3)))
+                .map_err(|_, _, _| ())
+                .map(move |_, _, _| leader))
+
+            // Assert that the promoted node is a voting member.
+            .and_then(|leader, act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| {
+                    let leader = act.metrics.get(&leader).expect("Expected leader's metrics to be present.");
+                    assert_eq!(leader.membership_config.members.len(), 4, "Expected four voting members.");
+                    assert!(leader.membership_config.members.contains(&4), "Expected node 4 to be a voting member.");
+                    assert!(leader.membership_config.learners.is_empty(), "Expected node 4 to no longer be a permanent non-voter.");
+                    assert!(!leader.membership_config.is_in_joint_consensus, "Expected cluster not to be in joint consensus.");
+
+                    let node4 = act.metrics.get(&4).expect("Expected node 4's metrics to be present.");
+                    assert_eq!(node4.state, State::Follower, "Expected node 4 to be a follower.");
+                    assert_eq!(node4.membership_config, leader.membership_config, "Expected node 4 to have matching config.");
+
+                    System::current().stop();
+                })));
+                fut::ok(())
+            })
+            .map_err(|err, _, _| panic!("Failure during test. {:?}", err));
+        ctx.spawn(task);
+    }));
+
+    // Run the test.
+    assert!(sys.run().is_ok(), "Error during test.");
+}
+
+impl RaftTestController {
+    /// Write 10 entries to the given leader, expecting each to be applied within a few seconds.
+    fn write_data(&mut self, leader: u64) -> impl ActorFuture<Actor=Self, Item=(), Error=()> {
+        let addr = self.nodes.get(&leader).expect("Expected leader to be registered.").clone();
+        fut::wrap_stream(futures::stream::iter_ok(0..10u64))
+            .and_then(move |data, _, _| {
+                let entry = EntryNormal{data: MemoryStorageData{data: data.to_string().into_bytes()}};
+                let payload = Payload::new(entry, ResponseMode::Applied);
+                fut::wrap_future(Timeout::new(addr.send(payload), Duration::from_secs(3)))
+                    .map_err(|err, _, _| panic!("Client request was not applied in time. {:?}", err))
+                    .map(|res, _, _| { res.expect("Expected client request to succeed."); })
+            })
+            .finish()
+    }
+}
@@ -0,0 +1,104 @@
+//! Test membership changes on a leader whose client request backlog is at capacity.
+
+mod fixtures;
+
+use std::{
+    collections::BTreeSet,
+    time::{Duration, Instant},
+};
+
+use actix::prelude::*;
+use actix_raft::{
+    admin::ChangeMembership,
+    messages::{ClientError, EntryNormal, ResponseMode},
+};
+use futures::future;
+use tokio_timer::Delay;
+
+use fixtures::{
+    Payload, RaftTestController, Node, setup_logger,
+    dev::{ExecuteInRaftRouter, GetCurrentLeader, RaftRouter, Register},
+    memory_storage::MemoryStorageData,
+};
+
+/// The latency of the nodes' disk writes.
+const LATENCY: Duration = Duration::from_millis(200);
+/// The capacity of the leader's client request queue.
+const CAPACITY: u64 = 5;
+
+/// Overloaded config change tests for a three node cluster with slow disk writes.
+///
+/// What does this test cover?
+///
+/// - A `ChangeMembership` command sent while the leader's client request backlog is at capacity
+///   should not have its config entries rejected as overloaded, and should succeed.
+/// - The cluster should leave joint consensus with the requested set of voting members.
+///
+/// `RUST_LOG=actix_raft,overloaded_config_change=debug cargo test overloaded_config_change`
+#[test]
+fn overloaded_config_change() {
+    setup_logger();
+    let sys = System::builder().stop_on_panic(true).name("test").build();
+
+    // Setup test dependencies.
+    let net = RaftRouter::new();
+    let network = net.start();
+    let members = vec![0, 1, 2];
+    let node0 = Node::builder(0, network.clone(), members.clone()).append_latency(LATENCY).client_queue_capacity(CAPACITY).build();
+    network.do_send(Register{id: 0, addr: node0.addr.clone()});
+    let node1 = Node::builder(1, network.clone(), members.clone()).append_latency(LATENCY).client_queue_capacity(CAPACITY).build();
+    network.do_send(Register{id: 1, addr: node1.addr.clone()});
+    let node2 = Node::builder(2, network.clone(), members.clone()).append_latency(LATENCY).client_queue_capacity(CAPACITY).build();
+    network.do_send(Register{id: 2, addr: node2.addr.clone()});
+
+    // Setup test controller and actions.
+    let mut ctl = RaftTestController::new(network);
+    ctl.register(0, node0.addr.clone()).register(1, node1.addr.clone()).register(2, node2.addr.clone());
+    ctl.start_with_test(10, Box::new(|act, ctx| {
+        let task = fut::wrap_future(act.network.send(GetCurrentLeader))
+            .map_err(|_, _: &mut RaftTestController, _| panic!("Failed to get current leader."))
+            .and_then(|res, _, _| fut::result(res)).and_then(|leader_opt, _, _| {
+                let leader = leader_opt.expect("Expected the cluster to have elected a leader.");
+                fut::ok(leader)
+            })
+
+            // Fill the leader's backlog with a burst of requests, then change the cluster's membership.
+            .and_then(|leader, act, _| {
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                let requests: Vec<_> = (0..50u64).map(|idx| {
+                    let entry = EntryNormal{data: MemoryStorageData{data: idx.to_string().into_bytes()}};
+                    node.send(Payload::new(entry, ResponseMode::Applied))
+                }).collect();
+                let target: BTreeSet<_> = vec![leader, (leader + 2) % 3].into_iter().collect();
+                let change = node.send(ChangeMembership::new(target.clone()));
+                fut::wrap_future(future::join_all(requests).join(change))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |(results, change), _, _| {
+                        let overloaded = results.iter().filter(|res| matches!(res, Err(ClientError::Overloaded))).count();
+                        assert!(overloaded > 0, "Expected the leader's backlog to have been at capacity.");
+                        change.expect("Expected ChangeMembership to succeed on an overloaded leader.");
+                        (leader, target)
+                    })
+            })
+            .and_then(|ids, _, _| fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(2)))
+                .map_err(|_, _, _| ())
+                .map(move |_, _, _| ids))
+
+            // Assert against the state of the cluster.
+            .and_then(|(leader, target), act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| {
+                    let leader = act.metrics.get(&leader).expect("Expected leader's metrics to be present.");
+                    let members: BTreeSet<_> = leader.membership_config.members.iter().cloned().collect();
+                    assert_eq!(members, target, "Expected the voting members to match the requested set.");
+                    assert!(!leader.membership_config.is_in_joint_consensus, "Expected cluster not to be in joint consensus.");
+                    System::current().stop();
+                })));
+                fut::ok(())
+            })
+            .map_err(|err, _, _| panic!("Failure during test. {:?}", err));
+        ctx.spawn(task);
+    }));
+
+    // Run the test.
+    assert!(sys.run().is_ok(), "Error during test.");
+}
@@ -0,0 +1,109 @@
+//! Test parallel leader append behavior.
+
+mod fixtures;
+
+use std::time::{Duration, Instant};
+
+use actix::prelude::*;
+use actix_raft::{
+    messages::{EntryNormal, ResponseMode},
+    metrics::RaftMetrics,
+};
+use futures::future;
+use tokio_timer::Delay;
+
+use fixtures::{
+    Payload, RaftTestController, Node, setup_logger,
+    dev::{ExecuteInRaftRouter, GetCurrentLeader, RaftRouter, Register},
+    memory_storage::MemoryStorageData,
+};
+
+/// The latency of both the leader's disk writes & the network round trip to followers.
+const LATENCY: Duration = Duration::from_millis(200);
+
+/// Parallel append tests for a three node cluster.
+///
+/// What does this test cover?
+///
+/// - The leader's disk write & replication to followers should overlap in time.
+/// - Client requests should be committed & applied, and the logs of all nodes should converge.
+///
+/// `RUST_LOG=actix_raft,parallel_append=debug cargo test parallel_append`
+#[test]
+fn parallel_append() {
+    setup_logger();
+    let sys = System::builder().stop_on_panic(true).name("test").build();
+
+    // Setup test dependencies.
+    let netarb = Arbiter::new();
+    let network = RaftRouter::start_in_arbiter(&netarb, |_| RaftRouter::new());
+    let members = vec![0, 1, 2];
+    let node0 = Node::builder(0, network.clone(), members.clone()).parallel_append(true).append_latency(LATENCY).build();
+    network.do_send(Register{id: 0, addr: node0.addr.clone()});
+    let node1 = Node::builder(1, network.clone(), members.clone()).parallel_append(true).append_latency(LATENCY).build();
+    network.do_send(Register{id: 1, addr: node1.addr.clone()});
+    let node2 = Node::builder(2, network.clone(), members.clone()).parallel_append(true).append_latency(LATENCY).build();
+    network.do_send(Register{id: 2, addr: node2.addr.clone()});
+    network.do_send(ExecuteInRaftRouter(Box::new(|act, _| act.set_append_entries_latency(LATENCY))));
+
+    // Setup test controller and actions.
+    let mut ctl = RaftTestController::new(network);
+    ctl.register(0, node0.addr.clone()).register(1, node1.addr.clone()).register(2, node2.addr.clone());
+    ctl.start_with_test(10,  Box::new(|act, ctx| {
+        // Get the current leader.
+        ctx.spawn(fut::wrap_future(act.network.send(GetCurrentLeader))
+            .map_err(|_, _: &mut RaftTestController, _| panic!("Failed to get current leader."))
+            .and_then(|res, _, _| fut::result(res)).and_then(|leader_opt, _, _| {
+                let leader = leader_opt.expect("Expected the cluster to have elected a leader.");
+                fut::ok(leader)
+            })
+
+            // Commit a single request. The disk write & the network round trip should overlap.
+            .and_then(|leader, act, _| {
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                let entry = EntryNormal{data: MemoryStorageData{data: b"0".to_vec()}};
+                let start = Instant::now();
+                fut::wrap_future(node.send(Payload::new(entry, ResponseMode::Committed)))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |res, _, _| {
+                        res.expect("Expected client request sent to leader to succeed.");
+                        let elapsed = start.elapsed();
+                        assert!(elapsed < LATENCY * 2, "Expected disk write & replication to overlap, took {:?}.", elapsed);
+                        leader
+                    })
+            })
+
+            // Send a burst of requests to the leader, all of which should be applied.
+            .and_then(|leader, act, _| {
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                let requests: Vec<_> = (1..31u64).map(|idx| {
+                    let entry = EntryNormal{data: MemoryStorageData{data: idx.to_string().into_bytes()}};
+                    node.send(Payload::new(entry, ResponseMode::Applied))
+                }).collect();
+                fut::wrap_future(future::join_all(requests))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(|results, _, _| {
+                        assert!(results.iter().all(|res| res.is_ok()), "Expected all client requests to succeed.");
+                    })
+            })
+            .and_then(|_, _, _| fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(2))).map_err(|_, _, _| ()))
+
+            // The logs of all nodes should have converged.
+            .and_then(|_, act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(|act, _| {
+                    let metrics: Vec<&RaftMetrics> = act.metrics.values().collect();
+                    let last_log_index = metrics[0].last_log_index;
+                    assert!(metrics.iter().all(|m| m.last_log_index == last_log_index), "Expected all nodes to have the same last log index, got {:?}.", metrics);
+                })));
+                fut::ok(())
+            })
+            .and_then(|_, _, ctx| {
+                ctx.run_later(Duration::from_secs(1), |_, _| System::current().stop());
+                fut::ok(())
+            })
+            .map_err(|err, _, _| panic!("Failure during test. {:?}", err)));
+    }));
+
+    // Run the test.
+    assert!(sys.run().is_ok(), "Error during test.");
+}
@@ -0,0 +1,113 @@
+//! Test the size limits of replication payloads.
+
+mod fixtures;
+
+use std::time::{Duration, Instant};
+
+use actix::prelude::*;
+use actix_raft::messages::{EntryNormal, ResponseMode};
+use tokio_timer::{Delay, Timeout};
+
+use fixtures::{
+    Payload, RaftTestController, Node, setup_logger,
+    dev::{ExecuteInRaftRouter, GetCurrentLeader, RaftRouter, Register},
+    memory_storage::MemoryStorageData,
+};
+
+/// The maximum size of each replication payload.
+const MAX_PAYLOAD_BYTES: u64 = 1000;
+/// The size of the data of each client request.
+const ENTRY_BYTES: usize = 400;
+
+/// Payload size limit tests for a three node cluster.
+///
+/// What does this test cover?
+///
+/// - Entries replicated at line rate should be sent in payloads within `max_payload_bytes`.
+/// - A follower which has fallen behind should be brought up-to-date with payloads within
+///   `max_payload_bytes`, even though many more entries fit within `max_payload_entries`.
+///
+/// `RUST_LOG=actix_raft,payload_limits=debug cargo test payload_limits`
+#[test]
+fn payload_limits() {
+    setup_logger();
+    let sys = System::builder().stop_on_panic(true).name("test").build();
+
+    // Setup test dependencies.
+    let net = RaftRouter::new();
+    let network = net.start();
+    let members = vec![0, 1, 2];
+    let node0 = Node::builder(0, network.clone(), members.clone()).max_payload_bytes(MAX_PAYLOAD_BYTES).build();
+    network.do_send(Register{id: 0, addr: node0.addr.clone()});
+    let node1 = Node::builder(1, network.clone(), members.clone()).max_payload_bytes(MAX_PAYLOAD_BYTES).build();
+    network.do_send(Register{id: 1, addr: node1.addr.clone()});
+    let node2 = Node::builder(2, network.clone(), members.clone()).max_payload_bytes(MAX_PAYLOAD_BYTES).build();
+    network.do_send(Register{id: 2, addr: node2.addr.clone()});
+
+    // Setup test controller and actions.
+    let mut ctl = RaftTestController::new(network);
+    ctl.register(0, node0.addr.clone()).register(1, node1.addr.clone()).register(2, node2.addr.clone());
+    ctl.start_with_test(10, Box::new(|act, ctx| {
+        let task = fut::wrap_future(act.network.send(GetCurrentLeader))
+            .map_err(|_, _: &mut RaftTestController, _| panic!("Failed to get current leader."))
+            .and_then(|res, _, _| fut::result(res)).and_then(|leader_opt, _, _| {
+                let leader = leader_opt.expect("Expected the cluster to have elected a leader.");
+                fut::ok(leader)
+            })
+            .and_then(|leader, act, _| act.write_data(leader).map(move |_, _, _| leader))
+
+            // Isolate a follower, & write more data which it will have to catch up on.
+            .and_then(|leader, act, _| {
+                let follower = act.nodes.keys().cloned().find(|id| id != &leader).expect("Expected a follower.");
+                act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| act.isolate_node(follower))));
+                act.write_data(leader).map(move |_, _, _| (leader, follower))
+            })
+
+            // Restore the follower, & give it time to catch up.
+            .and_then(|(leader, follower), act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| act.restore_node(follower))));
+                fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(3)))
+                    .map_err(|_, _, _| ())
+                    .map(move |_, _, _| leader)
+            })
+
+            // Assert that all nodes have converged, & that no payload exceeded the size limit.
+            .and_then(|leader, act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| {
+                    let leader = act.metrics.get(&leader).expect("Expected leader's metrics to be present.").clone();
+                    for node in act.metrics.values() {
+                        assert_eq!(node.last_log_index, leader.last_log_index, "Expected all cluster members to have matching last log index.");
+                    }
+                    assert!(act.max_append_entries_bytes > ENTRY_BYTES as u64, "Expected some payloads to carry multiple entries.");
+                    assert!(act.max_append_entries_bytes <= MAX_PAYLOAD_BYTES, "Expected all payloads to be within the size limit.");
+                    System::current().stop();
+                })));
+                fut::ok(())
+            })
+            .map_err(|err, _, _| panic!("Failure during test. {:?}", err));
+        ctx.spawn(task);
+    }));
+
+    // Run the test.
+    assert!(sys.run().is_ok(), "Error during test.");
+}
+
+impl RaftTestController {
+    /// Write 10 entries of `ENTRY_BYTES` each to the given leader, all at once, expecting each to be applied within a few seconds.
+    fn write_data(&mut self, leader: u64) -> impl ActorFuture<Actor=Self, Item=(), Error=()> {
+        let addr = self.nodes.get(&leader).expect("Expected leader to be registered.").clone();
+        let requests: Vec<_> = (0..10u64).map(|data| {
+            let mut data = data.to_string().into_bytes();
+            data.resize(ENTRY_BYTES, 0);
+            let payload = Payload::new(EntryNormal{data: MemoryStorageData{data}}, ResponseMode::Applied);
+            Timeout::new(addr.send(payload), Duration::from_secs(3))
+        }).collect();
+        fut::wrap_future(futures::future::join_all(requests))
+            .map_err(|err, _, _| panic!("Client request was not applied in time. {:?}", err))
+            .map(|res, _, _| {
+                for res in res {
+                    res.expect("Expected client request to succeed.");
+                }
+            })
+    }
+}
@@ -0,0 +1,120 @@
+//! Test pipelined replication behavior.
+
+mod fixtures;
+
+use std::time::{Duration, Instant};
+
+use actix::prelude::*;
+use actix_raft::{
+    NodeId,
+    messages::{EntryNormal, ResponseMode},
+    metrics::RaftMetrics,
+};
+use futures::stream;
+use tokio_timer::{Delay, Interval};
+
+use fixtures::{
+    Payload, RaftTestController, Node, setup_logger,
+    dev::{ExecuteInRaftRouter, GetCurrentLeader, MemRaft, RaftRouter, Register},
+    memory_storage::MemoryStorageData,
+};
+
+/// Send the given number of client requests to the given node, one every 10 milliseconds.
+fn send_requests(node: Addr<MemRaft>, count: u64) -> impl Future<Item=(), Error=()> {
+    Interval::new(Instant::now(), Duration::from_millis(10)).map_err(|_| ())
+        .zip(stream::iter_ok(0..count))
+        .map(move |(_, idx)| {
+            let entry = EntryNormal{data: MemoryStorageData{data: idx.to_string().into_bytes()}};
+            node.send(Payload::new(entry, ResponseMode::Committed)).map_err(|err| panic!("{}", err))
+        })
+        .buffered(count as usize)
+        .for_each(|res| {
+            res.expect("Expected client request sent to leader to succeed.");
+            Ok(())
+        })
+}
+
+/// Assert that all nodes of the cluster have the same last log index, according to their metrics.
+fn assert_logs_converged(act: &mut RaftRouter, _: &mut Context<RaftRouter>) {
+    let metrics: Vec<&RaftMetrics> = act.metrics.values().collect();
+    let last_log_index = metrics[0].last_log_index;
+    assert!(metrics.iter().all(|m| m.last_log_index == last_log_index), "Expected all nodes to have the same last log index, got {:?}.", metrics);
+}
+
+/// Pipelined replication tests for a three node cluster.
+///
+/// What does this test cover?
+///
+/// - With latency on the network, the leader should keep multiple AppendEntries RPCs in-flight.
+/// - Every client request should be committed, and the logs of all nodes should converge.
+/// - A follower which misses pipelined requests should be brought back up-to-date.
+///
+/// `RUST_LOG=actix_raft,pipelined_replication=debug cargo test pipelined_replication`
+#[test]
+fn pipelined_replication() {
+    setup_logger();
+    let sys = System::builder().stop_on_panic(true).name("test").build();
+
+    // Setup test dependencies.
+    let netarb = Arbiter::new();
+    let network = RaftRouter::start_in_arbiter(&netarb, |_| RaftRouter::new());
+    let members = vec![0, 1, 2];
+    let node0 = Node::builder(0, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 0, addr: node0.addr.clone()});
+    let node1 = Node::builder(1, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 1, addr: node1.addr.clone()});
+    let node2 = Node::builder(2, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 2, addr: node2.addr.clone()});
+    network.do_send(ExecuteInRaftRouter(Box::new(|act, _| act.set_append_entries_latency(Duration::from_millis(100)))));
+
+    // Setup test controller and actions.
+    let mut ctl = RaftTestController::new(network);
+    ctl.register(0, node0.addr.clone()).register(1, node1.addr.clone()).register(2, node2.addr.clone());
+    ctl.start_with_test(10,  Box::new(|act, ctx| {
+        // Get the current leader.
+        ctx.spawn(fut::wrap_future(act.network.send(GetCurrentLeader))
+            .map_err(|_, _: &mut RaftTestController, _| panic!("Failed to get current leader."))
+            .and_then(|res, _, _| fut::result(res)).and_then(|leader_opt, _, _| {
+                let leader = leader_opt.expect("Expected the cluster to have elected a leader.");
+                fut::ok(leader)
+            })
+
+            // Stream client requests to the leader faster than the network round trip.
+            .and_then(|leader, act, _| {
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.").clone();
+                fut::wrap_future(send_requests(node, 40)).map(move |_, _, _| leader)
+            })
+            .and_then(|leader, act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(|act, _| {
+                    assert!(act.max_append_entries_in_flight > 1, "Expected AppendEntries RPCs to be pipelined.");
+                })));
+                fut::ok(leader)
+            })
+
+            // Isolate a follower while requests are being pipelined, then restore it.
+            .and_then(|leader, act, _| {
+                let follower: NodeId = act.nodes.keys().cloned().find(|id| id != &leader).expect("Expected a follower.");
+                act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| act.isolate_node(follower))));
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.").clone();
+                fut::wrap_future(send_requests(node, 20)).map(move |_, _, _| follower)
+            })
+            .and_then(|follower, act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| act.restore_node(follower))));
+                fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(3))).map_err(|_, _, _| ())
+            })
+
+            // The logs of all nodes should have converged.
+            .and_then(|_, act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(assert_logs_converged)));
+                fut::ok(())
+            })
+            .and_then(|_, _, ctx| {
+                ctx.run_later(Duration::from_secs(1), |_, _| System::current().stop());
+                fut::ok(())
+            })
+            .map_err(|err, _, _| panic!("Failure during test. {:?}", err)));
+    }));
+
+    // Run the test.
+    assert!(sys.run().is_ok(), "Error during test.");
+}
@@ -0,0 +1,96 @@
+//! Test Pre-Vote behavior.
+
+mod fixtures;
+
+use std::time::{Duration, Instant};
+
+use actix::prelude::*;
+use actix_raft::metrics::{RaftMetrics, State};
+use tokio_timer::Delay;
+
+use fixtures::{
+    RaftTestController, Node, setup_logger,
+    dev::{ExecuteInRaftRouter, GetCurrentLeader, RaftRouter, Register},
+};
+
+/// Pre-Vote tests for a three node cluster.
+///
+/// What does this test cover?
+///
+/// - A follower which has been partitioned from the cluster should not increment its term while
+///   it is unable to win an election.
+/// - When the partitioned follower rejoins the cluster, it should not disrupt the leader.
+///
+/// `RUST_LOG=actix_raft,pre_vote=debug cargo test pre_vote`
+#[test]
+fn pre_vote() {
+    setup_logger();
+    let sys = System::builder().stop_on_panic(true).name("test").build();
+
+    // Setup test dependencies.
+    let netarb = Arbiter::new();
+    let network = RaftRouter::start_in_arbiter(&netarb, |_| RaftRouter::new());
+    let members = vec![0, 1, 2];
+    let node0 = Node::builder(0, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 0, addr: node0.addr.clone()});
+    let node1 = Node::builder(1, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 1, addr: node1.addr.clone()});
+    let node2 = Node::builder(2, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 2, addr: node2.addr.clone()});
+
+    // Setup test controller and actions.
+    let mut ctl = RaftTestController::new(network);
+    ctl.register(0, node0.addr.clone()).register(1, node1.addr.clone()).register(2, node2.addr.clone());
+    ctl.start_with_test(10,  Box::new(|act, ctx| {
+        // Get the current leader.
+        ctx.spawn(fut::wrap_future(act.network.send(GetCurrentLeader))
+            .map_err(|_, _: &mut RaftTestController, _| panic!("Failed to get current leader."))
+            .and_then(|res, _, _| fut::result(res)).and_then(|leader_opt, _, _| {
+                let leader = leader_opt.expect("Expected the cluster to have elected a leader.");
+                fut::ok(leader)
+            })
+
+            // Isolate a follower for long enough that its election timeout elapses a few times.
+            .and_then(|leader, act, _| {
+                let follower = act.nodes.keys().cloned().find(|id| id != &leader).expect("Expected a follower.");
+                act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| act.isolate_node(follower))));
+                fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(6))).map_err(|_, _, _| ())
+                    .map(move |_, _, _| (leader, follower))
+            })
+
+            // Assert that the isolated follower has not incremented its term, then restore it.
+            .and_then(|(leader, follower), act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| {
+                    let leader_metrics: &RaftMetrics = act.metrics.get(&leader).unwrap();
+                    let follower_metrics: &RaftMetrics = act.metrics.get(&follower).unwrap();
+                    assert_eq!(follower_metrics.current_term, leader_metrics.current_term, "Expected isolated follower to not have incremented its term.");
+                    act.restore_node(follower);
+                })));
+                fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(3))).map_err(|_, _, _| ())
+                    .map(move |_, _, _| leader)
+            })
+
+            // Assert that the original leader is still leader, and that the cluster is at the same term.
+            .and_then(|leader, act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| {
+                    let node0: &RaftMetrics = act.metrics.get(&0).unwrap();
+                    let node1: &RaftMetrics = act.metrics.get(&1).unwrap();
+                    let node2: &RaftMetrics = act.metrics.get(&2).unwrap();
+                    let data = [node0, node1, node2];
+                    let leader_metrics = data.iter().find(|e| e.state == State::Leader).expect("Expected leader to exist.");
+                    assert_eq!(leader_metrics.id, leader, "Expected the original leader to have retained leadership.");
+                    assert!(data.iter().all(|e| e.current_leader == Some(leader)), "Expected all nodes have the same leader.");
+                    assert!(data.iter().all(|e| e.current_term == leader_metrics.current_term), "Expected all nodes to be at the same term.");
+                })));
+                fut::ok(())
+            })
+            .and_then(|_, _, ctx| {
+                ctx.run_later(Duration::from_secs(2), |_, _| System::current().stop());
+                fut::ok(())
+            })
+            .map_err(|err, _, _| panic!("Failure during test. {:?}", err)));
+    }));
+
+    // Run the test.
+    assert!(sys.run().is_ok(), "Error during test.");
+}
@@ -0,0 +1,191 @@
+//! Test single-server membership changes.
+
+mod fixtures;
+
+use std::time::{Duration, Instant};
+
+use actix::prelude::*;
+use actix_raft::{
+    MembershipChangeMode,
+    admin::{ProposeConfigChange, ProposeConfigChangeError},
+    messages::{EntryNormal, ResponseMode},
+    metrics::State,
+};
+use tokio_timer::{Delay, Timeout};
+
+use fixtures::{
+    Payload, RaftTestController, Node, setup_logger,
+    dev::{ExecuteInRaftRouter, GetCurrentLeader, RaftRouter, Register, RemoveNodeFromCluster},
+    memory_storage::MemoryStorageData,
+};
+
+/// Single-server membership change tests for a three node cluster.
+///
+/// What does this test cover?
+///
+/// - A change which adds one node & removes another should be rejected.
+/// - A change which adds a single node should succeed without entering joint consensus.
+/// - A change proposed while a previous change is uncommitted should be rejected.
+/// - A change which removes the leader should succeed, after which the leader should step down.
+/// - The remaining members should elect a new leader & keep committing entries.
+///
+/// `RUST_LOG=actix_raft,single_server_changes=debug cargo test single_server_changes`
+#[test]
+fn single_server_changes() {
+    setup_logger();
+    let sys = System::builder().stop_on_panic(true).name("test").build();
+
+    // Setup test dependencies.
+    let net = RaftRouter::new();
+    let network = net.start();
+    let members = vec![0, 1, 2];
+    let node0 = Node::builder(0, network.clone(), members.clone()).membership_change_mode(MembershipChangeMode::SingleServer).build();
+    network.do_send(Register{id: 0, addr: node0.addr.clone()});
+    let node1 = Node::builder(1, network.clone(), members.clone()).membership_change_mode(MembershipChangeMode::SingleServer).build();
+    network.do_send(Register{id: 1, addr: node1.addr.clone()});
+    let node2 = Node::builder(2, network.clone(), members.clone()).membership_change_mode(MembershipChangeMode::SingleServer).build();
+    network.do_send(Register{id: 2, addr: node2.addr.clone()});
+
+    // Setup test controller and actions.
+    let mut ctl = RaftTestController::new(network);
+    ctl.register(0, node0.addr.clone()).register(1, node1.addr.clone()).register(2, node2.addr.clone());
+    ctl.start_with_test(10, Box::new(|act, ctx| {
+        let task = fut::wrap_future(act.network.send(GetCurrentLeader))
+            .map_err(|_, _: &mut RaftTestController, _| panic!("Failed to get current leader."))
+            .and_then(|res, _, _| fut::result(res)).and_then(|leader_opt, _, _| {
+                let leader = leader_opt.expect("Expected the cluster to have elected a leader.");
+                fut::ok(leader)
+            })
+            .and_then(|leader, act, _| act.write_data(leader).map(move |_, _, _| leader))
+
+            // Propose a change which adds one node & removes another. It should be rejected.
+            .and_then(|leader, act, _| {
+                let follower = act.nodes.keys().cloned().find(|id| id != &leader).expect("Expected a follower.");
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                fut::wrap_future(node.send(ProposeConfigChange::new(vec![3], vec![follower])))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |res, _, _| {
+                        match res {
+                            Err(ProposeConfigChangeError::MultipleChanges) => (),
+                            other => panic!("Expected MultipleChanges error, got {:?}.", other),
+                        }
+                        (leader, follower)
+                    })
+            })
+
+            // Add a new node, and immediately propose another change, which should be rejected
+            // as the first change has not yet been committed.
+            .and_then(|(leader, follower), act, _| {
+                let node3 = Node::builder(3, act.network.clone(), vec![3]).membership_change_mode(MembershipChangeMode::SingleServer).build();
+                act.register(3, node3.addr.clone());
+                act.network.do_send(Register{id: 3, addr: node3.addr.clone()});
+
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                let first = node.send(ProposeConfigChange::new(vec![3], vec![]));
+                let second = node.send(ProposeConfigChange::new(vec![], vec![follower]));
+                fut::wrap_future(first.join(second))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |(first, second), _, _| {
+                        first.expect("Expected the first config change to succeed.");
+                        match second {
+                            Err(ProposeConfigChangeError::ChangeInProgress) => (),
+                            other => panic!("Expected ChangeInProgress error, got {:?}.", other),
+                        }
+                        leader
+                    })
+            })
+            .and_then(|leader, act, _| act.write_data(leader).map(move |_, _, _| leader))
+            .and_then(|leader, _, _| fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(2)))
+                .map_err(|_, _, _| ())
+                .map(move |_, _, _| leader))
+
+            // Assert that the new node is a voting member & that joint consensus was never used.
+            .and_then(|leader, act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| {
+                    let leader = act.metrics.get(&leader).expect("Expected leader's metrics to be present.");
+                    assert_eq!(leader.membership_config.members.len(), 4, "Expected a four node cluster.");
+                    assert!(leader.membership_config.members.contains(&3), "Expected node 3 to be a voting member.");
+                    assert!(!leader.membership_config.is_in_joint_consensus, "Expected cluster not to be in joint consensus.");
+                    assert!(leader.membership_config.non_voters.is_empty(), "Expected no non-voters in cluster.");
+
+                    let node3 = act.metrics.get(&3).expect("Expected node 3's metrics to be present.");
+                    assert_eq!(node3.state, State::Follower, "Expected node 3 to be a follower.");
+                    assert_eq!(node3.last_log_index, leader.last_log_index, "Expected node 3 to have caught up.");
+                    assert_eq!(node3.membership_config, leader.membership_config, "Expected node 3 to have matching config.");
+                })));
+                fut::ok(leader)
+            })
+
+            // Remove the current leader from the cluster.
+            .and_then(|leader, act, _| {
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                fut::wrap_future(node.send(ProposeConfigChange::new(vec![], vec![leader])))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |res, _, _| {
+                        res.expect("Expected removal of the leader to succeed.");
+                        leader
+                    })
+            })
+            .and_then(|old_leader, _, _| fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(6)))
+                .map_err(|_, _, _| ())
+                .map(move |_, _, _| old_leader))
+            .and_then(|old_leader, act, _| {
+                fut::wrap_future(act.network.send(RemoveNodeFromCluster{id: old_leader}))
+                    .map_err(|_, _: &mut RaftTestController, _| panic!("Messaging error while attempting to remove old node."))
+                    .and_then(|res, _, _| fut::result(res))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |_, _, _| old_leader)
+            })
+
+            // Write more data to the new leader & assert against the state of the cluster.
+            .and_then(|old_leader, act, _| {
+                fut::wrap_future(act.network.send(GetCurrentLeader))
+                    .map_err(|_, _: &mut RaftTestController, _| panic!("Failed to get current leader."))
+                    .and_then(|res, _, _| fut::result(res))
+                    .and_then(move |leader_opt, act, _| {
+                        let leader = leader_opt.expect("Expected the cluster to have elected a new leader.");
+                        assert_ne!(leader, old_leader, "Expected a new leader to have been elected.");
+                        act.write_data(leader).map(move |_, _, _| (leader, old_leader))
+                    })
+            })
+            .and_then(|ids, _, _| fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(2)))
+                .map_err(|_, _, _| ())
+                .map(move |_, _, _| ids))
+            .and_then(|(leader, old_leader), act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| {
+                    let leader = act.metrics.get(&leader).expect("Expected leader's metrics to be present.");
+                    assert_eq!(leader.membership_config.members.len(), 3, "Expected a three node cluster.");
+                    assert!(!leader.membership_config.contains(&old_leader), "Expected old leader to have been removed.");
+                    for nodeid in leader.membership_config.members.iter().filter(|e| *e != &leader.id) {
+                        let node = act.metrics.get(nodeid).expect("Expected to find metrics entry for cluster member.");
+                        assert_eq!(node.state, State::Follower, "Expected all other cluster members to be followers.");
+                        assert_eq!(node.membership_config, leader.membership_config, "Expected all cluster members to have matching config.");
+                        assert_eq!(node.last_log_index, leader.last_log_index, "Expected all cluster members to have matching last log index.");
+                    }
+                    System::current().stop();
+                })));
+                fut::ok(())
+            })
+            .map_err(|err, _, _| panic!("Failure during test. {:?}", err));
+        ctx.spawn(task);
+    }));
+
+    // Run the test.
+    assert!(sys.run().is_ok(), "Error during test.");
+}
+
+impl RaftTestController {
+    /// Write 10 entries to the given leader, expecting each to be applied within a few seconds.
+    fn write_data(&mut self, leader: u64) -> impl ActorFuture<Actor=Self, Item=(), Error=()> {
+        let addr = self.nodes.get(&leader).expect("Expected leader to be registered.").clone();
+        fut::wrap_stream(futures::stream::iter_ok(0..10u64))
+            .and_then(move |data, _, _| {
+                let entry = EntryNormal{data: MemoryStorageData{data: data.to_string().into_bytes()}};
+                let payload = Payload::new(entry, ResponseMode::Applied);
+                fut::wrap_future(Timeout::new(addr.send(payload), Duration::from_secs(3)))
+                    .map_err(|err, _, _| panic!("Client request was not applied in time. {:?}", err))
+                    .map(|res, _, _| { res.expect("Expected client request to succeed."); })
+            })
+            .finish()
+    }
+}
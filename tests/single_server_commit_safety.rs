@@ -0,0 +1,119 @@
+//! Test that a node added via a single-server change does not count toward commitment early.
+
+mod fixtures;
+
+use std::time::{Duration, Instant};
+
+use actix::prelude::*;
+use actix_raft::{
+    MembershipChangeMode,
+    admin::ProposeConfigChange,
+    messages::{ClientError, EntryNormal, ResponseMode},
+};
+use tokio_timer::{Delay, Timeout};
+
+use fixtures::{
+    Payload, RaftTestController, Node, setup_logger,
+    dev::{ExecuteInRaftRouter, GetCurrentLeader, RaftRouter, Register},
+    memory_storage::MemoryStorageData,
+};
+
+/// Single-server change commit safety tests for a two node cluster.
+///
+/// What does this test cover?
+///
+/// - A node added via a single-server change, which has not yet replicated anything, must not
+///   count toward the commitment of entries which only the leader holds.
+/// - An entry held only by the leader, while the only follower lags behind, must not be committed
+///   once a third node is added, & should receive an `Indeterminate` error once the leader steps
+///   down.
+///
+/// `RUST_LOG=actix_raft,single_server_commit_safety=debug cargo test single_server_commit_safety`
+#[test]
+fn single_server_commit_safety() {
+    setup_logger();
+    let sys = System::builder().stop_on_panic(true).name("test").build();
+
+    // Setup test dependencies.
+    let net = RaftRouter::new();
+    let network = net.start();
+    let members = vec![0, 1];
+    let node0 = Node::builder(0, network.clone(), members.clone()).membership_change_mode(MembershipChangeMode::SingleServer).max_inflight_payloads(1).build();
+    network.do_send(Register{id: 0, addr: node0.addr.clone()});
+    let node1 = Node::builder(1, network.clone(), members.clone()).membership_change_mode(MembershipChangeMode::SingleServer).max_inflight_payloads(1).build();
+    network.do_send(Register{id: 1, addr: node1.addr.clone()});
+
+    // Setup test controller and actions.
+    let mut ctl = RaftTestController::new(network);
+    ctl.register(0, node0.addr.clone()).register(1, node1.addr.clone());
+    ctl.start_with_test(10, Box::new(|act, ctx| {
+        let task = fut::wrap_future(act.network.send(GetCurrentLeader))
+            .map_err(|_, _: &mut RaftTestController, _| panic!("Failed to get current leader."))
+            .and_then(|res, _, _| fut::result(res)).and_then(|leader_opt, _, _| {
+                let leader = leader_opt.expect("Expected the cluster to have elected a leader.");
+                fut::ok(leader)
+            })
+
+            // Commit an entry from the leader's term, so that config changes are accepted.
+            .and_then(|leader, act, _| {
+                let entry = EntryNormal{data: MemoryStorageData{data: b"0".to_vec()}};
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                fut::wrap_future(Timeout::new(node.send(Payload::new(entry, ResponseMode::Applied)), Duration::from_secs(3)))
+                    .map_err(|err, _, _| panic!("Client request was not applied in time. {:?}", err))
+                    .map(move |res, _, _| { res.expect("Expected client request to succeed."); leader })
+            })
+
+            // Delay the follower's acknowledgement of the next entry, & isolate the new node, so
+            // that it can not acknowledge any entries.
+            .and_then(|leader, act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(|act, _| {
+                    act.set_append_entries_latency(Duration::from_secs(1));
+                    act.isolate_node(2);
+                })));
+                let node2 = Node::builder(2, act.network.clone(), vec![2]).membership_change_mode(MembershipChangeMode::SingleServer).build();
+                act.register(2, node2.addr.clone());
+                act.network.do_send(Register{id: 2, addr: node2.addr.clone()});
+                fut::ok(leader)
+            })
+
+            // Write an entry, & while its acknowledgement is delayed, write a second entry & add
+            // the new node to the cluster. Then isolate the follower, so that only its delayed
+            // acknowledgement of the first entry arrives, at which point only the first entry may
+            // be committed, as the second entry is only held by the leader.
+            .and_then(|leader, act, _| {
+                let follower = act.nodes.keys().cloned().find(|id| id != &leader).expect("Expected a follower.");
+                let (node, network) = (act.nodes.get(&leader).expect("Expected leader to be registered.").clone(), act.network.clone());
+                let first = node.send(Payload::new(EntryNormal{data: MemoryStorageData{data: b"1".to_vec()}}, ResponseMode::Committed))
+                    .map_err(|err| panic!("{}", err));
+                let second = Delay::new(Instant::now() + Duration::from_millis(200))
+                    .map_err(|err| panic!("{}", err))
+                    .and_then(move |_| {
+                        let write = node.send(Payload::new(EntryNormal{data: MemoryStorageData{data: b"2".to_vec()}}, ResponseMode::Committed))
+                            .map_err(|err| panic!("{}", err));
+                        let change = Delay::new(Instant::now() + Duration::from_millis(200))
+                            .map_err(|err| panic!("{}", err))
+                            .and_then(move |_| node.send(ProposeConfigChange::new(vec![2], vec![])).map_err(|err| panic!("{}", err)));
+                        let isolate = Delay::new(Instant::now() + Duration::from_millis(400))
+                            .map_err(|err| panic!("{}", err))
+                            .map(move |_| network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| act.isolate_node(follower)))));
+                        write.join3(change, isolate).map(|(write, _, _)| match write {
+                            Err(ClientError::Indeterminate{..}) => (),
+                            other => panic!("Expected Indeterminate error for an entry held only by the leader, got {:?}.", other),
+                        })
+                    });
+                fut::wrap_future(first.join(second))
+                    .map(|(first, _), _: &mut RaftTestController, _| {
+                        first.expect("Expected the first entry to be committed.");
+                    })
+            })
+            .and_then(|_, _, ctx| {
+                ctx.run_later(Duration::from_secs(1), |_, _| System::current().stop());
+                fut::ok(())
+            })
+            .map_err(|err, _, _| panic!("Failure during test. {:?}", err));
+        ctx.spawn(task);
+    }));
+
+    // Run the test.
+    assert!(sys.run().is_ok(), "Error during test.");
+}
@@ -0,0 +1,116 @@
+//! Test lease based client reads after a failed leadership transfer.
+
+mod fixtures;
+
+use std::time::{Duration, Instant};
+
+use actix::prelude::*;
+use actix_raft::{
+    admin::{TransferLeadership, TransferLeadershipError},
+    messages::{ClientReadError, ClientReadRequest, EntryNormal, ResponseMode},
+};
+use tokio_timer::Delay;
+
+use fixtures::{
+    Payload, RaftTestController, Node, setup_logger,
+    dev::{ExecuteInRaftRouter, GetCurrentLeader, RaftRouter, Register},
+    memory_storage::MemoryStorageData,
+};
+
+/// Lease read tests for a leadership transfer which times out, for a three node cluster.
+///
+/// What does this test cover?
+///
+/// - Once a `TimeoutNowRequest` has been sent, heartbeats acknowledged before it must no longer
+///   count toward the leader's lease, even after the transfer has timed out, as the target may
+///   have been elected without waiting for the lease to expire.
+/// - A leader which has been partitioned from the rest of the cluster after sending a
+///   `TimeoutNowRequest` must not serve reads based on its lease.
+///
+/// `RUST_LOG=actix_raft,transfer_lease_reads=debug cargo test transfer_lease_reads`
+#[test]
+fn transfer_lease_reads() {
+    setup_logger();
+    let sys = System::builder().stop_on_panic(true).name("test").build();
+
+    // Setup test dependencies.
+    let net = RaftRouter::new();
+    let network = net.start();
+    let members = vec![0, 1, 2];
+    let node0 = Node::builder(0, network.clone(), members.clone()).lease_reads(true).build();
+    network.do_send(Register{id: 0, addr: node0.addr.clone()});
+    let node1 = Node::builder(1, network.clone(), members.clone()).lease_reads(true).build();
+    network.do_send(Register{id: 1, addr: node1.addr.clone()});
+    let node2 = Node::builder(2, network.clone(), members.clone()).lease_reads(true).build();
+    network.do_send(Register{id: 2, addr: node2.addr.clone()});
+
+    // Setup test controller and actions.
+    let mut ctl = RaftTestController::new(network);
+    ctl.register(0, node0.addr.clone()).register(1, node1.addr.clone()).register(2, node2.addr.clone());
+    ctl.start_with_test(10, Box::new(|act, ctx| {
+        let task = fut::wrap_future(act.network.send(GetCurrentLeader))
+            .map_err(|_, _: &mut RaftTestController, _| panic!("Failed to get current leader."))
+            .and_then(|res, _, _| fut::result(res)).and_then(|leader_opt, _, _| {
+                let leader = leader_opt.expect("Expected the cluster to have elected a leader.");
+                fut::ok(leader)
+            })
+
+            // Delay the acknowledgements of the next entry, so that the target of the transfer is
+            // only known to be up-to-date, & is sent a `TimeoutNowRequest`, about a second later.
+            .and_then(|leader, act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(|act, _| act.set_append_entries_latency(Duration::from_secs(1)))));
+                let entry = EntryNormal{data: MemoryStorageData{data: b"0".to_vec()}};
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                node.do_send(Payload::new(entry, ResponseMode::Committed));
+                fut::wrap_future(Delay::new(Instant::now() + Duration::from_millis(100)))
+                    .map_err(|_, _, _| ())
+                    .map(move |_, _, _| leader)
+            })
+
+            // Begin the transfer, & isolate both followers just before the `TimeoutNowRequest` is
+            // sent, so that the target is never elected & the transfer times out.
+            .and_then(|leader, act, _| {
+                let followers: Vec<_> = act.nodes.keys().cloned().filter(|id| id != &leader).collect();
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.").clone();
+                let network = act.network.clone();
+                let isolate = Delay::new(Instant::now() + Duration::from_millis(800))
+                    .map_err(|err| panic!("{}", err))
+                    .map(move |_| network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| {
+                        for id in followers {
+                            act.isolate_node(id);
+                        }
+                    }))));
+                let transfer = node.send(TransferLeadership::new(*act.nodes.keys().find(|id| *id != &leader).expect("Expected a follower.")))
+                    .map_err(|err| panic!("{}", err));
+                fut::wrap_future(transfer.join(isolate))
+                    .map(move |(res, _), _: &mut RaftTestController, _| {
+                        match res {
+                            Err(TransferLeadershipError::Timeout) => (),
+                            other => panic!("Expected the transfer to time out, got {:?}.", other),
+                        }
+                        leader
+                    })
+            })
+
+            // The leader's lease, based on heartbeats acknowledged before the transfer's
+            // `TimeoutNowRequest` was sent, has not yet expired. It must not be used to serve reads.
+            .and_then(|leader, act, _| {
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                fut::wrap_future(node.send(ClientReadRequest::new()))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(|res, _, _| match res {
+                        Err(ClientReadError::ForwardToLeader{..}) => (),
+                        other => panic!("Expected read to be rejected after a failed transfer, got {:?}.", other),
+                    })
+            })
+            .and_then(|_, _, ctx| {
+                ctx.run_later(Duration::from_secs(1), |_, _| System::current().stop());
+                fut::ok(())
+            })
+            .map_err(|err, _, _| panic!("Failure during test. {:?}", err));
+        ctx.spawn(task);
+    }));
+
+    // Run the test.
+    assert!(sys.run().is_ok(), "Error during test.");
+}
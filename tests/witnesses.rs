@@ -0,0 +1,197 @@
+//! Test witness members.
+
+mod fixtures;
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::{Duration, Instant},
+};
+
+use actix::prelude::*;
+use actix_raft::{
+    admin::{ChangeMembership, ChangeMembershipError, TransferLeadership, TransferLeadershipError},
+    messages::{EntryNormal, EntryPayload, ResponseMode},
+    metrics::State,
+};
+use tokio_timer::{Delay, Timeout};
+
+use fixtures::{
+    Payload, RaftTestController, Node, setup_logger,
+    dev::{ExecuteInRaftRouter, GetCurrentLeader, RaftRouter, Register},
+    memory_storage::{GetCurrentState, MemoryStorageData},
+};
+
+/// Witness member tests for a three node cluster.
+///
+/// What does this test cover?
+///
+/// - Designating the leader as a witness should be rejected.
+/// - Designating a follower as a witness should succeed, and repeating it should be a no-op.
+/// - A witness should not be a valid target for a leadership transfer.
+/// - A witness should not be able to become a full member again.
+/// - Once the leader is isolated, the remaining full member should become leader, with the
+///   witness voting & counting toward commitment, but never campaigning.
+/// - The witness should never store the data of normal entries.
+///
+/// `RUST_LOG=actix_raft,witnesses=debug cargo test witnesses`
+#[test]
+fn witnesses() {
+    setup_logger();
+    let sys = System::builder().stop_on_panic(true).name("test").build();
+
+    // Setup test dependencies.
+    let net = RaftRouter::new();
+    let network = net.start();
+    let members = vec![0, 1, 2];
+    let node0 = Node::builder(0, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 0, addr: node0.addr.clone()});
+    let node1 = Node::builder(1, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 1, addr: node1.addr.clone()});
+    let node2 = Node::builder(2, network.clone(), members.clone()).build();
+    network.do_send(Register{id: 2, addr: node2.addr.clone()});
+    let storages: BTreeMap<_, _> = vec![(0, node0.storage.clone()), (1, node1.storage.clone()), (2, node2.storage.clone())].into_iter().collect();
+
+    // Setup test controller and actions.
+    let mut ctl = RaftTestController::new(network);
+    ctl.register(0, node0.addr.clone()).register(1, node1.addr.clone()).register(2, node2.addr.clone());
+    ctl.start_with_test(10, Box::new(move |act, ctx| {
+        let task = fut::wrap_future(act.network.send(GetCurrentLeader))
+            .map_err(|_, _: &mut RaftTestController, _| panic!("Failed to get current leader."))
+            .and_then(|res, _, _| fut::result(res)).and_then(|leader_opt, _, _| {
+                let leader = leader_opt.expect("Expected the cluster to have elected a leader.");
+                fut::ok(leader)
+            })
+
+            // Designating the leader as a witness should be rejected.
+            .and_then(|leader, act, _| {
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                let members = act.nodes.keys().cloned().collect();
+                let witnesses = vec![leader].into_iter().collect();
+                fut::wrap_future(node.send(ChangeMembership::with_witnesses(members, witnesses)))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |res, _, _| {
+                        match res {
+                            Err(ChangeMembershipError::InvalidWitnesses) => (),
+                            other => panic!("Expected InvalidWitnesses error, got {:?}.", other),
+                        }
+                        leader
+                    })
+            })
+
+            // Designate a follower as a witness. Repeating the change should be a no-op.
+            .and_then(|leader, act, _| {
+                let mut followers = act.nodes.keys().cloned().filter(|id| id != &leader);
+                let (follower, witness) = (followers.next().expect("Expected a follower."), followers.next().expect("Expected a follower."));
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.").clone();
+                let members: BTreeSet<_> = act.nodes.keys().cloned().collect();
+                let witnesses: BTreeSet<_> = vec![witness].into_iter().collect();
+                fut::wrap_future(node.send(ChangeMembership::with_witnesses(members.clone(), witnesses.clone())))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .and_then(move |res, _, _| {
+                        res.expect("Expected designating a witness to succeed on the leader.");
+                        fut::wrap_future(node.send(ChangeMembership::with_witnesses(members, witnesses))).map_err(|err, _, _| panic!("{}", err))
+                    })
+                    .map(move |res, _, _| {
+                        match res {
+                            Err(ChangeMembershipError::Noop) => (),
+                            other => panic!("Expected Noop error, got {:?}.", other),
+                        }
+                        (leader, follower, witness)
+                    })
+            })
+
+            // The witness may not become leader via a transfer, nor may it become a full member.
+            .and_then(|(leader, follower, witness), act, _| {
+                let node = act.nodes.get(&leader).expect("Expected leader to be registered.");
+                let transfer = node.send(TransferLeadership::new(witness));
+                let promote = node.send(ChangeMembership::new(act.nodes.keys().cloned().collect()));
+                fut::wrap_future(transfer.join(promote))
+                    .map_err(|err, _, _| panic!("{}", err))
+                    .map(move |(transfer, promote), _, _| {
+                        match transfer {
+                            Err(TransferLeadershipError::InvalidTarget) => (),
+                            other => panic!("Expected InvalidTarget error, got {:?}.", other),
+                        }
+                        match promote {
+                            Err(ChangeMembershipError::InvalidWitnesses) => (),
+                            other => panic!("Expected InvalidWitnesses error, got {:?}.", other),
+                        }
+                        (leader, follower, witness)
+                    })
+            })
+            .and_then(|ids, act, _| act.write_data(ids.0).map(move |_, _, _| ids))
+
+            // Isolate the leader. Only the full follower may take over, and it needs the witness' vote.
+            .and_then(|(leader, follower, witness), act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| act.isolate_node(leader))));
+                fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(6)))
+                    .map_err(|_, _, _| ())
+                    .map(move |_, _, _| (follower, witness))
+            })
+            .and_then(|(follower, witness), act, _| {
+                fut::wrap_future(act.network.send(GetCurrentLeader))
+                    .map_err(|_, _: &mut RaftTestController, _| panic!("Failed to get current leader."))
+                    .and_then(|res, _, _| fut::result(res))
+                    .and_then(move |leader_opt, act, _| {
+                        let leader = leader_opt.expect("Expected the cluster to have elected a new leader.");
+                        assert_eq!(leader, follower, "Expected the full member to have become leader.");
+                        act.write_data(leader).map(move |_, _, _| (leader, witness))
+                    })
+            })
+            .and_then(|ids, _, _| fut::wrap_future(Delay::new(Instant::now() + Duration::from_secs(1)))
+                .map_err(|_, _, _| ())
+                .map(move |_, _, _| ids))
+
+            // Assert against the state of the witness.
+            .and_then(|(leader, witness), act, _| {
+                act.network.do_send(ExecuteInRaftRouter(Box::new(move |act, _| {
+                    let leader = act.metrics.get(&leader).expect("Expected leader's metrics to be present.");
+                    assert_eq!(leader.membership_config.witnesses, vec![witness], "Expected the cluster to have a single witness.");
+                    let node = act.metrics.get(&witness).expect("Expected witness' metrics to be present.");
+                    assert_eq!(node.state, State::Follower, "Expected the witness to be a follower.");
+                    assert_eq!(node.last_log_index, leader.last_log_index, "Expected the witness to have caught up.");
+                })));
+                fut::ok(witness)
+            })
+            .and_then(move |witness, _, _| {
+                let storage = storages.get(&witness).expect("Expected witness' storage to be present.");
+                fut::wrap_future(storage.send(GetCurrentState))
+                    .map_err(|err, _: &mut RaftTestController, _| panic!("{}", err))
+                    .and_then(|res, _, _| fut::result(res))
+                    .map(|state, _, _| {
+                        let (mut stripped, mut normal) = (0, 0);
+                        for entry in state.log.values().chain(state.state_machine.values()) {
+                            match entry.payload {
+                                EntryPayload::Stripped => stripped += 1,
+                                EntryPayload::Normal(_) => normal += 1,
+                                _ => (),
+                            }
+                        }
+                        assert_eq!(normal, 0, "Expected the witness to store no application data.");
+                        assert!(stripped >= 20, "Expected the witness to store the metadata of each normal entry.");
+                        System::current().stop();
+                    })
+            })
+            .map_err(|err, _, _| panic!("Failure during test. {:?}", err));
+        ctx.spawn(task);
+    }));
+
+    // Run the test.
+    assert!(sys.run().is_ok(), "Error during test.");
+}
+
+impl RaftTestController {
+    /// Write 10 entries to the given leader, expecting each to be applied within a few seconds.
+    fn write_data(&mut self, leader: u64) -> impl ActorFuture<Actor=Self, Item=(), Error=()> {
+        let addr = self.nodes.get(&leader).expect("Expected leader to be registered.").clone();
+        fut::wrap_stream(futures::stream::iter_ok(0..10u64))
+            .and_then(move |data, _, _| {
+                let entry = EntryNormal{data: MemoryStorageData{data: data.to_string().into_bytes()}};
+                let payload = Payload::new(entry, ResponseMode::Applied);
+                fut::wrap_future(Timeout::new(addr.send(payload), Duration::from_secs(3)))
+                    .map_err(|err, _, _| panic!("Client request was not applied in time. {:?}", err))
+                    .map(|res, _, _| { res.expect("Expected client request to succeed."); })
+            })
+            .finish()
+    }
+}